@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use std::{env, fs, io};
+use sinterpreter::libs::expr::ast::Stmt;
+use sinterpreter::libs::interpreter::Interpreter;
+use sinterpreter::libs::lex::Lox;
+use sinterpreter::libs::vm::Vm;
+
+// Times every script in benches/scripts on both backends: `cargo bench`, or `cargo bench -- name`
+// for the scripts whose name contains `name`.
+//
+// `cargo bench --bench interpreters -- --baseline=revision` also builds the sinterpreter at a git
+// revision, in a worktree under target/, and times it against this one, so that a change shows
+// what it gains. Both run as a process of their own per script. For the gain of NaN-boxing, give
+// the commit before the one that boxed the values.
+//
+// Against that commit, the gain by the fastest of three such runs, in the middle of the three:
+//
+//   script      tree    vm
+//   arithmetic  0.82x   1.25x
+//   strings     0.95x   2.33x
+//
+// The VM gains from 8-byte stack values and shared strings. The tree-walker has no stack of values
+// and loses a little, mostly to converting at its environments.
+const RUNS: u32 = 5;
+
+fn time(mut run: impl FnMut() -> bool) -> Option<(Duration, Duration)> {
+    let mut times = Vec::new();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        if !run() {
+            return None;
+        }
+        times.push(start.elapsed());
+    }
+    let fastest = times.iter().min().copied()?;
+    Some((fastest, times.iter().sum::<Duration>() / RUNS))
+}
+
+// Checks the git revision out in the worktree target/baseline, made on the first call, builds it and
+// gives the path of its sinterpreter.
+fn build(revision: &str) -> PathBuf {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let worktree = root.join("target").join("baseline");
+    let succeeds = |command: &mut Command| command.status().is_ok_and(|status| status.success());
+    let checked_out = match worktree.exists() {
+        true => succeeds(Command::new("git").current_dir(&worktree).args(["checkout", "--detach", revision])),
+        false => succeeds(Command::new("git").current_dir(root).args(["worktree", "add", "--detach"]).arg(&worktree).arg(revision)),
+    };
+    assert!(checked_out, "Failed to check {revision} out in {}", worktree.display());
+    let built = succeeds(Command::new("cargo").current_dir(&worktree).args(["build", "--release", "--bin", "sinterpreter"]));
+    assert!(built, "Failed to build {revision}");
+    worktree.join("target").join("release").join("sinterpreter")
+}
+
+// Runs `script` with the sinterpreter at `binary`, which fails when the script does.
+fn run(binary: &Path, backend: &str, script: &Path) -> bool {
+    Command::new(binary)
+        .arg(format!("--backend={backend}"))
+        .arg(script)
+        .stdout(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn main() {
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with("--"));
+    let baseline = env::args().skip(1).find_map(|arg| arg.strip_prefix("--baseline=").map(build));
+    let current = Path::new(env!("CARGO_BIN_EXE_sinterpreter"));
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches").join("scripts");
+    let mut scripts = fs::read_dir(&directory)
+        .unwrap_or_else(|error| panic!("Failed to read {}: {error}", directory.display()))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "slsf"))
+        .collect::<Vec<_>>();
+    scripts.sort();
+
+    println!("{:<16}{:<8}{:<6}{:>12}{:>12}{:>8}", "script", "backend", "build", "fastest", "mean", "gain");
+    for script in scripts {
+        let name = script.file_stem().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if filter.as_ref().is_some_and(|filter| !name.contains(filter.as_str())) {
+            continue;
+        }
+        let source = fs::read_to_string(&script).unwrap_or_else(|error| panic!("Failed to read {name}: {error}"));
+        let parse = || -> Vec<Stmt> { Lox::new(source.clone()).parse().unwrap_or_else(|| panic!("Failed to parse {name}")) };
+
+        let mut rows = vec![
+            ("tree", "", time(|| Interpreter::with_output(Box::new(io::sink())).run(parse()))),
+            ("vm", "", time(|| Vm::with_output(Box::new(io::sink())).run(parse()))),
+        ];
+        if let Some(baseline) = &baseline {
+            for backend in ["tree", "vm"] {
+                rows.push((backend, "old", time(|| run(baseline, backend, &script))));
+                rows.push((backend, "new", time(|| run(current, backend, &script))));
+            }
+        }
+        let mut old: Option<Duration> = None;
+        for (backend, build, times) in rows {
+            match times {
+                Some((fastest, mean)) => {
+                    // How many times faster than the old build the new one is, by the fastest runs.
+                    let gain = match (build, old) {
+                        ("new", Some(old)) => format!("{:.2}x", old.as_secs_f64() / fastest.as_secs_f64()),
+                        _ => String::new(),
+                    };
+                    println!("{name:<16}{backend:<8}{build:<6}{fastest:>12.2?}{mean:>12.2?}{gain:>8}");
+                }
+                None => println!("{name:<16}{backend:<8}{build:<6}{:>12}", "failed"),
+            }
+            old = times.filter(|_| build == "old").map(|(fastest, _)| fastest);
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use metaprogramming::Ast;
+
+const SPEC: &str = "src/libs/ast.def";
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={SPEC}");
+
+    let source = fs::read_to_string(SPEC).unwrap_or_else(|error| panic!("Failed to read {SPEC}: {error}"));
+    let ast = Ast::parse(&source).unwrap_or_else(|error| panic!("{SPEC}:{error}"));
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo.");
+    ast.write_to(&Path::new(&out_dir).join("expr.rs")).expect("Failed to write the generated AST.");
+}
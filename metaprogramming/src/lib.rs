@@ -0,0 +1,475 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub mod spec;
+
+pub use spec::SpecError;
+use spec::{Field, FieldType, Family, Object, Spec, Structure, Variant};
+
+// AST generator, driven by a spec file (see `spec` for the format).
+//
+// A field of a family type that is optional becomes `Option<Box<_>>`, so families can nest; the
+// other kinds map to `Vec<_>` (repeated), `Rc<Vec<_>>` (shared) and `Box<_>` (box).
+//
+// The first visited family is the primary one: its visitor trait is `Visitor` with `visit` and
+// `visit_<family>` for optional nodes, and its fold entry point is `fold`. The other families get
+// `<Family>Visitor` with `visit_<family>` and `fold_<family>`.
+
+pub struct Ast {
+    spec: Spec,
+}
+
+impl Ast {
+    pub fn parse(source: &str) -> Result<Self, SpecError> {
+        Ok(Self { spec: spec::parse(source)? })
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.define_ast())
+    }
+
+    pub fn define_ast(&self) -> String {
+        let mut file = String::new();
+
+        writeln!(file, "// That file is created by \"metaprogramming\" package, do not edit it by hand.\n").unwrap();
+
+        let mut body = String::new();
+        writeln!(body).unwrap();
+        for object in &self.spec.objects {
+            self.define_object(&mut body, object);
+        }
+        for family in &self.spec.families {
+            self.define_family(&mut body, family);
+        }
+        for structure in &self.spec.structures {
+            self.define_structure(&mut body, structure);
+        }
+        // Drop the blank line after the last definition.
+        body.pop();
+        let payloads = self.spec.objects.iter().flat_map(|object| &object.variants).flat_map(|variant| variant.payload.clone());
+        let field_types = self.all_fields().map(|field| self.field_type(&field.type_));
+        self.define_module(&mut file, "ast", &body, &type_names(payloads.chain(field_types)));
+        writeln!(file).unwrap();
+
+        self.define_visitor(&mut file);
+        self.define_fold(&mut file);
+
+        file
+    }
+
+    fn define_object(&self, file: &mut String, object: &Object) {
+        preamble(file, &object.docs, &object.attrs, 4);
+        writeln!(file, "    pub enum {} {{", object.name).unwrap();
+        for variant in &object.variants {
+            preamble(file, &variant.docs, &[], 8);
+            match &variant.payload {
+                Some(payload) => writeln!(file, "        {}({payload}),", variant.name).unwrap(),
+                None => writeln!(file, "        {},", variant.name).unwrap(),
+            }
+        }
+        writeln!(file, "    }}\n").unwrap();
+    }
+
+    fn define_family(&self, file: &mut String, family: &Family) {
+        preamble(file, &family.docs, &family.attrs, 4);
+        writeln!(file, "    pub enum {} {{", family.name).unwrap();
+        for variant in &family.variants {
+            self.define_type(file, variant);
+        }
+        writeln!(file, "    }}\n").unwrap();
+    }
+
+    fn define_type(&self, file: &mut String, variant: &Variant) {
+        preamble(file, &variant.docs, &variant.attrs, 8);
+        writeln!(file, "        {} {{", variant.name).unwrap();
+        for field in &variant.fields {
+            preamble(file, &field.docs, &[], 12);
+            writeln!(file, "            {}: {},", field.name, self.field_type(&field.type_)).unwrap();
+        }
+        writeln!(file, "        }},").unwrap();
+    }
+
+    fn define_structure(&self, file: &mut String, structure: &Structure) {
+        preamble(file, &structure.docs, &structure.attrs, 4);
+        writeln!(file, "    pub struct {} {{", structure.name).unwrap();
+        for field in &structure.fields {
+            preamble(file, &field.docs, &[], 8);
+            writeln!(file, "        pub {}: {},", field.name, self.field_type(&field.type_)).unwrap();
+        }
+        writeln!(file, "    }}\n").unwrap();
+    }
+
+    fn define_visitor(&self, out: &mut String) {
+        let mut body = String::new();
+        let file = &mut body;
+
+        for (position, family) in self.visited_families().enumerate() {
+            let primary = position == 0;
+            let lower = family.name.to_lowercase();
+            let trait_name = if primary { "Visitor".to_string() } else { format!("{}Visitor", family.name) };
+            let entry = if primary { "visit".to_string() } else { format!("visit_{lower}") };
+
+            writeln!(file, "\n    pub trait {trait_name}<T> {{").unwrap();
+            if primary {
+                writeln!(file, "        fn visit_{lower}(&mut self, {lower}: &Option<Box<{}>>) -> Option<T, > {{", family.name).unwrap();
+                writeln!(file, "            {lower}.as_ref().map(|box_| self.{entry}(box_))").unwrap();
+                writeln!(file, "        }}\n").unwrap();
+            }
+
+            writeln!(file, "        fn {entry}(&mut self, {lower}: &{}) -> T {{", family.name).unwrap();
+            writeln!(file, "            match {lower} {{").unwrap();
+            for variant in &family.variants {
+                let names = field_names(&variant.fields);
+                writeln!(
+                    file,
+                    "                {}::{} {{ {names} }} => self.visit_{}({names}),",
+                    family.name,
+                    variant.name,
+                    snake_case(&variant.name),
+                )
+                .unwrap();
+            }
+            writeln!(file, "            }}").unwrap();
+            writeln!(file, "        }}").unwrap();
+
+            for variant in &family.variants {
+                let params: Vec<String> = variant
+                    .fields
+                    .iter()
+                    .map(|field| format!("{}: {}", field.name, self.borrowed(&field.type_)))
+                    .collect();
+                let signature = signature(&format!("visit_{}", snake_case(&variant.name)), "&mut self", &params, "T", 8);
+                writeln!(file, "\n{signature};").unwrap();
+            }
+            writeln!(file, "    }}").unwrap();
+        }
+
+        let visited_fields = self.visited_families().flat_map(|family| &family.variants).flat_map(|variant| &variant.fields);
+        let used = type_names(visited_fields.map(|field| self.borrowed(&field.type_)));
+        self.define_module(out, "visitor", &body, &used);
+        writeln!(out).unwrap();
+    }
+
+    fn define_fold(&self, out: &mut String) {
+        let mut body = String::new();
+        let file = &mut body;
+        writeln!(file).unwrap();
+        writeln!(file, "    // Rewrites the tree by value. Every method rebuilds its node from folded children by").unwrap();
+        writeln!(file, "    // default, so a pass only overrides the nodes it changes.").unwrap();
+        writeln!(file, "    pub trait Fold {{").unwrap();
+
+        for (position, family) in self.visited_families().enumerate() {
+            let primary = position == 0;
+            let name = &family.name;
+            let lower = name.to_lowercase();
+            let entry = fold_entry(name, primary);
+            let optional = if primary { format!("fold_{lower}") } else { format!("fold_boxed_{lower}") };
+
+            writeln!(file, "        fn {entry}(&mut self, {lower}: {name}) -> {name} {{").unwrap();
+            writeln!(file, "            match {lower} {{").unwrap();
+            for variant in &family.variants {
+                let names = field_names(&variant.fields);
+                writeln!(
+                    file,
+                    "                {name}::{} {{ {names} }} => self.fold_{}({names}),",
+                    variant.name,
+                    snake_case(&variant.name),
+                )
+                .unwrap();
+            }
+            writeln!(file, "            }}").unwrap();
+            writeln!(file, "        }}\n").unwrap();
+
+            writeln!(file, "        fn {optional}(&mut self, {lower}: Option<Box<{name}>>) -> Option<Box<{name}>> {{").unwrap();
+            writeln!(file, "            {lower}.map(|box_| Box::new(self.{entry}(*box_)))").unwrap();
+            writeln!(file, "        }}\n").unwrap();
+
+            writeln!(file, "        fn fold_{lower}s(&mut self, {lower}s: Vec<{name}>) -> Vec<{name}> {{").unwrap();
+            writeln!(file, "            {lower}s.into_iter().map(|{lower}| self.{entry}({lower})).collect()").unwrap();
+            writeln!(file, "        }}\n").unwrap();
+
+            writeln!(file, "        // Shared lists also live in runtime objects, so a list that is still shared is copied.").unwrap();
+            writeln!(file, "        fn fold_shared_{lower}s(&mut self, {lower}s: Rc<Vec<{name}>>) -> Rc<Vec<{name}>> {{").unwrap();
+            writeln!(file, "            Rc::new(self.fold_{lower}s(Rc::unwrap_or_clone({lower}s)))").unwrap();
+            writeln!(file, "        }}\n").unwrap();
+
+            for variant in &family.variants {
+                let params: Vec<String> = variant
+                    .fields
+                    .iter()
+                    .map(|field| format!("{}: {}", field.name, self.field_type(&field.type_)))
+                    .collect();
+                let signature = signature(&format!("fold_{}", snake_case(&variant.name)), "&mut self", &params, name, 8);
+                writeln!(file, "{signature} {{").unwrap();
+                writeln!(file, "{}", self.rebuild(&format!("{name}::{}", variant.name), &variant.fields, 12)).unwrap();
+                writeln!(file, "        }}\n").unwrap();
+            }
+        }
+
+        for family in self.spec.families.iter().filter(|family| !family.visited) {
+            let lower = snake_case(&family.name);
+            writeln!(file, "        fn fold_{lower}(&mut self, {lower}: {}) -> {} {{", family.name, family.name).unwrap();
+            writeln!(file, "            {lower}").unwrap();
+            writeln!(file, "        }}\n").unwrap();
+        }
+
+        for structure in &self.spec.structures {
+            let lower = snake_case(&structure.name);
+            let names = field_names(&structure.fields);
+            writeln!(file, "        fn fold_{lower}(&mut self, {lower}: {}) -> {} {{", structure.name, structure.name).unwrap();
+            writeln!(file, "            let {} {{ {names} }} = {lower};", structure.name).unwrap();
+            writeln!(file, "{}", self.rebuild(&structure.name, &structure.fields, 12)).unwrap();
+            writeln!(file, "        }}\n").unwrap();
+        }
+
+        // Drop the blank line after the last method.
+        file.pop();
+        writeln!(file, "    }}").unwrap();
+
+        let mut used = type_names(self.all_fields().map(|field| self.field_type(&field.type_)));
+        used.insert("Rc".to_string());
+        self.define_module(out, "fold", &body, &used);
+    }
+
+    // Wraps a module body, importing only those of the spec's paths that bring in a `used` type.
+    fn define_module(&self, file: &mut String, name: &str, body: &str, used: &HashSet<String>) {
+        writeln!(file, "pub mod {name} {{").unwrap();
+        for path in &self.spec.uses {
+            if imported_names(path).iter().any(|imported| used.contains(imported)) {
+                writeln!(file, "    use {path};").unwrap();
+            }
+        }
+        if name != "ast" {
+            writeln!(file, "    use super::ast::*;").unwrap();
+        }
+        write!(file, "{body}").unwrap();
+        writeln!(file, "}}").unwrap();
+    }
+
+    // Builds `Name { field: <folded field>, .. }` for the default fold methods.
+    fn rebuild(&self, constructor: &str, fields: &[Field], indent: usize) -> String {
+        let pad = " ".repeat(indent);
+        if fields.is_empty() {
+            return format!("{pad}{constructor} {{}}");
+        }
+
+        let mut result = format!("{pad}{constructor} {{\n");
+        for field in fields {
+            match self.fold_call(field) {
+                Some(call) => writeln!(result, "{pad}    {}: {call},", field.name).unwrap(),
+                None => writeln!(result, "{pad}    {},", field.name).unwrap(),
+            }
+        }
+        write!(result, "{pad}}}").unwrap();
+        result
+    }
+
+    // The call that folds a field, or None when the field is moved over unchanged.
+    fn fold_call(&self, field: &Field) -> Option<String> {
+        let name = &field.name;
+        let type_ = &field.type_;
+        let fold = self.element_fold(&type_.name)?;
+
+        // Visited families have helpers for the usual kinds.
+        if let Some(position) = self.visited_families().position(|family| family.name == type_.name) {
+            let lower = type_.name.to_lowercase();
+            match (type_.optional, type_.repeated, type_.shared) {
+                (true, false, _) if position == 0 => return Some(format!("self.fold_{lower}({name})")),
+                (true, false, _) => return Some(format!("self.fold_boxed_{lower}({name})")),
+                (false, true, false) => return Some(format!("self.fold_{lower}s({name})")),
+                (false, true, true) => return Some(format!("self.fold_shared_{lower}s({name})")),
+                _ => (),
+            }
+        }
+
+        let value = |value: &str| -> String {
+            if type_.repeated {
+                let items = if type_.shared { format!("Rc::unwrap_or_clone({value})") } else { value.to_string() };
+                let folded = format!("{items}.into_iter().map(|item| {fold}(item)).collect()");
+                if type_.shared { format!("Rc::new({folded})") } else { folded }
+            } else if self.is_boxed(type_) {
+                format!("Box::new({fold}(*{value}))")
+            } else {
+                format!("{fold}({value})")
+            }
+        };
+        if type_.optional {
+            return Some(format!("{name}.map(|{name}| {})", value(name)));
+        }
+        Some(value(name))
+    }
+
+    // The method folding a single value of the named type, if it is a node type.
+    fn element_fold(&self, name: &str) -> Option<String> {
+        if let Some(position) = self.visited_families().position(|family| family.name == name) {
+            let family = self.visited_families().nth(position).unwrap();
+            return Some(format!("self.{}", fold_entry(&family.name, position == 0)));
+        }
+        let plain = self.spec.families.iter().filter(|family| !family.visited).map(|family| &family.name);
+        let mut nodes = plain.chain(self.spec.structures.iter().map(|structure| &structure.name));
+        nodes.any(|node| node == name).then(|| format!("self.fold_{}", snake_case(name)))
+    }
+
+    fn field_type(&self, type_: &FieldType) -> String {
+        let mut result = type_.name.clone();
+        if type_.repeated {
+            result = format!("Vec<{result}>");
+        }
+        if type_.shared {
+            result = format!("Rc<{result}>");
+        }
+        if self.is_boxed(type_) {
+            result = format!("Box<{result}>");
+        }
+        if type_.optional {
+            result = format!("Option<{result}>");
+        }
+        result
+    }
+
+    // Optional families are boxed so that a family can hold itself.
+    fn is_boxed(&self, type_: &FieldType) -> bool {
+        type_.boxed || (type_.optional && !type_.repeated && self.spec.families.iter().any(|family| family.name == type_.name))
+    }
+
+    // Visitor methods borrow their fields; a list is handed over as a slice.
+    fn borrowed(&self, type_: &FieldType) -> String {
+        if type_.repeated && !type_.shared && !type_.optional {
+            return format!("&[{}]", type_.name);
+        }
+        format!("&{}", self.field_type(type_))
+    }
+
+    fn all_fields(&self) -> impl Iterator<Item = &Field> {
+        let family_fields = self.spec.families.iter().flat_map(|family| &family.variants).flat_map(|variant| &variant.fields);
+        family_fields.chain(self.spec.structures.iter().flat_map(|structure| &structure.fields))
+    }
+
+    fn visited_families(&self) -> impl Iterator<Item = &Family> {
+        self.spec.families.iter().filter(|family| family.visited)
+    }
+}
+
+// "a::b::{C, D}" imports C and D, "a::b::C" imports C.
+fn imported_names(path: &str) -> Vec<String> {
+    match (path.find('{'), path.rfind('}')) {
+        (Some(open), Some(close)) => path[open + 1..close].split(',').map(|name| name.trim().to_string()).collect(),
+        _ => vec![path.rsplit("::").next().unwrap_or(path).trim().to_string()],
+    }
+}
+
+// Every identifier that occurs in `types`.
+fn type_names(types: impl Iterator<Item = String>) -> HashSet<String> {
+    types
+        .flat_map(|type_| {
+            type_
+                .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn fold_entry(name: &str, primary: bool) -> String {
+    if primary {
+        "fold".to_string()
+    } else {
+        format!("fold_{}", name.to_lowercase())
+    }
+}
+
+fn field_names(fields: &[Field]) -> String {
+    fields.iter().map(|field| field.name.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+// Doc comments and attributes in front of a definition.
+fn preamble(file: &mut String, docs: &[String], attrs: &[String], indent: usize) {
+    let pad = " ".repeat(indent);
+    for doc in docs {
+        writeln!(file, "{pad}///{}{doc}", if doc.is_empty() { "" } else { " " }).unwrap();
+    }
+    for attr in attrs {
+        writeln!(file, "{pad}{attr}").unwrap();
+    }
+}
+
+fn signature(name: &str, receiver: &str, params: &[String], returns: &str, indent: usize) -> String {
+    let pad = " ".repeat(indent);
+    let mut all = vec![receiver.to_string()];
+    all.extend(params.iter().cloned());
+
+    let line = format!("{pad}fn {name}({}) -> {returns}", all.join(", "));
+    if line.len() <= 120 {
+        return line;
+    }
+
+    let mut result = format!("{pad}fn {name}(\n");
+    for param in all {
+        writeln!(result, "{pad}    {param},").unwrap();
+    }
+    write!(result, "{pad}) -> {returns}").unwrap();
+    result
+}
+
+fn snake_case(name: &str) -> String {
+    let mut result = String::new();
+    for (position, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if position != 0 {
+                result.push('_');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Ast;
+
+    #[test]
+    fn test() {
+        let ast = Ast::parse(
+            "use std::rc::Rc;
+            use crate::lex::Token;
+
+            #[derive(Clone)]
+            object Object { Number(f64), Nil }
+
+            family Expr {
+                /// A prefix operator.
+                Unary { operator: Token, right: Expr? }
+                Call { callee: Expr?, arguments: Expr* }
+            }
+
+            family Stmt {
+                Function { name: Token, body: shared Stmt* }
+            }",
+        );
+        let generated = ast.unwrap_or_else(|error| panic!("{error}")).define_ast();
+
+        assert!(generated.contains("    #[derive(Clone)]\n    pub enum Object {\n        Number(f64),\n        Nil,\n"));
+        assert!(generated.contains("        /// A prefix operator.\n        Unary {\n"));
+        assert!(generated.contains("            right: Option<Box<Expr>>,\n"));
+        assert!(generated.contains("Expr::Unary { operator, right } => self.visit_unary(operator, right),"));
+        assert!(generated.contains("fn visit_call(&mut self, callee: &Option<Box<Expr>>, arguments: &[Expr]) -> T;"));
+        assert!(generated.contains("    pub trait StmtVisitor<T> {"));
+        assert!(generated.contains("                body: self.fold_shared_stmts(body),\n"));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source: &str| Ast::parse(source).err().map(|error| error.to_string());
+
+        assert_eq!(error("family Expr {\n    Unary { right Expr }\n}"), Some("2:19: Expect ':' after field name.".to_string()));
+        assert_eq!(error("family Expr { Unary {}, Unary {} }"), Some("1:25: 'Unary' is already defined here.".to_string()));
+        assert_eq!(error("struct Arm { body: shared Expr }"), Some("1:27: A 'shared' field must be repeated ('*').".to_string()));
+    }
+}
@@ -0,0 +1,445 @@
+use std::fmt::{self, Display};
+
+// Parser for the AST spec files (".def").
+//
+// spec    -> item*
+// item    -> docs attrs ( "use" PATH ";" | object | family | struct )
+// object  -> "object" NAME "{" ( docs NAME ( "(" TYPE ")" )? ","? )* "}"
+// family  -> "plain"? "family" NAME "{" ( docs attrs NAME "{" fields "}" ","? )* "}"
+// struct  -> "struct" NAME "{" fields "}"
+// fields  -> ( docs NAME ":" kind ","? )*
+// kind    -> ( "box" | "shared" )? TYPE ( "?" | "*" )?
+//
+// Lines starting with "///" are docs and are copied into the generated code, "//" lines are
+// comments, and "#[...]" attributes are copied onto the following definition.
+
+pub struct SpecError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldType {
+    pub name: String,
+    pub optional: bool,
+    pub repeated: bool,
+    pub boxed: bool,
+    pub shared: bool,
+}
+
+pub struct Field {
+    pub docs: Vec<String>,
+    pub name: String,
+    pub type_: FieldType,
+}
+
+pub struct Variant {
+    pub docs: Vec<String>,
+    pub attrs: Vec<String>,
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+pub struct ObjectVariant {
+    pub docs: Vec<String>,
+    pub name: String,
+    pub payload: Option<String>,
+}
+
+pub struct Object {
+    pub docs: Vec<String>,
+    pub attrs: Vec<String>,
+    pub name: String,
+    pub variants: Vec<ObjectVariant>,
+}
+
+pub struct Family {
+    pub docs: Vec<String>,
+    pub attrs: Vec<String>,
+    pub name: String,
+    pub variants: Vec<Variant>,
+    // Plain families get neither a visitor nor a recursing fold.
+    pub visited: bool,
+}
+
+pub struct Structure {
+    pub docs: Vec<String>,
+    pub attrs: Vec<String>,
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Default)]
+pub struct Spec {
+    pub uses: Vec<String>,
+    pub objects: Vec<Object>,
+    pub families: Vec<Family>,
+    pub structures: Vec<Structure>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Name(String),
+    Doc(String),
+    Attr(String),
+    Path(String),
+    Punct(char),
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+fn scan(source: &str) -> Result<Vec<Token>, SpecError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut position, mut line, mut column) = (0, 1, 1);
+
+    while position < chars.len() {
+        let c = chars[position];
+        let (start_line, start_column) = (line, column);
+        let rest_of_line = |from: usize| -> String {
+            chars[from..].iter().take_while(|c| **c != '\n').collect()
+        };
+
+        if c == '\n' {
+            position += 1;
+            line += 1;
+            column = 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            position += 1;
+            column += 1;
+            continue;
+        }
+
+        let (kind, length) = if chars[position..].starts_with(&['/', '/', '/']) {
+            let text = rest_of_line(position + 3);
+            let length = text.chars().count() + 3;
+            (Some(TokenKind::Doc(text.strip_prefix(' ').unwrap_or(&text).to_string())), length)
+        } else if chars[position..].starts_with(&['/', '/']) {
+            (None, rest_of_line(position).chars().count())
+        } else if chars[position..].starts_with(&['#', '[']) {
+            let mut depth = 0;
+            let mut end = position + 1;
+            while end < chars.len() {
+                match chars[end] {
+                    '[' => depth += 1,
+                    ']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    '\n' => break,
+                    _ => (),
+                }
+                end += 1;
+            }
+            if end >= chars.len() || chars[end] != ']' {
+                return Err(SpecError { line, column, message: "Unterminated attribute.".to_string() });
+            }
+            let text: String = chars[position..=end].iter().collect();
+            (Some(TokenKind::Attr(text)), end + 1 - position)
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = position;
+            loop {
+                if end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+                    end += 1;
+                } else if chars[end..].starts_with(&[':', ':']) {
+                    end += 2;
+                } else {
+                    break;
+                }
+            }
+            let text: String = chars[position..end].iter().collect();
+            let kind = if text.contains("::") { TokenKind::Path(text) } else { TokenKind::Name(text) };
+            (Some(kind), end - position)
+        } else if "{}()<>,:;?*".contains(c) {
+            (Some(TokenKind::Punct(c)), 1)
+        } else {
+            return Err(SpecError { line, column, message: format!("Unexpected character '{c}'.") });
+        };
+
+        if let Some(kind) = kind {
+            tokens.push(Token { kind, line: start_line, column: start_column });
+        }
+        position += length;
+        column += length;
+    }
+
+    tokens.push(Token { kind: TokenKind::Eof, line, column });
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    current: usize,
+}
+
+pub fn parse(source: &str) -> Result<Spec, SpecError> {
+    let mut parser = Parser { tokens: scan(source)?, current: 0 };
+    parser.spec()
+}
+
+impl Parser {
+    fn spec(&mut self) -> Result<Spec, SpecError> {
+        let mut spec = Spec::default();
+        while self.peek().kind != TokenKind::Eof {
+            let docs = self.docs();
+            let attrs = self.attrs();
+            let keyword = self.name("Expect 'use', 'object', 'family', 'plain family' or 'struct'.")?;
+            let defined = spec
+                .objects
+                .iter()
+                .map(|object| &object.name)
+                .chain(spec.families.iter().map(|family| &family.name))
+                .chain(spec.structures.iter().map(|structure| &structure.name));
+            let defined: Vec<String> = defined.cloned().collect();
+            match keyword.as_str() {
+                "use" => {
+                    spec.uses.push(self.path()?);
+                    self.punct(';', "Expect ';' after use path.")?;
+                }
+                "object" => {
+                    let name = self.new_name(&defined, "Expect object name.")?;
+                    let variants = self.object_variants()?;
+                    spec.objects.push(Object { docs, attrs, name, variants });
+                }
+                "family" | "plain" => {
+                    if keyword == "plain" {
+                        self.keyword("family", "Expect 'family' after 'plain'.")?;
+                    }
+                    let name = self.new_name(&defined, "Expect family name.")?;
+                    let variants = self.variants()?;
+                    spec.families.push(Family { docs, attrs, name, variants, visited: keyword == "family" });
+                }
+                "struct" => {
+                    let name = self.new_name(&defined, "Expect struct name.")?;
+                    self.punct('{', "Expect '{' after struct name.")?;
+                    let fields = self.fields()?;
+                    spec.structures.push(Structure { docs, attrs, name, fields });
+                }
+                _ => return Err(self.error_at(self.current - 1, &format!("Unknown item '{keyword}'."))),
+            }
+        }
+        Ok(spec)
+    }
+
+    fn object_variants(&mut self) -> Result<Vec<ObjectVariant>, SpecError> {
+        self.punct('{', "Expect '{' after object name.")?;
+        let mut variants = Vec::new();
+        loop {
+            let docs = self.docs();
+            if self.matching_punct('}') {
+                return Ok(variants);
+            }
+            let defined: Vec<String> = variants.iter().map(|variant: &ObjectVariant| variant.name.clone()).collect();
+            let name = self.new_name(&defined, "Expect object variant name.")?;
+            let payload = if self.matching_punct('(') {
+                let payload = self.type_()?;
+                self.punct(')', "Expect ')' after object payload.")?;
+                Some(payload)
+            } else {
+                None
+            };
+            variants.push(ObjectVariant { docs, name, payload });
+            self.matching_punct(',');
+        }
+    }
+
+    fn variants(&mut self) -> Result<Vec<Variant>, SpecError> {
+        self.punct('{', "Expect '{' after family name.")?;
+        let mut variants = Vec::new();
+        loop {
+            let docs = self.docs();
+            let attrs = self.attrs();
+            if self.matching_punct('}') {
+                return Ok(variants);
+            }
+            let defined: Vec<String> = variants.iter().map(|variant: &Variant| variant.name.clone()).collect();
+            let name = self.new_name(&defined, "Expect node name.")?;
+            self.punct('{', "Expect '{' after node name.")?;
+            let fields = self.fields()?;
+            variants.push(Variant { docs, attrs, name, fields });
+            self.matching_punct(',');
+        }
+    }
+
+    // Reads fields up to and including the closing '}'.
+    fn fields(&mut self) -> Result<Vec<Field>, SpecError> {
+        let mut fields = Vec::new();
+        loop {
+            let docs = self.docs();
+            if self.matching_punct('}') {
+                return Ok(fields);
+            }
+            let defined: Vec<String> = fields.iter().map(|field: &Field| field.name.clone()).collect();
+            let name = self.new_name(&defined, "Expect field name.")?;
+            self.punct(':', "Expect ':' after field name.")?;
+            let type_ = self.field_type()?;
+            fields.push(Field { docs, name, type_ });
+            if !self.matching_punct(',') {
+                self.punct('}', "Expect ',' or '}' after field.")?;
+                return Ok(fields);
+            }
+        }
+    }
+
+    fn field_type(&mut self) -> Result<FieldType, SpecError> {
+        let boxed = self.matching_name("box");
+        let shared = !boxed && self.matching_name("shared");
+        let name = self.type_()?;
+        let optional = self.matching_punct('?');
+        let repeated = !optional && self.matching_punct('*');
+
+        if shared && !repeated {
+            return Err(self.error_at(self.current - 1, "A 'shared' field must be repeated ('*')."));
+        }
+        if boxed && repeated {
+            return Err(self.error_at(self.current - 1, "A repeated field can't be boxed."));
+        }
+        Ok(FieldType { name, optional, repeated, boxed, shared })
+    }
+
+    // TYPE -> NAME ( "<" TYPE ( "," TYPE )* ">" )? | "(" TYPE ( "," TYPE )* ")"
+    fn type_(&mut self) -> Result<String, SpecError> {
+        if self.matching_punct('(') {
+            let mut parts = vec![self.type_()?];
+            while self.matching_punct(',') {
+                parts.push(self.type_()?);
+            }
+            self.punct(')', "Expect ')' after tuple type.")?;
+            return Ok(format!("({})", parts.join(", ")));
+        }
+
+        let mut type_ = self.name("Expect type.")?;
+        if self.matching_punct('<') {
+            let mut parts = vec![self.type_()?];
+            while self.matching_punct(',') {
+                parts.push(self.type_()?);
+            }
+            self.punct('>', "Expect '>' after type arguments.")?;
+            type_ = format!("{type_}<{}>", parts.join(", "));
+        }
+        Ok(type_)
+    }
+
+    fn docs(&mut self) -> Vec<String> {
+        let mut docs = Vec::new();
+        while let TokenKind::Doc(doc) = &self.peek().kind {
+            docs.push(doc.clone());
+            self.current += 1;
+        }
+        docs
+    }
+
+    fn attrs(&mut self) -> Vec<String> {
+        let mut attrs = Vec::new();
+        while let TokenKind::Attr(attr) = &self.peek().kind {
+            attrs.push(attr.clone());
+            self.current += 1;
+        }
+        attrs
+    }
+
+    fn path(&mut self) -> Result<String, SpecError> {
+        let mut path = match self.advance().kind {
+            TokenKind::Path(path) | TokenKind::Name(path) => path,
+            _ => return Err(self.error_at(self.current - 1, "Expect use path.")),
+        };
+        // "a::b::{C, D}" continues with a brace group.
+        if path.ends_with("::") {
+            self.punct('{', "Expect '{' after '::'.")?;
+            let mut names = vec![self.name("Expect name in use group.")?];
+            while self.matching_punct(',') {
+                names.push(self.name("Expect name in use group.")?);
+            }
+            self.punct('}', "Expect '}' after use group.")?;
+            path = format!("{path}{{{}}}", names.join(", "));
+        }
+        Ok(path)
+    }
+
+    fn name(&mut self, message: &str) -> Result<String, SpecError> {
+        match self.peek().kind.clone() {
+            TokenKind::Name(name) => {
+                self.current += 1;
+                Ok(name)
+            }
+            _ => Err(self.error_at(self.current, message)),
+        }
+    }
+
+    // Names must be unique in their scope, otherwise the generated code would not compile.
+    fn new_name(&mut self, defined: &[String], message: &str) -> Result<String, SpecError> {
+        let name = self.name(message)?;
+        if defined.contains(&name) {
+            return Err(self.error_at(self.current - 1, &format!("'{name}' is already defined here.")));
+        }
+        Ok(name)
+    }
+
+    fn keyword(&mut self, keyword: &str, message: &str) -> Result<(), SpecError> {
+        if self.matching_name(keyword) {
+            return Ok(());
+        }
+        Err(self.error_at(self.current, message))
+    }
+
+    fn punct(&mut self, punct: char, message: &str) -> Result<(), SpecError> {
+        if self.matching_punct(punct) {
+            return Ok(());
+        }
+        Err(self.error_at(self.current, message))
+    }
+
+    fn matching_name(&mut self, name: &str) -> bool {
+        if self.peek().kind == TokenKind::Name(name.to_string()) {
+            self.current += 1;
+            return true;
+        }
+        false
+    }
+
+    fn matching_punct(&mut self, punct: char) -> bool {
+        if self.peek().kind == TokenKind::Punct(punct) {
+            self.current += 1;
+            return true;
+        }
+        false
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.peek().clone();
+        if token.kind != TokenKind::Eof {
+            self.current += 1;
+        }
+        token
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    fn error_at(&self, position: usize, message: &str) -> SpecError {
+        let token = &self.tokens[position.min(self.tokens.len() - 1)];
+        SpecError {
+            line: token.line,
+            column: token.column,
+            message: message.to_string(),
+        }
+    }
+}
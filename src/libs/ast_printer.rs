@@ -1,38 +1,341 @@
-use crate::libs::expr::{visitor::Visitor, ast::Expr};
+use std::rc::Rc;
+use crate::libs::expr::{visitor::StmtVisitor, visitor::Visitor, ast::Expr, ast::MatchArm, ast::Object, ast::Pattern, ast::MethodSig, ast::Stmt, ast::TypeExpr, ast::TypedName};
+use crate::libs::lex::{Token, TokenType};
 
 pub struct AstPrinter {}
 
 impl Visitor<String> for AstPrinter {
-    fn visit_binary(&mut self, binary: &Expr) -> String {
-        if let Expr::Binary { ref left, ref operator, ref right } = binary {
-            return self.parenthesize(operator.lexeme.to_string(), vec![left, right]);
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) -> String {
+        self.parenthesize(format!("= {}", name.lexeme), vec![value])
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> String {
+        self.parenthesize(operator.lexeme.to_string(), vec![left, right])
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, _paren: &Token, arguments: &[Expr]) -> String {
+        let mut result = self.parenthesize("call".to_string(), vec![callee]);
+        result.pop();
+        for argument in arguments {
+            result.push(' ');
+            result.push_str(&self.visit(argument));
+        }
+        result.push(')');
+        result
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, name: &Token) -> String {
+        self.parenthesize(format!(". {}", name.lexeme), vec![object])
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) -> String {
+        self.parenthesize("group".to_string(), vec![expression])
+    }
+
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, _bracket: &Token, index: &Option<Box<Expr>>) -> String {
+        self.parenthesize("index".to_string(), vec![object, index])
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        _bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) -> String {
+        self.parenthesize("index=".to_string(), vec![object, index, value])
+    }
+
+    fn visit_lambda(
+        &mut self,
+        _keyword: &Token,
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> String {
+        self.function("fun".to_string(), params, result, body)
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> String {
+        let mut result = "(list".to_string();
+        for element in elements {
+            result.push(' ');
+            result.push_str(&self.visit(element));
+        }
+        result.push(')');
+        result
+    }
+
+    fn visit_literal(&mut self, _token: &Token, value: &Object) -> String {
+        value.to_string()
+    }
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> String {
+        self.parenthesize(operator.lexeme.to_string(), vec![left, right])
+    }
+
+    fn visit_match(&mut self, _keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) -> String {
+        let mut result = self.parenthesize("match".to_string(), vec![subject]);
+        result.pop();
+        for arm in arms {
+            let mut name = format!("arm {}", Self::pattern(&arm.pattern));
+            if let Some(guard) = self.visit_expr(&arm.guard) {
+                name.push_str(&format!(" (if {guard})"));
+            }
+            result.push(' ');
+            result.push_str(&self.parenthesize(name, vec![&arm.body]));
+        }
+        result.push(')');
+        result
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, name: &Token, value: &Option<Box<Expr>>) -> String {
+        self.parenthesize(format!(".= {}", name.lexeme), vec![object, value])
+    }
+
+    fn visit_self_ref(&mut self, _keyword: &Token) -> String {
+        "self".to_string()
+    }
+
+    fn visit_super(&mut self, _keyword: &Token, method: &Token) -> String {
+        format!("(super {})", method.lexeme)
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Option<Box<Expr>>) -> String {
+        self.parenthesize(operator.lexeme.to_string(), vec![right])
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> String {
+        name.lexeme.to_string()
+    }
+
+    fn visit_yield(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> String {
+        if value.is_none() {
+            return "(yield)".to_string();
+        }
+        self.parenthesize("yield".to_string(), vec![value])
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_block(&mut self, statements: &[Stmt]) -> String {
+        self.statements("block".to_string(), statements)
+    }
+
+    fn visit_box(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Box<Expr>>,
+        fields: &[TypedName],
+        methods: &[Stmt],
+    ) -> String {
+        let mut header = format!("box {}", name.lexeme);
+        if let Some(superclass) = self.visit_expr(superclass) {
+            header.push_str(&format!(" <- {superclass}"));
+        }
+        for field in fields {
+            header.push_str(&format!(" (field {})", Self::typed_name(field)));
+        }
+        self.statements(header, methods)
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) -> String {
+        self.parenthesize(";".to_string(), vec![expression])
+    }
+
+    fn visit_for(&mut self, _keyword: &Token, name: &Token, iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> String {
+        let mut result = self.parenthesize(format!("for {}", name.lexeme), vec![iterable]);
+        if let Some(body) = body {
+            result.pop();
+            result.push(' ');
+            result.push_str(&self.visit_stmt(body));
+            result.push(')');
+        }
+        result
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        type_params: &[Token],
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> String {
+        self.function(format!("fun {}{}", name.lexeme, Self::type_params(type_params)), params, result, body)
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Option<Box<Expr>>,
+        then_branch: &Option<Box<Stmt>>,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> String {
+        let mut result = self.parenthesize("if".to_string(), vec![condition]);
+        result.pop();
+        for branch in [then_branch, else_branch].into_iter().flatten() {
+            result.push(' ');
+            result.push_str(&self.visit_stmt(branch));
         }
-        "Something went wrong".to_string()
+        result.push(')');
+        result
+    }
+
+    fn visit_import(&mut self, keyword: &Token, path: &Token, names: &[Token]) -> String {
+        format!("({})", Self::import(keyword, path, names))
+    }
+
+    fn visit_interface(&mut self, name: &Token, methods: &[MethodSig]) -> String {
+        let methods: Vec<String> = methods.iter().map(|method| format!(" (method {})", Self::method_sig(method))).collect();
+        format!("(interface {}{})", name.lexeme, methods.concat())
+    }
+
+    fn visit_let(&mut self, name: &Token, annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) -> String {
+        let name = Self::annotated(name, annotation);
+        if initializer.is_none() {
+            return format!("(let {name})");
+        }
+        self.parenthesize(format!("let {name}"), vec![initializer])
     }
 
-    fn visit_grouping(&mut self, grouping: &Expr) -> String {
-        if let Expr::Grouping { ref expression } = grouping {
-            return self.parenthesize("group".to_string(), vec![expression]);
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) -> String {
+        self.parenthesize("print".to_string(), vec![expression])
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> String {
+        if value.is_none() {
+            return "(return)".to_string();
         }
-        "Something went wrong".to_string()
+        self.parenthesize("return".to_string(), vec![value])
+    }
+
+    fn visit_throw(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> String {
+        self.parenthesize("throw".to_string(), vec![value])
     }
 
-    fn visit_literal(&mut self, literal: &Expr) -> String {
-        if let Expr::Literal { ref value } = literal {
-            return value.to_string();
+    fn visit_try(
+        &mut self,
+        _keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) -> String {
+        let mut result = "(try".to_string();
+        if let Some(body) = body {
+            result.push_str(&format!(" {}", self.visit_stmt(body)));
         }
-        "Something went wrong".to_string()
+        if let (Some(name), Some(handler)) = (name, handler) {
+            result.push_str(&format!(" (catch {} {})", name.lexeme, self.visit_stmt(handler)));
+        }
+        if let Some(finally) = finally {
+            result.push_str(&format!(" (finally {})", self.visit_stmt(finally)));
+        }
+        result.push(')');
+        result
     }
 
-    fn visit_unary(&mut self, unary: &Expr) -> String {
-        if let Expr::Unary { ref operator, ref right } = unary {
-            return self.parenthesize(operator.lexeme.to_string(), vec![right]);
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> String {
+        let mut result = self.parenthesize("while".to_string(), vec![condition]);
+        if let Some(body) = body {
+            result.pop();
+            result.push(' ');
+            result.push_str(&self.visit_stmt(body));
+            result.push(')');
         }
-        "Something went wrong".to_string()
+        result
     }
 }
 
 impl AstPrinter {
+    pub(crate) fn pattern(pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Literal { value, .. } => value.to_string(),
+            Pattern::Binding { name } => name.lexeme.to_string(),
+            Pattern::Wildcard { .. } => "_".to_string(),
+            Pattern::List { elements, rest, .. } => {
+                let mut parts: Vec<String> = elements.iter().map(Self::pattern).collect();
+                if let Some(rest) = rest {
+                    parts.push(format!("..{}", Self::pattern(rest)));
+                }
+                format!("[{}]", parts.join(" "))
+            }
+            Pattern::Box { name, fields } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, pattern)| format!("{}: {}", field.lexeme, Self::pattern(pattern)))
+                    .collect();
+                format!("{} {{{}}}", name.lexeme, fields.join(", "))
+            }
+        }
+    }
+
+    // Types are written as `name:Type`, the result type as `-> Type` after the parameters.
+    pub(crate) fn type_expr(type_: &TypeExpr) -> String {
+        if type_.arguments.is_empty() {
+            return type_.name.lexeme.to_string();
+        }
+        let arguments: Vec<String> = type_.arguments.iter().map(Self::type_expr).collect();
+        format!("{}<{}>", type_.name.lexeme, arguments.join(", "))
+    }
+
+    pub(crate) fn type_params(type_params: &[Token]) -> String {
+        if type_params.is_empty() {
+            return String::new();
+        }
+        let names: Vec<&str> = type_params.iter().map(|param| param.lexeme.as_str()).collect();
+        format!("<{}>", names.join(", "))
+    }
+
+    // An import as it is written, without the semicolon.
+    pub(crate) fn import(keyword: &Token, path: &Token, names: &[Token]) -> String {
+        let names: Vec<String> = names.iter().map(|name| name.lexeme.to_string()).collect();
+        match keyword.token_type {
+            TokenType::FROM => format!("from {} import {}", path.lexeme, names.join(", ")),
+            _ => format!("import {} as {}", path.lexeme, names.join(", ")),
+        }
+    }
+
+    // An interface method, as `name(params) -> Result`.
+    pub(crate) fn method_sig(method: &MethodSig) -> String {
+        let params: Vec<String> = method.params.iter().map(Self::typed_name).collect();
+        format!("{}({}){}", method.name.lexeme, params.join(", "), Self::returns(&method.result))
+    }
+
+    pub(crate) fn typed_name(typed: &TypedName) -> String {
+        Self::annotated(&typed.name, &typed.annotation)
+    }
+
+    pub(crate) fn annotated(name: &Token, annotation: &Option<TypeExpr>) -> String {
+        match annotation {
+            Some(annotation) => format!("{}:{}", name.lexeme, Self::type_expr(annotation)),
+            None => name.lexeme.to_string(),
+        }
+    }
+
+    pub(crate) fn returns(result: &Option<TypeExpr>) -> String {
+        result.as_ref().map_or(String::new(), |result| format!(" -> {}", Self::type_expr(result)))
+    }
+
+    fn function(&mut self, name: String, params: &[TypedName], result: &Option<TypeExpr>, body: &[Stmt]) -> String {
+        let params: Vec<String> = params.iter().map(Self::typed_name).collect();
+        self.statements(format!("{name} ({}){}", params.join(" "), Self::returns(result)), body)
+    }
+
+    fn statements(&mut self, name: String, statements: &[Stmt]) -> String {
+        let mut result = String::new();
+
+        result += "(";
+        result.push_str(&name);
+        for statement in statements {
+            result.push(' ');
+            result.push_str(&self.visit_stmt(statement));
+        }
+        result += ")";
+
+        result
+    }
+
     fn parenthesize(&mut self, name: String, exprs: Vec<&Option<Box<Expr>>>) -> String {
         let mut result = String::new();
 
@@ -56,8 +359,22 @@ impl AstPrinter {
 mod test {
     use crate::libs::ast_printer::AstPrinter;
     use crate::libs::expr::ast::{Expr, Object};
-    use crate::libs::expr::visitor::Visitor;
+    use crate::libs::expr::visitor::{StmtVisitor, Visitor};
     use crate::libs::lex::{LiteralValue, Token, TokenType};
+    use crate::libs::parser::Parser;
+    use crate::libs::lex::Lox;
+
+    fn number(lexeme: &str) -> Token {
+        Token {
+            token_type: TokenType::NUMBER,
+            line: 1,
+            column: 1,
+            file: 0,
+            end: None,
+            lexeme: lexeme.into(),
+            literal: LiteralValue::number(lexeme).unwrap(),
+        }
+    }
 
     // Ok
     #[test]
@@ -68,23 +385,45 @@ mod test {
                 operator: Token {
                     token_type: TokenType::MINUS,
                     line: 1,
-                    lexeme: "-".to_string(),
+                    column: 1,
+                    file: 0,
+                    end: None,
+                    lexeme: "-".into(),
                     literal: LiteralValue::Nil,
                 },
-                right: Some(Box::new(Expr::Literal { value: Object::Number(123f64) })),
+                right: Some(Box::new(Expr::Literal { token: number("123"), value: Object::Int(123) })),
             })),
             operator: Token {
                 token_type: TokenType::STAR,
                 line: 1,
-                lexeme: "*".to_string(),
+                column: 6,
+                file: 0,
+                end: None,
+                lexeme: "*".into(),
                 literal: LiteralValue::Nil,
             },
             right: Some(Box::new(Expr::Grouping {
-                expression: Some(Box::new(Expr::Literal { value: Object::Number(45.67) }))
+                expression: Some(Box::new(Expr::Literal { token: number("45.67"), value: Object::Float(45.67) }))
             })),
         }));
         let mut ast_printer = AstPrinter {};
         let string = ast_printer.visit_expr(&some_expr).unwrap();
         assert_eq!(string, "(* (- 123) (group 45.67))")
     }
+
+    #[test]
+    fn function() {
+        let tokens = Lox::new("fun twice(x) { return x * 2; }".to_string()).get_token_list();
+        let statements = Parser::new(tokens).parse().unwrap_or_else(|_| panic!("Failed to parse"));
+        let mut ast_printer = AstPrinter {};
+        assert_eq!(ast_printer.visit_stmt(&statements[0]), "(fun twice (x) (return (* x 2)))")
+    }
+
+    #[test]
+    fn lambda() {
+        let tokens = Lox::new("let f = (x) -> x * 2;".to_string()).get_token_list();
+        let statements = Parser::new(tokens).parse().unwrap_or_else(|_| panic!("Failed to parse"));
+        let mut ast_printer = AstPrinter {};
+        assert_eq!(ast_printer.visit_stmt(&statements[0]), "(let f (fun (x) (return (* x 2))))")
+    }
 }
\ No newline at end of file
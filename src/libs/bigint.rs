@@ -0,0 +1,347 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::ops::{Add, Mul, Neg, Sub};
+
+// An integer of any size, for the results of integer arithmetic that don't fit in an `i64`. The
+// magnitude is held in base 2^32 digits, least significant first and without leading zeros, so
+// that equal numbers are equal values; zero has no digits and isn't negative.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+// The largest power of ten in a digit, for reading and writing decimals nine places at a time.
+const DECIMAL: u32 = 1_000_000_000;
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> Self {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Self { negative: negative && !digits.is_empty(), digits }
+    }
+
+    // None unless `text` is decimal digits, after an optional minus sign.
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Some(digits) = text.strip_prefix('-') {
+            return Self::parse(digits).map(|n| -&n);
+        }
+        if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        let mut digits = Vec::new();
+        // The first chunk is shorter when the length isn't a multiple of nine.
+        let mut start = 0;
+        let mut end = match text.len() % 9 {
+            0 => 9,
+            rest => rest,
+        };
+        while start < text.len() {
+            let (scale, chunk) = (10u32.pow((end - start) as u32), text[start..end].parse().ok()?);
+            multiply_add(&mut digits, scale, chunk);
+            (start, end) = (end, end + 9);
+        }
+        Some(Self::new(false, digits))
+    }
+
+    // The integral float `n` exactly; None for fractions, infinities and NaN.
+    pub fn from_f64(n: f64) -> Option<Self> {
+        if !n.is_finite() || n.fract() != 0.0 {
+            return None;
+        }
+        let bits = n.to_bits();
+        let exponent = (bits >> 52 & 0x7ff) as i64;
+        if exponent == 0 {
+            // Zero; every other subnormal is a fraction.
+            return Some(Self::new(false, Vec::new()));
+        }
+        let mantissa = bits & ((1 << 52) - 1) | 1 << 52;
+        let shift = exponent - 1075;
+        let magnitude = match shift {
+            0.. => shift_left(&[mantissa as u32, (mantissa >> 32) as u32], shift as usize),
+            _ => {
+                let mantissa = mantissa >> -shift;
+                vec![mantissa as u32, (mantissa >> 32) as u32]
+            }
+        };
+        Some(Self::new(n < 0.0, magnitude))
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None;
+        }
+        let magnitude = self.digits.iter().rev().fold(0u64, |value, &digit| value << 32 | digit as u64);
+        match self.negative {
+            true => (magnitude <= 1 << 63).then(|| (magnitude as i64).wrapping_neg()),
+            false => i64::try_from(magnitude).ok(),
+        }
+    }
+
+    // The nearest float, ties to even; infinite when the integer is beyond the floats.
+    pub fn to_f64(&self) -> f64 {
+        let bits = self.digits.len() * 32 - self.digits.last().map_or(0, |digit| digit.leading_zeros() as usize);
+        // The top 64 bits round correctly once any bits cut off below them are folded into the last.
+        let shift = bits.saturating_sub(64);
+        let top = shift_right(&self.digits, shift);
+        let mut value = top.iter().rev().fold(0u64, |value, &digit| value << 32 | digit as u64);
+        if compare(&shift_left(&top, shift), &self.digits) != Ordering::Equal {
+            value |= 1;
+        }
+        let magnitude = value as f64 * 2f64.powi(shift.min(i32::MAX as usize) as i32);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    // The quotient rounded toward zero and the remainder, which has the sign of `self`; None when
+    // dividing by zero.
+    pub fn div_rem(&self, divisor: &BigInt) -> Option<(BigInt, BigInt)> {
+        if divisor.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = divide(&self.digits, &divisor.digits);
+        Some((Self::new(self.negative != divisor.negative, quotient), Self::new(self.negative, remainder)))
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let (mut result, mut base) = (BigInt::from(1), self.clone());
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            base = &base * &base;
+            exponent >>= 1;
+        }
+        result
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let magnitude = n.unsigned_abs();
+        Self::new(n < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare(&self.digits, &other.digits),
+            (true, true) => compare(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add(&self.digits, &other.digits));
+        }
+        match compare(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::new(other.negative, subtract(&other.digits, &self.digits)),
+            _ => BigInt::new(self.negative, subtract(&self.digits, &other.digits)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut product = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.digits.iter().enumerate() {
+                let sum = product[i + j] as u64 + a as u64 * b as u64 + carry;
+                product[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            product[i + other.digits.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, product)
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Chunks of nine decimal places, least significant first.
+        let mut chunks = Vec::new();
+        let mut rest = self.digits.clone();
+        while !rest.is_empty() {
+            let mut remainder = 0u64;
+            for digit in rest.iter_mut().rev() {
+                let value = remainder << 32 | *digit as u64;
+                *digit = (value / DECIMAL as u64) as u32;
+                remainder = value % DECIMAL as u64;
+            }
+            while rest.last() == Some(&0) {
+                rest.pop();
+            }
+            chunks.push(remainder);
+        }
+
+        let mut text = String::from(if self.negative { "-" } else { "" });
+        match chunks.split_last() {
+            Some((first, others)) => {
+                text.push_str(&first.to_string());
+                others.iter().rev().for_each(|chunk| text.push_str(&format!("{chunk:09}")));
+            }
+            None => text.push('0'),
+        }
+        f.pad(&text)
+    }
+}
+
+// Magnitudes: digits least significant first, possibly with leading zeros.
+
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    let length = |digits: &[u32]| digits.iter().rposition(|&digit| digit != 0).map_or(0, |last| last + 1);
+    let (a, b) = (&a[..length(a)], &b[..length(b)]);
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &digit) in long.iter().enumerate() {
+        let value = digit as u64 + short.get(i).copied().unwrap_or(0) as u64 + carry;
+        sum.push(value as u32);
+        carry = value >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+// `a - b`, for `a` at least `b`.
+fn subtract(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &digit) in a.iter().enumerate() {
+        let mut value = digit as i64 - b.get(i).copied().unwrap_or(0) as i64 - borrow;
+        borrow = (value < 0) as i64;
+        value += borrow << 32;
+        difference.push(value as u32);
+    }
+    difference
+}
+
+// `digits * scale + addend`, in place.
+fn multiply_add(digits: &mut Vec<u32>, scale: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for digit in digits.iter_mut() {
+        let value = *digit as u64 * scale as u64 + carry;
+        *digit = value as u32;
+        carry = value >> 32;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+}
+
+fn shift_left(digits: &[u32], bits: usize) -> Vec<u32> {
+    let (words, bits) = (bits / 32, bits % 32);
+    let mut shifted = vec![0u32; words];
+    let mut carry = 0u32;
+    for &digit in digits {
+        shifted.push(digit << bits | carry);
+        carry = if bits == 0 { 0 } else { digit >> (32 - bits) };
+    }
+    shifted.push(carry);
+    shifted
+}
+
+fn shift_right(digits: &[u32], bits: usize) -> Vec<u32> {
+    let (words, bits) = (bits / 32, bits % 32);
+    let digits = digits.get(words..).unwrap_or_default();
+    (0..digits.len())
+        .map(|i| {
+            let high = if bits == 0 { 0 } else { digits.get(i + 1).map_or(0, |&next| next << (32 - bits)) };
+            digits[i] >> bits | high
+        })
+        .collect()
+}
+
+// Long division one bit at a time, which is plenty for the sizes scripts use.
+fn divide(dividend: &[u32], divisor: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quotient = vec![0u32; dividend.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for bit in (0..dividend.len() * 32).rev() {
+        remainder = shift_left(&remainder, 1);
+        if remainder.len() > divisor.len() + 1 {
+            remainder.pop();
+        }
+        remainder[0] |= dividend[bit / 32] >> (bit % 32) & 1;
+        if compare(&remainder, divisor) != Ordering::Less {
+            remainder = subtract(&remainder, divisor);
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::bigint::BigInt;
+
+    #[test]
+    fn arithmetic() {
+        let big = |text: &str| BigInt::parse(text).unwrap();
+        let factorial = (1..=30).fold(BigInt::from(1), |product, n| &product * &BigInt::from(n));
+        assert_eq!(factorial.to_string(), "265252859812191058636308480000000");
+        assert_eq!(big("265252859812191058636308480000000"), factorial);
+
+        let max = BigInt::from(i64::MAX);
+        assert_eq!((&max + &BigInt::from(1)).to_string(), "9223372036854775808");
+        assert_eq!((&max + &BigInt::from(1)).to_i64(), None);
+        assert_eq!((&-&max - &BigInt::from(1)).to_i64(), Some(i64::MIN));
+        assert_eq!((&BigInt::from(5) - &BigInt::from(7)).to_string(), "-2");
+        assert_eq!(BigInt::from(2).pow(100).to_string(), "1267650600228229401496703205376");
+
+        let (quotient, remainder) = big("-100000000000000000000007").div_rem(&big("1000000000000")).unwrap();
+        assert_eq!((quotient.to_string(), remainder.to_string()), ("-100000000000".to_string(), "-7".to_string()));
+        assert!(BigInt::from(1).div_rem(&BigInt::from(0)).is_none());
+        assert!(big("-12345678901234567890") < big("-2") && big("2") < big("12345678901234567890"));
+
+        assert_eq!(BigInt::from(2).pow(100).to_f64(), 2f64.powi(100));
+        assert_eq!(big("9007199254740993").to_f64(), 9007199254740992.0);
+        assert_eq!(big("9007199254740995").to_f64(), 9007199254740996.0);
+        assert_eq!(BigInt::from_f64(-1e30).map(|n| n.to_f64()), Some(-1e30));
+        assert_eq!(BigInt::from_f64(2.5), None);
+        assert_eq!(BigInt::from_f64(-0.0), Some(BigInt::from(0)));
+    }
+}
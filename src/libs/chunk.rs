@@ -0,0 +1,388 @@
+use std::rc::Rc;
+use crate::libs::bigint::BigInt;
+use crate::libs::lex::Token;
+use crate::libs::symbol::Symbol;
+
+// The instructions of the VM. Operands follow the opcode as big-endian u16s; the comments list them
+// and what the instruction does to the stack.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpCode {
+    // constant: pushes it.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    Dup,
+    Swap,
+    // name: defines the value on top, popped, as a global.
+    Define,
+    // slot: pushes a value of the frame's, counted from the callee.
+    GetLocal,
+    SetLocal,
+    // index, name: pushes the value of one of the frame's cells, which hold the variables closures
+    // share; the name is for the error if it is not defined yet.
+    GetCell,
+    SetCell,
+    // index, name: pushes the value of one of the cells the function closed over.
+    GetUpvalue,
+    SetUpvalue,
+    // depth, slot, name: pushes a variable of the environment the function closed over, which holds
+    // "self" and "super" and the variables of the tree-walker's scopes.
+    GetOuter,
+    SetOuter,
+    // index: gives the frame a new cell there, for a scope entered again.
+    NewCell,
+    // index: pops the value on top into the cell.
+    DefineCell,
+    // name: pushes a global.
+    GetGlobal,
+    SetGlobal,
+    // name: replaces the instance on top by its property.
+    GetProperty,
+    // name: fails unless the value on top is an instance, before the value to store is computed.
+    CheckFields,
+    // name: stores the value on top in a field of the instance under it, leaving the value.
+    SetProperty,
+    // depth, slot, name: pushes the method of the superclass at the slot, bound to "self" under it.
+    GetSuper,
+    Index,
+    IndexSet,
+    // count: pushes a list of the values on top.
+    List,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    IntDivide,
+    Remainder,
+    Not,
+    Negate,
+    Print,
+    // offset: jumps forward.
+    Jump,
+    // offset: jumps forward when the value on top, which stays, is falsy.
+    JumpIfFalse,
+    // offset: jumps back.
+    Loop,
+    // count: calls the value under the arguments.
+    Call,
+    // function: pushes a closure over the current environment.
+    Closure,
+    // box: replaces the superclass on top, or nil, by the box.
+    Box,
+    Return,
+    // count: pushes that many nils, for the variables of a match arm, and pops them at the end of a
+    // scope.
+    Reserve,
+    PopScope,
+    // count: pushes whether the value on top is a list of that length, or of at least that length.
+    MatchList,
+    MatchListRest,
+    // index: pushes an element of the list on top.
+    ListElement,
+    // start: pushes the elements of the list on top from `start` on, as a new list.
+    ListRest,
+    // name: with a box on top and a value under it, pushes whether the value is an instance of it; the
+    // name is the one the pattern used, for the error if it is not a box.
+    MatchInstance,
+    // name: with a box on top and an instance under it, pushes the instance's field.
+    MatchField,
+    // Fails with the value on top, which no match arm matched.
+    NoMatch,
+    // path: pushes the module the path names, running it the first time it is imported.
+    Import,
+    // offset: until the matching EndTry, an error goes back to the frame, stack and environment of
+    // here and jumps forward, with the exception pushed.
+    Try,
+    // Drops the handler of the innermost Try.
+    EndTry,
+    // Throws the value on top.
+    Throw,
+    // Pops the end and the start, and pushes the range between them.
+    Range,
+    RangeInclusive,
+    // Replaces the value on top by the iterator a `for` loop goes through.
+    Iterate,
+    // offset: pushes the next item of the iterator on top, or jumps forward when there are no more.
+    ForNext,
+    // Suspends the generator running, giving the value on top to whoever resumed it; the value of
+    // the next resume is pushed in its place.
+    Yield,
+}
+
+impl OpCode {
+    pub const ALL: [OpCode; 67] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::Dup,
+        OpCode::Swap,
+        OpCode::Define,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetCell,
+        OpCode::SetCell,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetOuter,
+        OpCode::SetOuter,
+        OpCode::NewCell,
+        OpCode::DefineCell,
+        OpCode::GetGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetProperty,
+        OpCode::CheckFields,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Index,
+        OpCode::IndexSet,
+        OpCode::List,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::IntDivide,
+        OpCode::Remainder,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::Box,
+        OpCode::Return,
+        OpCode::Reserve,
+        OpCode::PopScope,
+        OpCode::MatchList,
+        OpCode::MatchListRest,
+        OpCode::ListElement,
+        OpCode::ListRest,
+        OpCode::MatchInstance,
+        OpCode::MatchField,
+        OpCode::NoMatch,
+        OpCode::Import,
+        OpCode::Try,
+        OpCode::EndTry,
+        OpCode::Throw,
+        OpCode::Range,
+        OpCode::RangeInclusive,
+        OpCode::Iterate,
+        OpCode::ForNext,
+        OpCode::Yield,
+    ];
+
+    // Opcodes are in `ALL` in the order of their values.
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        Self::ALL.get(byte as usize).copied()
+    }
+
+    // How many u16 operands follow the opcode.
+    pub fn operands(&self) -> usize {
+        match self {
+            OpCode::GetOuter | OpCode::SetOuter | OpCode::GetSuper => 3,
+            OpCode::GetCell | OpCode::SetCell | OpCode::GetUpvalue | OpCode::SetUpvalue => 2,
+            OpCode::Constant
+            | OpCode::Define
+            | OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::NewCell
+            | OpCode::DefineCell
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::CheckFields
+            | OpCode::SetProperty
+            | OpCode::List
+            | OpCode::Jump
+            | OpCode::JumpIfFalse
+            | OpCode::Loop
+            | OpCode::Call
+            | OpCode::Closure
+            | OpCode::Box
+            | OpCode::Reserve
+            | OpCode::PopScope
+            | OpCode::MatchList
+            | OpCode::MatchListRest
+            | OpCode::ListElement
+            | OpCode::ListRest
+            | OpCode::MatchInstance
+            | OpCode::MatchField
+            | OpCode::Import
+            | OpCode::Try
+            | OpCode::ForNext => 1,
+            _ => 0,
+        }
+    }
+
+    // How many values the instruction takes off the stack and how many it leaves, given its first
+    // operand. An instruction that only looks at a value takes it and puts it back. Where the two
+    // ways on differ, this is the way that doesn't jump.
+    pub fn stack_effect(&self, operand: usize) -> (usize, usize) {
+        match self {
+            OpCode::Constant
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetLocal
+            | OpCode::GetCell
+            | OpCode::GetUpvalue
+            | OpCode::GetOuter
+            | OpCode::GetGlobal
+            | OpCode::GetSuper
+            | OpCode::Closure
+            | OpCode::Import => (0, 1),
+            OpCode::Pop | OpCode::Define | OpCode::DefineCell | OpCode::Print => (1, 0),
+            OpCode::Dup | OpCode::MatchList | OpCode::MatchListRest | OpCode::ListElement | OpCode::ListRest => (1, 2),
+            OpCode::Swap => (2, 2),
+            OpCode::SetLocal
+            | OpCode::SetCell
+            | OpCode::SetUpvalue
+            | OpCode::SetOuter
+            | OpCode::SetGlobal
+            | OpCode::Box
+            | OpCode::GetProperty
+            | OpCode::CheckFields
+            | OpCode::JumpIfFalse
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::Iterate
+            | OpCode::Yield => (1, 1),
+            OpCode::ForNext => (1, 2),
+            OpCode::SetProperty
+            | OpCode::Index
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::IntDivide
+            | OpCode::Remainder
+            | OpCode::Range
+            | OpCode::RangeInclusive => (2, 1),
+            OpCode::IndexSet => (3, 1),
+            OpCode::MatchInstance | OpCode::MatchField => (2, 3),
+            OpCode::List => (operand, 1),
+            OpCode::Reserve => (0, operand),
+            OpCode::PopScope => (operand, 0),
+            // The callee and its arguments, for the result.
+            OpCode::Call => (operand + 1, 1),
+            OpCode::Return | OpCode::Throw | OpCode::NoMatch => (1, 0),
+            OpCode::Jump | OpCode::Loop | OpCode::NewCell | OpCode::Try | OpCode::EndTry => (0, 0),
+        }
+    }
+}
+
+pub enum Constant {
+    Float(f64),
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    // String values, and the names of variables, properties and fields.
+    Str(Symbol),
+    Function(Rc<Prototype>),
+    Box(Rc<BoxPrototype>),
+}
+
+// Where a closure gets a cell it shares with the function it is made in: from that function's own
+// cells, or from the upvalues that function closed over itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capture {
+    Cell(usize),
+    Upvalue(usize),
+}
+
+// What a function compiles to. The VM makes a `Function` of it each time the declaration runs.
+pub struct Prototype {
+    pub name: Option<Token>,
+    pub params: Vec<Token>,
+    pub chunk: Chunk,
+    // How many cells a call has for the variables closures share.
+    pub cells: usize,
+    // The cells the function closes over, in the order of its upvalues.
+    pub upvalues: Vec<Capture>,
+    // Whether the function yields, so that calling it makes a generator.
+    pub generator: bool,
+    // The file it was compiled from, for stack traces; not saved in .slsc files.
+    pub file: usize,
+}
+
+pub struct BoxPrototype {
+    pub name: Symbol,
+    pub fields: Vec<Symbol>,
+    pub methods: Vec<Rc<Prototype>>,
+}
+
+// The code of one function with the constants it uses. `lines` holds the offset where each run of
+// instructions from the same source line starts, with that line.
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    pub lines: Vec<(usize, usize)>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize) {
+        if self.lines.last().is_none_or(|&(_, last)| last != line) {
+            self.lines.push((self.code.len(), line));
+        }
+        self.code.push(byte);
+    }
+
+    pub fn write_u16(&mut self, value: u16, line: usize) {
+        for byte in value.to_be_bytes() {
+            self.write(byte, line);
+        }
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    // Numbers and strings are stored once; None when the pool is full.
+    pub fn add_constant(&mut self, constant: Constant) -> Option<u16> {
+        let existing = self.constants.iter().position(|other| match (other, &constant) {
+            (Constant::Float(a), Constant::Float(b)) => a.to_bits() == b.to_bits(),
+            (Constant::Int(a), Constant::Int(b)) => a == b,
+            (Constant::BigInt(a), Constant::BigInt(b)) => a == b,
+            (Constant::Str(a), Constant::Str(b)) => a == b,
+            _ => false,
+        });
+        let index = existing.unwrap_or_else(|| {
+            self.constants.push(constant);
+            self.constants.len() - 1
+        });
+        u16::try_from(index).ok()
+    }
+
+    // The source line of the instruction at `offset`.
+    pub fn line(&self, offset: usize) -> usize {
+        let index = self.lines.partition_point(|&(start, _)| start <= offset);
+        index.checked_sub(1).map_or(0, |index| self.lines[index].1)
+    }
+}
@@ -0,0 +1,951 @@
+use std::fmt::{self, Display};
+use std::mem;
+use std::rc::Rc;
+use crate::libs::chunk::{BoxPrototype, Capture, Chunk, Constant, OpCode, Prototype};
+use crate::libs::expr::{visitor::StmtVisitor, visitor::Visitor, ast::Expr, ast::MatchArm, ast::Object, ast::Pattern, ast::MethodSig, ast::Stmt, ast::TypeExpr, ast::TypedName};
+use crate::libs::generator;
+use crate::libs::interpreter::Interpreter;
+use crate::libs::lex::{Token, TokenType};
+use crate::libs::resolver::Locals;
+use crate::libs::symbol::Symbol;
+
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Compile error: {}", self.line, self.message)
+    }
+}
+
+// Turns the resolved tree into bytecode for the VM. The compiler follows the resolver's scopes to
+// find where each variable is at run time. A local stays in the stack slot its value was pushed to,
+// unless a function inside its scope uses it, which makes it a cell that the closures made there
+// share. "self" and "super" stay in environments like in the tree-walker, and so do the variables of
+// a function the tree-walker runs, for the generators it hands to the VM.
+pub struct Compiler<'a> {
+    locals: &'a Locals,
+    chunk: Chunk,
+    // The line of the token being compiled, which every instruction emitted is marked with.
+    line: usize,
+    // The file the tokens come from.
+    file: usize,
+    // The resolver's scopes around the code being compiled, innermost last.
+    scopes: Vec<Scope>,
+    // Scopes the resolver doesn't know about: a match arm's own, while its pattern is tested, since
+    // the resolver looks the boxes of patterns up from outside it.
+    hidden_scopes: usize,
+    // The function being compiled and the ones it is in, innermost last.
+    functions: Vec<Captures>,
+    // How many values the function being compiled has on the stack, from its callee on.
+    height: usize,
+    // The try statements of the function being compiled that a return would leave, innermost last.
+    tries: Vec<Try>,
+    errors: Vec<CompileError>,
+}
+
+// A scope of the resolver's, with where its variables are.
+struct Scope {
+    // The function it is in, by index in `functions`; None for the environments that hold "self"
+    // and "super".
+    function: Option<usize>,
+    // The names it declares, each with its place once it is known: a cell from the start, a slot
+    // from the definition on.
+    variables: Vec<(Symbol, Option<Variable>)>,
+    // How many values it leaves on the stack, for its end to pop.
+    values: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Variable {
+    Slot(usize),
+    Cell(usize),
+}
+
+// Where the code being compiled finds a variable.
+enum Place {
+    Slot(usize),
+    Cell(usize),
+    Upvalue(usize),
+    // Depth and slot in the environment the function closes over.
+    Outer(usize, usize),
+    Global,
+}
+
+// The cells of a function being compiled, and the ones of enclosing functions it uses.
+#[derive(Default)]
+struct Captures {
+    cells: usize,
+    upvalues: Vec<Capture>,
+}
+
+// A jump to patch once its target is known: the offset of its operand and how many values are on
+// the stack at the target.
+#[derive(Clone, Copy)]
+struct Jump {
+    operand: usize,
+    height: usize,
+}
+
+// A try statement being compiled, for the returns inside it.
+struct Try {
+    // How many of its handlers are set where the return is: the catch clause's and the finally
+    // clause's in the body, the finally clause's in the handler.
+    handlers: usize,
+    finally: Option<Stmt>,
+}
+
+pub fn compile(statements: &[Stmt], locals: &Locals) -> Result<Rc<Prototype>, Vec<CompileError>> {
+    let mut compiler = Compiler::new(locals);
+    compiler.functions.push(Captures::default());
+    compiler.statements(statements);
+    compiler.emit(OpCode::Nil);
+    compiler.emit(OpCode::Return);
+
+    if compiler.errors.is_empty() {
+        let cells = compiler.functions.pop().map_or(0, |captures| captures.cells);
+        let chunk = compiler.chunk;
+        Ok(Rc::new(Prototype { name: None, params: Vec::new(), chunk, cells, upvalues: Vec::new(), generator: false, file: compiler.file }))
+    } else {
+        Err(compiler.errors)
+    }
+}
+
+// Compiles one function of a program the tree-walker runs, which hands its generators to the VM.
+pub fn compile_function(
+    name: Option<&Token>,
+    params: &[TypedName],
+    body: &[Stmt],
+    locals: &Locals,
+) -> Result<Rc<Prototype>, Vec<CompileError>> {
+    let mut compiler = Compiler::new(locals);
+    let prototype = compiler.function(name, params, body);
+    if compiler.errors.is_empty() { Ok(prototype) } else { Err(compiler.errors) }
+}
+
+// The same pipeline as the tree-walker's up to compiling, printing the errors of each step.
+pub fn build(statements: Vec<Stmt>) -> Option<Rc<Prototype>> {
+    let (statements, locals) = Interpreter::prepare(statements)?;
+    compile(&statements, &locals)
+        .map_err(|errors| {
+            for error in errors {
+                eprintln!("{error}");
+            }
+        })
+        .ok()
+}
+
+impl<'a> Compiler<'a> {
+    fn new(locals: &'a Locals) -> Self {
+        Self {
+            locals,
+            chunk: Chunk::new(),
+            line: 1,
+            file: 0,
+            scopes: Vec::new(),
+            hidden_scopes: 0,
+            functions: Vec::new(),
+            // A script has a slot where a function has its callee.
+            height: 1,
+            tries: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn at(&mut self, token: &Token) {
+        self.line = token.line;
+        self.file = token.file;
+    }
+
+    fn error(&mut self, message: &str) {
+        self.errors.push(CompileError { line: self.line, message: message.to_string() });
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.visit_stmt(statement);
+        }
+    }
+
+    // A missing expression is nil, like in the tree-walker.
+    fn expr(&mut self, expr: &Option<Box<Expr>>) {
+        if self.visit_expr(expr).is_none() {
+            self.emit(OpCode::Nil);
+        }
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        self.emit_with(op, &[]);
+    }
+
+    fn emit_with(&mut self, op: OpCode, operands: &[usize]) {
+        let (takes, leaves) = op.stack_effect(operands.first().copied().unwrap_or(0));
+        self.height = self.height.saturating_sub(takes) + leaves;
+        self.chunk.write(op as u8, self.line);
+        for &operand in operands {
+            let operand = u16::try_from(operand).unwrap_or_else(|_| {
+                self.error(&format!("Operand {operand} doesn't fit in an instruction."));
+                0
+            });
+            self.chunk.write_u16(operand, self.line);
+        }
+    }
+
+    fn constant(&mut self, constant: Constant) -> usize {
+        match self.chunk.add_constant(constant) {
+            Some(index) => index as usize,
+            None => {
+                self.error("Too many constants in one chunk.");
+                0
+            }
+        }
+    }
+
+    fn name(&mut self, name: &str) -> usize {
+        self.constant(Constant::Str(Symbol::intern(name)))
+    }
+
+    // Emits a jump to patch once the target is known. A try statement's handler starts with the
+    // exception pushed, and a loop ends without the item it didn't get.
+    fn jump(&mut self, op: OpCode) -> Jump {
+        self.emit_with(op, &[0]);
+        let height = match op {
+            OpCode::Try => self.height + 1,
+            OpCode::ForNext => self.height - 1,
+            _ => self.height,
+        };
+        Jump { operand: self.chunk.code.len() - 2, height }
+    }
+
+    fn patch(&mut self, jump: Jump) {
+        let Jump { operand, height } = jump;
+        self.height = height;
+        let distance = self.chunk.code.len() - operand - 2;
+        let Ok(distance) = u16::try_from(distance) else {
+            self.error("Too much code to jump over.");
+            return;
+        };
+        self.chunk.code[operand..operand + 2].copy_from_slice(&distance.to_be_bytes());
+    }
+
+    fn loop_back(&mut self, start: usize) {
+        let distance = self.chunk.code.len() + 3 - start;
+        if u16::try_from(distance).is_err() {
+            self.error("Loop body too large.");
+            return;
+        }
+        self.emit_with(OpCode::Loop, &[distance]);
+    }
+
+    // The arguments are in the slots after the callee's; one a closure uses is copied to its cell.
+    fn function(&mut self, name: Option<&Token>, params: &[TypedName], body: &[Stmt]) -> Rc<Prototype> {
+        let enclosing = mem::take(&mut self.chunk);
+        let tries = mem::take(&mut self.tries);
+        let height = mem::replace(&mut self.height, 1 + params.len());
+        self.functions.push(Captures::default());
+
+        let names = params.iter().map(|param| &param.name).chain(declarations(body)).collect::<Vec<_>>();
+        self.begin_scope(&names, false);
+        for (index, param) in params.iter().enumerate() {
+            self.at(&param.name);
+            match self.place_of(&param.name) {
+                Some(Variable::Cell(cell)) => {
+                    self.emit_with(OpCode::GetLocal, &[1 + index]);
+                    self.emit_with(OpCode::DefineCell, &[cell]);
+                }
+                _ => self.set_place(&param.name, Variable::Slot(1 + index)),
+            }
+        }
+        self.statements(body);
+        self.emit(OpCode::Nil);
+        self.emit(OpCode::Return);
+        self.scopes.pop();
+
+        let Captures { cells, upvalues } = self.functions.pop().unwrap_or_default();
+        let chunk = mem::replace(&mut self.chunk, enclosing);
+        self.tries = tries;
+        self.height = height;
+        let params = params.iter().map(|param| param.name.clone()).collect();
+        let generator = generator::yields(body);
+        Rc::new(Prototype { name: name.cloned(), params, chunk, cells, upvalues, generator, file: self.file })
+    }
+
+    // Enters a scope declaring `names`, giving the ones closures use their cells. A function's
+    // cells are new for each call; the other scopes get new ones each time they are entered.
+    fn begin_scope(&mut self, names: &[&Token], renew: bool) {
+        let function = self.functions.len() - 1;
+        let mut variables = Vec::new();
+        for name in names {
+            let variable = self.locals.is_captured(name).then(|| {
+                let cell = self.functions[function].cells;
+                self.functions[function].cells += 1;
+                if renew {
+                    self.emit_with(OpCode::NewCell, &[cell]);
+                }
+                Variable::Cell(cell)
+            });
+            variables.push((name.lexeme, variable));
+        }
+        self.scopes.push(Scope { function: Some(function), variables, values: 0 });
+    }
+
+    // Pops the values of the innermost scope. The scope itself stays: a match arm ends twice, once
+    // when it matches and once when it doesn't.
+    fn end_scope(&mut self) {
+        let values = self.scopes.last().map_or(0, |scope| scope.values);
+        if values > 0 {
+            self.emit_with(OpCode::PopScope, &[values]);
+        }
+    }
+
+    // The scopes holding "self" and "super".
+    fn begin_environment(&mut self) {
+        self.scopes.push(Scope { function: None, variables: Vec::new(), values: 0 });
+    }
+
+    fn place_of(&self, name: &Token) -> Option<Variable> {
+        let scope = self.scopes.last()?;
+        scope.variables.iter().find(|(declared, _)| *declared == name.lexeme).and_then(|&(_, variable)| variable)
+    }
+
+    fn set_place(&mut self, name: &Token, place: Variable) {
+        let Some(scope) = self.scopes.last_mut() else { return };
+        if let Some((_, variable)) = scope.variables.iter_mut().find(|(declared, _)| *declared == name.lexeme) {
+            *variable = Some(place);
+        }
+    }
+
+    // Defines the variable `name` declares with the value on top: as a global at the top level, in
+    // its cell, or by leaving the value where it is as its slot.
+    fn define(&mut self, name: &Token) {
+        self.at(name);
+        if self.scopes.is_empty() {
+            let index = self.name(&name.lexeme);
+            return self.emit_with(OpCode::Define, &[index]);
+        }
+        let declared = self.scopes.last().is_some_and(|scope| scope.variables.iter().any(|(declared, _)| *declared == name.lexeme));
+        match self.place_of(name) {
+            Some(Variable::Cell(cell)) => self.emit_with(OpCode::DefineCell, &[cell]),
+            Some(Variable::Slot(slot)) => {
+                self.emit_with(OpCode::SetLocal, &[slot]);
+                self.emit(OpCode::Pop);
+            }
+            None if declared => {
+                self.set_place(name, Variable::Slot(self.height - 1));
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.values += 1;
+                }
+            }
+            None => self.error(&format!("Can't define '{}' here.", name.lexeme)),
+        }
+    }
+
+    // Gives the variables of the scope that have no place yet slots of their own, holding nil
+    // until they are defined.
+    fn reserve(&mut self) {
+        let Some(scope) = self.scopes.last_mut() else { return };
+        let mut count = 0;
+        for (_, variable) in scope.variables.iter_mut().filter(|(_, variable)| variable.is_none()) {
+            *variable = Some(Variable::Slot(self.height + count));
+            count += 1;
+        }
+        scope.values = count;
+        if count > 0 {
+            self.emit_with(OpCode::Reserve, &[count]);
+        }
+    }
+
+    fn place(&mut self, name: &Token) -> Place {
+        let Some(&resolution) = self.locals.get(&name.position()) else { return Place::Global };
+        let visible = self.scopes.len() - self.hidden_scopes;
+        let environments = |scopes: &[Scope]| scopes.iter().filter(|scope| scope.function.is_none()).count();
+        // Outside the code being compiled, in the environments the tree-walker made.
+        let Some(index) = visible.checked_sub(resolution.depth + 1) else {
+            let depth = environments(&self.scopes[..visible]) + resolution.depth - visible;
+            return Place::Outer(depth, resolution.slot);
+        };
+        let Some(function) = self.scopes[index].function else {
+            return Place::Outer(environments(&self.scopes[index + 1..visible]), resolution.slot);
+        };
+        let variable = self.scopes[index].variables.iter().find(|(declared, _)| *declared == name.lexeme);
+        let current = self.functions.len() - 1;
+        match variable.and_then(|&(_, variable)| variable) {
+            Some(Variable::Slot(slot)) if function == current => Place::Slot(slot),
+            Some(Variable::Cell(cell)) if function == current => Place::Cell(cell),
+            Some(Variable::Cell(cell)) => Place::Upvalue(self.upvalue(current, function, cell)),
+            _ => {
+                self.error(&format!("Can't find where '{}' is.", name.lexeme));
+                Place::Global
+            }
+        }
+    }
+
+    // The upvalue of the function at `index` for cell `cell` of the enclosing function at `owner`,
+    // added to it and to the functions between them if they don't have it yet.
+    fn upvalue(&mut self, index: usize, owner: usize, cell: usize) -> usize {
+        let capture = match index - 1 == owner {
+            true => Capture::Cell(cell),
+            false => Capture::Upvalue(self.upvalue(index - 1, owner, cell)),
+        };
+        let upvalues = &mut self.functions[index].upvalues;
+        upvalues.iter().position(|&existing| existing == capture).unwrap_or_else(|| {
+            upvalues.push(capture);
+            upvalues.len() - 1
+        })
+    }
+
+    // Reads the variable, or with `assign` stores the value on top in it.
+    fn variable(&mut self, name: &Token, assign: bool) {
+        let index = self.name(&name.lexeme);
+        self.at(name);
+        let (op, operands) = match self.place(name) {
+            Place::Slot(slot) => (if assign { OpCode::SetLocal } else { OpCode::GetLocal }, vec![slot]),
+            Place::Cell(cell) => (if assign { OpCode::SetCell } else { OpCode::GetCell }, vec![cell, index]),
+            Place::Upvalue(upvalue) => (if assign { OpCode::SetUpvalue } else { OpCode::GetUpvalue }, vec![upvalue, index]),
+            Place::Outer(depth, slot) => (if assign { OpCode::SetOuter } else { OpCode::GetOuter }, vec![depth, slot, index]),
+            Place::Global => (if assign { OpCode::SetGlobal } else { OpCode::GetGlobal }, vec![index]),
+        };
+        self.emit_with(op, &operands);
+    }
+
+    fn literal(&mut self, token: &Token, value: &Object) {
+        self.at(token);
+        match value {
+            Object::Float(n) => {
+                let index = self.constant(Constant::Float(*n));
+                self.emit_with(OpCode::Constant, &[index]);
+            }
+            Object::Int(n) => {
+                let index = self.constant(Constant::Int(*n));
+                self.emit_with(OpCode::Constant, &[index]);
+            }
+            Object::BigInt(n) => {
+                let index = self.constant(Constant::BigInt(Rc::clone(n)));
+                self.emit_with(OpCode::Constant, &[index]);
+            }
+            Object::Str(s) => {
+                let index = self.constant(Constant::Str(Symbol::intern(s)));
+                self.emit_with(OpCode::Constant, &[index]);
+            }
+            Object::Bool(true) => self.emit(OpCode::True),
+            Object::Bool(false) => self.emit(OpCode::False),
+            _ => self.emit(OpCode::Nil),
+        }
+    }
+
+    // Tests the value on top against the pattern, replacing it with whether it matched and defining
+    // the bindings in the arm's scope. Whatever a nested pattern finds, the stack under it is
+    // the same, so all the failures of a pattern can jump to one clean-up.
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Literal { token, value } => {
+                self.literal(token, value);
+                self.emit(OpCode::Equal);
+            }
+            Pattern::Binding { name } => {
+                self.define(name);
+                self.emit(OpCode::True);
+            }
+            Pattern::Wildcard { .. } => {
+                self.emit(OpCode::Pop);
+                self.emit(OpCode::True);
+            }
+            Pattern::List { bracket, elements, rest } => {
+                self.at(bracket);
+                let op = if rest.is_some() { OpCode::MatchListRest } else { OpCode::MatchList };
+                self.emit_with(op, &[elements.len()]);
+                let mut failures = vec![self.jump(OpCode::JumpIfFalse)];
+                self.emit(OpCode::Pop);
+
+                for (index, element) in elements.iter().enumerate() {
+                    self.emit_with(OpCode::ListElement, &[index]);
+                    self.pattern(element);
+                    failures.push(self.jump(OpCode::JumpIfFalse));
+                    self.emit(OpCode::Pop);
+                }
+                if let Some(rest) = rest {
+                    self.emit_with(OpCode::ListRest, &[elements.len()]);
+                    self.pattern(rest);
+                    failures.push(self.jump(OpCode::JumpIfFalse));
+                    self.emit(OpCode::Pop);
+                }
+                self.finish_pattern(1, failures);
+            }
+            Pattern::Box { name, fields } => {
+                self.variable(name, false);
+                let index = self.name(&name.lexeme);
+                self.emit_with(OpCode::MatchInstance, &[index]);
+                let mut failures = vec![self.jump(OpCode::JumpIfFalse)];
+                self.emit(OpCode::Pop);
+
+                for (field, pattern) in fields {
+                    let index = self.name(&field.lexeme);
+                    self.at(field);
+                    self.emit_with(OpCode::MatchField, &[index]);
+                    self.pattern(pattern);
+                    failures.push(self.jump(OpCode::JumpIfFalse));
+                    self.emit(OpCode::Pop);
+                }
+                self.finish_pattern(2, failures);
+            }
+        }
+    }
+
+    // Replaces the `kept` values a list or box pattern works on with true, or on a failure with false.
+    fn finish_pattern(&mut self, kept: usize, failures: Vec<Jump>) {
+        for _ in 0..kept {
+            self.emit(OpCode::Pop);
+        }
+        self.emit(OpCode::True);
+        let end = self.jump(OpCode::Jump);
+
+        for failure in failures {
+            self.patch(failure);
+        }
+        for _ in 0..=kept {
+            self.emit(OpCode::Pop);
+        }
+        self.emit(OpCode::False);
+        self.patch(end);
+    }
+}
+
+// The names the statements of a block declare, like the resolver's.
+fn declarations(statements: &[Stmt]) -> impl Iterator<Item = &Token> {
+    statements.iter().flat_map(|statement| match statement {
+        Stmt::Let { name, .. } | Stmt::Function { name, .. } | Stmt::Box { name, .. } => Some(name),
+        _ => None,
+    })
+}
+
+// The names a pattern binds.
+fn bindings<'p>(pattern: &'p Pattern, names: &mut Vec<&'p Token>) {
+    match pattern {
+        Pattern::Binding { name } => names.push(name),
+        Pattern::List { elements, rest, .. } => {
+            elements.iter().chain(rest.as_deref()).for_each(|element| bindings(element, names));
+        }
+        Pattern::Box { fields, .. } => fields.iter().for_each(|(_, pattern)| bindings(pattern, names)),
+        Pattern::Literal { .. } | Pattern::Wildcard { .. } => (),
+    }
+}
+
+impl Visitor<()> for Compiler<'_> {
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) {
+        self.expr(value);
+        self.variable(name, true);
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) {
+        self.expr(left);
+        self.expr(right);
+        self.at(operator);
+        let op = match operator.token_type {
+            TokenType::PLUS => OpCode::Add,
+            TokenType::MINUS => OpCode::Subtract,
+            TokenType::STAR => OpCode::Multiply,
+            TokenType::SLASH => OpCode::Divide,
+            TokenType::TILDE_SLASH => OpCode::IntDivide,
+            TokenType::PERCENT => OpCode::Remainder,
+            TokenType::GREATER => OpCode::Greater,
+            TokenType::GREATER_EQUAL => OpCode::GreaterEqual,
+            TokenType::LESS => OpCode::Less,
+            TokenType::LESS_EQUAL => OpCode::LessEqual,
+            TokenType::EQUAL_EQUAL => OpCode::Equal,
+            TokenType::BANG_EQUAL => OpCode::NotEqual,
+            TokenType::DOT_DOT => OpCode::Range,
+            TokenType::DOT_DOT_EQUAL => OpCode::RangeInclusive,
+            _ => return self.error("Unknown binary operator."),
+        };
+        self.emit(op);
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, paren: &Token, arguments: &[Expr]) {
+        self.expr(callee);
+        for argument in arguments {
+            self.visit(argument);
+        }
+        self.at(paren);
+        self.emit_with(OpCode::Call, &[arguments.len()]);
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, name: &Token) {
+        self.expr(object);
+        let index = self.name(&name.lexeme);
+        self.at(name);
+        self.emit_with(OpCode::GetProperty, &[index]);
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) {
+        self.expr(expression);
+    }
+
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, bracket: &Token, index: &Option<Box<Expr>>) {
+        self.expr(object);
+        self.expr(index);
+        self.at(bracket);
+        self.emit(OpCode::Index);
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) {
+        self.expr(object);
+        self.expr(index);
+        self.expr(value);
+        self.at(bracket);
+        self.emit(OpCode::IndexSet);
+    }
+
+    fn visit_lambda(&mut self, keyword: &Token, params: &[TypedName], _result: &Option<TypeExpr>, body: &Rc<Vec<Stmt>>) {
+        let prototype = self.function(None, params, body);
+        let index = self.constant(Constant::Function(prototype));
+        self.at(keyword);
+        self.emit_with(OpCode::Closure, &[index]);
+    }
+
+    fn visit_list(&mut self, bracket: &Token, elements: &[Expr]) {
+        for element in elements {
+            self.visit(element);
+        }
+        self.at(bracket);
+        self.emit_with(OpCode::List, &[elements.len()]);
+    }
+
+    fn visit_literal(&mut self, token: &Token, value: &Object) {
+        self.literal(token, value);
+    }
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) {
+        self.expr(left);
+        self.at(operator);
+        match operator.token_type {
+            TokenType::OR => {
+                let otherwise = self.jump(OpCode::JumpIfFalse);
+                let end = self.jump(OpCode::Jump);
+                self.patch(otherwise);
+                self.emit(OpCode::Pop);
+                self.expr(right);
+                self.patch(end);
+            }
+            _ => {
+                let end = self.jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.expr(right);
+                self.patch(end);
+            }
+        }
+    }
+
+    // The subject stays on the stack while the arms are tried, and the arm that matches leaves its
+    // value in its place. An arm's slots are reserved before its pattern is tested so the bindings
+    // can go straight into them.
+    fn visit_match(&mut self, keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) {
+        self.expr(subject);
+        let slot = self.height - 1;
+
+        let mut ends = Vec::new();
+        for arm in arms {
+            let mut names = Vec::new();
+            bindings(&arm.pattern, &mut names);
+            self.begin_scope(&names, true);
+            self.reserve();
+            self.emit_with(OpCode::GetLocal, &[slot]);
+            self.hidden_scopes += 1;
+            self.pattern(&arm.pattern);
+            self.hidden_scopes -= 1;
+            let mut failures = vec![self.jump(OpCode::JumpIfFalse)];
+            self.emit(OpCode::Pop);
+
+            if arm.guard.is_some() {
+                self.expr(&arm.guard);
+                failures.push(self.jump(OpCode::JumpIfFalse));
+                self.emit(OpCode::Pop);
+            }
+            self.expr(&arm.body);
+            self.emit_with(OpCode::SetLocal, &[slot]);
+            self.emit(OpCode::Pop);
+            self.end_scope();
+            ends.push(self.jump(OpCode::Jump));
+
+            for failure in failures {
+                self.patch(failure);
+            }
+            self.emit(OpCode::Pop);
+            self.end_scope();
+            self.scopes.pop();
+        }
+
+        self.at(keyword);
+        self.emit(OpCode::NoMatch);
+        for end in ends {
+            self.patch(end);
+        }
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, name: &Token, value: &Option<Box<Expr>>) {
+        self.expr(object);
+        let index = self.name(&name.lexeme);
+        self.at(name);
+        self.emit_with(OpCode::CheckFields, &[index]);
+        self.expr(value);
+        self.at(name);
+        self.emit_with(OpCode::SetProperty, &[index]);
+    }
+
+    fn visit_self_ref(&mut self, keyword: &Token) {
+        self.variable(keyword, false);
+    }
+
+    // Without a resolution the depth is out of reach, which the VM reports like the tree-walker.
+    fn visit_super(&mut self, keyword: &Token, method: &Token) {
+        let index = self.name(&method.lexeme);
+        let (depth, slot) = match self.place(keyword) {
+            Place::Outer(depth, slot) => (depth, slot),
+            _ => (u16::MAX as usize, 0),
+        };
+        self.at(method);
+        self.emit_with(OpCode::GetSuper, &[depth, slot, index]);
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Option<Box<Expr>>) {
+        self.expr(right);
+        self.at(operator);
+        match operator.token_type {
+            TokenType::MINUS => self.emit(OpCode::Negate),
+            TokenType::BANG => self.emit(OpCode::Not),
+            _ => self.error("Unknown unary operator."),
+        }
+    }
+
+    fn visit_variable(&mut self, name: &Token) {
+        self.variable(name, false);
+    }
+
+    fn visit_yield(&mut self, keyword: &Token, value: &Option<Box<Expr>>) {
+        self.expr(value);
+        self.at(keyword);
+        self.emit(OpCode::Yield);
+    }
+}
+
+impl StmtVisitor<()> for Compiler<'_> {
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        let names = declarations(statements).collect::<Vec<_>>();
+        self.begin_scope(&names, true);
+        self.statements(statements);
+        self.end_scope();
+        self.scopes.pop();
+    }
+
+    // The methods are in the environments holding "super" and "self", like the resolver's scopes.
+    fn visit_box(&mut self, name: &Token, superclass: &Option<Box<Expr>>, fields: &[TypedName], methods: &[Stmt]) {
+        self.expr(superclass);
+        let environments = 1 + usize::from(superclass.is_some());
+        for _ in 0..environments {
+            self.begin_environment();
+        }
+        let methods = methods
+            .iter()
+            .filter_map(|method| match method {
+                Stmt::Function { name, params, body, .. } => Some(self.function(Some(name), params, body)),
+                _ => None,
+            })
+            .collect();
+        self.scopes.truncate(self.scopes.len() - environments);
+        let prototype = BoxPrototype {
+            name: name.lexeme,
+            fields: fields.iter().map(|field| field.name.lexeme).collect(),
+            methods,
+        };
+        let index = self.constant(Constant::Box(Rc::new(prototype)));
+        self.at(name);
+        self.emit_with(OpCode::Box, &[index]);
+        self.define(name);
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) {
+        self.expr(expression);
+        self.emit(OpCode::Pop);
+    }
+
+    // The iterator stays on the stack for the whole loop, and each item is defined in a scope of its
+    // own, like the interpreter's environment per item.
+    fn visit_for(&mut self, keyword: &Token, name: &Token, iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) {
+        self.expr(iterable);
+        self.at(keyword);
+        self.emit(OpCode::Iterate);
+        let start = self.chunk.code.len();
+        let exit = self.jump(OpCode::ForNext);
+
+        self.begin_scope(&[name], true);
+        self.define(name);
+        if let Some(body) = body {
+            self.visit_stmt(body);
+        }
+        self.end_scope();
+        self.scopes.pop();
+        self.loop_back(start);
+
+        self.patch(exit);
+        self.emit(OpCode::Pop);
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        _type_params: &[Token],
+        params: &[TypedName],
+        _result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) {
+        let prototype = self.function(Some(name), params, body);
+        let function = self.constant(Constant::Function(prototype));
+        self.at(name);
+        self.emit_with(OpCode::Closure, &[function]);
+        self.define(name);
+    }
+
+    fn visit_if(&mut self, condition: &Option<Box<Expr>>, then_branch: &Option<Box<Stmt>>, else_branch: &Option<Box<Stmt>>) {
+        self.expr(condition);
+        let otherwise = self.jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        if let Some(then_branch) = then_branch {
+            self.visit_stmt(then_branch);
+        }
+        let end = self.jump(OpCode::Jump);
+
+        self.patch(otherwise);
+        self.emit(OpCode::Pop);
+        if let Some(else_branch) = else_branch {
+            self.visit_stmt(else_branch);
+        }
+        self.patch(end);
+    }
+
+    // The module stays on the stack while each name is taken from it.
+    fn visit_import(&mut self, keyword: &Token, path: &Token, names: &[Token]) {
+        let path_index = self.name(path.lexeme.trim_matches('"'));
+        self.at(keyword);
+        self.emit_with(OpCode::Import, &[path_index]);
+        if let TokenType::FROM = keyword.token_type {
+            for name in names {
+                let index = self.name(&name.lexeme);
+                self.at(name);
+                self.emit(OpCode::Dup);
+                self.emit_with(OpCode::GetProperty, &[index]);
+                self.emit_with(OpCode::Define, &[index]);
+            }
+            self.emit(OpCode::Pop);
+        } else {
+            let index = self.name(&names[0].lexeme);
+            self.emit_with(OpCode::Define, &[index]);
+        }
+    }
+
+    fn visit_interface(&mut self, _name: &Token, _methods: &[MethodSig]) {}
+
+    fn visit_let(&mut self, name: &Token, _annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) {
+        self.expr(initializer);
+        self.define(name);
+    }
+
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) {
+        self.expr(expression);
+        self.emit(OpCode::Print);
+    }
+
+    // A return leaves the try statements it is in: their handlers are dropped and their finally
+    // clauses run, innermost first, once the value is on the stack.
+    fn visit_return(&mut self, keyword: &Token, value: &Option<Box<Expr>>) {
+        self.expr(value);
+        for index in (0..self.tries.len()).rev() {
+            self.at(keyword);
+            for _ in 0..self.tries[index].handlers {
+                self.emit(OpCode::EndTry);
+            }
+            if let Some(finally) = self.tries[index].finally.clone() {
+                let inner = self.tries.split_off(index);
+                self.visit_stmt(&finally);
+                self.tries.extend(inner);
+            }
+        }
+        self.at(keyword);
+        self.emit(OpCode::Return);
+    }
+
+    fn visit_throw(&mut self, keyword: &Token, value: &Option<Box<Expr>>) {
+        self.expr(value);
+        self.at(keyword);
+        self.emit(OpCode::Throw);
+    }
+
+    // The finally clause is compiled twice: where the body or handler ends, and as a handler of its
+    // own that runs it and throws the exception again. The exception a handler gets is defined in
+    // a scope around the catch block, like the resolver's.
+    fn visit_try(
+        &mut self,
+        keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) {
+        self.at(keyword);
+        let rethrow = finally.is_some().then(|| self.jump(OpCode::Try));
+        let catch = name.is_some().then(|| self.jump(OpCode::Try));
+        self.tries.push(Try { handlers: usize::from(rethrow.is_some()) + usize::from(catch.is_some()), finally: finally.as_deref().cloned() });
+        if let Some(body) = body {
+            self.visit_stmt(body);
+        }
+
+        if let (Some(catch), Some(name), Some(handler)) = (catch, name, handler) {
+            self.at(keyword);
+            self.emit(OpCode::EndTry);
+            let end = self.jump(OpCode::Jump);
+            self.patch(catch);
+            if let Some(current) = self.tries.last_mut() {
+                current.handlers -= 1;
+            }
+            self.begin_scope(&[name], true);
+            self.define(name);
+            self.visit_stmt(handler);
+            self.end_scope();
+            self.scopes.pop();
+            self.patch(end);
+        }
+        self.tries.pop();
+
+        if let (Some(rethrow), Some(finally)) = (rethrow, finally) {
+            self.at(keyword);
+            self.emit(OpCode::EndTry);
+            self.visit_stmt(finally);
+            let end = self.jump(OpCode::Jump);
+            self.patch(rethrow);
+            self.visit_stmt(finally);
+            self.at(keyword);
+            self.emit(OpCode::Throw);
+            self.patch(end);
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) {
+        let start = self.chunk.code.len();
+        self.expr(condition);
+        let exit = self.jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        if let Some(body) = body {
+            self.visit_stmt(body);
+        }
+        self.loop_back(start);
+
+        self.patch(exit);
+        self.emit(OpCode::Pop);
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::Write;
+use crate::libs::chunk::{Capture, Constant, OpCode, Prototype};
+
+// Lists the instructions of a compiled script, one per line: the offset, the source line ("|" when
+// it is the previous instruction's), the opcode and its operands, with the constants they refer to
+// and where jumps go. The functions and methods the script declares follow, each under its name
+// and the cells it has and closes over.
+pub fn disassemble(script: &Prototype) -> String {
+    let mut text = String::new();
+    function(&mut text, "<script>", script);
+    text
+}
+
+fn function(text: &mut String, title: &str, prototype: &Prototype) {
+    let chunk = &prototype.chunk;
+    let params = prototype.params.iter().map(|param| param.lexeme.as_str()).collect::<Vec<_>>();
+    let title = if prototype.generator { format!("generator {title}") } else { title.to_string() };
+    if params.is_empty() {
+        let _ = writeln!(text, "== {title} ==");
+    } else {
+        let _ = writeln!(text, "== {title}({}) ==", params.join(", "));
+    }
+    if prototype.cells > 0 || !prototype.upvalues.is_empty() {
+        let upvalues = prototype.upvalues.iter().map(|capture| match capture {
+            Capture::Cell(index) => format!("cell {index}"),
+            Capture::Upvalue(index) => format!("upvalue {index}"),
+        });
+        let _ = writeln!(text, "cells: {}, upvalues: [{}]", prototype.cells, upvalues.collect::<Vec<_>>().join(", "));
+    }
+
+    let mut offset = 0;
+    let mut previous_line = None;
+    while offset < chunk.code.len() {
+        let line = chunk.line(offset);
+        let line_text = if previous_line == Some(line) { "|".to_string() } else { line.to_string() };
+        previous_line = Some(line);
+
+        let Some(op) = OpCode::from_byte(chunk.code[offset]) else {
+            let _ = writeln!(text, "{offset:04} {line_text:>4} <unknown opcode {}>", chunk.code[offset]);
+            offset += 1;
+            continue;
+        };
+        let operands = (0..op.operands()).map(|index| chunk.read_u16(offset + 1 + 2 * index) as usize).collect::<Vec<_>>();
+        let next = offset + 1 + 2 * operands.len();
+        let constant = |index: usize| chunk.constants.get(index).map_or_else(|| "<missing>".to_string(), describe);
+
+        let details = match op {
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Try | OpCode::ForNext => format!("{} -> {:04}", operands[0], next + operands[0]),
+            OpCode::Loop => format!("{} -> {:04}", operands[0], next.wrapping_sub(operands[0])),
+            OpCode::GetOuter | OpCode::SetOuter | OpCode::GetSuper => {
+                format!("{} {} {}", operands[0], operands[1], constant(operands[2]))
+            }
+            OpCode::GetCell | OpCode::SetCell | OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                format!("{} {}", operands[0], constant(operands[1]))
+            }
+            OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::NewCell
+            | OpCode::DefineCell
+            | OpCode::Reserve
+            | OpCode::PopScope
+            | OpCode::List
+            | OpCode::Call
+            | OpCode::MatchList
+            | OpCode::MatchListRest
+            | OpCode::ListElement
+            | OpCode::ListRest => operands[0].to_string(),
+            _ if operands.is_empty() => String::new(),
+            _ => format!("{} {}", operands[0], constant(operands[0])),
+        };
+        let name = format!("{op:?}");
+        let instruction = format!("{offset:04} {line_text:>4} {name:<14}{details}");
+        let _ = writeln!(text, "{}", instruction.trim_end());
+        offset = next;
+    }
+
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Function(prototype) => {
+                let _ = writeln!(text);
+                function(text, &describe_name(prototype), prototype);
+            }
+            Constant::Box(prototype) => {
+                for method in &prototype.methods {
+                    let _ = writeln!(text);
+                    function(text, &format!("{}.{}", prototype.name, describe_name(method)), method);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn describe_name(prototype: &Prototype) -> String {
+    prototype.name.as_ref().map_or_else(|| "<fn>".to_string(), |name| name.lexeme.to_string())
+}
+
+fn describe(constant: &Constant) -> String {
+    match constant {
+        Constant::Float(n) => format!("{n:?}"),
+        Constant::Int(n) => n.to_string(),
+        Constant::BigInt(n) => n.to_string(),
+        Constant::Str(s) => format!("{s:?}"),
+        Constant::Function(prototype) => format!("<fn {}>", describe_name(prototype)),
+        Constant::Box(prototype) => format!("<box {}>", prototype.name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::compiler;
+    use crate::libs::disassembler::disassemble;
+    use crate::libs::lex::Lox;
+
+    #[test]
+    fn lists_instructions() {
+        let source = "fun add(a, b) {\n  return a + b;\n}\nlet i = 0;\nwhile (i < 2) i = add(i, 1);\nprint \"done\";";
+        let statements = Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+        let script = compiler::build(statements).unwrap_or_else(|| panic!("Failed to compile"));
+
+        let expected = "== <script> ==
+0000    1 Closure       0 <fn add>
+0003    | Define        1 \"add\"
+0006    4 Constant      2 0
+0009    | Define        3 \"i\"
+0012    5 GetGlobal     3 \"i\"
+0015    | Constant      4 2
+0018    | Less
+0019    | JumpIfFalse   20 -> 0042
+0022    | Pop
+0023    | GetGlobal     1 \"add\"
+0026    | GetGlobal     3 \"i\"
+0029    | Constant      5 1
+0032    | Call          2
+0035    | SetGlobal     3 \"i\"
+0038    | Pop
+0039    | Loop          30 -> 0012
+0042    | Pop
+0043    6 Constant      6 \"done\"
+0046    | Print
+0047    | Nil
+0048    | Return
+
+== add(a, b) ==
+0000    2 GetLocal      1
+0003    | GetLocal      2
+0006    | Add
+0007    | Return
+0008    | Nil
+0009    | Return
+";
+        assert_eq!(disassemble(&script), expected);
+    }
+}
@@ -0,0 +1,286 @@
+use std::rc::Rc;
+use crate::libs::ast_printer::AstPrinter;
+use crate::libs::expr::{visitor::StmtVisitor, visitor::Visitor, ast::Expr, ast::MatchArm, ast::Object, ast::MethodSig, ast::Stmt, ast::TypeExpr, ast::TypedName};
+use crate::libs::lex::Token;
+
+// Exports the tree as a Graphviz digraph: every visit adds its node and the edges to its children
+// and returns the node's id. Edges are labeled with the field the child comes from.
+#[derive(Default)]
+pub struct DotPrinter {
+    lines: Vec<String>,
+    count: usize,
+}
+
+impl DotPrinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn print(&mut self, statements: &[Stmt]) -> String {
+        let root = self.node("Program");
+        for statement in statements {
+            let child = self.visit_stmt(statement);
+            self.edge(root, child, "");
+        }
+
+        let mut result = "digraph AST {\n    node [shape=box, fontname=\"monospace\"];\n".to_string();
+        for line in self.lines.drain(..) {
+            result.push_str(&format!("    {line}\n"));
+        }
+        result.push('}');
+        result
+    }
+
+    fn node(&mut self, label: &str) -> usize {
+        let id = self.count;
+        self.count += 1;
+        self.lines.push(format!("n{id} [label={}];", quote(label)));
+        id
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: &str) {
+        if label.is_empty() {
+            self.lines.push(format!("n{from} -> n{to};"));
+        } else {
+            self.lines.push(format!("n{from} -> n{to} [label={}];", quote(label)));
+        }
+    }
+
+    // Adds a node and links the children to it; the children are visited first, so ids grow
+    // from the leaves up.
+    fn parent(&mut self, label: &str, children: Vec<(&str, usize)>) -> usize {
+        let id = self.node(label);
+        for (name, child) in children {
+            self.edge(id, child, name);
+        }
+        id
+    }
+
+    fn expr<'a>(&mut self, label: &'a str, expr: &Option<Box<Expr>>) -> Option<(&'a str, usize)> {
+        Some((label, self.visit(expr.as_ref()?)))
+    }
+
+    fn exprs<'a>(&mut self, label: &'a str, exprs: &[Expr]) -> Vec<(&'a str, usize)> {
+        exprs.iter().map(|expr| (label, self.visit(expr))).collect()
+    }
+
+    fn stmt<'a>(&mut self, label: &'a str, stmt: &Option<Box<Stmt>>) -> Option<(&'a str, usize)> {
+        Some((label, self.visit_stmt(stmt.as_ref()?)))
+    }
+
+    fn stmts<'a>(&mut self, label: &'a str, stmts: &[Stmt]) -> Vec<(&'a str, usize)> {
+        stmts.iter().map(|stmt| (label, self.visit_stmt(stmt))).collect()
+    }
+}
+
+fn quote(label: &str) -> String {
+    format!("\"{}\"", label.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn names(names: &[TypedName]) -> String {
+    names.iter().map(AstPrinter::typed_name).collect::<Vec<_>>().join(", ")
+}
+
+impl Visitor<usize> for DotPrinter {
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) -> usize {
+        let children = self.expr("value", value).into_iter().collect();
+        self.parent(&format!("Assign {}", name.lexeme), children)
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> usize {
+        let children = [self.expr("left", left), self.expr("right", right)];
+        self.parent(&format!("Binary {}", operator.lexeme), children.into_iter().flatten().collect())
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, _paren: &Token, arguments: &[Expr]) -> usize {
+        let mut children: Vec<_> = self.expr("callee", callee).into_iter().collect();
+        children.extend(self.exprs("argument", arguments));
+        self.parent("Call", children)
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, name: &Token) -> usize {
+        let children = self.expr("object", object).into_iter().collect();
+        self.parent(&format!("Get {}", name.lexeme), children)
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) -> usize {
+        let children = self.expr("expression", expression).into_iter().collect();
+        self.parent("Grouping", children)
+    }
+
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, _bracket: &Token, index: &Option<Box<Expr>>) -> usize {
+        let children = [self.expr("object", object), self.expr("index", index)];
+        self.parent("Index", children.into_iter().flatten().collect())
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        _bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) -> usize {
+        let children = [self.expr("object", object), self.expr("index", index), self.expr("value", value)];
+        self.parent("IndexSet", children.into_iter().flatten().collect())
+    }
+
+    fn visit_lambda(
+        &mut self,
+        _keyword: &Token,
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> usize {
+        let children = self.stmts("body", body);
+        self.parent(&format!("Lambda ({}){}", names(params), AstPrinter::returns(result)), children)
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> usize {
+        let children = self.exprs("element", elements);
+        self.parent("List", children)
+    }
+
+    fn visit_literal(&mut self, token: &Token, _value: &Object) -> usize {
+        self.node(&format!("Literal {}", token.lexeme))
+    }
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> usize {
+        let children = [self.expr("left", left), self.expr("right", right)];
+        self.parent(&format!("Logical {}", operator.lexeme), children.into_iter().flatten().collect())
+    }
+
+    fn visit_match(&mut self, _keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) -> usize {
+        let mut children: Vec<_> = self.expr("subject", subject).into_iter().collect();
+        for arm in arms {
+            let arm_children = [self.expr("guard", &arm.guard), self.expr("body", &arm.body)];
+            let label = format!("Arm {}", AstPrinter::pattern(&arm.pattern));
+            children.push(("arm", self.parent(&label, arm_children.into_iter().flatten().collect())));
+        }
+        self.parent("Match", children)
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, name: &Token, value: &Option<Box<Expr>>) -> usize {
+        let children = [self.expr("object", object), self.expr("value", value)];
+        self.parent(&format!("Set {}", name.lexeme), children.into_iter().flatten().collect())
+    }
+
+    fn visit_self_ref(&mut self, _keyword: &Token) -> usize {
+        self.node("SelfRef")
+    }
+
+    fn visit_super(&mut self, _keyword: &Token, method: &Token) -> usize {
+        self.node(&format!("Super {}", method.lexeme))
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Option<Box<Expr>>) -> usize {
+        let children = self.expr("right", right).into_iter().collect();
+        self.parent(&format!("Unary {}", operator.lexeme), children)
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> usize {
+        self.node(&format!("Variable {}", name.lexeme))
+    }
+
+    fn visit_yield(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> usize {
+        let children = self.expr("value", value).into_iter().collect();
+        self.parent("Yield", children)
+    }
+}
+
+impl StmtVisitor<usize> for DotPrinter {
+    fn visit_block(&mut self, statements: &[Stmt]) -> usize {
+        let children = self.stmts("statement", statements);
+        self.parent("Block", children)
+    }
+
+    fn visit_box(&mut self, name: &Token, superclass: &Option<Box<Expr>>, fields: &[TypedName], methods: &[Stmt]) -> usize {
+        let mut children: Vec<_> = self.expr("superclass", superclass).into_iter().collect();
+        children.extend(self.stmts("method", methods));
+        self.parent(&format!("Box {} ({})", name.lexeme, names(fields)), children)
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) -> usize {
+        let children = self.expr("expression", expression).into_iter().collect();
+        self.parent("Expression", children)
+    }
+
+    fn visit_for(&mut self, _keyword: &Token, name: &Token, iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> usize {
+        let children = [self.expr("iterable", iterable), self.stmt("body", body)];
+        self.parent(&format!("For {}", name.lexeme), children.into_iter().flatten().collect())
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        type_params: &[Token],
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> usize {
+        let children = self.stmts("body", body);
+        let type_params = AstPrinter::type_params(type_params);
+        let label = format!("Function {}{type_params} ({}){}", name.lexeme, names(params), AstPrinter::returns(result));
+        self.parent(&label, children)
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Option<Box<Expr>>,
+        then_branch: &Option<Box<Stmt>>,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> usize {
+        let children = [self.expr("condition", condition), self.stmt("then", then_branch), self.stmt("else", else_branch)];
+        self.parent("If", children.into_iter().flatten().collect())
+    }
+
+    fn visit_import(&mut self, keyword: &Token, path: &Token, names: &[Token]) -> usize {
+        self.node(&AstPrinter::import(keyword, path, names))
+    }
+
+    fn visit_interface(&mut self, name: &Token, methods: &[MethodSig]) -> usize {
+        let children = methods.iter().map(|method| ("method", self.node(&AstPrinter::method_sig(method)))).collect();
+        self.parent(&format!("Interface {}", name.lexeme), children)
+    }
+
+    fn visit_let(&mut self, name: &Token, annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) -> usize {
+        let children = self.expr("initializer", initializer).into_iter().collect();
+        let name = AstPrinter::annotated(name, annotation);
+        self.parent(&format!("Let {name}"), children)
+    }
+
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) -> usize {
+        let children = self.expr("expression", expression).into_iter().collect();
+        self.parent("Print", children)
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> usize {
+        let children = self.expr("value", value).into_iter().collect();
+        self.parent("Return", children)
+    }
+
+    fn visit_throw(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> usize {
+        let children = self.expr("value", value).into_iter().collect();
+        self.parent("Throw", children)
+    }
+
+    fn visit_try(
+        &mut self,
+        _keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) -> usize {
+        let children = [self.stmt("body", body), self.stmt("catch", handler), self.stmt("finally", finally)];
+        let head = match name {
+            Some(name) => format!("Try catch ({})", name.lexeme),
+            None => "Try".to_string(),
+        };
+        self.parent(&head, children.into_iter().flatten().collect())
+    }
+
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> usize {
+        let children = [self.expr("condition", condition), self.stmt("body", body)];
+        self.parent("While", children.into_iter().flatten().collect())
+    }
+}
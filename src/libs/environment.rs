@@ -0,0 +1,146 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::libs::expr::ast::Object;
+use crate::libs::interpreter::RuntimeError;
+use crate::libs::lex::Token;
+use crate::libs::symbol::Symbol;
+use crate::libs::value::Value;
+
+// Values live in slots numbered in the order they are defined, which is the order the resolver
+// gives them; the names are kept for globals and error messages. They are held as 8-byte values,
+// which storing and loading move into and out of the objects the interpreters work with.
+pub struct Environment {
+    values: Vec<Value>,
+    slots: HashMap<Symbol, usize>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            slots: HashMap::new(),
+            enclosing: None,
+        }
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: Vec::new(),
+            slots: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    // Defining a name again reuses its slot.
+    pub fn define(&mut self, name: Symbol, value: Object) {
+        match self.slots.get(&name) {
+            Some(&slot) => self.values[slot] = Value::from(value),
+            None => {
+                self.slots.insert(name, self.values.len());
+                self.values.push(Value::from(value));
+            }
+        }
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Object, RuntimeError> {
+        self.value(name.lexeme)
+            .ok_or_else(|| RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme)))
+    }
+
+    pub fn value(&self, name: Symbol) -> Option<Object> {
+        if let Some(&slot) = self.slots.get(&name) {
+            return Some(Object::clone(&self.values[slot].as_object()));
+        }
+
+        self.enclosing.as_ref()?.borrow().value(name)
+    }
+
+    pub fn enclosing(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.clone()
+    }
+
+    pub(crate) fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    // Drops everything the environment holds, for the heap to break cycles through it.
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+        self.slots.clear();
+        self.enclosing = None;
+    }
+
+    fn ancestor(environment: &Rc<RefCell<Environment>>, distance: usize) -> Option<Rc<RefCell<Environment>>> {
+        let mut current = Rc::clone(environment);
+        for _ in 0..distance {
+            let enclosing = current.borrow().enclosing.clone()?;
+            current = enclosing;
+        }
+        Some(current)
+    }
+
+    // The outermost environment around `environment`: the globals of the file it belongs to.
+    pub fn root(environment: &Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+        let mut current = Rc::clone(environment);
+        loop {
+            let enclosing = current.borrow().enclosing.clone();
+            match enclosing {
+                Some(enclosing) => current = enclosing,
+                None => return current,
+            }
+        }
+    }
+
+    // Looks a name up exactly `distance` scopes out from `environment`.
+    pub fn get_at(environment: &Rc<RefCell<Environment>>, distance: usize, name: Symbol) -> Option<Object> {
+        let ancestor = Self::ancestor(environment, distance)?;
+        let ancestor = ancestor.borrow();
+        ancestor.slots.get(&name).map(|&slot| Object::clone(&ancestor.values[slot].as_object()))
+    }
+
+    // None when the slot is not defined yet, like a function called before a later declaration.
+    pub fn get_slot(environment: &Rc<RefCell<Environment>>, distance: usize, slot: usize) -> Option<Object> {
+        let ancestor = Self::ancestor(environment, distance)?;
+        let value = ancestor.borrow().values.get(slot).map(|value| Object::clone(&value.as_object()));
+        value
+    }
+
+    pub fn assign_slot(environment: &Rc<RefCell<Environment>>, distance: usize, slot: usize, value: Object) -> bool {
+        let Some(ancestor) = Self::ancestor(environment, distance) else { return false };
+        let mut ancestor = ancestor.borrow_mut();
+        match ancestor.values.get_mut(slot) {
+            Some(current) => {
+                *current = Value::from(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Object) -> Result<(), RuntimeError> {
+        match self.set_value(name.lexeme, value) {
+            true => Ok(()),
+            false => Err(RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme))),
+        }
+    }
+
+    pub fn set_value(&mut self, name: Symbol, value: Object) -> bool {
+        if let Some(&slot) = self.slots.get(&name) {
+            self.values[slot] = Value::from(value);
+            return true;
+        }
+
+        match self.enclosing {
+            Some(ref enclosing) => enclosing.borrow_mut().set_value(name, value),
+            None => false,
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::libs::chunk::Constant;
+use crate::libs::compiler;
+use crate::libs::environment::Environment;
+use crate::libs::expr::ast::Object;
+use crate::libs::function::Function;
+use crate::libs::heap::Heap;
+use crate::libs::instance::BoxClass;
+use crate::libs::interpreter::RuntimeError;
+use crate::libs::lex::Lox;
+use crate::libs::loader::Loader;
+use crate::libs::resolver::Resolver;
+use crate::libs::symbol::{INIT, MESSAGE, STACK};
+
+// The name of the frame of the top level of a file.
+pub const SCRIPT: &str = "<script>";
+
+// A call an error came out of: the function, and the file and line the error was at in it.
+#[derive(Clone)]
+pub struct TraceFrame {
+    pub function: &'static str,
+    pub file: usize,
+    pub line: usize,
+}
+
+// The box runtime errors are caught as, which a program's exceptions may derive from. Its
+// initializer is compiled, so that both backends can run it.
+const ERROR: &str = "box Error { message; stack; init(message) { self.message = message; } }";
+
+thread_local! {
+    static ERROR_BOX: Rc<BoxClass> = declare();
+}
+
+fn declare() -> Rc<BoxClass> {
+    let statements = Lox::new(ERROR.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse the Error box"));
+    let locals = Resolver::new().resolve(&statements).unwrap_or_else(|_| panic!("Failed to resolve the Error box"));
+    let script = compiler::compile(&statements, &locals).unwrap_or_else(|_| panic!("Failed to compile the Error box"));
+    let Some(Constant::Box(prototype)) = script.chunk.constants.iter().find(|constant| matches!(constant, Constant::Box(_))) else {
+        panic!("The Error box compiles to a box");
+    };
+
+    let closure = Rc::new(RefCell::new(Environment::new()));
+    let methods = prototype
+        .methods
+        .iter()
+        .filter_map(|method| {
+            let name = method.name.as_ref()?.lexeme;
+            let function = Function::compiled(Rc::clone(method), Rc::clone(&closure), Vec::new(), name == *INIT).in_box(prototype.name);
+            Some((name, Rc::new(function)))
+        })
+        .collect();
+    Rc::new(BoxClass::new(prototype.name, None, prototype.fields.clone(), methods))
+}
+
+// The `Error` box, the same one in every module.
+pub fn error_box() -> Rc<BoxClass> {
+    ERROR_BOX.with(Rc::clone)
+}
+
+// What an uncaught throw of `value` reports: the message of an `Error`, or the value itself.
+pub fn message(value: &Object) -> String {
+    if let Object::Instance(instance) = value {
+        let instance = instance.borrow();
+        if instance.is_a(&error_box()) {
+            return instance.field(*MESSAGE).unwrap_or(Object::Nil).to_string();
+        }
+    }
+    value.to_string()
+}
+
+// What a `catch` gets for `error`: the value thrown, or an `Error` for an error the runtime raised.
+// An `Error` without a stack gets `frames`, the calls from where it was raised to the one catching
+// it.
+pub fn caught(error: RuntimeError, frames: &[TraceFrame], loader: &Loader, heap: &mut Heap) -> Object {
+    let stack = Object::Str(Rc::new(loader.trace(frames)));
+    match error.thrown.map(|value| *value) {
+        Some(Object::Instance(instance)) => {
+            let unset = instance.borrow().is_a(&error_box()) && matches!(instance.borrow().field(*STACK), Some(Object::Nil));
+            if unset {
+                instance.borrow_mut().put(*STACK, stack);
+            }
+            Object::Instance(instance)
+        }
+        Some(value) => value,
+        None => {
+            let exception = BoxClass::instantiate(&error_box(), heap);
+            if let Object::Instance(ref instance) = exception {
+                let mut instance = instance.borrow_mut();
+                instance.put(*MESSAGE, Object::Str(Rc::new(error.message)));
+                instance.put(*STACK, stack);
+            }
+            exception
+        }
+    }
+}
+
+// Prints an error nothing caught, with the calls it came out of. An exception thrown again after it
+// was caught keeps the stack from where it was first raised, which ends in the call that threw it
+// again.
+pub fn report(error: &RuntimeError, loader: &Loader) {
+    eprintln!("{error}");
+    let mut frames = error.trace.frames.as_slice();
+    if let Some(Object::Instance(instance)) = error.thrown.as_deref() {
+        if let Some(Object::Str(stack)) = instance.borrow().field(*STACK) {
+            stack.lines().for_each(|line| eprintln!("  {line}"));
+            frames = frames.get(1..).unwrap_or_default();
+        }
+    }
+    for line in loader.trace(frames).lines() {
+        eprintln!("  {line}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::testing::{run, run_both};
+
+    #[test]
+    fn runtime_errors_are_caught() {
+        let source = "
+            fun get(x) { return x.y; }
+            try { get(nil); } catch (e) { print e.message; }
+        ";
+        assert_eq!(run(source), "Only instances have properties.\n");
+    }
+
+    #[test]
+    fn finally_runs_on_return() {
+        let source = "
+            fun order() { try { return \"returned\"; } finally { print \"finally\"; } }
+            print order();
+        ";
+        assert_eq!(run(source), "finally\nreturned\n");
+    }
+
+    #[test]
+    fn errors_carry_fields_and_a_stack() {
+        let source = "
+            box Failure <- Error { code; init(message, code) { super.init(message); self.code = code; } }
+            fun deep(n) { if (n == 0) throw Failure(\"deep\", 7); deep(n - 1); }
+            try { deep(1); } catch (e) { print e.code; print e.stack; }
+        ";
+        assert_eq!(run(source), "7\nat deep (<input>:3)\nat deep (<input>:3)\nat <script> (<input>:4)\n");
+    }
+
+    #[test]
+    fn finally_runs_before_an_outer_catch() {
+        let source = "try { try { throw \"a\"; } finally { print \"cleanup\"; } } catch (e) { print e; }";
+        assert_eq!(run(source), "cleanup\na\n");
+    }
+
+    #[test]
+    fn throws_pass_through_native_callbacks() {
+        let source = "
+            fun check(n) { throw n; }
+            try { [1].map(check); } catch (e) { print e; }
+        ";
+        assert_eq!(run(source), "1\n");
+    }
+
+    #[test]
+    fn uncaught_throws_stop_the_program() {
+        let source = "
+            throw \"uncaught\";
+            print \"not reached\";
+        ";
+        assert_eq!(run_both(source), (false, String::new()));
+    }
+}
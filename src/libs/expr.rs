@@ -1,70 +1,78 @@
-//That file mostly created by "metaprogramming" package.
+//That file mostly created by "metaprogramming" package: ast.def holds the spec of the AST, `Object`,
+// the visitor traits and the fold trait, and only the impls below are written by hand.
 
-pub mod ast {
-    use crate::libs::lex::{Token};
+include!(concat!(env!("OUT_DIR"), "/expr.rs"));
 
-    pub enum Object {
-        Number(f64),
-        Str(String),
-        Bool(bool),
+use std::fmt::{self, Display};
+use ast::{Object, Pattern};
 
-        Nil,
-    }
-
-    impl ToString for Object {
-        fn to_string(&self) -> String {
-            match self {
-                Object::Number(n) => n.to_string(),
-                Object::Str(s) => s.clone(),
-                Object::Bool(b) => b.to_string(),
-                Object::Nil => "nil".to_string(),
+impl Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // With a decimal point or an exponent, so that floats don't look like integers.
+            Object::Float(n) => write!(f, "{n:?}"),
+            Object::Int(n) => write!(f, "{n}"),
+            Object::BigInt(n) => write!(f, "{n}"),
+            Object::Str(s) => write!(f, "{s}"),
+            Object::Bool(b) => write!(f, "{b}"),
+            Object::Function(function) => write!(f, "{function}"),
+            Object::NativeFunction(function) => write!(f, "{function}"),
+            Object::Box(box_class) => write!(f, "{}", box_class.name),
+            Object::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+            Object::List(list) => {
+                let elements: Vec<String> = list.borrow().iter().map(|element| element.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Object::Map(map) => {
+                let entries: Vec<String> = map.borrow().entries().iter().map(|(key, value)| format!("{key}: {value}")).collect();
+                write!(f, "{{{}}}", entries.join(", "))
             }
+            Object::Module(module) => write!(f, "{module}"),
+            Object::Range(range) => write!(f, "{range}"),
+            Object::Iterator(_) => write!(f, "<iterator>"),
+            Object::Generator(generator) => write!(f, "{}", generator.borrow()),
+            Object::Nil => write!(f, "nil"),
         }
     }
+}
 
-    pub enum Expr {
-        Binary {
-            left: Option<Box<Expr>>,
-            operator: Token,
-            right: Option<Box<Expr>>,
-        },
-        Grouping {
-            expression: Option<Box<Expr>>,
-        },
-        Literal {
-            value: Object,
-        },
-        Unary {
-            operator: Token,
-            right: Option<Box<Expr>>,
-        },
+impl Pattern {
+    // A pattern that matches every value, which makes a match with such an unguarded arm exhaustive.
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self, Pattern::Binding { .. } | Pattern::Wildcard { .. })
     }
 }
 
-pub mod visitor {
-    use super::ast::*;
+#[cfg(test)]
+mod test {
+    use crate::libs::ast_printer::AstPrinter;
+    use crate::libs::expr::ast::{Expr, Object};
+    use crate::libs::expr::fold::Fold;
+    use crate::libs::expr::visitor::StmtVisitor;
+    use crate::libs::lex::{Lox, Token};
+    use crate::libs::parser::Parser;
+
+    // Only overrides literals; everything around them is rebuilt by the default methods.
+    struct DoubleNumbers;
 
-    pub trait Visitor<T> {
-        fn visit_expr(&mut self, expr: &Option<Box<Expr>>) -> Option<T, > {
-            if let Some(box_) = expr {
-                match **box_ {
-                    Expr::Binary { .. } => Some(self.visit_binary(&box_)),
-                    Expr::Grouping { .. } => Some(self.visit_grouping(&box_)),
-                    Expr::Literal { .. } => Some(self.visit_literal(&box_)),
-                    Expr::Unary { .. } => Some(self.visit_unary(&box_)),
-                }
-            } else {
-                None
+    impl Fold for DoubleNumbers {
+        fn fold_literal(&mut self, token: Token, value: Object) -> Expr {
+            match value {
+                Object::Int(n) => Expr::Literal { token, value: Object::Int(n * 2) },
+                value => Expr::Literal { token, value },
             }
         }
+    }
 
-        fn visit_binary(&mut self, binary: &Expr) -> T;
-
-        fn visit_grouping(&mut self, grouping: &Expr) -> T;
-
-        fn visit_literal(&mut self, literal: &Expr) -> T;
-
-        fn visit_unary(&mut self, unary: &Expr) -> T;
+    #[test]
+    fn fold() {
+        let tokens = Lox::new("fun f(x) { return [x + 1, match x { y => 2 }]; }".to_string()).get_token_list();
+        let statements = Parser::new(tokens).parse().unwrap_or_else(|_| panic!("Failed to parse"));
+        let folded = DoubleNumbers.fold_stmts(statements);
+        let mut ast_printer = AstPrinter {};
+        assert_eq!(
+            ast_printer.visit_stmt(&folded[0]),
+            "(fun f (x) (return (list (+ x 2) (match x (arm y 4)))))"
+        );
     }
 }
-
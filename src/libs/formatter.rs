@@ -0,0 +1,801 @@
+use std::fmt::{self, Display};
+use std::rc::Rc;
+use crate::libs::ast_printer::AstPrinter;
+use crate::libs::expr::ast::{Expr, MatchArm, MethodSig, Object, Pattern, Stmt, TypeExpr, TypedName};
+use crate::libs::lex::{Comment, Lox, Token, TokenType};
+use crate::libs::serializer::{self, Tree};
+use crate::libs::span::{self, Span};
+
+// The canonical layout of a source file. Statements are printed from the tree, so spacing and
+// indentation come out the same whatever the input looked like, and groupings are dropped unless
+// the precedence rules need them. Comments are not in the tree: they are put back between the
+// statements they were found between, or after the statement they trail. A comment written inside
+// a statement stays next to the token it was written beside.
+
+const WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+const ASSIGNMENT: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const EQUALITY: u8 = 4;
+const COMPARISON: u8 = 5;
+const RANGE: u8 = 6;
+const TERM: u8 = 7;
+const FACTOR: u8 = 8;
+const UNARY: u8 = 9;
+const CALL: u8 = 10;
+const PRIMARY: u8 = 11;
+
+pub struct FormatError {
+    pub message: String,
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to format: {}", self.message)
+    }
+}
+
+// Syntax errors are reported by the parser as usual. The result is parsed again and refused if it
+// doesn't give the same tree, so formatting never changes what a program does.
+pub fn format(source: &str) -> Result<String, FormatError> {
+    let statements = Lox::new(source.to_string())
+        .parse()
+        .ok_or_else(|| FormatError { message: "the file has errors.".to_string() })?;
+
+    let mut lox = Lox::new(source.to_string());
+    let tokens = lox.get_token_list();
+    let mut formatter = Formatter::new(&tokens, lox.comments());
+    let formatted = formatter.program(&statements);
+    let inner: Vec<&Comment> = formatter.inner.iter().map(|index| &lox.comments()[*index]).collect();
+    let formatted = attach(formatted, &tokens, &inner);
+
+    let same = Lox::new(formatted.clone())
+        .parse()
+        .is_some_and(|reparsed| shape(serializer::serialize(&reparsed)) == shape(serializer::serialize(&statements)));
+    if !same {
+        return Err(FormatError { message: "the formatted program would differ from the original.".to_string() });
+    }
+    Ok(formatted)
+}
+
+// The parts of a tree formatting must keep: no positions and no groupings.
+fn shape(tree: Tree) -> Tree {
+    match tree {
+        Tree::List(elements) => Tree::List(elements.into_iter().map(shape).collect()),
+        Tree::Node(kind, fields) if kind == "Grouping" => {
+            let inner = fields.into_iter().find(|(name, _)| name == "expression");
+            inner.map_or(Tree::Nil, |(_, expression)| shape(expression))
+        }
+        Tree::Node(kind, fields) => {
+            let fields = fields
+                .into_iter()
+                .filter(|(name, _)| name != "span" && !(kind == "Token" && (name == "line" || name == "column")))
+                .map(|(name, value)| (name, shape(value)))
+                .collect();
+            Tree::Node(kind, fields)
+        }
+        other => other,
+    }
+}
+
+type Position = (usize, usize);
+
+struct PendingComment {
+    text: String,
+    start: Position,
+    end_line: usize,
+    // Some code comes before it on its line.
+    trailing: bool,
+}
+
+// What a box body or a block holds.
+enum Item<'a> {
+    Stmt(&'a Stmt),
+    Field(&'a TypedName),
+    Method(&'a Stmt),
+    Signature(&'a MethodSig),
+}
+
+struct Formatter {
+    // Where each token starts, the line it ends on and whether it can open a statement.
+    tokens: Vec<(Position, usize, bool)>,
+    comments: Vec<PendingComment>,
+    next_comment: usize,
+    // The comments found inside a statement rather than between statements, left for `attach`.
+    inner: Vec<usize>,
+    // Where each '{' and the '}' closing it are, in the order of the '{'s.
+    bodies: Vec<(Position, Position)>,
+    next_body: usize,
+    indent: usize,
+    // The last source line printed so far.
+    last_line: usize,
+}
+
+impl Formatter {
+    fn new(tokens: &[Token], comments: &[Comment]) -> Self {
+        let mut bodies = Vec::new();
+        let mut open = Vec::new();
+        for token in tokens {
+            match token.token_type {
+                TokenType::LEFT_BRACE => {
+                    open.push(bodies.len());
+                    bodies.push(((token.line, token.column), (token.line, token.column)));
+                }
+                TokenType::RIGHT_BRACE => {
+                    if let Some(index) = open.pop() {
+                        bodies[index].1 = (token.line, token.column);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let comments = comments
+            .iter()
+            .map(|comment| PendingComment {
+                text: comment.text.clone(),
+                start: (comment.line, comment.column),
+                end_line: comment.line + comment.text.matches('\n').count(),
+                trailing: tokens.iter().any(|token| token.line == comment.line && token.column < comment.column),
+            })
+            .collect();
+
+        let tokens = tokens
+            .iter()
+            .filter(|token| !matches!(token.token_type, TokenType::EOF))
+            .map(|token| {
+                let opener = matches!(
+                    token.token_type,
+                    TokenType::LET | TokenType::PRINT | TokenType::IF | TokenType::WHILE | TokenType::FUN
+                        | TokenType::BOX | TokenType::INTERFACE | TokenType::LEFT_PAREN | TokenType::LEFT_BRACE
+                );
+                ((token.line, token.column), Span::of(token).end_line, opener)
+            })
+            .collect();
+
+        Self { tokens, comments, next_comment: 0, inner: Vec::new(), bodies, next_body: 0, indent: 0, last_line: 0 }
+    }
+
+    fn program(&mut self, statements: &[Stmt]) -> String {
+        let items: Vec<Item> = statements.iter().map(Item::Stmt).collect();
+        let text = self.items(&items, None, None);
+        if text.is_empty() {
+            text
+        } else {
+            text + "\n"
+        }
+    }
+
+    fn pad(&self) -> String {
+        INDENT.repeat(self.indent)
+    }
+
+    // The items of a body, one per line and indented, each followed by the rest of its lines.
+    // Comments before `close` that are still pending end the list.
+    fn items(&mut self, items: &[Item], open: Option<Position>, close: Option<Position>) -> String {
+        // Comments still pending before the '{' are inside the code that leads up to it.
+        if let Some(open) = open {
+            self.skip_inner(open);
+        }
+
+        // A span leaves out the keyword or bracket a statement starts with, so its first token is
+        // looked for before the span, without going back into the item before it.
+        let mut limit = open.map(|(line, column)| (line, column + 1));
+        let mut starts = Vec::new();
+        for item in items {
+            let span = match item {
+                Item::Stmt(stmt) | Item::Method(stmt) => span::stmt_span(stmt),
+                Item::Field(field) => {
+                    let annotation = field.annotation.as_ref().and_then(span::type_span);
+                    Some(annotation.map_or(Span::of(&field.name), |annotation| Span::of(&field.name).to(annotation)))
+                }
+                Item::Signature(method) => {
+                    let last = method.result.as_ref().and_then(span::type_span).or_else(|| {
+                        let param = method.params.last()?;
+                        Some(param.annotation.as_ref().and_then(span::type_span).unwrap_or(Span::of(&param.name)))
+                    });
+                    Some(last.map_or(Span::of(&method.name), |last| Span::of(&method.name).to(last)))
+                }
+            };
+            starts.push(span.map(|span| self.first_token(span, limit)));
+            if let Some(span) = span {
+                limit = Some((span.end_line, span.end_column));
+            }
+        }
+
+        let mut lines = Vec::new();
+        for (index, item) in items.iter().enumerate() {
+            // Comments before the item come first; the ones inside it are placed while it's printed.
+            if let Some(start) = starts[index] {
+                while let Some(comment) = self.comments.get(self.next_comment) {
+                    if comment.start >= start {
+                        break;
+                    }
+                    self.comment_line(&mut lines, self.next_comment);
+                    self.next_comment += 1;
+                }
+                self.separate(&mut lines, start.0);
+            }
+
+            let text = match item {
+                Item::Stmt(stmt) => self.stmt(stmt),
+                Item::Field(field) => format!("{};", typed_name(field)),
+                Item::Method(stmt) => self.method(stmt),
+                Item::Signature(method) => {
+                    format!("{}({}){};", method.name.lexeme, names(&method.params), returns(&method.result))
+                }
+            };
+            lines.push(format!("{}{text}", self.pad()));
+
+            // The item ends with the last token before whatever comes next, so its ';' or '}' counts.
+            // Comments before that token that no body inside the item took are between its tokens.
+            let bound = starts[index + 1..].iter().find_map(|start| *start).or(close);
+            if let Some((last, end_line)) = self.last_token(bound) {
+                self.skip_inner(last);
+                self.last_line = self.last_line.max(end_line);
+            }
+            while let Some(comment) = self.comments.get(self.next_comment) {
+                let before_bound = bound.is_none_or(|bound| comment.start < bound);
+                if !comment.trailing || comment.start.0 != self.last_line || !before_bound {
+                    break;
+                }
+                let last = lines.len() - 1;
+                lines[last] = format!("{} {}", lines[last], comment.text);
+                self.last_line = comment.end_line;
+                self.next_comment += 1;
+            }
+        }
+
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if close.is_some_and(|close| comment.start >= close) {
+                break;
+            }
+            self.comment_line(&mut lines, self.next_comment);
+            self.next_comment += 1;
+        }
+        lines.join("\n")
+    }
+
+    // Where the last token before `bound` starts and the line it ends on.
+    fn last_token(&self, bound: Option<Position>) -> Option<(Position, usize)> {
+        let count = match bound {
+            Some(bound) => self.tokens.partition_point(|(start, _, _)| *start < bound),
+            None => self.tokens.len(),
+        };
+        let (start, end_line, _) = self.tokens[count.checked_sub(1)?];
+        Some((start, end_line))
+    }
+
+    fn skip_inner(&mut self, before: Position) {
+        while self.comments.get(self.next_comment).is_some_and(|comment| comment.start < before) {
+            self.inner.push(self.next_comment);
+            self.next_comment += 1;
+        }
+    }
+
+    fn first_token(&self, span: Span, limit: Option<Position>) -> Position {
+        let mut index = self.tokens.partition_point(|(start, _, _)| *start < (span.line, span.column));
+        while index > 0 {
+            let (position, _, opener) = self.tokens[index - 1];
+            if !opener || limit.is_some_and(|limit| position < limit) {
+                break;
+            }
+            index -= 1;
+        }
+        self.tokens.get(index).map_or((span.line, span.column), |(position, _, _)| *position)
+    }
+
+    fn comment_line(&mut self, lines: &mut Vec<String>, index: usize) {
+        let (line, text, end_line) = {
+            let comment = &self.comments[index];
+            (comment.start.0, comment.text.clone(), comment.end_line)
+        };
+        self.separate(lines, line);
+        lines.push(format!("{}{text}", self.pad()));
+        self.last_line = self.last_line.max(end_line);
+    }
+
+    // Blank lines between items are kept, but never more than one.
+    fn separate(&self, lines: &mut Vec<String>, line: usize) {
+        if !lines.is_empty() && line > self.last_line + 1 {
+            lines.push(String::new());
+        }
+    }
+
+    // A braced body, which matches the next '{' of the source.
+    fn body(&mut self, items: &[Item]) -> String {
+        let (open, close) = self.bodies.get(self.next_body).copied().unzip();
+        self.next_body += 1;
+
+        self.indent += 1;
+        let inner = self.items(items, open, close);
+        self.indent -= 1;
+
+        if inner.is_empty() {
+            "{}".to_string()
+        } else {
+            format!("{{\n{inner}\n{}}}", self.pad())
+        }
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) -> String {
+        let items: Vec<Item> = statements.iter().map(Item::Stmt).collect();
+        self.body(&items)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Block { statements } => self.statements(statements),
+            Stmt::Box { name, superclass, fields, methods } => {
+                let mut head = format!("box {}", name.lexeme);
+                if let Some(superclass) = superclass {
+                    head.push_str(&format!(" <- {}", self.expr(superclass, PRIMARY)));
+                }
+                let mut items: Vec<Item> = fields.iter().map(Item::Field).collect();
+                items.extend(methods.iter().map(Item::Method));
+                format!("{head} {}", self.body(&items))
+            }
+            Stmt::Expression { expression } => match expression.as_deref() {
+                Some(Expr::Assign { name, value }) => self.line(&format!("{} = ", name.lexeme), value, ";"),
+                _ => self.line("", expression, ";"),
+            },
+            Stmt::For { name, iterable, body, .. } => {
+                format!("for {} in {}{}", name.lexeme, self.operand(iterable, ASSIGNMENT), self.branch(body.as_deref()))
+            }
+            Stmt::Function { .. } => format!("fun {}", self.method(stmt)),
+            Stmt::If { condition, then_branch, else_branch } => {
+                let mut text = format!("if ({}){}", self.operand(condition, ASSIGNMENT), self.branch(then_branch.as_deref()));
+                if let Some(else_branch) = else_branch {
+                    let braced = matches!(then_branch.as_deref(), Some(Stmt::Block { .. }));
+                    text.push_str(&if braced { " else".to_string() } else { format!("\n{}else", self.pad()) });
+                    match else_branch.as_ref() {
+                        Stmt::If { .. } => text.push_str(&format!(" {}", self.stmt(else_branch))),
+                        _ => text.push_str(&self.branch(Some(else_branch))),
+                    }
+                }
+                text
+            }
+            Stmt::Import { keyword, path, names } => format!("{};", AstPrinter::import(keyword, path, names)),
+            Stmt::Interface { name, methods } => {
+                let items: Vec<Item> = methods.iter().map(Item::Signature).collect();
+                format!("interface {} {}", name.lexeme, self.body(&items))
+            }
+            Stmt::Let { name, annotation, initializer } => {
+                let name = annotated(name, annotation);
+                match initializer {
+                    Some(_) => self.line(&format!("let {name} = "), initializer, ";"),
+                    None => format!("let {name};"),
+                }
+            }
+            Stmt::Print { expression } => self.line("print ", expression, ";"),
+            Stmt::Return { value, .. } => match value {
+                Some(_) => self.line("return ", value, ";"),
+                None => "return;".to_string(),
+            },
+            Stmt::Throw { value, .. } => self.line("throw ", value, ";"),
+            Stmt::Try { body, name, handler, finally, .. } => {
+                let mut text = format!("try{}", self.branch(body.as_deref()));
+                if let Some(name) = name {
+                    text.push_str(&format!(" catch ({}){}", name.lexeme, self.branch(handler.as_deref())));
+                }
+                if finally.is_some() {
+                    text.push_str(&format!(" finally{}", self.branch(finally.as_deref())));
+                }
+                text
+            }
+            Stmt::While { condition, body } => {
+                format!("while ({}){}", self.operand(condition, ASSIGNMENT), self.branch(body.as_deref()))
+            }
+        }
+    }
+
+    // A function without the "fun", as it is written in a box.
+    fn method(&mut self, stmt: &Stmt) -> String {
+        let Stmt::Function { name, type_params, params, result, body } = stmt else { return self.stmt(stmt) };
+        let type_params: Vec<&str> = type_params.iter().map(|param| param.lexeme.as_str()).collect();
+        let type_params = if type_params.is_empty() { String::new() } else { format!("<{}>", type_params.join(", ")) };
+        format!("{}{type_params}({}){} {}", name.lexeme, names(params), returns(result), self.statements(body))
+    }
+
+    // A branch or loop body: a block stays on the line, anything else goes on the next one.
+    fn branch(&mut self, stmt: Option<&Stmt>) -> String {
+        match stmt {
+            Some(stmt @ Stmt::Block { .. }) => format!(" {}", self.stmt(stmt)),
+            Some(stmt) => {
+                self.indent += 1;
+                let text = format!("\n{}{}", self.pad(), self.stmt(stmt));
+                self.indent -= 1;
+                text
+            }
+            None => String::new(),
+        }
+    }
+
+    // A statement made of `prefix`, an expression and `suffix`. A '+' chain that is too long for the
+    // line is wrapped after the operators, with the rest indented once more.
+    fn line(&mut self, prefix: &str, expr: &Option<Box<Expr>>, suffix: &str) -> String {
+        let Some(expr) = expr.as_deref() else { return format!("{prefix}{suffix}") };
+        let operands = self.chain(expr, ASSIGNMENT);
+        let flat = operands.join(" + ");
+        let column = self.indent * INDENT.len() + prefix.len();
+        if operands.len() < 2 || column + flat.len() + suffix.len() <= WIDTH || flat.contains('\n') {
+            return format!("{prefix}{flat}{suffix}");
+        }
+
+        let pad = INDENT.repeat(self.indent + 1);
+        let mut lines = Vec::new();
+        let mut line = operands[0].clone();
+        let mut width = column + line.len();
+        for operand in &operands[1..] {
+            if width + 3 + operand.len() > WIDTH {
+                lines.push(format!("{line} +"));
+                line = format!("{pad}{operand}");
+                width = line.len();
+            } else {
+                line.push_str(&format!(" + {operand}"));
+                width += 3 + operand.len();
+            }
+        }
+        lines.push(line);
+        format!("{prefix}{}{suffix}", lines.join("\n"))
+    }
+
+    // The operands of a '+' chain, which is nested to the left; any other expression is one operand.
+    fn chain(&mut self, expr: &Expr, min: u8) -> Vec<String> {
+        match expr {
+            Expr::Grouping { expression: Some(expression) } => self.chain(expression, min),
+            Expr::Binary { left: Some(left), operator, right } if matches!(operator.token_type, TokenType::PLUS) && min <= TERM => {
+                let mut operands = self.chain(left, TERM);
+                operands.push(self.operand(right, TERM + 1));
+                operands
+            }
+            _ => vec![self.expr(expr, min)],
+        }
+    }
+
+    fn operand(&mut self, expr: &Option<Box<Expr>>, min: u8) -> String {
+        expr.as_deref().map_or(String::new(), |expr| self.expr(expr, min))
+    }
+
+    fn operands(&mut self, exprs: &[Expr]) -> String {
+        exprs.iter().map(|expr| self.expr(expr, ASSIGNMENT)).collect::<Vec<_>>().join(", ")
+    }
+
+    // Parentheses are added when the expression binds looser than `min`.
+    fn expr(&mut self, expr: &Expr, min: u8) -> String {
+        let text = match expr {
+            Expr::Grouping { expression: Some(expression) } => return self.expr(expression, min),
+            Expr::Grouping { expression: None } => "()".to_string(),
+            Expr::Assign { name, value } => format!("{} = {}", name.lexeme, self.operand(value, ASSIGNMENT)),
+            // Written tight, and with neither side a range since ranges don't chain.
+            Expr::Binary { left, operator, right } if precedence(expr) == RANGE => {
+                format!("{}{}{}", self.operand(left, RANGE + 1), operator.lexeme, self.operand(right, RANGE + 1))
+            }
+            Expr::Binary { left, operator, right } | Expr::Logical { left, operator, right } => {
+                let precedence = precedence(expr);
+                let left = self.operand(left, precedence);
+                format!("{left} {} {}", operator.lexeme, self.operand(right, precedence + 1))
+            }
+            Expr::Call { callee, arguments, .. } => {
+                let callee = self.operand(callee, CALL);
+                format!("{callee}({})", self.operands(arguments))
+            }
+            Expr::Get { object, name } => format!("{}.{}", self.operand(object, CALL), name.lexeme),
+            Expr::Index { object, index, .. } => {
+                let object = self.operand(object, CALL);
+                format!("{object}[{}]", self.operand(index, ASSIGNMENT))
+            }
+            Expr::IndexSet { object, index, value, .. } => {
+                let object = self.operand(object, CALL);
+                let index = self.operand(index, ASSIGNMENT);
+                format!("{object}[{index}] = {}", self.operand(value, ASSIGNMENT))
+            }
+            Expr::Lambda { keyword, params, result, body } => self.lambda(keyword, params, result, body),
+            Expr::List { elements, .. } => format!("[{}]", self.operands(elements)),
+            Expr::Literal { token, .. } => token.lexeme.to_string(),
+            Expr::Match { subject, arms, .. } => self.match_(subject, arms),
+            Expr::Set { object, name, value } => {
+                let object = self.operand(object, CALL);
+                format!("{object}.{} = {}", name.lexeme, self.operand(value, ASSIGNMENT))
+            }
+            Expr::SelfRef { .. } => "self".to_string(),
+            // The bare "super(...)" form gets a made-up "init" token at the keyword's position.
+            Expr::Super { keyword, method } if (method.line, method.column) == (keyword.line, keyword.column) => {
+                "super".to_string()
+            }
+            Expr::Super { method, .. } => format!("super.{}", method.lexeme),
+            Expr::Unary { operator, right } => format!("{}{}", operator.lexeme, self.operand(right, UNARY)),
+            Expr::Variable { name } => name.lexeme.to_string(),
+            Expr::Yield { value: None, .. } => "yield".to_string(),
+            Expr::Yield { value, .. } => format!("yield {}", self.operand(value, ASSIGNMENT)),
+        };
+
+        if precedence(expr) < min {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+
+    fn lambda(&mut self, keyword: &Token, params: &[TypedName], result: &Option<TypeExpr>, body: &Rc<Vec<Stmt>>) -> String {
+        if let TokenType::ARROW = keyword.token_type {
+            if let [Stmt::Return { value, .. }] = body.as_slice() {
+                return format!("({}) -> {}", names(params), self.operand(value, ASSIGNMENT));
+            }
+        }
+        format!("fun ({}){} {}", names(params), returns(result), self.statements(body))
+    }
+
+    fn match_(&mut self, subject: &Option<Box<Expr>>, arms: &[MatchArm]) -> String {
+        let subject = self.operand(subject, ASSIGNMENT);
+        self.next_body += 1;
+        if arms.is_empty() {
+            return format!("match {subject} {{}}");
+        }
+
+        self.indent += 1;
+        let mut lines = Vec::new();
+        for arm in arms {
+            let mut line = format!("{}{}", self.pad(), self.pattern(&arm.pattern));
+            if let Some(guard) = &arm.guard {
+                line.push_str(&format!(" if {}", self.expr(guard, ASSIGNMENT)));
+            }
+            line.push_str(&format!(" => {},", self.operand(&arm.body, ASSIGNMENT)));
+            lines.push(line);
+        }
+        self.indent -= 1;
+        format!("match {subject} {{\n{}\n{}}}", lines.join("\n"), self.pad())
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Literal { token, value: Object::Float(n) } if n.is_sign_negative() => format!("-{}", token.lexeme),
+            Pattern::Literal { token, value: Object::Int(n) } if *n < 0 => format!("-{}", token.lexeme),
+            Pattern::Literal { token, value: Object::BigInt(n) } if n.is_negative() => format!("-{}", token.lexeme),
+            Pattern::Literal { token, .. } => token.lexeme.to_string(),
+            Pattern::Binding { name } => name.lexeme.to_string(),
+            Pattern::Wildcard { .. } => "_".to_string(),
+            Pattern::List { elements, rest, .. } => {
+                let mut parts: Vec<String> = elements.iter().map(|element| self.pattern(element)).collect();
+                match rest.as_deref() {
+                    Some(Pattern::Binding { name }) => parts.push(format!("..{}", name.lexeme)),
+                    Some(_) => parts.push("..".to_string()),
+                    None => (),
+                }
+                format!("[{}]", parts.join(", "))
+            }
+            Pattern::Box { name, fields } => {
+                self.next_body += 1;
+                if fields.is_empty() {
+                    return format!("{} {{}}", name.lexeme);
+                }
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, pattern)| match pattern {
+                        Pattern::Binding { name } if name.lexeme == field.lexeme => field.lexeme.to_string(),
+                        _ => format!("{}: {}", field.lexeme, self.pattern(pattern)),
+                    })
+                    .collect();
+                format!("{} {{ {} }}", name.lexeme, fields.join(", "))
+            }
+        }
+    }
+}
+
+// Puts each comment from inside a statement next to the same token in the formatted text: after
+// the token before it when it was written on that token's line, else before the token after it. A
+// '//' comment ends its line, so whatever followed on the line moves to the next one.
+fn attach(formatted: String, tokens: &[Token], comments: &[&Comment]) -> String {
+    if comments.is_empty() {
+        return formatted;
+    }
+    let placed = Lox::new(formatted.clone()).get_token_list();
+    let mapping = align(tokens, &placed);
+    let line_starts: Vec<usize> =
+        std::iter::once(0).chain(formatted.match_indices('\n').map(|(index, _)| index + 1)).collect();
+    let offset = |token: &Token| {
+        let line = &formatted[line_starts[token.line - 1]..];
+        line_starts[token.line - 1] + line.char_indices().nth(token.column - 1).map_or(line.len(), |(index, _)| index)
+    };
+
+    let mut edits = Vec::new();
+    for comment in comments {
+        let position = (comment.line, comment.column);
+        let after = tokens.partition_point(|token| (token.line, token.column) < position);
+        let before = after.checked_sub(1).filter(|before| Span::of(&tokens[*before]).end_line == comment.line);
+        let edit = match (before.and_then(|before| mapping[before]), mapping.get(after).copied().flatten()) {
+            (Some(before), _) => (offset(&placed[before]) + placed[before].lexeme.len(), true),
+            (None, Some(after)) => (offset(&placed[after]), false),
+            (None, None) => (formatted.len(), true),
+        };
+        edits.push((edit, comment.text.as_str()));
+    }
+
+    // From the end, so the offsets still to use stay right; comments at one place keep their order.
+    let mut text = formatted;
+    for ((mut offset, trailing), comment) in edits.into_iter().rev() {
+        let line_comment = comment.starts_with("//");
+        // Closing punctuation is not worth a line of its own, so a '//' comment goes after it.
+        if trailing && line_comment && rest_of_line(&text, offset).chars().all(|c| ",;)]".contains(c)) {
+            offset += rest_of_line(&text, offset).len();
+        }
+        let line_start = text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+        let pad: String = text[line_start..].chars().take_while(|c| *c == ' ').collect();
+        // What follows a '}' on its line, like an "else", lines up with it; anything else is indented once more.
+        let next_pad = if text[..offset].ends_with('}') { pad.clone() } else { format!("{pad}{INDENT}") };
+        let break_line = line_comment && !rest_of_line(&text, offset).trim().is_empty();
+
+        let insert = if !trailing && text[line_start..offset].trim().is_empty() {
+            format!("{comment}\n{pad}")
+        } else if trailing && break_line {
+            format!(" {comment}\n{next_pad}")
+        } else if trailing {
+            format!(" {comment}")
+        } else if break_line {
+            format!("{comment}\n{next_pad}")
+        } else {
+            format!("{comment} ")
+        };
+        let spaces = text[offset..].len() - text[offset..].trim_start_matches(' ').len();
+        let end = if trailing && break_line { offset + spaces } else { offset };
+        text.replace_range(offset..end, &insert);
+    }
+    text
+}
+
+fn rest_of_line(text: &str, offset: usize) -> &str {
+    text[offset..].split('\n').next().unwrap_or("")
+}
+
+// Pairs each source token with the same token in the formatted text, if it is still there. The
+// formatter only drops or adds parentheses, commas and a spelled-out "self", so on a mismatch the
+// side whose next token matches again is skipped.
+fn align(from: &[Token], to: &[Token]) -> Vec<Option<usize>> {
+    let mut mapping = vec![None; from.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < from.len() && j < to.len() {
+        if from[i].lexeme == to[j].lexeme {
+            mapping[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if from.get(i + 1).is_some_and(|next| next.lexeme == to[j].lexeme) {
+            i += 1;
+        } else if to.get(j + 1).is_some_and(|next| next.lexeme == from[i].lexeme) {
+            j += 1;
+        } else {
+            i += 1;
+        }
+    }
+    mapping
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Assign { .. } | Expr::Set { .. } | Expr::IndexSet { .. } | Expr::Yield { .. } => ASSIGNMENT,
+        // An arrow lambda's body takes everything after it.
+        Expr::Lambda { keyword, .. } if matches!(keyword.token_type, TokenType::ARROW) => ASSIGNMENT,
+        Expr::Logical { operator, .. } if matches!(operator.token_type, TokenType::OR) => OR,
+        Expr::Logical { .. } => AND,
+        Expr::Binary { operator, .. } => match operator.token_type {
+            TokenType::BANG_EQUAL | TokenType::EQUAL_EQUAL => EQUALITY,
+            TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL => COMPARISON,
+            TokenType::DOT_DOT | TokenType::DOT_DOT_EQUAL => RANGE,
+            TokenType::MINUS | TokenType::PLUS => TERM,
+            _ => FACTOR,
+        },
+        Expr::Unary { .. } => UNARY,
+        Expr::Call { .. } | Expr::Get { .. } | Expr::Index { .. } => CALL,
+        Expr::Grouping { expression: Some(expression) } => precedence(expression),
+        _ => PRIMARY,
+    }
+}
+
+fn names(names: &[TypedName]) -> String {
+    names.iter().map(typed_name).collect::<Vec<_>>().join(", ")
+}
+
+fn type_expr(type_: &TypeExpr) -> String {
+    if type_.arguments.is_empty() {
+        return type_.name.lexeme.to_string();
+    }
+    let arguments: Vec<String> = type_.arguments.iter().map(type_expr).collect();
+    format!("{}<{}>", type_.name.lexeme, arguments.join(", "))
+}
+
+fn typed_name(typed: &TypedName) -> String {
+    annotated(&typed.name, &typed.annotation)
+}
+
+fn annotated(name: &Token, annotation: &Option<TypeExpr>) -> String {
+    match annotation {
+        Some(annotation) => format!("{}: {}", name.lexeme, type_expr(annotation)),
+        None => name.lexeme.to_string(),
+    }
+}
+
+fn returns(result: &Option<TypeExpr>) -> String {
+    result.as_ref().map_or(String::new(), |result| format!(" -> {}", type_expr(result)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::formatter::format;
+
+    fn formatted(source: &str) -> String {
+        format(source).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    #[test]
+    fn layout() {
+        let source = "// Greets.\nbox Greeter <- Base { name :String; greet( self ,who:String)->Nil { print (self.name) + ((\", \" + who)); } }\n\n\n\
+                      let x=(1+2)*-(3);   // trailing\nif (x>1) print x; else { x=x-(1-2); }\n/* done */\n";
+        let expected = "// Greets.
+box Greeter <- Base {
+    name: String;
+    greet(who: String) -> Nil {
+        print self.name + (\", \" + who);
+    }
+}
+
+let x = (1 + 2) * -3; // trailing
+if (x > 1)
+    print x;
+else {
+    x = x - (1 - 2);
+}
+/* done */
+";
+        assert_eq!(formatted(source), expected);
+        assert_eq!(formatted(expected), expected);
+    }
+
+    #[test]
+    fn wrapping() {
+        let source = format!("fun f() {{ print {}; }}", ["\"a long piece of text\""; 6].join(" + "));
+        let expected = "fun f() {
+    print \"a long piece of text\" + \"a long piece of text\" + \"a long piece of text\" +
+        \"a long piece of text\" + \"a long piece of text\" + \"a long piece of text\";
+}
+";
+        assert_eq!(formatted(&source), expected);
+        assert_eq!(formatted(expected), expected);
+    }
+
+    #[test]
+    fn comments_stay_by_their_tokens() {
+        let source = "fun f(a, // first\n      b) {\n    return a;\n}\nlet xs = [1, // one\n  2];\nlet y = 1 /* inline */ + 2;\n";
+        let expected = "fun f(a, // first
+    b) {
+    return a;
+}
+let xs = [1, // one
+    2];
+let y = 1 /* inline */ + 2;
+";
+        assert_eq!(formatted(source), expected);
+        assert_eq!(formatted(expected), expected);
+    }
+
+    #[test]
+    fn comments_in_match_arms() {
+        let source = "print match 1 {\n    1 => \"a\", // one\n    _ => \"b\" // other\n};\n";
+        let expected = "print match 1 {
+    1 => \"a\", // one
+    _ => \"b\", // other
+};
+";
+        assert_eq!(formatted(source), expected);
+    }
+
+    #[test]
+    fn main_file() {
+        let source = include_str!("../../main.slsf");
+        let once = formatted(source);
+        assert_eq!(formatted(&once), once);
+        for comment in ["// This is a comment", "/* This also is a\n   comment */"] {
+            assert!(once.contains(comment), "{comment:?} was lost");
+        }
+    }
+}
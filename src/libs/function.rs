@@ -0,0 +1,179 @@
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::mem;
+use std::rc::Rc;
+use crate::libs::chunk::Prototype;
+use crate::libs::environment::Environment;
+use crate::libs::expr::ast::{Object, Stmt};
+use crate::libs::heap::Heap;
+use crate::libs::interpreter::{Interpreter, RuntimeError, Unwind};
+use crate::libs::lex::Token;
+use crate::libs::symbol::{Symbol, SELF};
+use crate::libs::value::Value;
+
+// A variable compiled functions share with the closures made in them, which is empty until its
+// definition runs.
+pub type Upvalue = Rc<RefCell<Option<Value>>>;
+
+// What runs when a function is called: statements for the tree-walker, or code compiled for the VM.
+#[derive(Clone)]
+pub enum Body {
+    Tree(Rc<Vec<Stmt>>),
+    Compiled(Rc<Prototype>),
+}
+
+// Both named declarations and lambdas end up here; lambdas just have no name.
+pub struct Function {
+    pub name: Option<Token>,
+    params: Vec<Token>,
+    body: Body,
+    closure: Rc<RefCell<Environment>>,
+    // The variables of the enclosing compiled functions the body uses.
+    upvalues: Vec<Upvalue>,
+    // The globals of the file the function was written in, which its body sees wherever it is called.
+    globals: Rc<RefCell<Environment>>,
+    // The box a method was declared in, for stack traces.
+    owner: Option<Symbol>,
+    is_initializer: bool,
+}
+
+impl Function {
+    pub fn new(
+        name: Option<Token>,
+        params: Vec<Token>,
+        body: Rc<Vec<Stmt>>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            body: Body::Tree(body),
+            globals: Environment::root(&closure),
+            closure,
+            upvalues: Vec::new(),
+            owner: None,
+            is_initializer,
+        }
+    }
+
+    pub fn compiled(
+        prototype: Rc<Prototype>,
+        closure: Rc<RefCell<Environment>>,
+        upvalues: Vec<Upvalue>,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            name: prototype.name.clone(),
+            params: prototype.params.clone(),
+            body: Body::Compiled(prototype),
+            globals: Environment::root(&closure),
+            closure,
+            upvalues,
+            owner: None,
+            is_initializer,
+        }
+    }
+
+    // Makes the function a method of the box `owner`.
+    pub fn in_box(mut self, owner: Symbol) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    // The name of the function in stack traces, like `Box.method`.
+    pub(crate) fn trace_name(&self) -> &'static str {
+        match (self.owner, &self.name) {
+            (Some(owner), Some(name)) => Symbol::intern(&format!("{owner}.{}", name.lexeme)).as_str(),
+            (None, Some(name)) => name.lexeme.as_str(),
+            (_, None) => "<fn>",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn params(&self) -> &[Token] {
+        &self.params
+    }
+
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    pub fn closure(&self) -> &Rc<RefCell<Environment>> {
+        &self.closure
+    }
+
+    pub fn upvalues(&self) -> &[Upvalue] {
+        &self.upvalues
+    }
+
+    pub fn globals(&self) -> &Rc<RefCell<Environment>> {
+        &self.globals
+    }
+
+    // The value a call returns in place of what the body returned: "self" for an initializer.
+    pub fn initialized(&self) -> Option<Object> {
+        match self.is_initializer {
+            true => Some(Environment::get_at(&self.closure, 0, *SELF).unwrap_or(Object::Nil)),
+            false => None,
+        }
+    }
+
+    // Returns a copy of the method whose closure has "self" bound to the instance.
+    pub fn bind(&self, instance: Object, heap: &mut Heap) -> Rc<Function> {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define(*SELF, instance);
+
+        let closure = heap.environment(environment);
+        heap.function(Function {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: self.body.clone(),
+            closure,
+            upvalues: self.upvalues.clone(),
+            globals: Rc::clone(&self.globals),
+            owner: self.owner,
+            is_initializer: self.is_initializer,
+        })
+    }
+
+    pub fn call(self: &Rc<Self>, interpreter: &mut Interpreter, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+        let Body::Tree(ref body) = self.body else {
+            return interpreter.run_compiled(self, arguments);
+        };
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, argument) in self.params.iter().zip(arguments) {
+            environment.define(param.lexeme, argument);
+        }
+
+        let environment = interpreter.heap.environment(environment);
+        let globals = mem::replace(&mut interpreter.globals, Rc::clone(&self.globals));
+        let caller = interpreter.function.replace(Rc::clone(self));
+        interpreter.depth += 1;
+        let result = interpreter.execute_block(body, environment);
+        interpreter.depth -= 1;
+        interpreter.globals = globals;
+        interpreter.function = caller;
+        let returned = match result {
+            Ok(()) => Object::Nil,
+            Err(Unwind::Return(value)) => value,
+            Err(Unwind::Error(mut error)) => {
+                error.leave(self.trace_name());
+                return Err(error);
+            }
+        };
+        Ok(self.initialized().unwrap_or(returned))
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "<fn {}>", name.lexeme),
+            None => write!(f, "<fn>"),
+        }
+    }
+}
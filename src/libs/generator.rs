@@ -0,0 +1,210 @@
+use std::fmt::{self, Display};
+use crate::libs::expr::ast::{Expr, Object, Stmt};
+use crate::libs::vm::Suspended;
+
+// A function whose body has a `yield` is a generator: calling it runs nothing yet and gives a
+// `Generator` holding the call. Each resume runs the call up to its next `yield`, where its frame is
+// put aside with its part of the stack and its try statements. What a `yield` is given goes back to
+// whoever resumed the generator, and the value of the next resume is what the `yield` evaluates to.
+// Only the VM can put a frame aside, so the tree-walker compiles generator functions and runs them
+// on its nested VM.
+
+pub struct Generator {
+    pub(crate) state: State,
+    // The function's name, as stack traces write it.
+    name: &'static str,
+}
+
+pub(crate) enum State {
+    // Not started yet, or stopped at a `yield`.
+    Suspended(Suspended),
+    Running,
+    // Returned, or failed with an error.
+    Done,
+}
+
+// Where a run of a generator stopped.
+pub enum Resumed {
+    Yielded(Object),
+    Returned(Object),
+}
+
+impl Generator {
+    pub(crate) fn new(name: &'static str, call: Suspended) -> Self {
+        Self { state: State::Suspended(call), name }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+}
+
+impl Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<generator {}>", self.name)
+    }
+}
+
+// Whether a function with this body is a generator. The `yield`s of the functions declared in it
+// are theirs, not its.
+pub fn yields(body: &[Stmt]) -> bool {
+    body.iter().any(stmt_yields)
+}
+
+fn stmt_yields(stmt: &Stmt) -> bool {
+    let branch = |stmt: &Option<Box<Stmt>>| stmt.as_deref().is_some_and(stmt_yields);
+    match stmt {
+        Stmt::Block { statements } => yields(statements),
+        Stmt::Box { superclass, .. } => operand_yields(superclass),
+        Stmt::Expression { expression } | Stmt::Print { expression } => operand_yields(expression),
+        Stmt::For { iterable, body, .. } => operand_yields(iterable) || branch(body),
+        Stmt::If { condition, then_branch, else_branch } => {
+            operand_yields(condition) || branch(then_branch) || branch(else_branch)
+        }
+        Stmt::Let { initializer, .. } => operand_yields(initializer),
+        Stmt::Return { value, .. } | Stmt::Throw { value, .. } => operand_yields(value),
+        Stmt::Try { body, handler, finally, .. } => branch(body) || branch(handler) || branch(finally),
+        Stmt::While { condition, body } => operand_yields(condition) || branch(body),
+        Stmt::Function { .. } | Stmt::Import { .. } | Stmt::Interface { .. } => false,
+    }
+}
+
+fn operand_yields(expr: &Option<Box<Expr>>) -> bool {
+    expr.as_deref().is_some_and(expr_yields)
+}
+
+fn expr_yields(expr: &Expr) -> bool {
+    match expr {
+        Expr::Yield { .. } => true,
+        Expr::Assign { value, .. } => operand_yields(value),
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+            operand_yields(left) || operand_yields(right)
+        }
+        Expr::Call { callee, arguments, .. } => operand_yields(callee) || arguments.iter().any(expr_yields),
+        Expr::Get { object, .. } => operand_yields(object),
+        Expr::Grouping { expression } => operand_yields(expression),
+        Expr::Index { object, index, .. } => operand_yields(object) || operand_yields(index),
+        Expr::IndexSet { object, index, value, .. } => {
+            operand_yields(object) || operand_yields(index) || operand_yields(value)
+        }
+        Expr::List { elements, .. } => elements.iter().any(expr_yields),
+        Expr::Match { subject, arms, .. } => {
+            operand_yields(subject) || arms.iter().any(|arm| operand_yields(&arm.guard) || operand_yields(&arm.body))
+        }
+        Expr::Set { object, value, .. } => operand_yields(object) || operand_yields(value),
+        Expr::Unary { right, .. } => operand_yields(right),
+        Expr::Lambda { .. } | Expr::Literal { .. } | Expr::SelfRef { .. } | Expr::Super { .. } | Expr::Variable { .. } => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::testing::run;
+
+    const COUNT: &str = "
+        fun count(n) {
+            let i = 0;
+            while (i < n) {
+                yield i;
+                i = i + 1;
+            }
+            return \"done\";
+        }
+    ";
+
+    #[test]
+    fn loops_over_generators() {
+        let source = format!("{COUNT} for x in count(2) print x;");
+        assert_eq!(run(&source), "0\n1\n");
+    }
+
+    #[test]
+    fn loops_go_on_past_nil() {
+        let source = "
+            fun g() { yield 1; yield nil; yield 2; return 3; }
+            for x in g() print x;
+            let it = g().iter();
+            for x in it print x;
+        ";
+        assert_eq!(run(source), "1\nnil\n2\n1\nnil\n2\n");
+    }
+
+    #[test]
+    fn next_and_done() {
+        let source = format!("
+            {COUNT}
+            let g = count(1);
+            print g.next();
+            print g.next();
+            print g.next();
+            print g.done();
+        ");
+        assert_eq!(run(&source), "0\nnil\nnil\ntrue\n");
+    }
+
+    #[test]
+    fn yields_that_never_run() {
+        // A function is a generator for its `yield`s, whether or not they can run.
+        let source = "
+            fun never() { if (false) { yield 1; } return 5; }
+            print never();
+            let no = false;
+            fun maybe() { if (no) { yield 1; } return 5; }
+            print maybe();
+            fun looping() { while (false) yield 1; }
+            print looping();
+            fun plain() { if (false) { fun inner() { yield 1; } } return 5; }
+            print plain();
+        ";
+        assert_eq!(run(source), "<generator never>\n<generator maybe>\n<generator looping>\n5\n");
+    }
+
+    #[test]
+    fn resume_sends_values() {
+        let source = "
+            fun sum() {
+                let total = 0;
+                while (true) {
+                    let n = yield total;
+                    if (n == nil) return -total;
+                    total = total + n;
+                }
+            }
+            let s = sum();
+            s.resume(nil);
+            print s.resume(2);
+            print s.resume(3);
+            print s.resume(nil);
+            try { s.resume(1); } catch (e) { print e.message; }
+        ";
+        assert_eq!(run(source), "2\n5\n-5\nCan't resume a finished generator.\n");
+    }
+
+    #[test]
+    fn generator_methods_as_iterators() {
+        let source = "
+            box Pair {
+                a; b;
+                init(a, b) { self.a = a; self.b = b; }
+                iter() { yield self.a; yield self.b; }
+            }
+            let helper = fun(x) { return x * 10; };
+            for x in Pair(1, 2) print helper(x);
+        ";
+        assert_eq!(run(source), "10\n20\n");
+    }
+
+    #[test]
+    fn errors_finish_the_generator() {
+        let source = "
+            fun failing() {
+                try { yield 1; throw \"boom\"; } finally { print \"cleanup\"; }
+            }
+            let f = failing();
+            print f.next();
+            try { f.next(); } catch (e) { print e; }
+            print f.done();
+        ";
+        assert_eq!(run(source), "1\ncleanup\nboom\ntrue\n");
+    }
+}
@@ -0,0 +1,404 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+use crate::libs::environment::Environment;
+use crate::libs::expr::ast::Object;
+use crate::libs::function::{Function, Upvalue};
+use crate::libs::generator::{Generator, State};
+use crate::libs::instance::{BoxClass, Instance};
+use crate::libs::iterator::Iter;
+use crate::libs::map::Map;
+
+// How many objects the heap holds before the first collection.
+pub const DEFAULT_THRESHOLD: usize = 4096;
+
+// Owns the environments, instances, lists, maps, functions, boxes, iterators, generators and
+// upvalues the interpreters make at run time, next to the `Rc`s they are used through, so that
+// cycles `Rc` alone would leak, like an instance holding a closure over itself, are freed.
+//
+// A collection is mark and sweep. The roots are whatever holds an object from outside the heap:
+// the interpreter's environments, the VM's stack and frames, and the values Rust code is in the
+// middle of using. They are found by taking each object's reference count minus the references
+// other objects in the heap hold; what is left comes from outside. Objects reachable from a root
+// are marked, and the rest are emptied, which breaks their cycles, and dropped.
+pub struct Heap {
+    objects: Vec<Traced>,
+    threshold: usize,
+    next_collection: usize,
+    // Collects on every allocation, to find objects used without being reachable.
+    stress: bool,
+    collections: usize,
+}
+
+#[derive(Clone)]
+pub(crate) enum Traced {
+    Environment(Rc<RefCell<Environment>>),
+    Instance(Rc<RefCell<Instance>>),
+    List(Rc<RefCell<Vec<Object>>>),
+    Map(Rc<RefCell<Map>>),
+    Function(Rc<Function>),
+    Box(Rc<BoxClass>),
+    Iterator(Rc<RefCell<Iter>>),
+    Generator(Rc<RefCell<Generator>>),
+    Upvalue(Upvalue),
+}
+
+impl Traced {
+    pub(crate) fn of(object: &Object) -> Option<Traced> {
+        match object {
+            Object::Function(function) => Some(Traced::Function(Rc::clone(function))),
+            Object::Box(class) => Some(Traced::Box(Rc::clone(class))),
+            Object::Instance(instance) => Some(Traced::Instance(Rc::clone(instance))),
+            Object::List(list) => Some(Traced::List(Rc::clone(list))),
+            Object::Map(map) => Some(Traced::Map(Rc::clone(map))),
+            Object::Iterator(iter) => Some(Traced::Iterator(Rc::clone(iter))),
+            Object::Generator(generator) => Some(Traced::Generator(Rc::clone(generator))),
+            _ => None,
+        }
+    }
+
+    fn address(&self) -> *const () {
+        match self {
+            Traced::Environment(environment) => Rc::as_ptr(environment) as *const (),
+            Traced::Instance(instance) => Rc::as_ptr(instance) as *const (),
+            Traced::List(list) => Rc::as_ptr(list) as *const (),
+            Traced::Map(map) => Rc::as_ptr(map) as *const (),
+            Traced::Function(function) => Rc::as_ptr(function) as *const (),
+            Traced::Box(class) => Rc::as_ptr(class) as *const (),
+            Traced::Iterator(iter) => Rc::as_ptr(iter) as *const (),
+            Traced::Generator(generator) => Rc::as_ptr(generator) as *const (),
+            Traced::Upvalue(upvalue) => Rc::as_ptr(upvalue) as *const (),
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            Traced::Environment(environment) => Rc::strong_count(environment),
+            Traced::Instance(instance) => Rc::strong_count(instance),
+            Traced::List(list) => Rc::strong_count(list),
+            Traced::Map(map) => Rc::strong_count(map),
+            Traced::Function(function) => Rc::strong_count(function),
+            Traced::Box(class) => Rc::strong_count(class),
+            Traced::Iterator(iter) => Rc::strong_count(iter),
+            Traced::Generator(generator) => Rc::strong_count(generator),
+            Traced::Upvalue(upvalue) => Rc::strong_count(upvalue),
+        }
+    }
+
+    // None while the object is borrowed: it is being used, so it is a root.
+    fn references(&self) -> Option<Vec<Traced>> {
+        let references = match self {
+            Traced::Environment(environment) => {
+                let environment = environment.try_borrow().ok()?;
+                let enclosing = environment.enclosing().map(Traced::Environment);
+                environment.values().iter().filter_map(|value| Traced::of(&value.as_object())).chain(enclosing).collect()
+            }
+            Traced::Instance(instance) => {
+                let instance = instance.try_borrow().ok()?;
+                let class = Traced::Box(Rc::clone(&instance.class));
+                instance.values().filter_map(Traced::of).chain([class]).collect()
+            }
+            Traced::List(list) => list.try_borrow().ok()?.iter().filter_map(Traced::of).collect(),
+            Traced::Map(map) => {
+                let map = map.try_borrow().ok()?;
+                map.entries().iter().flat_map(|(key, value)| [key, value]).filter_map(Traced::of).collect()
+            }
+            Traced::Function(function) => {
+                let upvalues = function.upvalues().iter().map(|upvalue| Traced::Upvalue(Rc::clone(upvalue)));
+                upvalues.chain([Traced::Environment(Rc::clone(function.closure()))]).collect()
+            }
+            Traced::Box(class) => {
+                let superclass = class.superclass.clone().map(Traced::Box);
+                class.methods().map(|method| Traced::Function(Rc::clone(method))).chain(superclass).collect()
+            }
+            Traced::Iterator(iter) => match &*iter.try_borrow().ok()? {
+                Iter::List { list, .. } => vec![Traced::List(Rc::clone(list))],
+                Iter::Keys { map, .. } => vec![Traced::Map(Rc::clone(map))],
+                Iter::Object(object) => Traced::of(object).into_iter().collect(),
+                Iter::Generator(generator) => vec![Traced::Generator(Rc::clone(generator))],
+                Iter::Chars { .. } | Iter::Range { .. } => Vec::new(),
+            },
+            // A running generator's call is the VM's, which is a root.
+            Traced::Generator(generator) => match &generator.try_borrow().ok()?.state {
+                State::Suspended(call) => call.references(),
+                State::Running | State::Done => Vec::new(),
+            },
+            Traced::Upvalue(upvalue) => upvalue.try_borrow().ok()?.iter().filter_map(|value| Traced::of(&value.as_object())).collect(),
+        };
+        Some(references)
+    }
+
+    // Functions and boxes can't change, so a cycle through them also goes through one of these.
+    fn empty(&self) {
+        match self {
+            Traced::Environment(environment) => environment.borrow_mut().clear(),
+            Traced::Instance(instance) => instance.borrow_mut().clear(),
+            Traced::List(list) => list.borrow_mut().clear(),
+            Traced::Map(map) => map.borrow_mut().clear(),
+            // One that is done, which holds nothing.
+            Traced::Iterator(iter) => *iter.borrow_mut() = Iter::Range { next: None, last: 0 },
+            Traced::Generator(generator) => generator.borrow_mut().state = State::Done,
+            Traced::Upvalue(upvalue) => *upvalue.borrow_mut() = None,
+            Traced::Function(_) | Traced::Box(_) => (),
+        }
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::with_threshold(DEFAULT_THRESHOLD)
+    }
+
+    pub fn with_threshold(threshold: usize) -> Self {
+        Self {
+            objects: Vec::new(),
+            threshold,
+            next_collection: threshold,
+            stress: false,
+            collections: 0,
+        }
+    }
+
+    pub fn stressed(mut self) -> Self {
+        self.stress = true;
+        self
+    }
+
+    pub fn live_objects(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn collections(&self) -> usize {
+        self.collections
+    }
+
+    pub fn environment(&mut self, environment: Environment) -> Rc<RefCell<Environment>> {
+        let environment = Rc::new(RefCell::new(environment));
+        self.track(Traced::Environment(Rc::clone(&environment)));
+        environment
+    }
+
+    pub fn instance(&mut self, instance: Instance) -> Object {
+        let instance = Rc::new(RefCell::new(instance));
+        self.track(Traced::Instance(Rc::clone(&instance)));
+        Object::Instance(instance)
+    }
+
+    pub fn list(&mut self, values: Vec<Object>) -> Object {
+        let list = Rc::new(RefCell::new(values));
+        self.track(Traced::List(Rc::clone(&list)));
+        Object::List(list)
+    }
+
+    pub fn map(&mut self, map: Map) -> Object {
+        let map = Rc::new(RefCell::new(map));
+        self.track(Traced::Map(Rc::clone(&map)));
+        Object::Map(map)
+    }
+
+    pub fn function(&mut self, function: Function) -> Rc<Function> {
+        let function = Rc::new(function);
+        self.track(Traced::Function(Rc::clone(&function)));
+        function
+    }
+
+    pub fn class(&mut self, class: BoxClass) -> Rc<BoxClass> {
+        let class = Rc::new(class);
+        self.track(Traced::Box(Rc::clone(&class)));
+        class
+    }
+
+    pub fn iterator(&mut self, iter: Iter) -> Rc<RefCell<Iter>> {
+        let iter = Rc::new(RefCell::new(iter));
+        self.track(Traced::Iterator(Rc::clone(&iter)));
+        iter
+    }
+
+    pub fn generator(&mut self, generator: Generator) -> Rc<RefCell<Generator>> {
+        let generator = Rc::new(RefCell::new(generator));
+        self.track(Traced::Generator(Rc::clone(&generator)));
+        generator
+    }
+
+    pub fn upvalue(&mut self) -> Upvalue {
+        let upvalue = Rc::new(RefCell::new(None));
+        self.track(Traced::Upvalue(Rc::clone(&upvalue)));
+        upvalue
+    }
+
+    // Collects before the new object is added, while the caller still holds what it is made of.
+    fn track(&mut self, object: Traced) {
+        if self.stress || self.objects.len() >= self.next_collection {
+            self.collect();
+        }
+        self.objects.push(object);
+    }
+
+    pub fn collect(&mut self) {
+        // Most garbage is held by nothing but the heap, and freeing it can free more.
+        let before = self.objects.len();
+        loop {
+            let count = self.objects.len();
+            self.objects.retain(|object| object.count() > 1);
+            if self.objects.len() == count {
+                break;
+            }
+        }
+
+        let index = self.objects.iter().enumerate().map(|(i, object)| (object.address(), i)).collect::<HashMap<_, _>>();
+        // Less the reference the heap holds.
+        let mut outside = self.objects.iter().map(|object| object.count() - 1).collect::<Vec<_>>();
+        let references = self
+            .objects
+            .iter()
+            .map(|object| {
+                let references = object.references()?;
+                Some(references.iter().filter_map(|reference| index.get(&reference.address()).copied()).collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>();
+        for &reference in references.iter().flatten().flatten() {
+            outside[reference] -= 1;
+        }
+
+        let mut marked = vec![false; self.objects.len()];
+        let mut pending = (0..self.objects.len())
+            .filter(|&i| outside[i] > 0 || references[i].is_none())
+            .collect::<Vec<_>>();
+        while let Some(i) = pending.pop() {
+            if mem::replace(&mut marked[i], true) {
+                continue;
+            }
+            pending.extend(references[i].iter().flatten().filter(|&&reference| !marked[reference]));
+        }
+
+        let (live, cycles): (Vec<_>, Vec<_>) =
+            mem::take(&mut self.objects).into_iter().zip(marked).partition(|&(_, marked)| marked);
+        self.objects = live.into_iter().map(|(object, _)| object).collect();
+        for (object, _) in &cycles {
+            object.empty();
+        }
+        self.collections += 1;
+        self.next_collection = self.threshold.max(self.objects.len() * 2);
+        drop(cycles);
+        log::debug!("Collected {} objects, {} live", before - self.objects.len(), self.objects.len());
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use crate::libs::expr::ast::Object;
+    use crate::libs::heap::Heap;
+    use crate::libs::interpreter::Interpreter;
+    use crate::libs::lex::Lox;
+    use crate::libs::vm::Vm;
+
+    #[test]
+    fn collects_cycles() {
+        // Each call leaves two instances holding each other and a closure over one of them.
+        let source = "
+            box Node { next; callback; }
+            fun cycle(i) {
+                let a = Node();
+                let b = Node();
+                a.next = b;
+                b.next = a;
+                a.callback = () -> a;
+                return i;
+            }
+            let total = 0;
+            let i = 0;
+            while (i < 100) { total = total + cycle(i); i = i + 1; }
+            let kept = Node();
+            kept.next = [1, [2, kept]];
+            let second = kept.next[1][0];";
+        let parse = || Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+        let number = |value: Option<Object>| match value {
+            Some(Object::Int(n)) => n,
+            _ => panic!("Expected a number"),
+        };
+
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        interpreter.heap = Heap::new().stressed();
+        assert!(interpreter.run(parse()));
+        interpreter.heap.collect();
+        let globals = interpreter.globals.borrow();
+        assert_eq!((number(globals.value("total".into())), number(globals.value("second".into()))), (4950, 2));
+        // Node, cycle, kept and its two lists.
+        assert_eq!(interpreter.heap.live_objects(), 5);
+
+        let mut vm = Vm::with_output(Box::new(io::sink()));
+        vm.heap = Heap::new().stressed();
+        assert!(vm.run(parse()));
+        vm.heap.collect();
+        let globals = vm.globals.borrow();
+        assert_eq!((number(globals.value("total".into())), number(globals.value("second".into()))), (4950, 2));
+        assert_eq!(vm.heap.live_objects(), 5);
+    }
+
+    #[test]
+    fn collects_iterators() {
+        // Each call leaves a list holding an iterator over itself.
+        let source = "
+            fun cycle() {
+                let xs = [0];
+                let it = xs.iter();
+                xs[0] = it;
+            }
+            for i in 0..10 cycle();";
+        let parse = || Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        assert!(interpreter.run(parse()));
+        interpreter.heap.collect();
+        // Only `cycle` is left.
+        assert_eq!(interpreter.heap.live_objects(), 1);
+
+        let mut vm = Vm::with_output(Box::new(io::sink()));
+        assert!(vm.run(parse()));
+        vm.heap.collect();
+        assert_eq!(vm.heap.live_objects(), 1);
+    }
+
+    #[test]
+    fn collects_generators() {
+        // Each call leaves a generator whose closure holds it, one stopped at a yield, and one
+        // stopped in a loop over a list holding it.
+        let source = "
+            fun cycle() {
+                let g = nil;
+                fun inner() { yield g; }
+                g = inner();
+                let started = nil;
+                fun outer() { let self_ = started; yield 1; }
+                started = outer();
+                started.next();
+                let xs = [];
+                fun each() { for x in xs yield x; }
+                let looping = each();
+                xs.push(looping);
+                looping.next();
+            }
+            for i in 0..10 cycle();";
+        let parse = || Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        assert!(interpreter.run(parse()));
+        interpreter.heap.collect();
+        // Only `cycle` is left.
+        assert_eq!(interpreter.heap.live_objects(), 1);
+
+        let mut vm = Vm::with_output(Box::new(io::sink()));
+        vm.heap = Heap::new().stressed();
+        assert!(vm.run(parse()));
+        vm.heap.collect();
+        assert_eq!(vm.heap.live_objects(), 1);
+    }
+}
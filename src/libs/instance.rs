@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::libs::expr::ast::Object;
+use crate::libs::function::Function;
+use crate::libs::heap::Heap;
+use crate::libs::interpreter::{Interpreter, RuntimeError};
+use crate::libs::lex::Token;
+use crate::libs::symbol::{Symbol, INIT};
+
+pub struct BoxClass {
+    pub name: Symbol,
+    pub superclass: Option<Rc<BoxClass>>,
+    fields: Vec<Symbol>,
+    methods: HashMap<Symbol, Rc<Function>>,
+}
+
+impl BoxClass {
+    pub fn new(
+        name: Symbol,
+        superclass: Option<Rc<BoxClass>>,
+        fields: Vec<Symbol>,
+        methods: HashMap<Symbol, Rc<Function>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            fields,
+            methods,
+        }
+    }
+
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<Function>> {
+        if let Some(method) = self.methods.get(&name) {
+            return Some(Rc::clone(method));
+        }
+
+        match self.superclass {
+            Some(ref superclass) => superclass.find_method(name),
+            None => None,
+        }
+    }
+
+    pub(crate) fn methods(&self) -> impl Iterator<Item = &Rc<Function>> {
+        self.methods.values()
+    }
+
+    pub fn has_field(&self, name: Symbol) -> bool {
+        self.fields.contains(&name)
+            || self.superclass.as_ref().is_some_and(|superclass| superclass.has_field(name))
+    }
+
+    pub fn arity(&self) -> usize {
+        self.find_method(*INIT).map_or(0, |init| init.arity())
+    }
+
+    // Runs "init" on a new instance.
+    pub fn call(class: &Rc<BoxClass>, interpreter: &mut Interpreter, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+        let instance = Self::instantiate(class, &mut interpreter.heap);
+        if let Some(initializer) = class.find_method(*INIT) {
+            initializer.bind(instance.clone(), &mut interpreter.heap).call(interpreter, arguments)?;
+        }
+
+        Ok(instance)
+    }
+
+    // An instance with every declared field set to nil.
+    pub fn instantiate(class: &Rc<BoxClass>, heap: &mut Heap) -> Object {
+        let mut fields = HashMap::new();
+        let mut current = Some(class);
+        while let Some(box_class) = current {
+            for field in &box_class.fields {
+                fields.entry(*field).or_insert(Object::Nil);
+            }
+            current = box_class.superclass.as_ref();
+        }
+
+        heap.instance(Instance {
+            class: Rc::clone(class),
+            fields,
+        })
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<BoxClass>,
+    fields: HashMap<Symbol, Object>,
+}
+
+impl Instance {
+    pub fn field(&self, name: Symbol) -> Option<Object> {
+        self.fields.get(&name).cloned()
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Object> {
+        self.fields.values()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.fields.clear();
+    }
+
+    // True when the instance was made from `class` or from a box derived from it.
+    pub fn is_a(&self, class: &Rc<BoxClass>) -> bool {
+        let mut current = Some(&self.class);
+        while let Some(box_class) = current {
+            if Rc::ptr_eq(box_class, class) {
+                return true;
+            }
+            current = box_class.superclass.as_ref();
+        }
+        false
+    }
+
+    // Fields shadow methods, so a field holding a function can be called like a method.
+    pub fn get(instance: &Rc<RefCell<Instance>>, name: &Token, heap: &mut Heap) -> Result<Object, RuntimeError> {
+        Self::property(instance, name.lexeme, heap)
+            .ok_or_else(|| RuntimeError::new(name, &format!("Undefined property '{}'.", name.lexeme)))
+    }
+
+    pub fn property(instance: &Rc<RefCell<Instance>>, name: Symbol, heap: &mut Heap) -> Option<Object> {
+        if let Some(value) = instance.borrow().fields.get(&name) {
+            return Some(value.clone());
+        }
+
+        let class = Rc::clone(&instance.borrow().class);
+        let method = class.find_method(name)?;
+        Some(Object::Function(method.bind(Object::Instance(Rc::clone(instance)), heap)))
+    }
+
+    // Sets a field the runtime knows the box declares, like the `stack` of an exception.
+    pub(crate) fn put(&mut self, name: Symbol, value: Object) {
+        self.fields.insert(name, value);
+    }
+
+    pub fn set(&mut self, name: &Token, value: Object) -> Result<(), RuntimeError> {
+        if !self.class.has_field(name.lexeme) {
+            return Err(RuntimeError::new(
+                name,
+                &format!("Undefined field '{}' in box '{}'.", name.lexeme, self.class.name),
+            ));
+        }
+
+        self.fields.insert(name.lexeme, value);
+        Ok(())
+    }
+}
@@ -1,24 +1,1096 @@
-use crate::libs::expr::ast::{Expr, Object};
-use crate::libs::expr::visitor::Visitor;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+use std::slice;
+use crate::libs::chunk::Prototype;
+use crate::libs::compiler;
+use crate::libs::environment::Environment;
+use crate::libs::exception::{self, TraceFrame, SCRIPT};
+use crate::libs::expr::ast::{Expr, MatchArm, MethodSig, Object, Pattern, Stmt, TypeExpr, TypedName};
+use crate::libs::expr::visitor::{StmtVisitor, Visitor};
+use crate::libs::function::Function;
+use crate::libs::generator::{self, Generator, Resumed};
+use crate::libs::heap::Heap;
+use crate::libs::instance::{BoxClass, Instance};
+use crate::libs::iterator::{self, Range};
+use crate::libs::lex::{Token, TokenType};
+use crate::libs::loader::{Load, Loader};
+use crate::libs::map::Key;
+use crate::libs::module::Module;
+use crate::libs::native::{self, NativeFn};
+use crate::libs::number::{self, Arithmetic};
+use crate::libs::symbol::{Symbol, INIT, SUPER};
+use crate::libs::optimizer;
+use crate::libs::resolver::{Locals, Resolver};
+use crate::libs::stdlib;
+use crate::libs::type_checker;
+use crate::libs::vm::Vm;
 
-struct Interpreter {
+pub struct RuntimeError {
+    pub token: Token,
+    pub message: String,
+    // What a `throw` threw; None for an error the runtime raised. This and the trace are boxed, so
+    // that results carrying an error stay small.
+    pub thrown: Option<Box<Object>>,
+    pub trace: Box<Trace>,
+}
 
+// The calls an error came out of.
+pub struct Trace {
+    // Innermost first.
+    pub frames: Vec<TraceFrame>,
+    // The file and line the error is at in the call it is in now: where it was raised, or the call
+    // it came out of. Only the tree-walker keeps it; the VM reads lines from its frames.
+    position: (usize, usize),
 }
 
-impl Visitor<Object> for Interpreter {
-    fn visit_binary(&mut self, binary: &Expr) -> Object {
-        todo!()
+impl RuntimeError {
+    pub fn new(token: &Token, message: &str) -> Self {
+        Self {
+            token: token.clone(),
+            message: message.to_string(),
+            thrown: None,
+            trace: Box::new(Trace { frames: Vec::new(), position: (token.file, token.line) }),
+        }
+    }
+
+    // An error carrying a value a script threw, with the message the value holds.
+    pub fn thrown(token: &Token, value: Object) -> Self {
+        Self { thrown: Some(Box::new(value.clone())), ..Self::new(token, &exception::message(&value)) }
+    }
+
+    // Moves the error to `token`, the call it came out of.
+    pub(crate) fn at(&mut self, token: &Token) {
+        self.trace.position = (token.file, token.line);
+    }
+
+    // The frame of `function`, the call the error is in.
+    pub(crate) fn frame(&self, function: &'static str) -> TraceFrame {
+        let (file, line) = self.trace.position;
+        TraceFrame { function, file, line }
+    }
+
+    // Records that the error left the call of `function`.
+    pub(crate) fn leave(&mut self, function: &'static str) {
+        let frame = self.frame(function);
+        self.trace.frames.push(frame);
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Runtime error: {}", self.token.line, self.message)
+    }
+}
+
+// Anything that stops a statement from completing normally.
+pub enum Unwind {
+    Error(RuntimeError),
+    Return(Object),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+type EvalResult = Result<Object, RuntimeError>;
+type ExecResult = Result<(), Unwind>;
+
+// How many calls may run at once, on both backends, before a call fails with a stack overflow.
+pub const MAX_FRAMES: usize = 4096;
+// The native stack programs run on: the tree-walker recurses on it, so it must hold MAX_FRAMES
+// calls, even in a debug build.
+pub const STACK_SIZE: usize = 1 << 30;
+
+pub struct Interpreter {
+    pub globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    locals: Locals,
+    pub heap: Heap,
+    pub(crate) output: Box<dyn Write>,
+    pub loader: Loader,
+    // The function running, for the stack traces of the errors it catches; None at the top level.
+    pub(crate) function: Option<Rc<Function>>,
+    // Runs generators and the compiled functions native functions call back, kept between runs.
+    compiled: Option<Box<Vm>>,
+    // The generator functions, compiled, by where they are declared; None for the other functions.
+    generators: HashMap<(usize, usize, usize), Option<Rc<Prototype>>>,
+    // The calls running, counted against MAX_FRAMES; a VM calling out adds its frames for the call.
+    pub(crate) depth: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        // The globals live as long as the interpreter, so they stay out of the heap, as a root.
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        native::define_builtins(&mut globals.borrow_mut());
+        Self::sharing(globals, output)
+    }
+
+    // An interpreter for the VM to run native functions with, on the VM's globals.
+    pub(crate) fn sharing(globals: Rc<RefCell<Environment>>, output: Box<dyn Write>) -> Self {
+        Self {
+            environment: Rc::clone(&globals),
+            globals,
+            locals: Locals::default(),
+            heap: Heap::new(),
+            output,
+            loader: Loader::default(),
+            function: None,
+            compiled: None,
+            generators: HashMap::new(),
+            depth: 0,
+        }
+    }
+
+    // Makes a Rust function callable from scripts as a global named `name`.
+    pub fn register(&mut self, name: &str, arity: usize, function: NativeFn) {
+        native::define(&mut self.globals.borrow_mut(), name, arity, function);
+        self.loader.register(name, arity, function);
+    }
+
+    // Writes a line of the program's output.
+    pub fn print(&mut self, value: &Object) {
+        if let Err(error) = writeln!(self.output, "{value}") {
+            log::error!("Failed to write output: {error}");
+        }
+    }
+
+    // The whole pipeline after parsing: resolve, check types, optimize, then interpret. Resolve and
+    // type errors are reported and nothing runs.
+    pub fn run(&mut self, statements: Vec<Stmt>) -> bool {
+        let Some((statements, locals)) = Self::prepare(statements) else { return false };
+        self.resolve(locals);
+        self.interpret(&statements)
+    }
+
+    // What every backend does before running: resolve, check types and optimize, reporting the
+    // errors and warnings found. The checks come first so a branch the optimizer drops is still
+    // checked; the locals are keyed by token position, which folding keeps.
+    pub fn prepare(statements: Vec<Stmt>) -> Option<(Vec<Stmt>, Locals)> {
+        let locals = match Resolver::new().resolve(&statements) {
+            Ok(locals) => locals,
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{error}");
+                }
+                return None;
+            }
+        };
+        match type_checker::check(&statements) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    eprintln!("{warning}");
+                }
+            }
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{error}");
+                }
+                return None;
+            }
+        }
+        Some((optimizer::optimize(statements), locals))
+    }
+
+    pub fn resolve(&mut self, locals: Locals) {
+        self.locals.extend(locals);
     }
 
-    fn visit_grouping(&mut self, grouping: &Expr) -> Object {
-        todo!()
+    pub fn interpret(&mut self, statements: &[Stmt]) -> bool {
+        for statement in statements {
+            match self.execute(statement) {
+                Ok(()) => (),
+                Err(Unwind::Error(mut error)) => {
+                    error.leave(SCRIPT);
+                    exception::report(&error, &self.loader);
+                    return false;
+                }
+                Err(Unwind::Return(_)) => break,
+            }
+        }
+        true
     }
 
-    fn visit_literal(&mut self, literal: &Expr) -> Object {
-        todo!()
+    fn execute(&mut self, stmt: &Stmt) -> ExecResult {
+        self.visit_stmt(stmt)
     }
 
-    fn visit_unary(&mut self, unary: &Expr) -> Object {
-        todo!()
+    fn evaluate(&mut self, expr: &Option<Box<Expr>>) -> EvalResult {
+        self.visit_expr(expr).unwrap_or(Ok(Object::Nil))
     }
-}
\ No newline at end of file
+
+    pub fn execute_block(&mut self, statements: &[Stmt], environment: Rc<RefCell<Environment>>) -> ExecResult {
+        let previous = std::mem::replace(&mut self.environment, environment);
+
+        let mut result = Ok(());
+        for statement in statements {
+            result = self.execute(statement);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.environment = previous;
+        result
+    }
+
+    // Runs a module the first time it is imported, in globals of its own.
+    fn import(&mut self, path: &Token) -> Result<Rc<Module>, RuntimeError> {
+        let statements = match self.loader.start(path)? {
+            Load::Loaded(module) => return Ok(module),
+            Load::Parsed(statements) => statements,
+        };
+        let Some((statements, locals)) = Self::prepare(statements) else {
+            self.loader.abandon();
+            return Err(RuntimeError::new(path, &format!("Failed to load module '{}'.", path.lexeme.trim_matches('"'))));
+        };
+        self.resolve(locals);
+
+        let globals = self.loader.globals();
+        let enclosing = mem::replace(&mut self.globals, Rc::clone(&globals));
+        let result = self.execute_block(&statements, Rc::clone(&globals));
+        self.globals = enclosing;
+        match result {
+            Ok(()) | Err(Unwind::Return(_)) => Ok(self.loader.finish(globals)),
+            Err(Unwind::Error(mut error)) => {
+                self.loader.abandon();
+                error.leave(SCRIPT);
+                error.at(path);
+                Err(error)
+            }
+        }
+    }
+
+    // The exception a `catch` gets for `error`, caught in the function running.
+    fn caught(&mut self, error: RuntimeError) -> Object {
+        let function = self.function.as_ref().map_or(SCRIPT, |function| function.trace_name());
+        let frames = [error.trace.frames.as_slice(), &[error.frame(function)]].concat();
+        exception::caught(error, &frames, &self.loader, &mut self.heap)
+    }
+
+    // Calls the method `name` of a value with no arguments, as loops call `iter` and `next`.
+    pub(crate) fn call_method(&mut self, token: &Token, object: &Object, name: Symbol) -> EvalResult {
+        let name = Token { lexeme: name, ..token.clone() };
+        let method = match object {
+            Object::Instance(instance) => Instance::get(instance, &name, &mut self.heap)?,
+            other => stdlib::property(other, &name)?,
+        };
+        self.call_value(method, token, Vec::new()).map_err(|mut error| {
+            error.at(token);
+            error
+        })
+    }
+
+    pub fn call_value(&mut self, callee: Object, paren: &Token, arguments: Vec<Object>) -> EvalResult {
+        let arity = match callee {
+            Object::Function(ref function) => function.arity(),
+            Object::NativeFunction(ref function) => function.arity(),
+            Object::Box(ref class) => class.arity(),
+            _ => return Err(RuntimeError::new(paren, "Can only call functions and boxes.")),
+        };
+        if arguments.len() != arity {
+            return Err(RuntimeError::new(
+                paren,
+                &format!("Expected {} arguments but got {}.", arity, arguments.len()),
+            ));
+        }
+        let frame = match callee {
+            Object::Function(_) => true,
+            Object::Box(ref class) => class.find_method(*INIT).is_some(),
+            _ => false,
+        };
+        if frame && self.depth >= MAX_FRAMES {
+            return Err(RuntimeError::new(paren, "Stack overflow."));
+        }
+
+        match callee {
+            Object::Function(function) => function.call(self, arguments),
+            Object::NativeFunction(function) => function.call(self, paren, &arguments),
+            Object::Box(class) => BoxClass::call(&class, self, arguments),
+            _ => unreachable!(),
+        }
+    }
+
+    // Runs a function compiled for the VM: a generator function, or one a native function the VM
+    // called calls back.
+    pub(crate) fn run_compiled(&mut self, function: &Rc<Function>, arguments: Vec<Object>) -> EvalResult {
+        self.on_vm(|vm| vm.call_function(function, arguments))
+    }
+
+    // Runs a generator until it yields or returns; generators only run on the VM.
+    pub(crate) fn resume(&mut self, generator: &Rc<RefCell<Generator>>, value: Object) -> Result<Resumed, RuntimeError> {
+        self.on_vm(|vm| vm.resume(generator, value))
+    }
+
+    // The interpreter is lent to the VM for the run.
+    fn on_vm<T>(&mut self, run: impl FnOnce(&mut Vm) -> T) -> T {
+        let mut vm = self.compiled.take().unwrap_or_else(|| Box::new(Vm::nested(Rc::clone(&self.globals))));
+        vm.lend(self);
+        let result = run(&mut vm);
+        vm.give_back(self);
+        self.compiled = Some(vm);
+        result
+    }
+
+    // Collects the pattern's bindings into `bindings`; on a failed match they must be discarded.
+    fn match_pattern(&mut self, pattern: &Pattern, value: &Object, bindings: &mut Vec<(Symbol, Object)>) -> Result<bool, RuntimeError> {
+        match pattern {
+            Pattern::Literal { value: expected, .. } => Ok(Self::is_equal(expected, value)),
+            Pattern::Binding { name } => {
+                bindings.push((name.lexeme, value.clone()));
+                Ok(true)
+            }
+            Pattern::Wildcard { .. } => Ok(true),
+            Pattern::List { elements, rest, .. } => {
+                let Object::List(list) = value else { return Ok(false) };
+                let list = list.borrow().clone();
+                let fits = match rest {
+                    Some(_) => list.len() >= elements.len(),
+                    None => list.len() == elements.len(),
+                };
+                if !fits {
+                    return Ok(false);
+                }
+
+                for (element, item) in elements.iter().zip(&list) {
+                    if !self.match_pattern(element, item, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                match rest {
+                    Some(rest) => {
+                        let remaining = self.heap.list(list[elements.len()..].to_vec());
+                        self.match_pattern(rest, &remaining, bindings)
+                    }
+                    None => Ok(true),
+                }
+            }
+            Pattern::Box { name, fields } => {
+                let Object::Box(class) = self.look_up(name)? else {
+                    return Err(RuntimeError::new(name, &format!("'{}' is not a box.", name.lexeme)));
+                };
+                let Object::Instance(instance) = value else { return Ok(false) };
+                if !instance.borrow().is_a(&class) {
+                    return Ok(false);
+                }
+
+                for (field, pattern) in fields {
+                    let Some(field_value) = instance.borrow().field(field.lexeme) else {
+                        return Err(RuntimeError::new(
+                            field,
+                            &format!("Undefined field '{}' in box '{}'.", field.lexeme, class.name),
+                        ));
+                    };
+                    if !self.match_pattern(pattern, &field_value, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn list_index(index: &Object, len: usize) -> Result<usize, String> {
+        match index {
+            Object::Int(n) if *n >= 0 => match usize::try_from(*n) {
+                Ok(position) if position < len => Ok(position),
+                _ => Err(format!("List index {index} is out of bounds.")),
+            },
+            Object::BigInt(n) if !n.is_negative() => Err(format!("List index {index} is out of bounds.")),
+            Object::Int(_) | Object::BigInt(_) | Object::Float(_) => Err("List index must be a non-negative integer.".to_string()),
+            _ => Err("List index must be a number.".to_string()),
+        }
+    }
+
+    // These operations give the message of an error rather than the error, which each interpreter
+    // puts where it knows the line: only once there is one, as the VM runs them in its hot paths.
+    pub(crate) fn index(object: &Object, index: &Object) -> Result<Object, String> {
+        match object {
+            Object::List(list) => {
+                let list = list.borrow();
+                let position = Self::list_index(index, list.len())?;
+                Ok(list[position].clone())
+            }
+            Object::Map(map) => {
+                let key = Self::map_key(index)?;
+                let value = map.borrow().get(&key);
+                value.ok_or_else(|| format!("Undefined key '{index}'."))
+            }
+            _ => Err("Only lists and maps can be indexed.".to_string()),
+        }
+    }
+
+    pub(crate) fn index_set(object: &Object, index: Object, value: Object) -> Result<(), String> {
+        match object {
+            Object::List(list) => {
+                let mut list = list.borrow_mut();
+                let position = Self::list_index(&index, list.len())?;
+                list[position] = value;
+            }
+            Object::Map(map) => {
+                let key = Self::map_key(&index)?;
+                map.borrow_mut().insert(key, index, value);
+            }
+            _ => return Err("Only lists and maps can be indexed.".to_string()),
+        }
+        Ok(())
+    }
+
+    fn map_key(index: &Object) -> Result<Key, String> {
+        Key::of(index).ok_or_else(|| "Map keys must be numbers, strings, booleans or nil.".to_string())
+    }
+
+    // Operators on values that are already evaluated, shared with constant folding.
+    pub(crate) fn binary(operator: &TokenType, left: Object, right: Object) -> Result<Object, &'static str> {
+        let operation = match operator {
+            TokenType::PLUS => match (&left, &right) {
+                (Object::Str(left), Object::Str(right)) => return Ok(Object::Str(Rc::new(format!("{left}{right}")))),
+                _ => Arithmetic::Add,
+            },
+            TokenType::MINUS => Arithmetic::Subtract,
+            TokenType::STAR => Arithmetic::Multiply,
+            TokenType::SLASH => Arithmetic::Divide,
+            TokenType::TILDE_SLASH => Arithmetic::IntDivide,
+            TokenType::PERCENT => Arithmetic::Remainder,
+            TokenType::GREATER => return Self::comparison(&left, &right, Ordering::is_gt),
+            TokenType::GREATER_EQUAL => return Self::comparison(&left, &right, Ordering::is_ge),
+            TokenType::LESS => return Self::comparison(&left, &right, Ordering::is_lt),
+            TokenType::LESS_EQUAL => return Self::comparison(&left, &right, Ordering::is_le),
+            TokenType::EQUAL_EQUAL => return Ok(Object::Bool(Self::is_equal(&left, &right))),
+            TokenType::BANG_EQUAL => return Ok(Object::Bool(!Self::is_equal(&left, &right))),
+            TokenType::DOT_DOT => return Self::range(&left, &right, false),
+            TokenType::DOT_DOT_EQUAL => return Self::range(&left, &right, true),
+            _ => return Err("Unknown binary operator."),
+        };
+        match number::arithmetic(operation, &left, &right) {
+            Some(result) => result,
+            None if matches!(operator, TokenType::PLUS) => Err("Operands must be two numbers or two strings."),
+            None => Err("Operands must be numbers."),
+        }
+    }
+
+    // NaN is neither less than, equal to, nor greater than anything.
+    fn comparison(left: &Object, right: &Object, holds: fn(Ordering) -> bool) -> Result<Object, &'static str> {
+        match number::compare(left, right) {
+            Some(ordering) => Ok(Object::Bool(ordering.is_some_and(holds))),
+            None => Err("Operands must be numbers."),
+        }
+    }
+
+    fn range(start: &Object, end: &Object, inclusive: bool) -> Result<Object, &'static str> {
+        match (start, end) {
+            (Object::Int(start), Object::Int(end)) => {
+                Ok(Object::Range(Rc::new(Range { start: *start, end: *end, inclusive })))
+            }
+            (Object::Int(_) | Object::BigInt(_), Object::Int(_) | Object::BigInt(_)) => {
+                Err("Range bounds must fit in 64 bits.")
+            }
+            _ => Err("Range bounds must be integers."),
+        }
+    }
+
+    pub(crate) fn unary(operator: &TokenType, right: Object) -> Result<Object, &'static str> {
+        match operator {
+            TokenType::MINUS => number::negate(&right).ok_or("Operand must be a number."),
+            TokenType::BANG => Ok(Object::Bool(!Self::is_truthy(&right))),
+            _ => Err("Unknown unary operator."),
+        }
+    }
+
+    // Types are only checked before running; functions just need the parameter names.
+    fn names(params: &[TypedName]) -> Vec<Token> {
+        params.iter().map(|param| param.name.clone()).collect()
+    }
+
+    // The function a declaration or lambda at `token` makes. A generator function is compiled, the
+    // first time, so that it runs on the VM, which can put its frame aside at each `yield`.
+    fn new_function(
+        &mut self,
+        token: &Token,
+        name: Option<&Token>,
+        params: &[TypedName],
+        body: &Rc<Vec<Stmt>>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Result<Function, RuntimeError> {
+        let prototype = match self.generators.get(&token.position()) {
+            Some(prototype) => prototype.clone(),
+            None => {
+                let prototype = match generator::yields(body) {
+                    true => Some(compiler::compile_function(name, params, body, &self.locals).map_err(|errors| {
+                        RuntimeError::new(token, &errors[0].message)
+                    })?),
+                    false => None,
+                };
+                self.generators.insert(token.position(), prototype.clone());
+                prototype
+            }
+        };
+        Ok(match prototype {
+            Some(prototype) => Function::compiled(prototype, closure, Vec::new(), is_initializer),
+            None => Function::new(name.cloned(), Self::names(params), Rc::clone(body), closure, is_initializer),
+        })
+    }
+
+    pub(crate) fn is_truthy(object: &Object) -> bool {
+        !matches!(object, Object::Nil | Object::Bool(false))
+    }
+
+    pub(crate) fn is_equal(a: &Object, b: &Object) -> bool {
+        match (a, b) {
+            (Object::Nil, Object::Nil) => true,
+            (Object::Int(a), Object::Int(b)) => a == b,
+            (Object::Str(a), Object::Str(b)) => a == b,
+            (Object::Bool(a), Object::Bool(b)) => a == b,
+            (Object::Function(a), Object::Function(b)) => Rc::ptr_eq(a, b),
+            (Object::NativeFunction(a), Object::NativeFunction(b)) => Rc::ptr_eq(a, b),
+            (Object::Box(a), Object::Box(b)) => Rc::ptr_eq(a, b),
+            (Object::Instance(a), Object::Instance(b)) => Rc::ptr_eq(a, b),
+            (Object::List(a), Object::List(b)) => Rc::ptr_eq(a, b),
+            (Object::Map(a), Object::Map(b)) => Rc::ptr_eq(a, b),
+            (Object::Module(a), Object::Module(b)) => Rc::ptr_eq(a, b),
+            (Object::Range(a), Object::Range(b)) => (a.start, a.end, a.inclusive) == (b.start, b.end, b.inclusive),
+            (Object::Iterator(a), Object::Iterator(b)) => Rc::ptr_eq(a, b),
+            (Object::Generator(a), Object::Generator(b)) => Rc::ptr_eq(a, b),
+            // Numbers of different kinds are equal when their values are.
+            _ => number::compare(a, b) == Some(Some(Ordering::Equal)),
+        }
+    }
+
+    // Resolved names are read from their slot; the rest are globals.
+    fn look_up(&self, name: &Token) -> EvalResult {
+        match self.locals.get(&name.position()) {
+            Some(resolution) => Environment::get_slot(&self.environment, resolution.depth, resolution.slot)
+                .ok_or_else(|| RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme))),
+            None => self.globals.borrow().get(name),
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Visitor<EvalResult> for Interpreter {
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) -> EvalResult {
+        let value = self.evaluate(value)?;
+        match self.locals.get(&name.position()) {
+            Some(resolution) => {
+                if !Environment::assign_slot(&self.environment, resolution.depth, resolution.slot, value.clone()) {
+                    return Err(RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme)));
+                }
+            }
+            None => self.globals.borrow_mut().assign(name, value.clone())?,
+        }
+        Ok(value)
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> EvalResult {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+        Self::binary(&operator.token_type, left, right).map_err(|message| RuntimeError::new(operator, message))
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, paren: &Token, arguments: &[Expr]) -> EvalResult {
+        let callee = self.evaluate(callee)?;
+
+        let mut values = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            values.push(self.visit(argument)?);
+        }
+
+        self.call_value(callee, paren, values).map_err(|mut error| {
+            error.at(paren);
+            error
+        })
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, name: &Token) -> EvalResult {
+        match self.evaluate(object)? {
+            Object::Instance(instance) => Instance::get(&instance, name, &mut self.heap),
+            other => stdlib::property(&other, name),
+        }
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) -> EvalResult {
+        self.evaluate(expression)
+    }
+
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, bracket: &Token, index: &Option<Box<Expr>>) -> EvalResult {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        Self::index(&object, &index).map_err(|message| RuntimeError::new(bracket, &message))
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) -> EvalResult {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = self.evaluate(value)?;
+        Self::index_set(&object, index, value.clone()).map_err(|message| RuntimeError::new(bracket, &message))?;
+        Ok(value)
+    }
+
+    fn visit_lambda(
+        &mut self,
+        keyword: &Token,
+        params: &[TypedName],
+        _result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> EvalResult {
+        let function = self.new_function(keyword, None, params, body, Rc::clone(&self.environment), false)?;
+        Ok(Object::Function(self.heap.function(function)))
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> EvalResult {
+        let mut values = Vec::with_capacity(elements.len());
+        for element in elements {
+            values.push(self.visit(element)?);
+        }
+        Ok(self.heap.list(values))
+    }
+
+    fn visit_literal(&mut self, _token: &Token, value: &Object) -> EvalResult {
+        Ok(value.clone())
+    }
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> EvalResult {
+        let left = self.evaluate(left)?;
+
+        match operator.token_type {
+            TokenType::OR if Self::is_truthy(&left) => Ok(left),
+            TokenType::AND if !Self::is_truthy(&left) => Ok(left),
+            _ => self.evaluate(right),
+        }
+    }
+
+    fn visit_match(&mut self, keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) -> EvalResult {
+        let subject = self.evaluate(subject)?;
+
+        for arm in arms {
+            let mut bindings = Vec::new();
+            if !self.match_pattern(&arm.pattern, &subject, &mut bindings)? {
+                continue;
+            }
+
+            let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+            for (name, value) in bindings {
+                environment.define(name, value);
+            }
+            let environment = self.heap.environment(environment);
+            let previous = std::mem::replace(&mut self.environment, environment);
+
+            let result = match self.evaluate(&arm.guard) {
+                Ok(guard) if arm.guard.is_some() && !Self::is_truthy(&guard) => None,
+                Ok(_) => Some(self.evaluate(&arm.body)),
+                Err(error) => Some(Err(error)),
+            };
+
+            self.environment = previous;
+            if let Some(result) = result {
+                return result;
+            }
+        }
+
+        Err(RuntimeError::new(keyword, &format!("No match arm matches value '{subject}'.")))
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, name: &Token, value: &Option<Box<Expr>>) -> EvalResult {
+        let Object::Instance(instance) = self.evaluate(object)? else {
+            return Err(RuntimeError::new(name, "Only instances have fields."));
+        };
+
+        let value = self.evaluate(value)?;
+        instance.borrow_mut().set(name, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_self_ref(&mut self, keyword: &Token) -> EvalResult {
+        self.look_up(keyword)
+    }
+
+    fn visit_super(&mut self, keyword: &Token, method: &Token) -> EvalResult {
+        // "self" is always in the scope just inside the one holding "super".
+        let resolution = self.locals.get(&keyword.position()).copied();
+        let found = resolution.and_then(|resolution| {
+            let superclass = Environment::get_slot(&self.environment, resolution.depth, resolution.slot)?;
+            let instance = Environment::get_slot(&self.environment, resolution.depth.checked_sub(1)?, 0)?;
+            Some((superclass, instance))
+        });
+        let Some((Object::Box(superclass), instance)) = found else {
+            return Err(RuntimeError::new(keyword, "Can't use 'super' here."));
+        };
+
+        match superclass.find_method(method.lexeme) {
+            Some(found) => Ok(Object::Function(found.bind(instance, &mut self.heap))),
+            None => Err(RuntimeError::new(method, &format!("Undefined property '{}'.", method.lexeme))),
+        }
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Option<Box<Expr>>) -> EvalResult {
+        let right = self.evaluate(right)?;
+        Self::unary(&operator.token_type, right).map_err(|message| RuntimeError::new(operator, message))
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> EvalResult {
+        self.look_up(name)
+    }
+
+    // Generator functions run on the VM, so only a `yield` the resolver let through gets here.
+    fn visit_yield(&mut self, keyword: &Token, _value: &Option<Box<Expr>>) -> EvalResult {
+        Err(RuntimeError::new(keyword, "Can't yield outside a generator."))
+    }
+}
+
+impl StmtVisitor<ExecResult> for Interpreter {
+    fn visit_block(&mut self, statements: &[Stmt]) -> ExecResult {
+        let environment = self.heap.environment(Environment::with_enclosing(Rc::clone(&self.environment)));
+        self.execute_block(statements, environment)
+    }
+
+    fn visit_box(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Box<Expr>>,
+        fields: &[TypedName],
+        methods: &[Stmt],
+    ) -> ExecResult {
+        let superclass = match self.evaluate(superclass)? {
+            Object::Box(superclass) => Some(superclass),
+            Object::Nil => None,
+            _ => return Err(RuntimeError::new(name, "Superclass must be a box.").into()),
+        };
+
+        self.environment.borrow_mut().define(name.lexeme, Object::Nil);
+
+        // Methods of a derived box see "super" one scope above their "self".
+        let closure = match superclass {
+            Some(ref superclass) => {
+                let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                environment.define(*SUPER, Object::Box(Rc::clone(superclass)));
+                self.heap.environment(environment)
+            }
+            None => Rc::clone(&self.environment),
+        };
+
+        let owner = name.lexeme;
+        let mut method_table = HashMap::new();
+        for method in methods {
+            let Stmt::Function { name, params, body, .. } = method else { unreachable!() };
+            let function = self.new_function(name, Some(name), params, body, Rc::clone(&closure), name.lexeme == *INIT)?;
+            method_table.insert(name.lexeme, self.heap.function(function.in_box(owner)));
+        }
+
+        let class = BoxClass::new(
+            name.lexeme,
+            superclass,
+            fields.iter().map(|field| field.name.lexeme).collect(),
+            method_table,
+        );
+        let class = self.heap.class(class);
+        self.environment.borrow_mut().assign(name, Object::Box(class))?;
+        Ok(())
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) -> ExecResult {
+        self.evaluate(expression)?;
+        Ok(())
+    }
+
+    // Each item gets an environment of its own, so closures made in the body keep their item.
+    fn visit_for(&mut self, keyword: &Token, name: &Token, iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> ExecResult {
+        let value = self.evaluate(iterable)?;
+        let iterable = iterator::iterable(keyword, value, &mut |object, method| self.call_method(keyword, object, method))?;
+        let iter = iterator::of(&iterable, &mut self.heap).expect("a value loops go through");
+        while let Some(item) = iterator::next(keyword, &iter, &mut |object, method| self.call_method(keyword, object, method))? {
+            let Some(body) = body else { continue };
+            let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+            environment.define(name.lexeme, item);
+            let environment = self.heap.environment(environment);
+            self.execute_block(slice::from_ref(body), environment)?;
+        }
+        Ok(())
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        _type_params: &[Token],
+        params: &[TypedName],
+        _result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> ExecResult {
+        let function = self.new_function(name, Some(name), params, body, Rc::clone(&self.environment), false)?;
+        let function = self.heap.function(function);
+        self.environment.borrow_mut().define(name.lexeme, Object::Function(function));
+        Ok(())
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Option<Box<Expr>>,
+        then_branch: &Option<Box<Stmt>>,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> ExecResult {
+        let branch = if Self::is_truthy(&self.evaluate(condition)?) {
+            then_branch
+        } else {
+            else_branch
+        };
+
+        match branch {
+            Some(branch) => self.execute(branch),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_import(&mut self, keyword: &Token, path: &Token, names: &[Token]) -> ExecResult {
+        let module = self.import(path)?;
+        if let TokenType::FROM = keyword.token_type {
+            for name in names {
+                let value = module.get(name)?;
+                self.environment.borrow_mut().define(name.lexeme, value);
+            }
+        } else {
+            self.environment.borrow_mut().define(names[0].lexeme, Object::Module(module));
+        }
+        Ok(())
+    }
+
+    // Interfaces are only there for the type checker.
+    fn visit_interface(&mut self, _name: &Token, _methods: &[MethodSig]) -> ExecResult {
+        Ok(())
+    }
+
+    fn visit_let(&mut self, name: &Token, _annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) -> ExecResult {
+        let value = self.evaluate(initializer)?;
+        self.environment.borrow_mut().define(name.lexeme, value);
+        Ok(())
+    }
+
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) -> ExecResult {
+        let value = self.evaluate(expression)?;
+        self.print(&value);
+        Ok(())
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> ExecResult {
+        Err(Unwind::Return(self.evaluate(value)?))
+    }
+
+    fn visit_throw(&mut self, keyword: &Token, value: &Option<Box<Expr>>) -> ExecResult {
+        let value = self.evaluate(value)?;
+        Err(RuntimeError::thrown(keyword, value).into())
+    }
+
+    // An error leaving the finally clause is the exception again, thrown from the `try`, which is
+    // what the VM does.
+    fn visit_try(
+        &mut self,
+        keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) -> ExecResult {
+        let mut result = body.as_ref().map_or(Ok(()), |body| self.execute(body));
+        if let (Some(name), Some(handler)) = (name, handler) {
+            result = match result {
+                Err(Unwind::Error(error)) => {
+                    let exception = self.caught(error);
+                    let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                    environment.define(name.lexeme, exception);
+                    let environment = self.heap.environment(environment);
+                    self.execute_block(slice::from_ref(handler), environment)
+                }
+                result => result,
+            };
+        }
+
+        let Some(finally) = finally else { return result };
+        match result {
+            Err(Unwind::Error(error)) => {
+                let exception = self.caught(error);
+                self.execute(finally)?;
+                Err(RuntimeError::thrown(keyword, exception).into())
+            }
+            result => {
+                self.execute(finally)?;
+                result
+            }
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> ExecResult {
+        while Self::is_truthy(&self.evaluate(condition)?) {
+            if let Some(body) = body {
+                self.execute(body)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::interpreter::Interpreter;
+    use crate::libs::lex::Lox;
+    use crate::libs::testing::run_tree as run;
+
+    #[test]
+    fn closures() {
+        let source = "
+            fun counter() {
+                let count = 0;
+                fun next() {
+                    count = count + 1;
+                    return count;
+                }
+                return next;
+            }
+            let next = counter();
+            next();
+            print next();
+        ";
+        assert_eq!(run(source), "2\n");
+    }
+
+    #[test]
+    fn boxes() {
+        let source = "
+            box Named {
+                name;
+
+                init(name) {
+                    self.name = name;
+                }
+
+                greet(self) {
+                    return \"hello \" + self.name;
+                }
+            }
+
+            box Loud <- Named {
+                init(name) {
+                    super(name + \"!\");
+                }
+
+                greet() {
+                    return super.greet() + \"!\";
+                }
+            }
+
+            print Loud(\"box\").greet();
+        ";
+        assert_eq!(run(source), "hello box!!\n");
+    }
+
+    #[test]
+    fn lambdas() {
+        let source = "
+            fun apply(f, x) { return f(x); }
+            let double = fun (x) { return x * 2; };
+            print apply(double, 4);
+            print apply((x) -> x + 1, 4);
+            print (() -> \"no params\")();
+
+            fun counter() {
+                let count = 0;
+                return () -> count = count + 1;
+            }
+            let next = counter();
+            next();
+            print next();
+
+            box Holder {
+                callback;
+
+                init(callback) {
+                    self.callback = callback;
+                }
+            }
+            let holder = Holder((a, b) -> a * b);
+            print holder.callback(6, 7);
+        ";
+        assert_eq!(run(source), "8\n5\nno params\n2\n42\n");
+    }
+
+    #[test]
+    fn match_patterns() {
+        let source = "
+            box Point {
+                x;
+                y;
+
+                init(x, y) {
+                    self.x = x;
+                    self.y = y;
+                }
+            }
+
+            fun describe(value) {
+                return match value {
+                    0 => \"zero\",
+                    -1 => \"minus one\",
+                    \"hi\" => \"greeting\",
+                    [] => \"empty\",
+                    [only] => \"one: \" + only,
+                    [first, _, ..rest] => rest,
+                    Point { x: 0, y } => \"on y axis\",
+                    Point { x, y } if x == y => \"diagonal\",
+                    Point { } => \"point\",
+                    n if n == 500 => \"big\",
+                    _ => \"other\",
+                };
+            }
+
+            print describe(0);
+            print describe(-1);
+            print describe(\"hi\");
+            print describe([]);
+            print describe([\"a\"]);
+            print describe([1, 2, 3, 4]);
+            print describe(Point(0, 5));
+            print describe(Point(2, 2));
+            print describe(Point(1, 2));
+            print describe(500);
+            print describe(nil);
+        ";
+        assert_eq!(
+            run(source),
+            "zero\nminus one\ngreeting\nempty\none: a\n[3, 4]\non y axis\ndiagonal\npoint\nbig\nother\n"
+        );
+    }
+
+    #[test]
+    fn dead_branches_are_checked() {
+        let sources = [
+            "if (false) { return 1; }",
+            "if (false) { print self; }",
+            "while (false) { let a = 1; let a = 2; }",
+            "if (false) { let x: Number = \"s\"; }",
+        ];
+        for source in sources {
+            let statements = Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+            assert!(Interpreter::prepare(statements).is_none(), "{source} was accepted");
+        }
+    }
+}
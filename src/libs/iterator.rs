@@ -0,0 +1,226 @@
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+use crate::libs::expr::ast::Object;
+use crate::libs::generator::Generator;
+use crate::libs::heap::Heap;
+use crate::libs::interpreter::RuntimeError;
+use crate::libs::lex::Token;
+use crate::libs::map::Map;
+use crate::libs::native::type_name;
+use crate::libs::symbol::{Symbol, ITER, NEXT};
+
+// What `for` loops go through. Lists, the keys of maps, the characters of strings and ranges are
+// gone through in place, an item at a time, rather than copied first, and a generator runs up to
+// each item. A box takes part by having an `iter` method that gives an object with a `next`
+// method, which returns nil once it is done.
+
+// `start..end`, or `start..=end` when it includes its end.
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub inclusive: bool,
+}
+
+impl Range {
+    // None when the range is empty.
+    fn last(&self) -> Option<i64> {
+        let last = if self.inclusive { self.end } else { self.end.checked_sub(1)? };
+        (last >= self.start).then_some(last)
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = if self.inclusive { "..=" } else { ".." };
+        write!(f, "{}{operator}{}", self.start, self.end)
+    }
+}
+
+// Where a loop is in what it goes through.
+pub enum Iter {
+    // `len` is the length when the loop started; adding or removing elements after that is an error.
+    List { list: Rc<RefCell<Vec<Object>>>, index: usize, len: usize },
+    Keys { map: Rc<RefCell<Map>>, index: usize, len: usize },
+    // `offset` is in bytes.
+    Chars { string: Rc<String>, offset: usize },
+    Range { next: Option<i64>, last: i64 },
+    // An object whose `next` method gives the items.
+    Object(Object),
+    // A generator, which goes on until it returns: nil is an item like any other.
+    Generator(Rc<RefCell<Generator>>),
+}
+
+impl Iter {
+    // Only for the built-in kinds; the interpreters call the `next` of an object.
+    fn advance(&mut self) -> Result<Option<Object>, &'static str> {
+        match self {
+            Iter::List { list, index, len } => {
+                let list = list.borrow();
+                if list.len() != *len {
+                    return Err("List modified during iteration.");
+                }
+                let item = list.get(*index).cloned();
+                *index += usize::from(item.is_some());
+                Ok(item)
+            }
+            Iter::Keys { map, index, len } => {
+                let map = map.borrow();
+                if map.len() != *len {
+                    return Err("Map modified during iteration.");
+                }
+                let key = map.entries().get(*index).map(|(key, _)| key.clone());
+                *index += usize::from(key.is_some());
+                Ok(key)
+            }
+            Iter::Chars { string, offset } => {
+                let Some(c) = string[*offset..].chars().next() else { return Ok(None) };
+                *offset += c.len_utf8();
+                Ok(Some(Object::Str(Rc::new(c.to_string()))))
+            }
+            Iter::Range { next, last } => {
+                let Some(n) = *next else { return Ok(None) };
+                *next = (n < *last).then(|| n + 1);
+                Ok(Some(Object::Int(n)))
+            }
+            Iter::Object(_) | Iter::Generator(_) => unreachable!("objects are advanced through their next method"),
+        }
+    }
+}
+
+// Calls a method of an instance or generator with no arguments, in whichever interpreter runs the loop.
+pub type CallMethod<'a> = dyn FnMut(&Object, Symbol) -> Result<Object, RuntimeError> + 'a;
+
+// The iterator of what a loop can go through, made on the heap; an iterator is its own. An object
+// other than a built-in value is gone through with its `next` method.
+pub fn of(value: &Object, heap: &mut Heap) -> Option<Rc<RefCell<Iter>>> {
+    let iter = match value {
+        Object::List(list) => Iter::List { list: Rc::clone(list), index: 0, len: list.borrow().len() },
+        Object::Map(map) => Iter::Keys { map: Rc::clone(map), index: 0, len: map.borrow().len() },
+        Object::Str(string) => Iter::Chars { string: Rc::clone(string), offset: 0 },
+        Object::Range(range) => Iter::Range { next: range.last().map(|_| range.start), last: range.last().unwrap_or(0) },
+        Object::Iterator(iter) => return Some(Rc::clone(iter)),
+        Object::Generator(generator) => Iter::Generator(Rc::clone(generator)),
+        Object::Instance(_) => Iter::Object(value.clone()),
+        _ => return None,
+    };
+    Some(heap.iterator(iter))
+}
+
+// What a loop over `value` goes through. An instance is asked for it with its `iter` method, which
+// may give an object with a `next` method or anything a loop can go through.
+pub fn iterable(token: &Token, value: Object, call: &mut CallMethod) -> Result<Object, RuntimeError> {
+    let Object::Instance(instance) = &value else {
+        return match built_in(&value) {
+            true => Ok(value),
+            false => Err(RuntimeError::new(token, &format!("Can't loop over {}.", type_name(&value)))),
+        };
+    };
+    if instance.borrow().class.find_method(*ITER).is_none() {
+        return Err(RuntimeError::new(token, &format!("Can't loop over {}.", type_name(&value))));
+    }
+
+    match call(&value, *ITER)? {
+        iterator @ Object::Instance(_) => Ok(iterator),
+        other if built_in(&other) => Ok(other),
+        other => Err(RuntimeError::new(token, &format!("iter() must return an iterator, got {}.", type_name(&other)))),
+    }
+}
+
+fn built_in(value: &Object) -> bool {
+    matches!(
+        value,
+        Object::List(_) | Object::Map(_) | Object::Str(_) | Object::Range(_) | Object::Iterator(_) | Object::Generator(_)
+    )
+}
+
+// The next item of a built-in iterator, or None for an object, whose `next` method gives it.
+pub fn advance(iter: &Rc<RefCell<Iter>>) -> Option<Result<Option<Object>, &'static str>> {
+    match &mut *iter.borrow_mut() {
+        Iter::Object(_) | Iter::Generator(_) => None,
+        iter => Some(iter.advance()),
+    }
+}
+
+// The next item, or None once there are no more.
+pub fn next(token: &Token, iter: &Rc<RefCell<Iter>>, call: &mut CallMethod) -> Result<Option<Object>, RuntimeError> {
+    let (object, generator) = match &mut *iter.borrow_mut() {
+        Iter::Object(object) => (object.clone(), None),
+        Iter::Generator(generator) => (Object::Generator(Rc::clone(generator)), Some(Rc::clone(generator))),
+        iter => return iter.advance().map_err(|message| RuntimeError::new(token, message)),
+    };
+    // Not borrowed while `next` runs, which may use the iterator again.
+    let item = call(&object, *NEXT)?;
+    match generator {
+        // Once it has returned, what `next` gave is not an item.
+        Some(generator) => Ok((!generator.borrow().is_done()).then_some(item)),
+        None => Ok((!matches!(item, Object::Nil)).then_some(item)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::testing::run;
+
+    #[test]
+    fn built_in_iterables() {
+        let source = "
+            let m = Map();
+            m[\"a\"] = 1;
+            m[\"b\"] = 2;
+            for k in m print k;
+            for c in \"hi\" print c;
+        ";
+        assert_eq!(run(source), "a\nb\nh\ni\n");
+    }
+
+    #[test]
+    fn ranges() {
+        let source = "
+            for i in 0..2 print i;
+            for i in 1..=2 print i;
+            for i in 2..1 print i;
+        ";
+        assert_eq!(run(source), "0\n1\n1\n2\n");
+    }
+
+    #[test]
+    fn iterator_protocol() {
+        let source = "
+            box Countdown {
+                n;
+                init(n) { self.n = n; }
+                iter() { return self; }
+                next() {
+                    if (self.n == 0) return nil;
+                    self.n = self.n - 1;
+                    return self.n;
+                }
+            }
+            for n in Countdown(2) print n;
+            let it = [1, 2].iter();
+            print it.next();
+            for x in it print x;
+        ";
+        assert_eq!(run(source), "1\n0\n1\n2\n");
+    }
+
+    #[test]
+    fn each_iteration_binds_its_own_variable() {
+        let source = "
+            let fs = [];
+            for x in [1, 2] fs.push(fun() { return x; });
+            print fs[0]() + fs[1]();
+        ";
+        assert_eq!(run(source), "3\n");
+    }
+
+    #[test]
+    fn modifying_the_list_fails() {
+        let source = "
+            let xs = [1, 2];
+            try { for x in xs xs.push(x); } catch (e) { print e.message; }
+        ";
+        assert_eq!(run(source), "List modified during iteration.\n");
+    }
+}
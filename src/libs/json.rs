@@ -0,0 +1,251 @@
+use crate::libs::serializer::{DecodeError, Tree};
+
+// JSON form of a syntax tree. A node is an object whose "kind" key comes first, the other types
+// map to their JSON counterparts.
+
+const WIDTH: usize = 100;
+
+pub fn write(tree: &Tree) -> String {
+    let mut result = String::new();
+    write_tree(&mut result, tree, 0);
+    result
+}
+
+// Values that fit in the line stay on it, the others put one element per line.
+fn write_tree(out: &mut String, tree: &Tree, indent: usize) {
+    let flat = flat(tree);
+    if indent + flat.len() <= WIDTH {
+        out.push_str(&flat);
+        return;
+    }
+
+    let pad = " ".repeat(indent + 2);
+    match tree {
+        Tree::List(elements) => {
+            out.push('[');
+            for (position, element) in elements.iter().enumerate() {
+                out.push_str(if position == 0 { "\n" } else { ",\n" });
+                out.push_str(&pad);
+                write_tree(out, element, indent + 2);
+            }
+            out.push_str(&format!("\n{}]", " ".repeat(indent)));
+        }
+        Tree::Node(kind, fields) => {
+            out.push_str(&format!("{{\n{pad}\"kind\": {}", string(kind)));
+            for (name, value) in fields {
+                out.push_str(&format!(",\n{pad}{}: ", string(name)));
+                write_tree(out, value, indent + 2);
+            }
+            out.push_str(&format!("\n{}}}", " ".repeat(indent)));
+        }
+        _ => out.push_str(&flat),
+    }
+}
+
+fn flat(tree: &Tree) -> String {
+    match tree {
+        Tree::Nil => "null".to_string(),
+        Tree::Bool(b) => b.to_string(),
+        Tree::Number(n) => n.to_string(),
+        Tree::Str(s) => string(s),
+        Tree::List(elements) => format!("[{}]", elements.iter().map(flat).collect::<Vec<_>>().join(", ")),
+        Tree::Node(kind, fields) => {
+            let mut parts = vec![format!("\"kind\": {}", string(kind))];
+            parts.extend(fields.iter().map(|(name, value)| format!("{}: {}", string(name), flat(value))));
+            format!("{{{}}}", parts.join(", "))
+        }
+    }
+}
+
+pub(crate) fn string(s: &str) -> String {
+    let mut result = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+pub fn parse(text: &str) -> Result<Tree, DecodeError> {
+    let mut reader = Reader::new(text);
+    let tree = reader.value()?;
+    reader.end()?;
+    Ok(tree)
+}
+
+// Character reader shared with the S-expression form.
+pub(crate) struct Reader {
+    chars: Vec<char>,
+    current: usize,
+}
+
+impl Reader {
+    pub(crate) fn new(text: &str) -> Self {
+        Self { chars: text.chars().collect(), current: 0 }
+    }
+
+    pub(crate) fn end(&mut self) -> Result<(), DecodeError> {
+        self.skip_whitespace();
+        if self.current < self.chars.len() {
+            return Err(self.error("Expect end of input."));
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Tree, DecodeError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => {
+                self.current += 1;
+                let mut elements = Vec::new();
+                if !self.matching(']') {
+                    loop {
+                        elements.push(self.value()?);
+                        if self.matching(']') {
+                            break;
+                        }
+                        self.consume(',', "Expect ',' or ']' in array.")?;
+                    }
+                }
+                Ok(Tree::List(elements))
+            }
+            Some('"') => Ok(Tree::Str(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) if self.keyword("null") => Ok(Tree::Nil),
+            Some(_) if self.keyword("true") => Ok(Tree::Bool(true)),
+            Some(_) if self.keyword("false") => Ok(Tree::Bool(false)),
+            _ => Err(self.error("Expect a value.")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Tree, DecodeError> {
+        self.current += 1;
+        let mut kind = None;
+        let mut fields = Vec::new();
+        if !self.matching('}') {
+            loop {
+                self.skip_whitespace();
+                let name = self.string()?;
+                self.consume(':', "Expect ':' after key.")?;
+                let value = self.value()?;
+                match (name.as_str(), value) {
+                    ("kind", Tree::Str(value)) => kind = Some(value),
+                    ("kind", _) => return Err(self.error("Expect \"kind\" to be a string.")),
+                    (_, value) => fields.push((name, value)),
+                }
+                if self.matching('}') {
+                    break;
+                }
+                self.consume(',', "Expect ',' or '}' in object.")?;
+            }
+        }
+        match kind {
+            Some(kind) => Ok(Tree::Node(kind, fields)),
+            None => Err(self.error("Expect an object to have a \"kind\".")),
+        }
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, DecodeError> {
+        self.consume('"', "Expect a string.")?;
+        let mut result = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(result),
+                Some('\\') => match self.advance() {
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    Some('/') => result.push('/'),
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some('b') => result.push('\u{8}'),
+                    Some('f') => result.push('\u{c}'),
+                    Some('u') => {
+                        let digits: String = (0..4).filter_map(|_| self.advance()).collect();
+                        match u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) {
+                            Some(c) => result.push(c),
+                            None => return Err(self.error("Invalid unicode escape.")),
+                        }
+                    }
+                    _ => return Err(self.error("Invalid escape.")),
+                },
+                Some(c) => result.push(c),
+                None => return Err(self.error("Unterminated string.")),
+            }
+        }
+    }
+
+    pub(crate) fn number(&mut self) -> Result<Tree, DecodeError> {
+        let start = self.current;
+        while let Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9') = self.peek() {
+            self.current += 1;
+        }
+        let text: String = self.chars[start..self.current].iter().collect();
+        text.parse().map(Tree::Number).map_err(|_| self.error(&format!("Invalid number '{text}'.")))
+    }
+
+    pub(crate) fn word(&mut self) -> String {
+        let start = self.current;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.current += 1;
+        }
+        self.chars[start..self.current].iter().collect()
+    }
+
+    pub(crate) fn keyword(&mut self, keyword: &str) -> bool {
+        let end = self.current + keyword.len();
+        if end <= self.chars.len() && self.chars[self.current..end].iter().copied().eq(keyword.chars()) {
+            self.current = end;
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn consume(&mut self, expected: char, message: &str) -> Result<(), DecodeError> {
+        if self.matching(expected) {
+            return Ok(());
+        }
+        Err(self.error(message))
+    }
+
+    pub(crate) fn matching(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.current += 1;
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.current += 1;
+        }
+    }
+
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).copied()
+    }
+
+    pub(crate) fn advance(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.current += 1;
+        c
+    }
+
+    pub(crate) fn error(&self, message: &str) -> DecodeError {
+        let before = &self.chars[..self.current.min(self.chars.len())];
+        let line = before.iter().filter(|c| **c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
+        DecodeError::new(&format!("[{line}:{column}] {message}"))
+    }
+}
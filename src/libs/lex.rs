@@ -1,38 +1,58 @@
-use crate::libs::expr::ast::Object;
+use crate::libs::expr::ast::{Object, Stmt};
 use std::collections::HashMap;
-use std::fmt::Debug;
-use std::{env, fs, io};
-use crate::libs::expr::visitor::Visitor;
+use std::fmt::{self, Debug, Display};
+use std::rc::Rc;
+use std::{fs, io};
+use crate::libs::bigint::BigInt;
+use crate::libs::interpreter::Interpreter;
 use crate::libs::parser::Parser;
+use crate::libs::symbol::Symbol;
 
 #[derive(Clone)]
 pub enum LiteralValue {
-    Number(f64),
-    String(String),
+    Float(f64),
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    String(Symbol),
     Nil,
 }
 
-impl ToString for LiteralValue {
-    fn to_string(&self) -> String {
+impl Display for LiteralValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LiteralValue::Number(n) => n.to_string(),
-            LiteralValue::String(s) => s.clone(),
-            LiteralValue::Nil => "Nil".to_string(),
+            LiteralValue::Float(n) => write!(f, "{n:?}"),
+            LiteralValue::Int(n) => write!(f, "{n}"),
+            LiteralValue::BigInt(n) => write!(f, "{n}"),
+            LiteralValue::String(s) => write!(f, "{s}"),
+            LiteralValue::Nil => write!(f, "Nil"),
         }
     }
 }
 
 impl LiteralValue {
+    // The value of a number's lexeme: a float with a decimal point, an integer without one.
+    pub fn number(lexeme: &str) -> Option<LiteralValue> {
+        if lexeme.contains('.') {
+            return lexeme.parse().ok().map(LiteralValue::Float);
+        }
+        match lexeme.parse() {
+            Ok(n) => Some(LiteralValue::Int(n)),
+            Err(_) => BigInt::parse(lexeme).map(|n| LiteralValue::BigInt(Rc::new(n))),
+        }
+    }
+
     pub fn to_object(&self) -> Object {
         match self {
-            LiteralValue::Number(n) => Object::Number(*n),
-            LiteralValue::String(s) => Object::Str(s.clone()),
+            LiteralValue::Float(n) => Object::Float(*n),
+            LiteralValue::Int(n) => Object::Int(*n),
+            LiteralValue::BigInt(n) => Object::BigInt(Rc::clone(n)),
+            LiteralValue::String(s) => Object::Str(Rc::new(s.to_string())),
             LiteralValue::Nil => Object::Nil,
         }
     }
 }
 
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, Debug)]
 pub enum TokenType {
     // Single characters tokens
@@ -40,15 +60,25 @@ pub enum TokenType {
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
     COMMA,
+    COLON,
     DOT,
     MINUS,
     PLUS,
     SEMICOLON,
     SLASH,
     STAR,
+    PERCENT,
 
     // One/two characters tokens
+    TILDE_SLASH,
+    ARROW,
+    LEFT_ARROW,
+    FAT_ARROW,
+    DOT_DOT,
+    DOT_DOT_EQUAL,
     BANG,
     BANG_EQUAL,
     EQUAL,
@@ -68,7 +98,9 @@ pub enum TokenType {
     ELSE,
     FUN,
     FOR,
+    IN,
     IF,
+    INTERFACE,
     OR,
     PRINT,
     RETURN,
@@ -78,56 +110,121 @@ pub enum TokenType {
     FALSE,
     AND,
     LET,
+    MATCH,
     WHILE,
     NIL,
+    IMPORT,
+    FROM,
+    AS,
+    TRY,
+    CATCH,
+    FINALLY,
+    THROW,
+    YIELD,
 
     EOF,
 }
 
+impl TokenType {
+    // Every token type, in the order of the enum.
+    pub const ALL: [TokenType; 60] = [
+        TokenType::LEFT_PAREN, TokenType::RIGHT_PAREN, TokenType::LEFT_BRACE, TokenType::RIGHT_BRACE,
+        TokenType::LEFT_BRACKET, TokenType::RIGHT_BRACKET, TokenType::COMMA, TokenType::COLON,
+        TokenType::DOT, TokenType::MINUS, TokenType::PLUS, TokenType::SEMICOLON, TokenType::SLASH,
+        TokenType::STAR, TokenType::PERCENT, TokenType::TILDE_SLASH, TokenType::ARROW, TokenType::LEFT_ARROW, TokenType::FAT_ARROW, TokenType::DOT_DOT,
+        TokenType::DOT_DOT_EQUAL,
+        TokenType::BANG, TokenType::BANG_EQUAL, TokenType::EQUAL, TokenType::EQUAL_EQUAL,
+        TokenType::GREATER, TokenType::GREATER_EQUAL, TokenType::LESS, TokenType::LESS_EQUAL,
+        TokenType::IDENTIFIER, TokenType::STRING, TokenType::NUMBER, TokenType::BOX, TokenType::ELSE,
+        TokenType::FUN, TokenType::FOR, TokenType::IN, TokenType::IF, TokenType::INTERFACE, TokenType::OR, TokenType::PRINT, TokenType::RETURN,
+        TokenType::SUPER, TokenType::SELF, TokenType::TRUE, TokenType::FALSE, TokenType::AND,
+        TokenType::LET, TokenType::MATCH, TokenType::WHILE, TokenType::NIL, TokenType::IMPORT, TokenType::FROM,
+        TokenType::AS, TokenType::TRY, TokenType::CATCH, TokenType::FINALLY, TokenType::THROW, TokenType::YIELD, TokenType::EOF,
+    ];
+
+    // The inverse of the `Debug` name, like "LEFT_PAREN".
+    pub fn from_name(name: &str) -> Option<TokenType> {
+        Self::ALL.into_iter().find(|token_type| format!("{token_type:?}") == name)
+    }
+}
+
 #[derive(Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub literal: LiteralValue,
     pub(crate) line: usize,
-    pub lexeme: String,
+    // Counted in characters from 1, like `line`.
+    pub(crate) column: usize,
+    // The file the token was read from, so that positions in different modules don't meet.
+    pub(crate) file: usize,
+    // Where a token made up for other code ends, like a literal folded from a longer expression. A
+    // scanned token ends where its lexeme does.
+    pub(crate) end: Option<(u32, u32)>,
+    pub lexeme: Symbol,
 }
 
 impl Token {
-    fn new(token_type: TokenType, literal: LiteralValue, line: usize, lexeme: String) -> Self {
+    fn new(token_type: TokenType, literal: LiteralValue, line: usize, column: usize, lexeme: Symbol) -> Self {
         Self {
             token_type,
             literal,
             line,
+            column,
+            file: 0,
+            end: None,
             lexeme,
         }
     }
+
+    // Tells apart the uses of a name, in whichever file they are.
+    pub(crate) fn position(&self) -> (usize, usize, usize) {
+        (self.file, self.line, self.column)
+    }
+
+    // A token for code that only knows the line, like the VM reporting a runtime error.
+    pub(crate) fn synthetic(token_type: TokenType, lexeme: Symbol, line: usize) -> Self {
+        Self::new(token_type, LiteralValue::Nil, line, 0, lexeme)
+    }
+}
+
+// The scanner skips comments but keeps them aside for tools that rewrite the source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub line: usize,
+    pub column: usize,
 }
 
-impl ToString for Token {
-    fn to_string(&self) -> String {
-        format!(
-            "{} {} {}",
-            self.token_type.to_string(),
-            self.lexeme,
-            self.literal.to_string()
-        )
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.token_type, self.lexeme, self.literal)
     }
 }
 
-impl ToString for TokenType {
-    fn to_string(&self) -> String {
-        match self {
+impl Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
             TokenType::LEFT_PAREN => "(",
             TokenType::RIGHT_PAREN => ")",
             TokenType::LEFT_BRACE => "{",
             TokenType::RIGHT_BRACE => "}",
+            TokenType::LEFT_BRACKET => "[",
+            TokenType::RIGHT_BRACKET => "]",
             TokenType::COMMA => ",",
+            TokenType::COLON => ":",
             TokenType::DOT => ".",
             TokenType::MINUS => "-",
             TokenType::PLUS => "+",
             TokenType::SEMICOLON => ";",
             TokenType::SLASH => "/",
             TokenType::STAR => "*",
+            TokenType::PERCENT => "%",
+            TokenType::TILDE_SLASH => "~/",
+            TokenType::ARROW => "->",
+            TokenType::LEFT_ARROW => "<-",
+            TokenType::FAT_ARROW => "=>",
+            TokenType::DOT_DOT => "..",
+            TokenType::DOT_DOT_EQUAL => "..=",
             TokenType::BANG => "!",
             TokenType::BANG_EQUAL => "!=",
             TokenType::EQUAL => "=",
@@ -143,7 +240,9 @@ impl ToString for TokenType {
             TokenType::ELSE => "else",
             TokenType::FUN => "fun",
             TokenType::FOR => "for",
+            TokenType::IN => "in",
             TokenType::IF => "if",
+            TokenType::INTERFACE => "interface",
             TokenType::OR => "or",
             TokenType::PRINT => "print",
             TokenType::RETURN => "return",
@@ -153,16 +252,27 @@ impl ToString for TokenType {
             TokenType::FALSE => "false",
             TokenType::AND => "and",
             TokenType::LET => "let",
+            TokenType::MATCH => "match",
             TokenType::WHILE => "while",
             TokenType::NIL => "nil",
+            TokenType::IMPORT => "import",
+            TokenType::FROM => "from",
+            TokenType::AS => "as",
+            TokenType::TRY => "try",
+            TokenType::CATCH => "catch",
+            TokenType::FINALLY => "finally",
+            TokenType::THROW => "throw",
+            TokenType::YIELD => "yield",
             TokenType::EOF => "EOF",
-        }
-            .to_string()
+        };
+        write!(f, "{name}")
     }
 }
 
 pub struct Lox {
     file: String,
+    // Given to every token; the main file is 0 and each imported module gets a number of its own.
+    file_id: usize,
 
     start_pos: usize,
     current_pos: usize,
@@ -170,23 +280,32 @@ pub struct Lox {
     line: usize,
 
     keywords: HashMap<&'static str, TokenType>,
+    comments: Vec<Comment>,
 }
 
 impl Lox {
-    pub fn init() -> Result<Self, io::Error> {
-        let file_name = env::args().nth(1).unwrap_or_else(|| {
+    pub fn init(file_name: Option<String>) -> Result<Self, io::Error> {
+        let file_name = file_name.unwrap_or_else(|| {
             println!("No input file name. \"main.slsf\" will be used instead.");
             //slsf - simple language source file
             "main.slsf".to_string()
         });
         let file = fs::read_to_string(file_name)?;
+
+        Ok(Self::new(file))
+    }
+
+    pub fn new(file: String) -> Self {
         let keywords: HashMap<&'static str, TokenType> = HashMap::from([
             ("or", TokenType::OR),
             ("and", TokenType::AND),
             ("box", TokenType::BOX),
             ("else", TokenType::ELSE),
             ("if", TokenType::IF),
+            ("interface", TokenType::INTERFACE),
             ("fun", TokenType::FUN),
+            ("for", TokenType::FOR),
+            ("in", TokenType::IN),
             ("print", TokenType::PRINT),
             ("return", TokenType::RETURN),
             ("super", TokenType::SUPER),
@@ -194,12 +313,22 @@ impl Lox {
             ("true", TokenType::TRUE),
             ("false", TokenType::FALSE),
             ("let", TokenType::LET),
+            ("match", TokenType::MATCH),
             ("while", TokenType::WHILE),
             ("nil", TokenType::NIL),
+            ("import", TokenType::IMPORT),
+            ("from", TokenType::FROM),
+            ("as", TokenType::AS),
+            ("try", TokenType::TRY),
+            ("catch", TokenType::CATCH),
+            ("finally", TokenType::FINALLY),
+            ("throw", TokenType::THROW),
+            ("yield", TokenType::YIELD),
         ]);
 
-        Ok(Self {
+        Self {
             file,
+            file_id: 0,
 
             start_pos: 0,
             current_pos: 0,
@@ -207,7 +336,24 @@ impl Lox {
             line: 1,
 
             keywords,
-        })
+            comments: Vec::new(),
+        }
+    }
+
+    pub fn with_file_id(mut self, file_id: usize) -> Self {
+        self.file_id = file_id;
+        self
+    }
+
+    // The comments met by `get_token_list`, in source order.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    fn add_comment(&mut self, line: usize) {
+        let text = self.file[self.start_pos..self.current_pos].trim_end().to_string();
+        let column = self.column(self.start_pos);
+        self.comments.push(Comment { text, line, column });
     }
 
     fn error(&mut self, line: usize, message: &str) {
@@ -220,36 +366,35 @@ impl Lox {
     }
 
     pub fn run(&mut self) {
+        if let Some(statements) = self.parse() {
+            Interpreter::new().run(statements);
+        }
+    }
+
+    // Scans and parses the file; errors are reported as they are found.
+    pub fn parse(&mut self) -> Option<Vec<Stmt>> {
         let tokens = self.get_token_list();
 
-        println!("Tokens count: {}", tokens.len());
+        log::debug!("Tokens count: {}", tokens.len());
         for token in &tokens {
-            println!("{}", token.to_string())
+            log::debug!("{token}")
         }
         if self.gotten_error {
-            return;
-        }
-        let mut parser = Parser::new(tokens);
-        let expr = parser.parse();
-        if let Ok(expr) = expr {
-            let mut ast_printer = crate::libs::ast_printer::AstPrinter {};
-
-            println!("{}", ast_printer.visit_expr(&expr).unwrap());
+            return None;
         }
+        Parser::new(tokens).parse().ok()
     }
 
     fn scan_identifier(&mut self) -> TokenType {
-        let start = self.current_pos;
         while let Some('0'..='9' | 'a'..='z' | 'A'..='Z' | '_') = self.peek_by(0) {
             self.advance();
         }
 
-        let text = self.file[start - 1..self.current_pos].to_string();
-        let token_option = self.keywords.get(&text as &str);
-        (if let None = token_option {
-            return TokenType::IDENTIFIER;
-        });
-        *token_option.unwrap()
+        let text = &self.file[self.start_pos..self.current_pos];
+        match self.keywords.get(text) {
+            Some(token_type) => *token_type,
+            None => TokenType::IDENTIFIER,
+        }
     }
 
     fn scan_string(&mut self) -> TokenType {
@@ -278,7 +423,9 @@ impl Lox {
             if let Some('0'..='9') = self.peek_by(1) {
                 self.advance();
 
-                while let Some('0'..='9') = self.advance() {}
+                while let Some('0'..='9') = self.peek_by(0) {
+                    self.advance();
+                }
             }
         }
 
@@ -286,27 +433,30 @@ impl Lox {
     }
 
     fn matching(&mut self, expect: char) -> bool {
-        if let Some(e) = self.file.chars().nth(self.current_pos) {
-            if e == expect {
-                self.current_pos += 1;
-                return true;
-            }
+        if self.peek_by(0) == Some(expect) {
+            self.current_pos += expect.len_utf8();
+            return true;
         }
         false
     }
 
+    // Positions are byte offsets into the file, so every step moves by the width of a whole character.
     fn peek_by(&self, pos: usize) -> Option<char> {
-        self.file.chars().nth(self.current_pos + pos)
+        self.file[self.current_pos..].chars().nth(pos)
     }
 
     fn advance(&mut self) -> Option<char> {
-        self.current_pos += 1;
-        self.file.chars().nth(self.current_pos - 1)
+        let next = self.peek_by(0)?;
+        self.current_pos += next.len_utf8();
+        Some(next)
     }
 
     fn advance_by(&mut self, n: usize) -> Option<char> {
-        self.current_pos += n;
-        self.file.chars().nth(self.current_pos - n)
+        let first = self.advance();
+        for _ in 1..n {
+            self.advance();
+        }
+        first
     }
 
     // fn add_token(list: &mut Vec<TokenType>, token_type: TokenType) {
@@ -316,42 +466,68 @@ impl Lox {
     fn add_token(&mut self, list: &mut Vec<Token>, token_type: TokenType) {
         let lexeme = self.file[self.start_pos..self.current_pos].to_string();
         let literal = match token_type {
-            TokenType::NUMBER => LiteralValue::Number(
-                self.file[self.start_pos..self.current_pos]
-                    .parse::<f64>()
-                    .unwrap_or_else(|_| {
-                        self.error(self.line, "Failed to parse number.");
-                        f64::NAN
-                    }),
-            ),
-            TokenType::STRING => LiteralValue::String(
-                self.file[self.start_pos + 1..self.current_pos - 1].to_string(),
-            ),
+            TokenType::NUMBER => LiteralValue::number(&lexeme).unwrap_or_else(|| {
+                self.error(self.line, "Failed to parse number.");
+                LiteralValue::Float(f64::NAN)
+            }),
+            // An unterminated string has no closing quote to drop.
+            TokenType::STRING => LiteralValue::String(Symbol::intern(lexeme[1..].strip_suffix('"').unwrap_or(&lexeme[1..]))),
             _ => LiteralValue::Nil,
         };
-        list.push(Token {
-            token_type,
-            literal,
-            lexeme,
-            line: self.line,
-        })
+        // A string can span several lines and the token starts on the first of them.
+        let line = self.line - lexeme.matches('\n').count();
+        let column = self.column(self.start_pos);
+        list.push(Token { file: self.file_id, ..Token::new(token_type, literal, line, column, Symbol::intern(&lexeme)) })
+    }
+
+    fn column(&self, position: usize) -> usize {
+        let line_start = self.file[..position].rfind('\n').map_or(0, |newline| newline + 1);
+        self.file[line_start..position].chars().count() + 1
     }
 
-    fn get_token_list(&mut self) -> Vec<Token> {
+    pub fn get_token_list(&mut self) -> Vec<Token> {
         let mut list: Vec<Token> = Vec::new();
-        while let Some(a) = self.advance() {
-            self.start_pos = self.current_pos - 1;
+        loop {
+            self.start_pos = self.current_pos;
+            let Some(a) = self.advance() else { break };
             match a {
                 '(' => self.add_token(&mut list, TokenType::LEFT_PAREN),
                 ')' => self.add_token(&mut list, TokenType::RIGHT_PAREN),
-                '{' => self.add_token(&mut list, TokenType::RIGHT_BRACE),
-                '}' => self.add_token(&mut list, TokenType::LEFT_BRACE),
+                '{' => self.add_token(&mut list, TokenType::LEFT_BRACE),
+                '}' => self.add_token(&mut list, TokenType::RIGHT_BRACE),
+                '[' => self.add_token(&mut list, TokenType::LEFT_BRACKET),
+                ']' => self.add_token(&mut list, TokenType::RIGHT_BRACKET),
                 ',' => self.add_token(&mut list, TokenType::COMMA),
+                ':' => self.add_token(&mut list, TokenType::COLON),
                 ';' => self.add_token(&mut list, TokenType::SEMICOLON),
-                '.' => self.add_token(&mut list, TokenType::DOT),
-                '-' => self.add_token(&mut list, TokenType::MINUS),
+                '.' => {
+                    if self.matching('.') {
+                        if self.matching('=') {
+                            self.add_token(&mut list, TokenType::DOT_DOT_EQUAL)
+                        } else {
+                            self.add_token(&mut list, TokenType::DOT_DOT)
+                        }
+                    } else {
+                        self.add_token(&mut list, TokenType::DOT)
+                    }
+                }
+                '-' => {
+                    if self.matching('>') {
+                        self.add_token(&mut list, TokenType::ARROW)
+                    } else {
+                        self.add_token(&mut list, TokenType::MINUS)
+                    }
+                }
                 '+' => self.add_token(&mut list, TokenType::PLUS),
                 '*' => self.add_token(&mut list, TokenType::STAR),
+                '%' => self.add_token(&mut list, TokenType::PERCENT),
+                '~' => {
+                    if self.matching('/') {
+                        self.add_token(&mut list, TokenType::TILDE_SLASH)
+                    } else {
+                        self.error(self.line, "Unexpected character.");
+                    }
+                }
 
                 '!' => {
                     if self.matching('=') {
@@ -363,6 +539,8 @@ impl Lox {
                 '<' => {
                     if self.matching('=') {
                         self.add_token(&mut list, TokenType::LESS_EQUAL)
+                    } else if self.matching('-') {
+                        self.add_token(&mut list, TokenType::LEFT_ARROW)
                     } else {
                         self.add_token(&mut list, TokenType::LESS)
                     }
@@ -377,6 +555,8 @@ impl Lox {
                 '=' => {
                     if self.matching('=') {
                         self.add_token(&mut list, TokenType::EQUAL_EQUAL)
+                    } else if self.matching('>') {
+                        self.add_token(&mut list, TokenType::FAT_ARROW)
                     } else {
                         self.add_token(&mut list, TokenType::EQUAL)
                     }
@@ -386,12 +566,11 @@ impl Lox {
                     if self.matching('/') {
                         while let Some(s) = self.peek_by(0) {
                             if s == '\n' {
-                                self.line += 1;
-                                self.advance();
                                 break;
                             }
                             self.advance();
                         }
+                        self.add_comment(self.line);
                     } else if self.matching('*') {
                         // Challenges 4.
                         let mut closed = false;
@@ -406,9 +585,9 @@ impl Lox {
                             } else if s == '\n' {
                                 self.line += 1;
                             }
-                            print!("{s}");
                             self.advance();
                         }
+                        self.add_comment(start);
                         if !closed {
                             self.error(
                                 self.line,
@@ -433,7 +612,7 @@ impl Lox {
                     if a.is_numeric() {
                         let token_type = self.scan_number();
                         self.add_token(&mut list, token_type);
-                    } else if a.is_alphabetic() {
+                    } else if a.is_alphabetic() || a == '_' {
                         let token_type = self.scan_identifier();
                         self.add_token(&mut list, token_type);
                     } else {
@@ -444,8 +623,11 @@ impl Lox {
         }
         list.push(Token {
             token_type: TokenType::EOF,
-            lexeme: "".to_string(),
+            lexeme: Symbol::intern(""),
             line: self.line,
+            column: self.column(self.file.len()),
+            file: self.file_id,
+            end: None,
             literal: LiteralValue::Nil,
         });
         list
@@ -454,10 +636,10 @@ impl Lox {
 
 #[cfg(test)]
 mod test {
-    use crate::libs::lex::LiteralValue::{Nil, Number, String};
-    use crate::libs::lex::TokenType::{EOF, EQUAL, IDENTIFIER, LET, NUMBER, SEMICOLON, STRING};
+    use crate::libs::lex::LiteralValue::{Nil, String};
+    use crate::libs::lex::TokenType::{EOF, EQUAL, IDENTIFIER, LET, SEMICOLON, STRING};
     use crate::libs::lex::{Token, TokenType};
-    use crate::Lox;
+    use crate::libs::lex::Lox;
     use std::collections::HashMap;
 
     #[test]
@@ -478,12 +660,20 @@ mod test {
             ("let", TokenType::LET),
             ("while", TokenType::WHILE),
             ("nil", TokenType::NIL),
+            ("import", TokenType::IMPORT),
+            ("from", TokenType::FROM),
+            ("as", TokenType::AS),
+            ("try", TokenType::TRY),
+            ("catch", TokenType::CATCH),
+            ("finally", TokenType::FINALLY),
+            ("throw", TokenType::THROW),
         ]);
 
         let file = "let x = \"smth\";".to_string();
 
         let mut lex = Lox {
             file,
+            file_id: 0,
 
             start_pos: 0,
             current_pos: 0,
@@ -491,24 +681,46 @@ mod test {
             line: 1,
 
             keywords,
+            comments: Vec::new(),
         };
         let a = lex.get_token_list();
-        let a_correct = vec![
-            Token::new(LET, Nil, 1, "let".to_string()),
-            Token::new(IDENTIFIER, Nil, 1, "x".to_string()),
-            Token::new(EQUAL, Nil, 1, "=".to_string()),
+        let a_correct = [
+            Token::new(LET, Nil, 1, 1, "let".into()),
+            Token::new(IDENTIFIER, Nil, 1, 5, "x".into()),
+            Token::new(EQUAL, Nil, 1, 7, "=".into()),
             Token::new(
                 STRING,
-                String("smth".to_string()),
+                String("smth".into()),
                 1,
-                "\"smth\"".to_string(),
+                9,
+                "\"smth\"".into(),
             ),
-            Token::new(SEMICOLON, Nil, 1, ";".to_string()),
-            Token::new(EOF, Nil, 1, "".to_string()),
+            Token::new(SEMICOLON, Nil, 1, 15, ";".into()),
+            Token::new(EOF, Nil, 1, 16, "".into()),
         ];
         assert_eq!(a.len(), a_correct.len());
         for i in 0..a.len() {
             assert_eq!(a[i].to_string(), a_correct[i].to_string());
+            assert_eq!(a[i].column, a_correct[i].column);
         }
     }
+
+    #[test]
+    fn non_ascii() {
+        let mut lex = Lox::new("// café\nlet é = \"naïve\"; /* ü */ x".to_string());
+        let tokens = lex.get_token_list();
+        let columns: Vec<_> = tokens.iter().map(|token| (token.lexeme.to_string(), token.column)).collect();
+        assert_eq!(columns, [
+            ("let".to_string(), 1),
+            ("é".to_string(), 5),
+            ("=".to_string(), 7),
+            ("\"naïve\"".to_string(), 9),
+            (";".to_string(), 16),
+            ("x".to_string(), 26),
+            ("".to_string(), 27),
+        ]);
+        assert_eq!(tokens[3].literal.to_string(), "naïve");
+        let comments: Vec<_> = lex.comments().iter().map(|comment| (comment.text.as_str(), comment.line, comment.column)).collect();
+        assert_eq!(comments, [("// café", 1, 1), ("/* ü */", 2, 18)]);
+    }
 }
@@ -0,0 +1,220 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::libs::environment::Environment;
+use crate::libs::exception::TraceFrame;
+use crate::libs::expr::ast::Stmt;
+use crate::libs::interpreter::RuntimeError;
+use crate::libs::lex::{Lox, Token};
+use crate::libs::module::Module;
+use crate::libs::native::{self, NativeFn};
+use crate::libs::symbol::Symbol;
+
+// What `Loader::start` found for an import.
+pub enum Load {
+    // The module ran before; it is not run again.
+    Loaded(Rc<Module>),
+    // The statements of a module to run, in globals of its own, then to give to `finish`.
+    Parsed(Vec<Stmt>),
+}
+
+// A file being run, under the name it was imported by.
+struct Source {
+    path: PathBuf,
+    name: String,
+}
+
+// Finds, reads and keeps the modules a program imports, for both backends, which run them. A path
+// is looked for next to the file importing it, then in each directory of the search path.
+pub struct Loader {
+    search_path: Vec<PathBuf>,
+    // By their canonical path, so that two ways of writing a path load the same module.
+    modules: HashMap<PathBuf, Rc<Module>>,
+    // The files being run, each imported by the one before it; the first is the main file, when the
+    // program has one.
+    loading: Vec<Source>,
+    // The native functions registered by the host, which every module gets with the built-in ones.
+    natives: Vec<(String, usize, NativeFn)>,
+    // The name of each file read, by file id; the main file is 0.
+    files: Vec<String>,
+}
+
+impl Loader {
+    pub fn new(main: Option<&Path>, search_path: Vec<PathBuf>) -> Self {
+        let loading: Vec<Source> = main
+            .map(|main| Source { path: canonical(main), name: main.display().to_string() })
+            .into_iter()
+            .collect();
+        let main = loading.first().map_or("<input>".to_string(), |source| source.name.clone());
+        Self {
+            search_path,
+            modules: HashMap::new(),
+            loading,
+            natives: Vec::new(),
+            files: vec![main],
+        }
+    }
+
+    pub(crate) fn register(&mut self, name: &str, arity: usize, function: NativeFn) {
+        self.natives.push((name.to_string(), arity, function));
+    }
+
+    // Fresh globals for a module, with the functions every program can call.
+    pub(crate) fn globals(&self) -> Rc<RefCell<Environment>> {
+        let mut globals = Environment::new();
+        native::define_builtins(&mut globals);
+        for (name, arity, function) in &self.natives {
+            native::define(&mut globals, name, *arity, *function);
+        }
+        Rc::new(RefCell::new(globals))
+    }
+
+    // Finds the module `path` names and, unless it ran before, reads it. A module that is still
+    // running when it is imported again is a cycle, reported with the chain of imports.
+    pub(crate) fn start(&mut self, path: &Token) -> Result<Load, RuntimeError> {
+        let name = path.lexeme.trim_matches('"');
+        let Some(file) = self.find(name) else {
+            return Err(RuntimeError::new(path, &format!("Can't find module '{name}'.")));
+        };
+        if let Some(module) = self.modules.get(&file) {
+            return Ok(Load::Loaded(Rc::clone(module)));
+        }
+        if self.loading.iter().any(|source| source.path == file) {
+            let chain: Vec<&str> = self.loading.iter().map(|source| source.name.as_str()).chain([name]).collect();
+            return Err(RuntimeError::new(path, &format!("Import cycle: {}.", chain.join(" -> "))));
+        }
+
+        let source = fs::read_to_string(&file)
+            .map_err(|error| RuntimeError::new(path, &format!("Can't read module '{name}': {error}")))?;
+        self.files.push(name.to_string());
+        let Some(statements) = Lox::new(source).with_file_id(self.files.len() - 1).parse() else {
+            return Err(RuntimeError::new(path, &format!("Failed to parse module '{name}'.")));
+        };
+        self.loading.push(Source { path: file, name: name.to_string() });
+        Ok(Load::Parsed(statements))
+    }
+
+    // Keeps the module that `start` gave the statements of, once they ran with `globals`.
+    pub(crate) fn finish(&mut self, globals: Rc<RefCell<Environment>>) -> Rc<Module> {
+        let source = self.loading.pop().expect("a module is loading");
+        let name = source.path.file_stem().map_or(source.name.clone(), |stem| stem.to_string_lossy().into_owned());
+        let module = Rc::new(Module::new(Symbol::intern(&name), globals));
+        self.modules.insert(source.path, Rc::clone(&module));
+        module
+    }
+
+    // Forgets the module that `start` gave the statements of, which failed; importing it again
+    // tries again.
+    pub(crate) fn abandon(&mut self) {
+        self.loading.pop();
+    }
+
+    // The frames of a stack trace, one per line, as `at function (file:line)`.
+    pub(crate) fn trace(&self, frames: &[TraceFrame]) -> String {
+        let lines: Vec<String> = frames
+            .iter()
+            .map(|frame| {
+                let file = self.files.get(frame.file).map_or("?", String::as_str);
+                format!("at {} ({file}:{})", frame.function, frame.line)
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        let importer = self.loading.last().and_then(|source| source.path.parent()).unwrap_or(Path::new("."));
+        [importer].into_iter()
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(name))
+            .find(|candidate| candidate.is_file())
+            .map(|file| canonical(&file))
+    }
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        Self::new(None, Vec::new())
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use std::{env, fs, process};
+    use crate::libs::lex::{Token, TokenType};
+    use crate::libs::loader::{Load, Loader};
+    use crate::libs::testing::run_both_with;
+
+    // Writes the files to a directory of their own, named after the test.
+    fn write(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("sinterpreter-{test}-{}", process::id()));
+        for (name, source) in files {
+            let path = directory.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        directory
+    }
+
+    // Runs main.slsf of the directory on both backends, with library/ on the search path.
+    fn run(directory: &Path) -> (bool, String) {
+        let main = directory.join("main.slsf");
+        let loader = || Loader::new(Some(&main), vec![directory.join("library")]);
+        let source = fs::read_to_string(&main).unwrap();
+        run_both_with(&source, |interpreter| interpreter.loader = loader(), |vm| vm.loader = loader())
+    }
+
+    #[test]
+    fn imports() {
+        let main = "
+            import \"util.slsf\" as util;
+            from \"util.slsf\" import twice, Counter;
+            let name = \"main\";
+            print util.name + \" \" + name + \" \" + util.own_name();
+            print twice(21);
+            let counter = Counter();
+            print counter.next() + counter.next();
+        ";
+        let util = "
+            print \"util runs\";
+            let name = \"util\";
+            fun own_name() { return name; }
+            fun twice(n) { return n * 2; }
+            box Counter { count; init() { self.count = 0; } next() { self.count = self.count + 1; return self.count; } }
+        ";
+        let directory = write("imports", &[("main.slsf", main), ("util.slsf", util)]);
+        assert_eq!(run(&directory), (true, "util runs\nutil main util\n42\n3\n".to_string()));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn search_path() {
+        let main = "
+            from \"greeting.slsf\" import hello;
+            print hello;
+        ";
+        let greeting = "let hello = \"hello from \" + type_of(1);";
+        let directory = write("search-path", &[("main.slsf", main), ("library/greeting.slsf", greeting)]);
+        assert_eq!(run(&directory), (true, "hello from Int\n".to_string()));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn import_cycles() {
+        // A module imported while it runs is a cycle.
+        let directory = write("cycles", &[("a.slsf", "import \"b.slsf\" as b;"), ("b.slsf", "import \"a.slsf\" as a;")]);
+        let path = |name: &str| Token::synthetic(TokenType::STRING, format!("\"{name}\"").as_str().into(), 1);
+        let a = directory.join("a.slsf");
+        let mut loader = Loader::new(Some(&a), Vec::new());
+        assert!(matches!(loader.start(&path("b.slsf")), Ok(Load::Parsed(_))));
+        let error = loader.start(&path("a.slsf")).err().map(|error| error.message);
+        assert_eq!(error, Some(format!("Import cycle: {} -> b.slsf -> a.slsf.", a.display())));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::libs::bigint::BigInt;
+use crate::libs::expr::ast::Object;
+use crate::libs::number;
+
+// What a map can be keyed by: values compared by content. Integral floats are keyed as the
+// integers they equal, and other floats by their bits, so that keys equal with `==` are the same key.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Float(u64),
+    Str(Rc<String>),
+    Bool(bool),
+    Nil,
+}
+
+impl Key {
+    // None for values that can't be keys.
+    pub fn of(object: &Object) -> Option<Key> {
+        match object {
+            Object::Int(n) => Some(Key::Int(*n)),
+            Object::BigInt(n) => Some(Key::BigInt(Rc::clone(n))),
+            Object::Float(n) => match BigInt::from_f64(*n) {
+                Some(n) => Key::of(&number::int(n)),
+                None => (!n.is_nan()).then(|| Key::Float(n.to_bits())),
+            },
+            Object::Str(s) => Some(Key::Str(Rc::clone(s))),
+            Object::Bool(b) => Some(Key::Bool(*b)),
+            Object::Nil => Some(Key::Nil),
+            _ => None,
+        }
+    }
+}
+
+// A map from keys to values that keeps the order entries were first added in.
+#[derive(Default)]
+pub struct Map {
+    entries: Vec<(Object, Object)>,
+    index: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &Key) -> Option<Object> {
+        self.index.get(key).map(|&i| self.entries[i].1.clone())
+    }
+
+    pub fn contains(&self, key: &Key) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: Key, object: Object, value: Object) {
+        match self.index.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index.insert(key, self.entries.len());
+                self.entries.push((object, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Object> {
+        let i = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for later in self.index.values_mut().filter(|later| **later > i) {
+            *later -= 1;
+        }
+        Some(value)
+    }
+
+    pub fn entries(&self) -> &[(Object, Object)] {
+        &self.entries
+    }
+
+    // Drops everything the map holds, for the heap to break cycles through it.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+}
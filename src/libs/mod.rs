@@ -2,4 +2,38 @@ pub mod lex;
 pub mod expr;
 pub mod parser;
 pub mod ast_printer;
-pub mod interpreter;
\ No newline at end of file
+pub mod interpreter;
+pub mod environment;
+pub mod function;
+pub mod instance;
+pub mod span;
+pub mod serializer;
+pub mod json;
+pub mod sexp;
+pub mod tree_printer;
+pub mod dot_printer;
+pub mod rpn_printer;
+pub mod formatter;
+pub mod optimizer;
+pub mod resolver;
+pub mod type_checker;
+pub mod chunk;
+pub mod compiler;
+pub mod vm;
+pub mod slsc;
+pub mod disassembler;
+pub mod heap;
+pub mod symbol;
+pub mod value;
+pub mod native;
+pub mod map;
+pub mod module;
+pub mod stdlib;
+pub mod loader;
+pub mod exception;
+pub mod bigint;
+pub mod number;
+pub mod iterator;
+pub mod generator;
+#[cfg(test)]
+pub mod testing;
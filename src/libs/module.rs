@@ -0,0 +1,32 @@
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+use crate::libs::environment::Environment;
+use crate::libs::expr::ast::Object;
+use crate::libs::interpreter::RuntimeError;
+use crate::libs::lex::Token;
+use crate::libs::symbol::Symbol;
+
+// A namespace of globals, like the standard library's `math`, whose names are read as properties.
+pub struct Module {
+    pub name: Symbol,
+    pub globals: Rc<RefCell<Environment>>,
+}
+
+impl Module {
+    pub fn new(name: Symbol, globals: Rc<RefCell<Environment>>) -> Self {
+        Self { name, globals }
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Object, RuntimeError> {
+        self.globals.borrow().value(name.lexeme).ok_or_else(|| {
+            RuntimeError::new(name, &format!("Undefined property '{}' in module '{}'.", name.lexeme, self.name))
+        })
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt::{self, Display};
+use std::io::{self, BufRead};
+use std::rc::Rc;
+use std::slice;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::libs::environment::Environment;
+use crate::libs::exception;
+use crate::libs::expr::ast::Object;
+use crate::libs::interpreter::{Interpreter, RuntimeError};
+use crate::libs::lex::{Token, TokenType};
+use crate::libs::stdlib;
+use crate::libs::symbol::Symbol;
+
+// A host function: it gets the interpreter running it and the arguments, already checked against
+// its arity.
+pub type NativeFn = fn(&mut Interpreter, &[Object]) -> Result<Object, RuntimeError>;
+
+// A function written in Rust that scripts call like any other. A method of a built-in value, like
+// a string's `split`, is one bound to the value, which it gets as its first argument.
+pub struct NativeFunction {
+    pub name: Symbol,
+    arity: usize,
+    function: NativeFn,
+    receiver: Option<Object>,
+}
+
+impl NativeFunction {
+    pub fn new(name: Symbol, arity: usize, function: NativeFn) -> Self {
+        Self {
+            name,
+            arity,
+            function,
+            receiver: None,
+        }
+    }
+
+    pub fn method(receiver: Object, name: Symbol, arity: usize, function: NativeFn) -> Self {
+        Self {
+            name,
+            arity,
+            function,
+            receiver: Some(receiver),
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    // Errors from `error`, which have no line, are put at the call.
+    pub fn call(&self, interpreter: &mut Interpreter, call: &Token, arguments: &[Object]) -> Result<Object, RuntimeError> {
+        let result = match self.receiver {
+            Some(ref receiver) => {
+                let arguments = [slice::from_ref(receiver), arguments].concat();
+                (self.function)(interpreter, &arguments)
+            }
+            None => (self.function)(interpreter, arguments),
+        };
+        result.map_err(|mut error| {
+            if error.token.line == 0 {
+                error.token = call.clone();
+                error.at(call);
+            }
+            error
+        })
+    }
+}
+
+impl Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+// An error for a native function to return, put at the line of the call.
+pub fn error(message: &str) -> RuntimeError {
+    RuntimeError::new(&Token::synthetic(TokenType::IDENTIFIER, Symbol::intern(""), 0), message)
+}
+
+// Calls a function or box given to a native function, like a comparator.
+pub fn call(interpreter: &mut Interpreter, callee: &Object, arguments: Vec<Object>) -> Result<Object, RuntimeError> {
+    let call = Token::synthetic(TokenType::IDENTIFIER, Symbol::intern(""), 0);
+    interpreter.call_value(callee.clone(), &call, arguments)
+}
+
+// Defines `function` in `globals` under `name`.
+pub fn define(globals: &mut Environment, name: &str, arity: usize, function: NativeFn) {
+    let name = Symbol::intern(name);
+    globals.define(name, Object::NativeFunction(Rc::new(NativeFunction::new(name, arity, function))));
+}
+
+// The functions and boxes every program can use.
+pub fn define_builtins(globals: &mut Environment) {
+    globals.define(Symbol::intern("Error"), Object::Box(exception::error_box()));
+    define(globals, "clock", 0, clock);
+    define(globals, "input", 0, input);
+    define(globals, "len", 1, len);
+    define(globals, "type_of", 1, type_of);
+    stdlib::define(globals);
+}
+
+// Seconds since the Unix epoch.
+fn clock(_: &mut Interpreter, _: &[Object]) -> Result<Object, RuntimeError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|_| error("The system clock is before 1970."))?;
+    Ok(Object::Float(now.as_secs_f64()))
+}
+
+// A line from standard input without its line break, or nil at the end of the input.
+fn input(_: &mut Interpreter, _: &[Object]) -> Result<Object, RuntimeError> {
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) => Ok(Object::Nil),
+        Ok(_) => {
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            Ok(Object::Str(Rc::new(line.strip_suffix('\r').unwrap_or(line).to_string())))
+        }
+        Err(failure) => Err(error(&format!("Failed to read input: {failure}"))),
+    }
+}
+
+fn len(_: &mut Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    match &arguments[0] {
+        Object::Str(s) => Ok(Object::Int(s.chars().count() as i64)),
+        Object::List(list) => Ok(Object::Int(list.borrow().len() as i64)),
+        Object::Map(map) => Ok(Object::Int(map.borrow().len() as i64)),
+        other => Err(error(&format!("len() takes a string, a list or a map, not {}.", type_name(other)))),
+    }
+}
+
+fn type_of(_: &mut Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+    Ok(Object::Str(Rc::new(type_name(&arguments[0]))))
+}
+
+// The name of a value's type, as the type checker writes it but with numbers told apart as Int and
+// Float; an instance's is its box's.
+pub fn type_name(object: &Object) -> String {
+    match object {
+        Object::Float(_) => "Float".to_string(),
+        Object::Int(_) | Object::BigInt(_) => "Int".to_string(),
+        Object::Str(_) => "String".to_string(),
+        Object::Bool(_) => "Bool".to_string(),
+        Object::Function(_) | Object::NativeFunction(_) => "Function".to_string(),
+        Object::Box(_) => "Box".to_string(),
+        Object::Instance(instance) => instance.borrow().class.name.to_string(),
+        Object::List(_) => "List".to_string(),
+        Object::Map(_) => "Map".to_string(),
+        Object::Module(_) => "Module".to_string(),
+        Object::Range(_) => "Range".to_string(),
+        Object::Iterator(_) => "Iterator".to_string(),
+        Object::Generator(_) => "Generator".to_string(),
+        Object::Nil => "Nil".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::expr::ast::Object;
+    use crate::libs::interpreter::{Interpreter, RuntimeError};
+    use crate::libs::lex::{Token, TokenType};
+    use crate::libs::native::{self, NativeFunction};
+    use crate::libs::testing::run_both_with;
+
+    fn add(_: &mut Interpreter, arguments: &[Object]) -> Result<Object, RuntimeError> {
+        match arguments {
+            [Object::Int(a), Object::Int(b)] => Ok(Object::Int(a + b)),
+            _ => Err(native::error("add() takes two numbers.")),
+        }
+    }
+
+    // Runs the source on both backends with `add` registered.
+    fn run(source: &str) -> (bool, String) {
+        run_both_with(source, |interpreter| interpreter.register("add", 2, add), |vm| vm.register("add", 2, add))
+    }
+
+    #[test]
+    fn registered_functions() {
+        let source = "
+            print add(len([1, 2]), len(\"abc\"));
+            print type_of(add) + \" \" + type_of(\"\");
+            print add;
+        ";
+        assert_eq!(run(source), (true, "5\nFunction String\n<native fn add>\n".to_string()));
+    }
+
+    #[test]
+    fn native_errors_stop_the_program() {
+        let source = "
+            print \"before\";
+            add(1,
+                \"2\");
+            print \"after\";
+        ";
+        assert_eq!(run(source), (false, "before\n".to_string()));
+    }
+
+    #[test]
+    fn errors_are_put_at_the_call() {
+        let call = Token::synthetic(TokenType::IDENTIFIER, "add".into(), 7);
+        let mut interpreter = Interpreter::new();
+        let error = NativeFunction::new("add".into(), 2, add).call(&mut interpreter, &call, &[Object::Nil, Object::Nil]);
+        assert!(error.is_err_and(|error| error.token.line == 7));
+    }
+}
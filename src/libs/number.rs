@@ -0,0 +1,211 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::rc::Rc;
+use crate::libs::bigint::BigInt;
+use crate::libs::expr::ast::Object;
+
+// How the kinds of numbers mix. Integers are exact: an `Int` that overflows becomes a `BigInt`,
+// and a `BigInt` that fits in an `i64` is an `Int` again, so a program only ever sees one integer
+// type. With a `Float` on either side, the operation is done on floats. `/` always gives a float,
+// `~/` rounds the quotient toward zero and `%` takes the sign of the dividend, so that
+// `a == (a ~/ b) * b + a % b`; dividing integers by zero is an error.
+
+#[derive(Clone, Copy)]
+pub enum Arithmetic {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    IntDivide,
+    Remainder,
+}
+
+pub const DIVISION_BY_ZERO: &str = "Division by zero.";
+
+// None when either operand isn't a number.
+pub fn arithmetic(operation: Arithmetic, left: &Object, right: &Object) -> Option<Result<Object, &'static str>> {
+    match (left, right) {
+        (Object::Int(a), Object::Int(b)) => Some(small(operation, *a, *b).unwrap_or_else(|| {
+            big(operation, &BigInt::from(*a), &BigInt::from(*b))
+        })),
+        (Object::Float(_), _) | (_, Object::Float(_)) => Some(Ok(Object::Float(float(operation, to_f64(left)?, to_f64(right)?)))),
+        _ => Some(big(operation, &*integer(left)?, &*integer(right)?)),
+    }
+}
+
+// None when either operand isn't a number; Some(None) when one is NaN.
+pub fn compare(left: &Object, right: &Object) -> Option<Option<Ordering>> {
+    match (left, right) {
+        (Object::Int(a), Object::Int(b)) => Some(Some(a.cmp(b))),
+        (Object::Float(a), Object::Float(b)) => Some(a.partial_cmp(b)),
+        (Object::Float(a), _) => Some(compare_float(&*integer(right)?, *a).map(Ordering::reverse)),
+        (_, Object::Float(b)) => Some(compare_float(&*integer(left)?, *b)),
+        _ => Some(Some(integer(left)?.cmp(&integer(right)?))),
+    }
+}
+
+pub fn negate(value: &Object) -> Option<Object> {
+    match value {
+        Object::Int(n) => Some(n.checked_neg().map_or_else(|| int(-&BigInt::from(*n)), Object::Int)),
+        Object::BigInt(n) => Some(int(-n.as_ref())),
+        Object::Float(n) => Some(Object::Float(-n)),
+        _ => None,
+    }
+}
+
+// An integer in the one form it has.
+pub fn int(n: BigInt) -> Object {
+    n.to_i64().map_or_else(|| Object::BigInt(Rc::new(n)), Object::Int)
+}
+
+pub fn to_f64(value: &Object) -> Option<f64> {
+    match value {
+        Object::Int(n) => Some(*n as f64),
+        Object::BigInt(n) => Some(n.to_f64()),
+        Object::Float(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn integer(value: &Object) -> Option<Cow<'_, BigInt>> {
+    match value {
+        Object::Int(n) => Some(Cow::Owned(BigInt::from(*n))),
+        Object::BigInt(n) => Some(Cow::Borrowed(n)),
+        _ => None,
+    }
+}
+
+// Exact, rather than through the nearest float, so that integers beyond 2^53 still compare right.
+fn compare_float(integer: &BigInt, float: f64) -> Option<Ordering> {
+    if float.is_infinite() {
+        return Some(if float > 0.0 { Ordering::Less } else { Ordering::Greater });
+    }
+    let whole = float.trunc();
+    let ordering = integer.cmp(&BigInt::from_f64(whole)?);
+    // Equal whole parts leave the fraction to decide.
+    Some(ordering.then(0.0.partial_cmp(&(float - whole))?))
+}
+
+// None when the result doesn't fit in an `i64`.
+fn small(operation: Arithmetic, a: i64, b: i64) -> Option<Result<Object, &'static str>> {
+    let result = match operation {
+        Arithmetic::Add => a.checked_add(b)?,
+        Arithmetic::Subtract => a.checked_sub(b)?,
+        Arithmetic::Multiply => a.checked_mul(b)?,
+        _ if b == 0 => return Some(Err(DIVISION_BY_ZERO)),
+        Arithmetic::Divide => return Some(Ok(Object::Float(a as f64 / b as f64))),
+        Arithmetic::IntDivide => a.checked_div(b)?,
+        Arithmetic::Remainder => a.checked_rem(b)?,
+    };
+    Some(Ok(Object::Int(result)))
+}
+
+fn big(operation: Arithmetic, a: &BigInt, b: &BigInt) -> Result<Object, &'static str> {
+    let result = match operation {
+        Arithmetic::Add => a + b,
+        Arithmetic::Subtract => a - b,
+        Arithmetic::Multiply => a * b,
+        _ if b.is_zero() => return Err(DIVISION_BY_ZERO),
+        Arithmetic::Divide => return Ok(Object::Float(a.to_f64() / b.to_f64())),
+        Arithmetic::IntDivide => a.div_rem(b).ok_or(DIVISION_BY_ZERO)?.0,
+        Arithmetic::Remainder => a.div_rem(b).ok_or(DIVISION_BY_ZERO)?.1,
+    };
+    Ok(int(result))
+}
+
+fn float(operation: Arithmetic, a: f64, b: f64) -> f64 {
+    match operation {
+        Arithmetic::Add => a + b,
+        Arithmetic::Subtract => a - b,
+        Arithmetic::Multiply => a * b,
+        Arithmetic::Divide => a / b,
+        Arithmetic::IntDivide => (a / b).trunc(),
+        Arithmetic::Remainder => a % b,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+    use crate::libs::bigint::BigInt;
+    use crate::libs::expr::ast::Object;
+    use crate::libs::number::{self, Arithmetic};
+    use crate::libs::testing::run;
+
+    #[test]
+    fn mixing() {
+        let run = |operation, left: Object, right: Object| match number::arithmetic(operation, &left, &right) {
+            Some(Ok(value)) => value.to_string(),
+            Some(Err(message)) => message.to_string(),
+            None => "not numbers".to_string(),
+        };
+        assert_eq!(run(Arithmetic::Add, Object::Int(i64::MAX), Object::Int(1)), "9223372036854775808");
+        assert_eq!(run(Arithmetic::Multiply, Object::Int(1 << 40), Object::Int(1 << 40)), "1208925819614629174706176");
+        assert_eq!(run(Arithmetic::Divide, Object::Int(7), Object::Int(2)), "3.5");
+        assert_eq!(run(Arithmetic::Divide, Object::Int(6), Object::Int(3)), "2.0");
+        assert_eq!(run(Arithmetic::IntDivide, Object::Int(-7), Object::Int(2)), "-3");
+        assert_eq!(run(Arithmetic::Remainder, Object::Int(-7), Object::Int(2)), "-1");
+        assert_eq!(run(Arithmetic::IntDivide, Object::Int(i64::MIN), Object::Int(-1)), "9223372036854775808");
+        assert_eq!(run(Arithmetic::Remainder, Object::Int(1), Object::Int(0)), "Division by zero.");
+        assert_eq!(run(Arithmetic::Add, Object::Int(1), Object::Float(0.5)), "1.5");
+        assert_eq!(run(Arithmetic::Remainder, Object::Float(7.5), Object::Int(2)), "1.5");
+        assert_eq!(run(Arithmetic::Divide, Object::Float(1.0), Object::Int(0)), "inf");
+        assert_eq!(run(Arithmetic::Add, Object::Nil, Object::Int(1)), "not numbers");
+
+        // Back to an Int once the result fits.
+        let big = number::int(BigInt::from(i64::MAX).pow(2));
+        let back = number::arithmetic(Arithmetic::IntDivide, &big, &Object::Int(i64::MAX));
+        assert!(matches!(back, Some(Ok(Object::Int(i64::MAX)))));
+
+        let precise = Object::Int((1 << 53) + 1);
+        assert_eq!(number::compare(&precise, &Object::Float((1u64 << 53) as f64)), Some(Some(Ordering::Greater)));
+        assert_eq!(number::compare(&Object::Float(2.5), &Object::Int(2)), Some(Some(Ordering::Greater)));
+        assert_eq!(number::compare(&Object::Int(3), &Object::Float(3.0)), Some(Some(Ordering::Equal)));
+        assert_eq!(number::compare(&big, &Object::Float(f64::INFINITY)), Some(Some(Ordering::Less)));
+        assert_eq!(number::compare(&Object::Int(1), &Object::Float(f64::NAN)), Some(None));
+    }
+
+    #[test]
+    fn big_ints() {
+        let source = "
+            let big = 9223372036854775807 + 1;
+            print big;
+            print -9223372036854775808;
+            print type_of(1) + \" \" + type_of(1.0) + \" \" + type_of(big);
+            print math.pow(2, 100);
+        ";
+        assert_eq!(run(source), "9223372036854775808\n-9223372036854775808\nInt Float Int\n1267650600228229401496703205376\n");
+    }
+
+    #[test]
+    fn operators() {
+        let source = "
+            print 7 / 2;
+            print 7 ~/ 2;
+            print -7 % 3;
+            print 1 + 0.5;
+            print 1 == 1.0;
+        ";
+        assert_eq!(run(source), "3.5\n3\n-1\n1.5\ntrue\n");
+    }
+
+    #[test]
+    fn equal_numbers_are_one_key() {
+        let source = "
+            let m = Map();
+            m[1] = \"a\";
+            m[1.0] = \"b\";
+            print m;
+        ";
+        assert_eq!(run(source), "{1: b}\n");
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let source = "
+            fun divide(a, b) { return a ~/ b; }
+            try { divide(1, 0); } catch (e) { print e.message; }
+        ";
+        assert_eq!(run(source), "Division by zero.\n");
+    }
+}
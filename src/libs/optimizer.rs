@@ -0,0 +1,207 @@
+use std::rc::Rc;
+use std::slice;
+use crate::libs::expr::ast::{Expr, Object, Stmt};
+use crate::libs::expr::fold::Fold;
+use crate::libs::generator;
+use crate::libs::interpreter::Interpreter;
+use crate::libs::lex::{LiteralValue, Token, TokenType};
+use crate::libs::span;
+use crate::libs::symbol::Symbol;
+
+// Constant folding: operators whose operands are all literals are evaluated once here, with the
+// interpreter's own rules, and replaced by the result. An operation that would fail is left alone
+// so the error still happens at run time, at its operator.
+pub struct ConstantFolder;
+
+pub fn optimize(statements: Vec<Stmt>) -> Vec<Stmt> {
+    ConstantFolder.fold_stmts(statements)
+}
+
+impl ConstantFolder {
+    // Only the truthiness of a condition matters, so double negations can go: `!!x` is `x` there.
+    // The operands of `and` and `or` in a condition are conditions too.
+    fn condition(&mut self, expr: Option<Box<Expr>>) -> Option<Box<Expr>> {
+        self.fold_expr(expr).map(|expr| Box::new(Self::simplify(*expr)))
+    }
+
+    fn simplify(expr: Expr) -> Expr {
+        match expr {
+            Expr::Unary { operator: outer, right: Some(right) } if matches!(outer.token_type, TokenType::BANG) => {
+                match Self::ungroup(*right) {
+                    Expr::Unary { operator, right: Some(inner) } if matches!(operator.token_type, TokenType::BANG) => {
+                        Self::simplify(*inner)
+                    }
+                    right => Expr::Unary { operator: outer, right: Some(Box::new(right)) },
+                }
+            }
+            Expr::Logical { left, operator, right } => Expr::Logical {
+                left: left.map(|left| Box::new(Self::simplify(*left))),
+                operator,
+                right: right.map(|right| Box::new(Self::simplify(*right))),
+            },
+            Expr::Grouping { expression: Some(expression) } => {
+                Expr::Grouping { expression: Some(Box::new(Self::simplify(*expression))) }
+            }
+            expr => expr,
+        }
+    }
+
+    fn ungroup(expr: Expr) -> Expr {
+        match expr {
+            Expr::Grouping { expression: Some(expression) } => Self::ungroup(*expression),
+            expr => expr,
+        }
+    }
+
+    fn yields(stmt: &Option<Box<Stmt>>) -> bool {
+        stmt.as_deref().is_some_and(|stmt| generator::yields(slice::from_ref(stmt)))
+    }
+
+    fn literal(expr: &Option<Box<Expr>>) -> Option<&Object> {
+        match expr.as_deref()? {
+            Expr::Literal { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    // The literal that replaces `expr`. Its token covers the text `expr` came from, so the span of
+    // the folded code is still the original one.
+    fn folded(expr: &Expr, value: Object) -> Expr {
+        let (token_type, literal, lexeme) = match &value {
+            Object::Float(n) => (TokenType::NUMBER, LiteralValue::Float(*n), value.to_string()),
+            Object::Int(n) => (TokenType::NUMBER, LiteralValue::Int(*n), value.to_string()),
+            Object::BigInt(n) => (TokenType::NUMBER, LiteralValue::BigInt(Rc::clone(n)), value.to_string()),
+            Object::Str(s) => (TokenType::STRING, LiteralValue::String(Symbol::intern(s)), format!("\"{s}\"")),
+            Object::Bool(true) => (TokenType::TRUE, LiteralValue::Nil, "true".to_string()),
+            Object::Bool(false) => (TokenType::FALSE, LiteralValue::Nil, "false".to_string()),
+            _ => (TokenType::NIL, LiteralValue::Nil, "nil".to_string()),
+        };
+        let span = span::expr_span(expr);
+        let (line, column) = span.map_or((0, 0), |span| (span.line, span.column));
+        let end = span.map(|span| (span.end_line as u32, span.end_column as u32));
+        let file = match expr {
+            Expr::Binary { operator, .. } | Expr::Unary { operator, .. } => operator.file,
+            _ => 0,
+        };
+        let token = Token { token_type, literal, line, column, file, end, lexeme: Symbol::intern(&lexeme) };
+        Expr::Literal { token, value }
+    }
+}
+
+impl Fold for ConstantFolder {
+    fn fold_binary(&mut self, left: Option<Box<Expr>>, operator: Token, right: Option<Box<Expr>>) -> Expr {
+        let left = self.fold_expr(left);
+        let right = self.fold_expr(right);
+        let value = match (Self::literal(&left), Self::literal(&right)) {
+            (Some(l), Some(r)) => Interpreter::binary(&operator.token_type, l.clone(), r.clone()).ok(),
+            _ => None,
+        };
+        // A range has no literal to be written as.
+        let value = value.filter(|value| !matches!(value, Object::Range(_)));
+
+        let expr = Expr::Binary { left, operator, right };
+        match value {
+            Some(value) => Self::folded(&expr, value),
+            None => expr,
+        }
+    }
+
+    fn fold_grouping(&mut self, expression: Option<Box<Expr>>) -> Expr {
+        match self.fold_expr(expression) {
+            Some(expression) if matches!(*expression, Expr::Literal { .. }) => *expression,
+            expression => Expr::Grouping { expression },
+        }
+    }
+
+    fn fold_unary(&mut self, operator: Token, right: Option<Box<Expr>>) -> Expr {
+        let right = if matches!(operator.token_type, TokenType::BANG) {
+            self.condition(right)
+        } else {
+            self.fold_expr(right)
+        };
+        let value = Self::literal(&right).and_then(|r| Interpreter::unary(&operator.token_type, r.clone()).ok());
+
+        let expr = Expr::Unary { operator, right };
+        match value {
+            Some(value) => Self::folded(&expr, value),
+            None => expr,
+        }
+    }
+
+    // A branch that can't run is dropped; an empty block stands for an `if` left with nothing. A
+    // branch that yields stays, as its function is a generator whether or not it runs.
+    fn fold_if(
+        &mut self,
+        condition: Option<Box<Expr>>,
+        then_branch: Option<Box<Stmt>>,
+        else_branch: Option<Box<Stmt>>,
+    ) -> Stmt {
+        let condition = self.condition(condition);
+        let then_branch = self.fold_boxed_stmt(then_branch);
+        let else_branch = self.fold_boxed_stmt(else_branch);
+
+        let truthy = match Self::literal(&condition) {
+            Some(value) => Interpreter::is_truthy(value),
+            None => return Stmt::If { condition, then_branch, else_branch },
+        };
+        if Self::yields(if truthy { &else_branch } else { &then_branch }) {
+            return Stmt::If { condition, then_branch, else_branch };
+        }
+        let branch = if truthy { then_branch } else { else_branch };
+        branch.map_or(Stmt::Block { statements: Vec::new() }, |branch| *branch)
+    }
+
+    fn fold_while(&mut self, condition: Option<Box<Expr>>, body: Option<Box<Stmt>>) -> Stmt {
+        let condition = self.condition(condition);
+        let body = self.fold_boxed_stmt(body);
+
+        match Self::literal(&condition) {
+            Some(value) if !Interpreter::is_truthy(value) && !Self::yields(&body) => Stmt::Block { statements: Vec::new() },
+            _ => Stmt::While { condition, body },
+        }
+    }
+
+    // Empty blocks do nothing, whether they were written or left by dead branches.
+    fn fold_stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts
+            .into_iter()
+            .map(|stmt| self.fold_stmt(stmt))
+            .filter(|stmt| !matches!(stmt, Stmt::Block { statements } if statements.is_empty()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::ast_printer::AstPrinter;
+    use crate::libs::expr::ast::{Expr, Stmt};
+    use crate::libs::expr::visitor::StmtVisitor;
+    use crate::libs::lex::Lox;
+    use crate::libs::optimizer::optimize;
+    use crate::libs::span;
+
+    fn optimized(source: &str) -> Vec<String> {
+        let statements = Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+        optimize(statements).iter().map(|statement| AstPrinter {}.visit_stmt(statement)).collect()
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(optimized("print (1 + 2) * 3;"), ["(print 9)"]);
+        assert_eq!(optimized("print \"a\" + \"b\" == \"ab\";"), ["(print true)"]);
+        assert_eq!(optimized("print -(2 - x) + 1 * 2;"), ["(print (+ (- (group (- 2 x))) 2))"]);
+        // Left for the run time to fail.
+        assert_eq!(optimized("print 1 + \"a\";"), ["(print (+ 1 a))"]);
+        assert_eq!(optimized("if (!!x and !!!y) print 1;"), ["(if (group (and x (! y))) (print 1))"]);
+        assert_eq!(optimized("let y = !!x;"), ["(let y (! (! x)))"]);
+        assert_eq!(optimized("if (1 > 2) print 1; else print 2; while (!true) print 3; print 4;"), ["(print 2)", "(print 4)"]);
+
+        let source = "let x =\n  (1 + 2) * 3;".to_string();
+        let statements = Lox::new(source).with_file_id(2).parse().unwrap_or_else(|| panic!("Failed to parse"));
+        let folded = optimize(statements);
+        let Stmt::Let { initializer: Some(value), .. } = &folded[0] else { panic!("Expected a let") };
+        assert_eq!(span::expr_span(value).map(|span| span.to_string()), Some("2:4-2:14".to_string()));
+        let Expr::Literal { token, .. } = value.as_ref() else { panic!("Expected a literal") };
+        assert_eq!(token.file, 2);
+    }
+}
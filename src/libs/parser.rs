@@ -1,6 +1,9 @@
 use std::mem;
+use std::rc::Rc;
 use crate::libs::{lex::TokenType, lex::TokenType::*, expr::ast::*};
-use crate::libs::lex::{Token, Lox};
+use crate::libs::lex::{LiteralValue, Token, Lox};
+use crate::libs::number;
+use crate::libs::symbol::Symbol;
 
 type BoxExpr = Option<Box<Expr>>;
 
@@ -9,6 +12,7 @@ pub struct ParseError;
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    gotten_error: bool,
 }
 
 type ParseResult = Result<BoxExpr, ParseError>;
@@ -18,147 +22,811 @@ impl Parser {
         Self {
             tokens,
             current: 0,
+            gotten_error: false,
         }
     }
 
-    pub fn parse(&mut self) -> ParseResult {
-        self.expression()
+    // program -> declaration* EOF
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            match self.declaration() {
+                Ok(stmt) => statements.push(stmt),
+                Err(_) => {
+                    self.gotten_error = true;
+                    self.sync();
+                }
+            }
+        }
+
+        if self.gotten_error {
+            return Err(ParseError);
+        }
+        Ok(statements)
     }
 
-    fn expression(&mut self) -> ParseResult {
-        self.equality()
+    // declaration -> importDecl | boxDecl | interfaceDecl | funDecl | letDecl | statement
+    fn declaration(&mut self) -> Result<Stmt, ParseError> {
+        if self.matching([IMPORT, FROM]) {
+            return self.import_declaration();
+        }
+        if self.matching([BOX]) {
+            return self.box_declaration();
+        }
+        if self.matching([INTERFACE]) {
+            return self.interface_declaration();
+        }
+        // A "fun" that is not followed by a name is a lambda in an expression statement.
+        if self.check(&FUN) && self.check_next(&IDENTIFIER) {
+            self.advance();
+            return self.function(false);
+        }
+        if self.matching([LET]) {
+            return self.let_declaration();
+        }
+        self.statement()
     }
 
-    // equality -> comparison (("!=" | "==" comparison)*
-    fn equality(&mut self) -> Result<BoxExpr, ParseError> {
-        let mut expr = match self.comparison() {
-            Ok(expr) => expr,
-            Err(e) => return Err(e)
+    // importDecl -> "import" STRING "as" IDENTIFIER ";"
+    //             | "from" STRING "import" IDENTIFIER ( "," IDENTIFIER )* ";"
+    fn import_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        let path = self.consume(STRING, "Expect a module path in quotes.")?.clone();
+
+        let mut names = Vec::new();
+        if let IMPORT = keyword.token_type {
+            self.consume(AS, "Expect 'as' after module path.")?;
+            names.push(self.consume(IDENTIFIER, "Expect module name.")?.clone());
+        } else {
+            self.consume(IMPORT, "Expect 'import' after module path.")?;
+            loop {
+                names.push(self.consume(IDENTIFIER, "Expect name to import.")?.clone());
+                if !self.matching([COMMA]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(SEMICOLON, "Expect ';' after import.")?;
+        Ok(Stmt::Import { keyword, path, names })
+    }
+
+    // boxDecl -> "box" IDENTIFIER ( "<-" IDENTIFIER )? "{" ( field | method )* "}"
+    // field -> IDENTIFIER ( ":" type )? ";"
+    fn box_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume(IDENTIFIER, "Expect box name.")?.clone();
+
+        let superclass = if self.matching([LEFT_ARROW]) {
+            let name = self.consume(IDENTIFIER, "Expect superclass name.")?.clone();
+            Some(Box::new(Expr::Variable { name }))
+        } else {
+            None
         };
 
-        while self.matching([BANG, BANG_EQUAL]) {
-            let operator = self.previous().clone();
-            expr = match self.comparison() {
-                Ok(right) => Some(Box::new(Expr::Binary {
-                    left: expr,
-                    operator,
-                    right,
-                })),
-                Err(e) => return Err(e),
+        self.consume(LEFT_BRACE, "Expect '{' before box body.")?;
+
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            if self.check(&IDENTIFIER) && (self.check_next(&SEMICOLON) || self.check_next(&COLON)) {
+                fields.push(self.typed_name("Expect field name.")?);
+                self.consume(SEMICOLON, "Expect ';' after field.")?;
+            } else {
+                methods.push(self.function(true)?);
+            }
+        }
+
+        self.consume(RIGHT_BRACE, "Expect '}' after box body.")?;
+
+        Ok(Stmt::Box { name, superclass, fields, methods })
+    }
+
+    // interfaceDecl -> "interface" IDENTIFIER "{" ( IDENTIFIER "(" parameters? ")" ( "->" type )? ";" )* "}"
+    fn interface_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume(IDENTIFIER, "Expect interface name.")?.clone();
+        self.consume(LEFT_BRACE, "Expect '{' before interface body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            let name = self.consume(IDENTIFIER, "Expect method name.")?.clone();
+            self.consume(LEFT_PAREN, "Expect '(' after method name.")?;
+            let params = self.parameters(true)?;
+            let result = self.result_type()?;
+            self.consume(SEMICOLON, "Expect ';' after method signature.")?;
+            methods.push(MethodSig { name, params, result });
+        }
+
+        self.consume(RIGHT_BRACE, "Expect '}' after interface body.")?;
+        Ok(Stmt::Interface { name, methods })
+    }
+
+    // function -> IDENTIFIER ( "<" IDENTIFIER ( "," IDENTIFIER )* ">" )? "(" parameters? ")" ( "->" type )? block
+    fn function(&mut self, is_method: bool) -> Result<Stmt, ParseError> {
+        let kind = if is_method { "method" } else { "function" };
+        let name = self.consume(IDENTIFIER, &format!("Expect {kind} name."))?.clone();
+        let mut type_params = Vec::new();
+        if self.matching([LESS]) {
+            loop {
+                type_params.push(self.consume(IDENTIFIER, "Expect type parameter name.")?.clone());
+                if !self.matching([COMMA]) {
+                    break;
+                }
+            }
+            self.consume(GREATER, "Expect '>' after type parameters.")?;
+        }
+        self.consume(LEFT_PAREN, &format!("Expect '(' after {kind} name."))?;
+        let params = self.parameters(is_method)?;
+        let result = self.result_type()?;
+        self.consume(LEFT_BRACE, &format!("Expect '{{' before {kind} body."))?;
+        let body = Rc::new(self.block()?);
+
+        Ok(Stmt::Function { name, type_params, params, result, body })
+    }
+
+    // parameters -> IDENTIFIER ( ":" type )? ( "," IDENTIFIER ( ":" type )? )* ")"
+    // Methods may spell out the receiver as a leading "self", which is skipped.
+    fn parameters(&mut self, is_method: bool) -> Result<Vec<TypedName>, ParseError> {
+        let mut params = Vec::new();
+        if is_method && self.matching([SELF]) && !self.check(&RIGHT_PAREN) {
+            self.consume(COMMA, "Expect ',' after 'self'.")?;
+        }
+        if !self.check(&RIGHT_PAREN) {
+            loop {
+                params.push(self.typed_name("Expect parameter name.")?);
+                if !self.matching([COMMA]) {
+                    break;
+                }
+            }
+        }
+        self.consume(RIGHT_PAREN, "Expect ')' after parameters.")?;
+
+        Ok(params)
+    }
+
+    fn typed_name(&mut self, msg: &str) -> Result<TypedName, ParseError> {
+        let name = self.consume(IDENTIFIER, msg)?.clone();
+        let annotation = self.annotation()?;
+        Ok(TypedName { name, annotation })
+    }
+
+    // Types are optional everywhere: `: type` after a name, `-> type` after parameters.
+    fn annotation(&mut self) -> Result<Option<TypeExpr>, ParseError> {
+        if self.matching([COLON]) {
+            return self.type_expr().map(Some);
+        }
+        Ok(None)
+    }
+
+    fn result_type(&mut self) -> Result<Option<TypeExpr>, ParseError> {
+        if self.matching([ARROW]) {
+            return self.type_expr().map(Some);
+        }
+        Ok(None)
+    }
+
+    // type -> IDENTIFIER ( "<" type ( "," type )* ">" )?
+    fn type_expr(&mut self) -> Result<TypeExpr, ParseError> {
+        let name = self.consume(IDENTIFIER, "Expect type name.")?.clone();
+        let mut arguments = Vec::new();
+        if self.matching([LESS]) {
+            loop {
+                arguments.push(self.type_expr()?);
+                if !self.matching([COMMA]) {
+                    break;
+                }
+            }
+            self.consume(GREATER, "Expect '>' after type arguments.")?;
+        }
+        Ok(TypeExpr { name, arguments })
+    }
+
+    // letDecl -> "let" IDENTIFIER ( ":" type )? ( "=" expression )? ";"
+    fn let_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume(IDENTIFIER, "Expect variable name.")?.clone();
+        let annotation = self.annotation()?;
+
+        let initializer = if self.matching([EQUAL]) {
+            self.expression()?
+        } else {
+            None
+        };
+
+        self.consume(SEMICOLON, "Expect ';' after variable declaration.")?;
+        Ok(Stmt::Let { name, annotation, initializer })
+    }
+
+    // statement -> exprStmt | ifStmt | printStmt | returnStmt | throwStmt | tryStmt | whileStmt | block
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.matching([FOR]) {
+            return self.for_statement();
+        }
+        if self.matching([IF]) {
+            return self.if_statement();
+        }
+        if self.matching([PRINT]) {
+            return self.print_statement();
+        }
+        if self.matching([RETURN]) {
+            return self.return_statement();
+        }
+        if self.matching([THROW]) {
+            return self.throw_statement();
+        }
+        if self.matching([TRY]) {
+            return self.try_statement();
+        }
+        if self.matching([WHILE]) {
+            return self.while_statement();
+        }
+        if self.matching([LEFT_BRACE]) {
+            return Ok(Stmt::Block { statements: self.block()? });
+        }
+        self.expression_statement()
+    }
+
+    // forStmt -> "for" IDENTIFIER "in" expression statement
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        let name = self.consume(IDENTIFIER, "Expect variable name after 'for'.")?.clone();
+        self.consume(IN, "Expect 'in' after loop variable.")?;
+        let iterable = self.expression()?;
+        let body = Some(Box::new(self.statement()?));
+
+        Ok(Stmt::For { keyword, name, iterable, body })
+    }
+
+    // ifStmt -> "if" expression statement ( "else" statement )?
+    // The condition is a plain expression, so the usual "if (x)" is just a grouping.
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let condition = self.expression()?;
+        let then_branch = Some(Box::new(self.statement()?));
+        let else_branch = if self.matching([ELSE]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If { condition, then_branch, else_branch })
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expression = self.expression()?;
+        self.consume(SEMICOLON, "Expect ';' after value.")?;
+        Ok(Stmt::Print { expression })
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        let value = if !self.check(&SEMICOLON) {
+            self.expression()?
+        } else {
+            None
+        };
+
+        self.consume(SEMICOLON, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    fn throw_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume(SEMICOLON, "Expect ';' after thrown value.")?;
+        Ok(Stmt::Throw { keyword, value })
+    }
+
+    // tryStmt -> "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )?
+    fn try_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        self.consume(LEFT_BRACE, "Expect '{' after 'try'.")?;
+        let body = Some(Box::new(Stmt::Block { statements: self.block()? }));
+
+        let (mut name, mut handler) = (None, None);
+        if self.matching([CATCH]) {
+            self.consume(LEFT_PAREN, "Expect '(' after 'catch'.")?;
+            name = Some(self.consume(IDENTIFIER, "Expect exception name.")?.clone());
+            self.consume(RIGHT_PAREN, "Expect ')' after exception name.")?;
+            self.consume(LEFT_BRACE, "Expect '{' before catch body.")?;
+            handler = Some(Box::new(Stmt::Block { statements: self.block()? }));
+        }
+        let finally = if self.matching([FINALLY]) {
+            self.consume(LEFT_BRACE, "Expect '{' after 'finally'.")?;
+            Some(Box::new(Stmt::Block { statements: self.block()? }))
+        } else {
+            None
+        };
+
+        if name.is_none() && finally.is_none() {
+            return Err(self.error(&keyword, "Expect 'catch' or 'finally' after try block."));
+        }
+        Ok(Stmt::Try { keyword, body, name, handler, finally })
+    }
+
+    // whileStmt -> "while" expression statement
+    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        let condition = self.expression()?;
+        let body = Some(Box::new(self.statement()?));
+
+        Ok(Stmt::While { condition, body })
+    }
+
+    // block -> "{" declaration* "}"
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements = Vec::new();
+
+        while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            statements.push(self.declaration()?);
+        }
+
+        self.consume(RIGHT_BRACE, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expression = self.expression()?;
+        self.consume(SEMICOLON, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression { expression })
+    }
+
+    fn expression(&mut self) -> ParseResult {
+        self.assignment()
+    }
+
+    // assignment -> ( call "." )? IDENTIFIER "=" assignment | call "[" expression "]" "=" assignment | yield | logic_or
+    fn assignment(&mut self) -> ParseResult {
+        if self.matching([YIELD]) {
+            return self.yield_();
+        }
+        let expr = self.or()?;
+
+        if self.matching([EQUAL]) {
+            let equals = self.previous().clone();
+            let value = self.assignment()?;
+
+            return match expr.map(|expr| *expr) {
+                Some(Expr::Variable { name }) => Ok(Some(Box::new(Expr::Assign { name, value }))),
+                Some(Expr::Get { object, name }) => Ok(Some(Box::new(Expr::Set { object, name, value }))),
+                Some(Expr::Index { object, bracket, index }) => {
+                    Ok(Some(Box::new(Expr::IndexSet { object, bracket, index, value })))
+                }
+                _ => Err(self.error(&equals, "Invalid assignment target.")),
             };
         }
 
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<BoxExpr, ParseError> {
-        let mut expr = match self.term() {
-            Ok(expr) => expr,
-            Err(e) => return Err(e)
+    // yield -> "yield" assignment?
+    // Without a value when whatever encloses it ends right after.
+    fn yield_(&mut self) -> ParseResult {
+        let keyword = self.previous().clone();
+        let value = if [SEMICOLON, RIGHT_PAREN, RIGHT_BRACKET, RIGHT_BRACE, COMMA].iter().any(|end| self.check(end)) {
+            None
+        } else {
+            self.assignment()?
         };
+        Ok(Some(Box::new(Expr::Yield { keyword, value })))
+    }
+
+    // logic_or -> logic_and ( "or" logic_and )*
+    fn or(&mut self) -> ParseResult {
+        let mut expr = self.and()?;
+
+        while self.matching([OR]) {
+            let operator = self.previous().clone();
+            let right = self.and()?;
+            expr = Some(Box::new(Expr::Logical {
+                left: expr,
+                operator,
+                right,
+            }));
+        }
+
+        Ok(expr)
+    }
+
+    // logic_and -> equality ( "and" equality )*
+    fn and(&mut self) -> ParseResult {
+        let mut expr = self.equality()?;
+
+        while self.matching([AND]) {
+            let operator = self.previous().clone();
+            let right = self.equality()?;
+            expr = Some(Box::new(Expr::Logical {
+                left: expr,
+                operator,
+                right,
+            }));
+        }
+
+        Ok(expr)
+    }
+
+    // equality -> comparison (("!=" | "==" comparison)*
+    fn equality(&mut self) -> ParseResult {
+        let mut expr = self.comparison()?;
+
+        while self.matching([BANG_EQUAL, EQUAL_EQUAL]) {
+            let operator = self.previous().clone();
+            let right = self.comparison()?;
+            expr = Some(Box::new(Expr::Binary {
+                left: expr,
+                operator,
+                right,
+            }));
+        }
+
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> ParseResult {
+        let mut expr = self.range()?;
 
         while self.matching([GREATER, GREATER_EQUAL, LESS, LESS_EQUAL]) {
             let operator = self.previous().clone();
-            expr = match self.term() {
-                Ok(right) => Some(Box::new(Expr::Binary {
-                    left: expr,
-                    operator,
-                    right,
-                })),
-                Err(e) => return Err(e),
-            };
+            let right = self.range()?;
+            expr = Some(Box::new(Expr::Binary {
+                left: expr,
+                operator,
+                right,
+            }));
         }
 
         Ok(expr)
     }
 
-    fn term(&mut self) -> Result<BoxExpr, ParseError> {
-        let mut expr = match self.factor() {
-            Ok(expr) => expr,
-            Err(e) => return Err(e)
-        };
+    // range -> term ( ( ".." | "..=" ) term )?
+    // Not chained: `a..b..c` is an error rather than a range of ranges.
+    fn range(&mut self) -> ParseResult {
+        let expr = self.term()?;
+        if !self.matching([DOT_DOT, DOT_DOT_EQUAL]) {
+            return Ok(expr);
+        }
+        let operator = self.previous().clone();
+        let right = self.term()?;
+
+        Ok(Some(Box::new(Expr::Binary { left: expr, operator, right })))
+    }
+
+    fn term(&mut self) -> ParseResult {
+        let mut expr = self.factor()?;
 
         while self.matching([MINUS, PLUS]) {
             let operator = self.previous().clone();
-            expr = match self.factor() {
-                Ok(right) => Some(Box::new(Expr::Binary {
-                    left: expr,
-                    operator,
-                    right,
-                })),
-                Err(e) => return Err(e)
-            };
+            let right = self.factor()?;
+            expr = Some(Box::new(Expr::Binary {
+                left: expr,
+                operator,
+                right,
+            }));
         }
 
         Ok(expr)
     }
 
-    fn factor(&mut self) -> Result<BoxExpr, ParseError> {
-        let mut expr = match self.unary() {
-            Ok(expr) => expr,
-            Err(e) => return Err(e)
-        };
+    fn factor(&mut self) -> ParseResult {
+        let mut expr = self.unary()?;
 
-        while self.matching([SLASH, STAR]) {
+        while self.matching([SLASH, STAR, PERCENT, TILDE_SLASH]) {
             let operator = self.previous().clone();
-            expr = match self.unary() {
-                Ok(right) => {
-                    Some(Box::new(Expr::Binary {
-                        left: expr,
-                        operator,
-                        right,
-                    }))
-                },
-                Err(e) => return Err(e)
-            };
+            let right = self.unary()?;
+            expr = Some(Box::new(Expr::Binary {
+                left: expr,
+                operator,
+                right,
+            }));
         }
 
         Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<BoxExpr, ParseError> {
+    fn unary(&mut self) -> ParseResult {
         if self.matching([BANG, MINUS]) {
             let operator = self.previous().clone();
-            return match self.unary() {
-                Ok(right) => {
-                    Ok(Some(Box::new(Expr::Unary {
-                        operator,
-                        right,
-                    })))
-                },
-                Err(e) => Err(e)
+            let right = self.unary()?;
+            return Ok(Some(Box::new(Expr::Unary {
+                operator,
+                right,
+            })));
+        }
+        self.call()
+    }
+
+    // call -> primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )*
+    fn call(&mut self) -> ParseResult {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.matching([LEFT_PAREN]) {
+                expr = self.finish_call(expr)?;
+            } else if self.matching([DOT]) {
+                let name = self.consume(IDENTIFIER, "Expect property name after '.'.")?.clone();
+                expr = Some(Box::new(Expr::Get { object: expr, name }));
+            } else if self.matching([LEFT_BRACKET]) {
+                let bracket = self.previous().clone();
+                let index = self.expression()?;
+                self.consume(RIGHT_BRACKET, "Expect ']' after index.")?;
+                expr = Some(Box::new(Expr::Index { object: expr, bracket, index }));
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: BoxExpr) -> ParseResult {
+        let mut arguments = Vec::new();
+        if !self.check(&RIGHT_PAREN) {
+            loop {
+                if let Some(argument) = self.expression()? {
+                    arguments.push(*argument);
+                }
+                if !self.matching([COMMA]) {
+                    break;
+                }
             }
         }
-        self.primary()
+
+        let paren = self.consume(RIGHT_PAREN, "Expect ')' after arguments.")?.clone();
+
+        Ok(Some(Box::new(Expr::Call { callee, paren, arguments })))
     }
 
-    fn primary(&mut self) -> Result<BoxExpr, ParseError> {
+    fn primary(&mut self) -> ParseResult {
         if self.matching([FALSE]) {
-            Ok(Some(Box::new(Expr::Literal { value: Object::Bool(false) })))
+            Ok(Some(Box::new(Expr::Literal { token: self.previous().clone(), value: Object::Bool(false) })))
         } else if self.matching([TRUE]) {
-            Ok(Some(Box::new(Expr::Literal { value: Object::Bool(true) })))
+            Ok(Some(Box::new(Expr::Literal { token: self.previous().clone(), value: Object::Bool(true) })))
         } else if self.matching([NIL]) {
-            Ok(Some(Box::new(Expr::Literal { value: Object::Nil })))
+            Ok(Some(Box::new(Expr::Literal { token: self.previous().clone(), value: Object::Nil })))
         } else if self.matching([NUMBER, STRING]) {
             let prev = self.previous();
             match prev.token_type {
-                STRING | NUMBER => Ok(Some(Box::new(Expr::Literal { value: prev.literal.to_object() }))),
+                STRING | NUMBER => Ok(Some(Box::new(Expr::Literal { token: prev.clone(), value: prev.literal.to_object() }))),
                 _ => panic!("Oh wow, that is more stranger thing")
             }
+        } else if self.matching([SELF]) {
+            Ok(Some(Box::new(Expr::SelfRef { keyword: self.previous().clone() })))
+        } else if self.matching([SUPER]) {
+            self.super_()
+        } else if self.matching([IDENTIFIER]) {
+            Ok(Some(Box::new(Expr::Variable { name: self.previous().clone() })))
+        } else if self.matching([FUN]) {
+            self.lambda()
+        } else if self.matching([LEFT_BRACKET]) {
+            self.list()
+        } else if self.matching([MATCH]) {
+            self.match_()
+        } else if self.check(&LEFT_PAREN) && self.is_arrow_lambda() {
+            self.advance();
+            self.arrow_lambda()
         } else if self.matching([LEFT_PAREN]) {
-            let expression = match self.expression() {
-                Ok(expression) => expression,
-                Err(e) => return Err(e),
-            };
-            if let Err(e) = self.consume(RIGHT_PAREN, "Expect ')' after expression.") {
-                return Err(e);
-            }
+            let expression = self.expression()?;
+            self.consume(RIGHT_PAREN, "Expect ')' after expression.")?;
             Ok(Some(Box::new(Expr::Grouping { expression })))
         } else {
             Err(self.error(self.peek(), "Expect expression."))
         }
     }
 
+    // list -> "[" ( expression ( "," expression )* ","? )? "]"
+    fn list(&mut self) -> ParseResult {
+        let bracket = self.previous().clone();
+        let mut elements = Vec::new();
+        while !self.check(&RIGHT_BRACKET) && !self.is_at_end() {
+            if let Some(element) = self.expression()? {
+                elements.push(*element);
+            }
+            if !self.matching([COMMA]) {
+                break;
+            }
+        }
+        self.consume(RIGHT_BRACKET, "Expect ']' after list elements.")?;
+
+        Ok(Some(Box::new(Expr::List { bracket, elements })))
+    }
+
+    // match -> "match" expression "{" ( pattern ( "if" expression )? "=>" expression ","? )* "}"
+    fn match_(&mut self) -> ParseResult {
+        let keyword = self.previous().clone();
+        let subject = self.expression()?;
+        self.consume(LEFT_BRACE, "Expect '{' after match subject.")?;
+
+        let mut arms = Vec::new();
+        while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            let pattern = self.pattern()?;
+            let guard = if self.matching([IF]) {
+                self.expression()?
+            } else {
+                None
+            };
+            self.consume(FAT_ARROW, "Expect '=>' after match pattern.")?;
+            let body = self.expression()?;
+            arms.push(MatchArm { pattern, guard, body });
+
+            if !self.matching([COMMA]) {
+                break;
+            }
+        }
+        self.consume(RIGHT_BRACE, "Expect '}' after match arms.")?;
+
+        Ok(Some(Box::new(Expr::Match { keyword, subject, arms })))
+    }
+
+    // pattern -> literal | "-" NUMBER | "_" | IDENTIFIER | IDENTIFIER "{" fieldPatterns? "}" | "[" listPatterns? "]"
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        if self.matching([NUMBER, STRING]) {
+            let token = self.previous().clone();
+            let value = token.literal.to_object();
+            return Ok(Pattern::Literal { token, value });
+        }
+        if self.matching([TRUE, FALSE, NIL]) {
+            let token = self.previous().clone();
+            let value = match token.token_type {
+                TRUE => Object::Bool(true),
+                FALSE => Object::Bool(false),
+                _ => Object::Nil,
+            };
+            return Ok(Pattern::Literal { token, value });
+        }
+        if self.matching([MINUS]) {
+            let token = self.consume(NUMBER, "Expect number after '-' in pattern.")?.clone();
+            let literal = token.literal.to_object();
+            let value = number::negate(&literal).unwrap_or(literal);
+            return Ok(Pattern::Literal { token, value });
+        }
+        if self.matching([LEFT_BRACKET]) {
+            return self.list_pattern();
+        }
+        if self.matching([IDENTIFIER]) {
+            let name = self.previous().clone();
+            if name.lexeme == "_" {
+                return Ok(Pattern::Wildcard { token: name });
+            }
+            if self.matching([LEFT_BRACE]) {
+                return self.box_pattern(name);
+            }
+            return Ok(Pattern::Binding { name });
+        }
+
+        Err(self.error(self.peek(), "Expect pattern."))
+    }
+
+    // listPatterns -> pattern ( "," pattern )* ( "," ".." IDENTIFIER? )? | ".." IDENTIFIER?
+    fn list_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let bracket = self.previous().clone();
+        let mut elements = Vec::new();
+        let mut rest = None;
+        while !self.check(&RIGHT_BRACKET) && !self.is_at_end() {
+            if self.matching([DOT_DOT]) {
+                let token = self.previous().clone();
+                rest = Some(Box::new(if self.check(&IDENTIFIER) {
+                    Pattern::Binding { name: self.advance().clone() }
+                } else {
+                    Pattern::Wildcard { token }
+                }));
+                break;
+            }
+            elements.push(self.pattern()?);
+            if !self.matching([COMMA]) {
+                break;
+            }
+        }
+        self.consume(RIGHT_BRACKET, "Expect ']' after list pattern.")?;
+
+        Ok(Pattern::List { bracket, elements, rest })
+    }
+
+    // fieldPatterns -> IDENTIFIER ( ":" pattern )? ( "," IDENTIFIER ( ":" pattern )? )*
+    // A bare field name binds the field to a variable of the same name.
+    fn box_pattern(&mut self, name: Token) -> Result<Pattern, ParseError> {
+        let mut fields = Vec::new();
+        while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            let field = self.consume(IDENTIFIER, "Expect field name in box pattern.")?.clone();
+            let pattern = if self.matching([COLON]) {
+                self.pattern()?
+            } else {
+                Pattern::Binding { name: field.clone() }
+            };
+            fields.push((field, pattern));
+
+            if !self.matching([COMMA]) {
+                break;
+            }
+        }
+        self.consume(RIGHT_BRACE, "Expect '}' after box pattern.")?;
+
+        Ok(Pattern::Box { name, fields })
+    }
+
+    // super -> "super" "." IDENTIFIER | "super" "(" arguments? ")"
+    // The bare call form runs the superclass initializer.
+    fn super_(&mut self) -> ParseResult {
+        let keyword = self.previous().clone();
+        let method = if self.check(&LEFT_PAREN) {
+            Token {
+                token_type: IDENTIFIER,
+                literal: LiteralValue::Nil,
+                line: keyword.line,
+                column: keyword.column,
+                file: keyword.file,
+                end: None,
+                lexeme: Symbol::intern("init"),
+            }
+        } else {
+            self.consume(DOT, "Expect '.' or '(' after 'super'.")?;
+            self.consume(IDENTIFIER, "Expect superclass method name.")?.clone()
+        };
+
+        Ok(Some(Box::new(Expr::Super { keyword, method })))
+    }
+
+    // lambda -> "fun" "(" parameters? ")" ( "->" type )? block
+    fn lambda(&mut self) -> ParseResult {
+        let keyword = self.previous().clone();
+        self.consume(LEFT_PAREN, "Expect '(' after 'fun'.")?;
+        let params = self.parameters(false)?;
+        let result = self.result_type()?;
+        self.consume(LEFT_BRACE, "Expect '{' before function body.")?;
+        let body = Rc::new(self.block()?);
+
+        Ok(Some(Box::new(Expr::Lambda { keyword, params, result, body })))
+    }
+
+    // arrowLambda -> "(" parameters? ")" "->" expression
+    // The body is wrapped into a single return statement.
+    fn arrow_lambda(&mut self) -> ParseResult {
+        let params = self.parameters(false)?;
+        let keyword = self.consume(ARROW, "Expect '->' after lambda parameters.")?.clone();
+        let value = self.expression()?;
+        let body = Rc::new(vec![Stmt::Return { keyword: keyword.clone(), value }]);
+
+        Ok(Some(Box::new(Expr::Lambda { keyword, params, result: None, body })))
+    }
+
+    // Looks ahead from the current '(' for "( param ( "," param )* )? ")" "->"", where a param is an
+    // IDENTIFIER with an optional ": type".
+    fn is_arrow_lambda(&self) -> bool {
+        let mut position = self.current + 1;
+        if let Some(after) = self.skip_param(position) {
+            position = after;
+            while let Some(COMMA) = self.token_type_at(position) {
+                let Some(after) = self.skip_param(position + 1) else { return false };
+                position = after;
+            }
+        }
+
+        matches!((self.token_type_at(position), self.token_type_at(position + 1)), (Some(RIGHT_PAREN), Some(ARROW)))
+    }
+
+    fn token_type_at(&self, position: usize) -> Option<TokenType> {
+        self.tokens.get(position).map(|token| token.token_type)
+    }
+
+    // Where the param starting at `position` ends, if there is one there.
+    fn skip_param(&self, position: usize) -> Option<usize> {
+        let Some(IDENTIFIER) = self.token_type_at(position) else { return None };
+        match self.token_type_at(position + 1) {
+            Some(COLON) => self.skip_type(position + 2),
+            _ => Some(position + 1),
+        }
+    }
+
+    // Where the type starting at `position` ends, read like `type_expr` does.
+    fn skip_type(&self, position: usize) -> Option<usize> {
+        let Some(IDENTIFIER) = self.token_type_at(position) else { return None };
+        let mut position = position + 1;
+        if let Some(LESS) = self.token_type_at(position) {
+            position = self.skip_type(position + 1)?;
+            while let Some(COMMA) = self.token_type_at(position) {
+                position = self.skip_type(position + 1)?;
+            }
+            let Some(GREATER) = self.token_type_at(position) else { return None };
+            position += 1;
+        }
+        Some(position)
+    }
+
     fn consume(&mut self, token_type: TokenType, msg: &str) -> Result<&Token, ParseError> {
         if self.check(&token_type) {
             return Ok(self.advance());
@@ -176,7 +844,7 @@ impl Parser {
             }
 
             match self.peek().token_type {
-                BOX | FUN | LET | FOR | IF | WHILE | PRINT | RETURN => return,
+                BOX | INTERFACE | FUN | LET | FOR | IF | WHILE | PRINT | RETURN | IMPORT | FROM | THROW | TRY => return,
                 _ => ()
             }
 
@@ -206,6 +874,13 @@ impl Parser {
         mem::discriminant(token_type) == mem::discriminant(&current_token.token_type)
     }
 
+    fn check_next(&self, token_type: &TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(next) => mem::discriminant(token_type) == mem::discriminant(&next.token_type),
+            None => false,
+        }
+    }
+
     fn is_at_end(&self) -> bool {
         if let EOF = self.peek().token_type {
             return true;
@@ -238,4 +913,4 @@ impl Parser {
         }
         false
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,550 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::ops::Deref;
+use std::rc::Rc;
+use crate::libs::expr::{visitor::StmtVisitor, visitor::Visitor, ast::Expr, ast::MatchArm, ast::Object, ast::Pattern, ast::MethodSig, ast::Stmt, ast::TypeExpr, ast::TypedName};
+use crate::libs::lex::Token;
+use crate::libs::symbol::{Symbol, INIT, SELF, SUPER};
+
+// Where a local variable lives: `depth` environments out from the one in use, at `slot` in it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Resolution {
+    pub depth: usize,
+    pub slot: usize,
+}
+
+// Resolutions by the position of the token that names the variable: its file, line and column.
+// Globals are not in here, they are looked up by name. The declarations that a function inside
+// their scope uses are kept apart, for the VM to give them cells that outlive the call.
+#[derive(Default)]
+pub struct Locals {
+    resolutions: HashMap<(usize, usize, usize), Resolution>,
+    captured: HashSet<(usize, usize, usize)>,
+}
+
+impl Locals {
+    pub fn extend(&mut self, other: Locals) {
+        self.resolutions.extend(other.resolutions);
+        self.captured.extend(other.captured);
+    }
+
+    pub fn is_captured(&self, declaration: &Token) -> bool {
+        self.captured.contains(&declaration.position())
+    }
+}
+
+impl Deref for Locals {
+    type Target = HashMap<(usize, usize, usize), Resolution>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.resolutions
+    }
+}
+
+pub struct ResolveError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error at '{}': {}", self.token.line, self.token.lexeme, self.message)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BoxKind {
+    None,
+    Box,
+    Subbox,
+}
+
+// The names a scope declares, with their slots and whether the declaration has run yet. A block
+// declares all of its names up front, so using one too early can be told apart from using an outer
+// variable with the same name.
+struct Scope {
+    names: HashMap<Symbol, (usize, bool)>,
+    // The positions of the declarations, by slot.
+    declarations: Vec<(usize, usize, usize)>,
+    // How many functions the scope is nested in.
+    functions: usize,
+}
+
+// Runs between the parser and the interpreter: binds every use of a local variable to the scope that
+// declares it and reports the mistakes that can be seen without running the program.
+pub struct Resolver {
+    // The first scope holds the globals, which get no slots.
+    scopes: Vec<Scope>,
+    function: FunctionKind,
+    box_kind: BoxKind,
+    functions: usize,
+    locals: Locals,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            function: FunctionKind::None,
+            box_kind: BoxKind::None,
+            functions: 0,
+            locals: Locals::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(mut self, statements: &[Stmt]) -> Result<Locals, Vec<ResolveError>> {
+        self.begin_scope();
+        self.declarations(statements);
+        self.statements(statements);
+        self.end_scope();
+
+        if self.errors.is_empty() {
+            Ok(self.locals)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ResolveError { token: token.clone(), message: message.to_string() });
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.visit_stmt(statement);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Scope { names: HashMap::new(), declarations: Vec::new(), functions: self.functions });
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    // Gives the next slot to `name`, which is not usable until `define`.
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else { return };
+        if scope.names.contains_key(&name.lexeme) {
+            self.error(name, "Already a variable with this name in this scope.");
+            return;
+        }
+        let slot = scope.names.len();
+        scope.names.insert(name.lexeme, (slot, false));
+        scope.declarations.push(name.position());
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some((_, defined)) = self.scopes.last_mut().and_then(|scope| scope.names.get_mut(&name.lexeme)) {
+            *defined = true;
+        }
+    }
+
+    fn declare_and_define(&mut self, name: &Token) {
+        self.declare(name);
+        self.define(name);
+    }
+
+    // The names the statements of a block declare, in the order the interpreter defines them.
+    fn declarations(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            match statement {
+                Stmt::Let { name, .. } | Stmt::Function { name, .. } | Stmt::Box { name, .. } => self.declare(name),
+                Stmt::Import { names, .. } => names.iter().for_each(|name| self.declare(name)),
+                _ => (),
+            }
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        self.begin_scope();
+        self.declarations(statements);
+        self.statements(statements);
+        self.end_scope();
+    }
+
+    fn local(&mut self, name: &Token) {
+        for (index, scope) in self.scopes.iter().enumerate().rev() {
+            let Some(&(slot, defined)) = scope.names.get(&name.lexeme) else { continue };
+            // Code in a function body runs later, when the declarations around it have run.
+            if !defined && scope.functions == self.functions {
+                self.error(name, &format!("Can't use '{}' before its definition.", name.lexeme));
+                return;
+            }
+            if index > 0 {
+                let depth = self.scopes.len() - 1 - index;
+                self.locals.resolutions.insert(name.position(), Resolution { depth, slot });
+                // "self" and "super" are always in environments.
+                if scope.functions < self.functions && name.lexeme != "self" && name.lexeme != "super" {
+                    self.locals.captured.insert(scope.declarations[slot]);
+                }
+            }
+            return;
+        }
+    }
+
+    fn function(&mut self, kind: FunctionKind, params: &[TypedName], body: &[Stmt]) {
+        let enclosing = self.function;
+        self.function = kind;
+        self.functions += 1;
+
+        self.begin_scope();
+        for param in params {
+            self.declare_and_define(&param.name);
+        }
+        self.declarations(body);
+        self.statements(body);
+        self.end_scope();
+
+        self.functions -= 1;
+        self.function = enclosing;
+    }
+
+    // Binds the names in the order the interpreter collects them when the pattern matches.
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding { name } => self.declare_and_define(name),
+            Pattern::List { elements, rest, .. } => {
+                for element in elements {
+                    self.pattern(element);
+                }
+                if let Some(rest) = rest {
+                    self.pattern(rest);
+                }
+            }
+            Pattern::Box { fields, .. } => {
+                for (_, pattern) in fields {
+                    self.pattern(pattern);
+                }
+            }
+            Pattern::Literal { .. } | Pattern::Wildcard { .. } => (),
+        }
+    }
+
+    // Box patterns name their box, which is looked up before the arm's scope exists.
+    fn pattern_boxes(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Box { name, fields } => {
+                self.local(name);
+                for (_, pattern) in fields {
+                    self.pattern_boxes(pattern);
+                }
+            }
+            Pattern::List { elements, rest, .. } => {
+                for element in elements {
+                    self.pattern_boxes(element);
+                }
+                if let Some(rest) = rest {
+                    self.pattern_boxes(rest);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Visitor<()> for Resolver {
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) {
+        self.visit_expr(value);
+        self.local(name);
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, _operator: &Token, right: &Option<Box<Expr>>) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, _paren: &Token, arguments: &[Expr]) {
+        self.visit_expr(callee);
+        for argument in arguments {
+            self.visit(argument);
+        }
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, _name: &Token) {
+        self.visit_expr(object);
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) {
+        self.visit_expr(expression);
+    }
+
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, _bracket: &Token, index: &Option<Box<Expr>>) {
+        self.visit_expr(object);
+        self.visit_expr(index);
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        _bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) {
+        self.visit_expr(object);
+        self.visit_expr(index);
+        self.visit_expr(value);
+    }
+
+    fn visit_lambda(&mut self, _keyword: &Token, params: &[TypedName], _result: &Option<TypeExpr>, body: &Rc<Vec<Stmt>>) {
+        self.function(FunctionKind::Function, params, body);
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) {
+        for element in elements {
+            self.visit(element);
+        }
+    }
+
+    fn visit_literal(&mut self, _token: &Token, _value: &Object) {}
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, _operator: &Token, right: &Option<Box<Expr>>) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+
+    fn visit_match(&mut self, _keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) {
+        self.visit_expr(subject);
+        for arm in arms {
+            self.pattern_boxes(&arm.pattern);
+            self.begin_scope();
+            self.pattern(&arm.pattern);
+            self.visit_expr(&arm.guard);
+            self.visit_expr(&arm.body);
+            self.end_scope();
+        }
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, _name: &Token, value: &Option<Box<Expr>>) {
+        self.visit_expr(value);
+        self.visit_expr(object);
+    }
+
+    fn visit_self_ref(&mut self, keyword: &Token) {
+        if self.box_kind == BoxKind::None {
+            self.error(keyword, "Can't use 'self' outside of a box.");
+            return;
+        }
+        self.local(keyword);
+    }
+
+    fn visit_super(&mut self, keyword: &Token, _method: &Token) {
+        match self.box_kind {
+            BoxKind::None => self.error(keyword, "Can't use 'super' outside of a box."),
+            BoxKind::Box => self.error(keyword, "Can't use 'super' in a box with no superclass."),
+            BoxKind::Subbox => self.local(keyword),
+        }
+    }
+
+    fn visit_unary(&mut self, _operator: &Token, right: &Option<Box<Expr>>) {
+        self.visit_expr(right);
+    }
+
+    fn visit_variable(&mut self, name: &Token) {
+        self.local(name);
+    }
+
+    // Only a function can be a generator, and an initializer has to give back its instance.
+    fn visit_yield(&mut self, keyword: &Token, value: &Option<Box<Expr>>) {
+        match self.function {
+            FunctionKind::None => self.error(keyword, "Can't yield from top-level code."),
+            FunctionKind::Initializer => self.error(keyword, "Can't yield from an initializer."),
+            FunctionKind::Function => (),
+        }
+        self.visit_expr(value);
+    }
+}
+
+impl StmtVisitor<()> for Resolver {
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.block(statements);
+    }
+
+    // Methods see "super" in a scope of its own when there is a superclass, then "self" in the
+    // scope their call binds, like the environments the interpreter makes.
+    fn visit_box(&mut self, name: &Token, superclass: &Option<Box<Expr>>, _fields: &[TypedName], methods: &[Stmt]) {
+        let enclosing = self.box_kind;
+        self.box_kind = BoxKind::Box;
+
+        if let Some(superclass) = superclass {
+            self.visit(superclass);
+            self.box_kind = BoxKind::Subbox;
+        }
+        self.define(name);
+
+        if superclass.is_some() {
+            self.begin_scope();
+            self.declare_and_define(&Token { lexeme: *SUPER, ..name.clone() });
+        }
+        self.begin_scope();
+        self.declare_and_define(&Token { lexeme: *SELF, ..name.clone() });
+
+        for method in methods {
+            if let Stmt::Function { name, params, body, .. } = method {
+                let kind = if name.lexeme == *INIT { FunctionKind::Initializer } else { FunctionKind::Function };
+                self.function(kind, params, body);
+            }
+        }
+
+        self.end_scope();
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        self.box_kind = enclosing;
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) {
+        self.visit_expr(expression);
+    }
+
+    // The item is in a scope of its own, around the body.
+    fn visit_for(&mut self, _keyword: &Token, name: &Token, iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) {
+        self.visit_expr(iterable);
+        self.begin_scope();
+        self.declare_and_define(name);
+        if let Some(body) = body {
+            self.visit_stmt(body);
+        }
+        self.end_scope();
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        _type_params: &[Token],
+        params: &[TypedName],
+        _result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) {
+        self.define(name);
+        self.function(FunctionKind::Function, params, body);
+    }
+
+    fn visit_if(&mut self, condition: &Option<Box<Expr>>, then_branch: &Option<Box<Stmt>>, else_branch: &Option<Box<Stmt>>) {
+        self.visit_expr(condition);
+        if let Some(then_branch) = then_branch {
+            self.visit_stmt(then_branch);
+        }
+        if let Some(else_branch) = else_branch {
+            self.visit_stmt(else_branch);
+        }
+    }
+
+    // A module's names become globals of the importing file, so it can only be imported there.
+    fn visit_import(&mut self, keyword: &Token, _path: &Token, names: &[Token]) {
+        if self.scopes.len() > 1 {
+            self.error(keyword, "Imports are only allowed at the top level of a file.");
+        }
+        names.iter().for_each(|name| self.define(name));
+    }
+
+    fn visit_interface(&mut self, _name: &Token, _methods: &[MethodSig]) {}
+
+    fn visit_let(&mut self, name: &Token, _annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) {
+        self.visit_expr(initializer);
+        self.define(name);
+    }
+
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) {
+        self.visit_expr(expression);
+    }
+
+    fn visit_return(&mut self, keyword: &Token, value: &Option<Box<Expr>>) {
+        if self.function == FunctionKind::None {
+            self.error(keyword, "Can't return from top-level code.");
+        }
+        self.visit_expr(value);
+    }
+
+    fn visit_throw(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) {
+        self.visit_expr(value);
+    }
+
+    // The exception is in a scope of its own, around the handler's block.
+    fn visit_try(
+        &mut self,
+        _keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) {
+        if let Some(body) = body {
+            self.visit_stmt(body);
+        }
+        if let (Some(name), Some(handler)) = (name, handler) {
+            self.begin_scope();
+            self.declare_and_define(name);
+            self.visit_stmt(handler);
+            self.end_scope();
+        }
+        if let Some(finally) = finally {
+            self.visit_stmt(finally);
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) {
+        self.visit_expr(condition);
+        if let Some(body) = body {
+            self.visit_stmt(body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::lex::Lox;
+    use crate::libs::resolver::{Resolution, Resolver};
+
+    fn errors(source: &str) -> Vec<String> {
+        let statements = Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+        match Resolver::new().resolve(&statements) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn resolution() {
+        let source = "fun f(a) { let b = a; return fun () { return b; }; }";
+        let statements = Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+        let locals = Resolver::new().resolve(&statements).unwrap_or_else(|_| panic!("Failed to resolve"));
+        // `a` in the initializer, then `b` one function out.
+        assert_eq!(locals.get(&(0, 1, 20)), Some(&Resolution { depth: 0, slot: 0 }));
+        assert_eq!(locals.get(&(0, 1, 46)), Some(&Resolution { depth: 1, slot: 1 }));
+        assert_eq!(locals.len(), 2);
+        // Only `b` is used by the inner function.
+        assert!(locals.captured.contains(&(0, 1, 16)));
+        assert!(!locals.captured.contains(&(0, 1, 7)));
+    }
+
+    #[test]
+    fn mistakes() {
+        assert_eq!(errors("print a; let a = 1;"), ["[line 1] Error at 'a': Can't use 'a' before its definition."]);
+        assert_eq!(errors("{ let a = 1; let a = 2; }"), ["[line 1] Error at 'a': Already a variable with this name in this scope."]);
+        assert_eq!(errors("return 1;"), ["[line 1] Error at 'return': Can't return from top-level code."]);
+        assert_eq!(errors("print self;"), ["[line 1] Error at 'self': Can't use 'self' outside of a box."]);
+        assert_eq!(
+            errors("box A { f() { return super.f(); } }"),
+            ["[line 1] Error at 'super': Can't use 'super' in a box with no superclass."]
+        );
+        // Function bodies run later, so they may use what is declared after them.
+        assert_eq!(errors("fun f() { return g(); } fun g() { return 1; } print f();"), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,280 @@
+use std::rc::Rc;
+use crate::libs::ast_printer::AstPrinter;
+use crate::libs::expr::{visitor::StmtVisitor, visitor::Visitor, ast::Expr, ast::MatchArm, ast::Object, ast::MethodSig, ast::Stmt, ast::TypeExpr, ast::TypedName};
+use crate::libs::lex::{Token, TokenType};
+
+// Reverse Polish notation: operands come first and every operator follows them, so
+// "(1 + 2) * -3" becomes "1 2 + 3 neg *". Calls and lists carry their size ("call/2", "list/3")
+// and statements are postfix too, with nested statements in braces.
+pub struct RpnPrinter {}
+
+impl RpnPrinter {
+    pub fn print(&mut self, statements: &[Stmt]) -> String {
+        statements.iter().map(|statement| self.visit_stmt(statement)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn postfix(&mut self, operands: Vec<&Option<Box<Expr>>>, operator: &str) -> String {
+        let mut parts: Vec<String> = operands.into_iter().filter_map(|operand| self.visit_expr(operand)).collect();
+        parts.push(operator.to_string());
+        parts.join(" ")
+    }
+
+    fn block(&mut self, statements: &[Stmt]) -> String {
+        if statements.is_empty() {
+            return "{ }".to_string();
+        }
+        let statements: Vec<String> = statements.iter().map(|statement| self.visit_stmt(statement)).collect();
+        format!("{{ {}; }}", statements.join("; "))
+    }
+
+    // Branches and loop bodies are always braced, a block only once.
+    fn branch(&mut self, statement: &Option<Box<Stmt>>) -> Option<String> {
+        match statement.as_deref()? {
+            Stmt::Block { statements } => Some(self.block(statements)),
+            statement => Some(self.block(std::slice::from_ref(statement))),
+        }
+    }
+}
+
+fn names(names: &[TypedName]) -> String {
+    names.iter().map(AstPrinter::typed_name).collect::<Vec<_>>().join(", ")
+}
+
+impl Visitor<String> for RpnPrinter {
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) -> String {
+        self.postfix(vec![value], &format!("={}", name.lexeme))
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> String {
+        self.postfix(vec![left, right], &operator.lexeme)
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, _paren: &Token, arguments: &[Expr]) -> String {
+        let mut parts: Vec<String> = self.visit_expr(callee).into_iter().collect();
+        parts.extend(arguments.iter().map(|argument| self.visit(argument)));
+        parts.push(format!("call/{}", arguments.len()));
+        parts.join(" ")
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, name: &Token) -> String {
+        self.postfix(vec![object], &format!(".{}", name.lexeme))
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) -> String {
+        self.visit_expr(expression).unwrap_or_default()
+    }
+
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, _bracket: &Token, index: &Option<Box<Expr>>) -> String {
+        self.postfix(vec![object, index], "[]")
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        _bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) -> String {
+        self.postfix(vec![object, index, value], "[]=")
+    }
+
+    fn visit_lambda(
+        &mut self,
+        _keyword: &Token,
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> String {
+        format!("{} fun({}){}", self.block(body), names(params), AstPrinter::returns(result))
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> String {
+        let mut parts: Vec<String> = elements.iter().map(|element| self.visit(element)).collect();
+        parts.push(format!("list/{}", elements.len()));
+        parts.join(" ")
+    }
+
+    fn visit_literal(&mut self, token: &Token, _value: &Object) -> String {
+        token.lexeme.to_string()
+    }
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> String {
+        self.postfix(vec![left, right], &operator.lexeme)
+    }
+
+    fn visit_match(&mut self, _keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) -> String {
+        let arms: Vec<String> = arms
+            .iter()
+            .map(|arm| {
+                let mut parts = vec![AstPrinter::pattern(&arm.pattern)];
+                if let Some(guard) = self.visit_expr(&arm.guard) {
+                    parts.push(format!("{guard} if"));
+                }
+                parts.push(format!("=> {}", self.visit_expr(&arm.body).unwrap_or_default()));
+                parts.join(" ")
+            })
+            .collect();
+        let subject = self.visit_expr(subject).unwrap_or_default();
+        format!("{subject} {{ {}; }} match", arms.join("; "))
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, name: &Token, value: &Option<Box<Expr>>) -> String {
+        self.postfix(vec![object, value], &format!(".{}=", name.lexeme))
+    }
+
+    fn visit_self_ref(&mut self, _keyword: &Token) -> String {
+        "self".to_string()
+    }
+
+    fn visit_super(&mut self, _keyword: &Token, method: &Token) -> String {
+        format!("super.{}", method.lexeme)
+    }
+
+    // Negation gets its own name so it can't be mistaken for subtraction.
+    fn visit_unary(&mut self, operator: &Token, right: &Option<Box<Expr>>) -> String {
+        match operator.token_type {
+            TokenType::MINUS => self.postfix(vec![right], "neg"),
+            _ => self.postfix(vec![right], &operator.lexeme),
+        }
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> String {
+        name.lexeme.to_string()
+    }
+
+    fn visit_yield(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> String {
+        self.postfix(vec![value], "yield")
+    }
+}
+
+impl StmtVisitor<String> for RpnPrinter {
+    fn visit_block(&mut self, statements: &[Stmt]) -> String {
+        self.block(statements)
+    }
+
+    fn visit_box(&mut self, name: &Token, superclass: &Option<Box<Expr>>, fields: &[TypedName], methods: &[Stmt]) -> String {
+        let mut parts: Vec<String> = self.visit_expr(superclass).into_iter().collect();
+        parts.push(self.block(methods));
+        parts.push(format!("box {}({})", name.lexeme, names(fields)));
+        parts.join(" ")
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) -> String {
+        self.visit_expr(expression).unwrap_or_default()
+    }
+
+    fn visit_for(&mut self, _keyword: &Token, name: &Token, iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> String {
+        let parts = [self.visit_expr(iterable), self.branch(body)];
+        parts.into_iter().flatten().chain([format!("for({})", name.lexeme)]).collect::<Vec<_>>().join(" ")
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        type_params: &[Token],
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> String {
+        let type_params = AstPrinter::type_params(type_params);
+        format!("{} fun {}{type_params}({}){}", self.block(body), name.lexeme, names(params), AstPrinter::returns(result))
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Option<Box<Expr>>,
+        then_branch: &Option<Box<Stmt>>,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> String {
+        let parts = [self.visit_expr(condition), self.branch(then_branch), self.branch(else_branch)];
+        let keyword = if else_branch.is_some() { "if-else" } else { "if" };
+        parts.into_iter().flatten().chain([keyword.to_string()]).collect::<Vec<_>>().join(" ")
+    }
+
+    fn visit_import(&mut self, keyword: &Token, path: &Token, names: &[Token]) -> String {
+        AstPrinter::import(keyword, path, names)
+    }
+
+    fn visit_interface(&mut self, name: &Token, methods: &[MethodSig]) -> String {
+        let methods: Vec<String> = methods.iter().map(AstPrinter::method_sig).collect();
+        format!("{{ {} }} interface {}", methods.join("; "), name.lexeme)
+    }
+
+    fn visit_let(&mut self, name: &Token, annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) -> String {
+        let name = AstPrinter::annotated(name, annotation);
+        self.postfix(vec![initializer], &format!("let {name}"))
+    }
+
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) -> String {
+        self.postfix(vec![expression], "print")
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> String {
+        self.postfix(vec![value], "return")
+    }
+
+    fn visit_throw(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> String {
+        self.postfix(vec![value], "throw")
+    }
+
+    fn visit_try(
+        &mut self,
+        _keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) -> String {
+        let mut parts: Vec<String> = self.branch(body).into_iter().chain(["try".to_string()]).collect();
+        if let (Some(name), Some(handler)) = (name, self.branch(handler)) {
+            parts.extend([handler, format!("catch({})", name.lexeme)]);
+        }
+        if let Some(finally) = self.branch(finally) {
+            parts.extend([finally, "finally".to_string()]);
+        }
+        parts.join(" ")
+    }
+
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> String {
+        let parts = [self.visit_expr(condition), self.branch(body)];
+        parts.into_iter().flatten().chain(["while".to_string()]).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::dot_printer::DotPrinter;
+    use crate::libs::lex::Lox;
+    use crate::libs::parser::Parser;
+    use crate::libs::rpn_printer::RpnPrinter;
+    use crate::libs::tree_printer::TreePrinter;
+
+    fn parse(source: &str) -> Vec<crate::libs::expr::ast::Stmt> {
+        let tokens = Lox::new(source.to_string()).get_token_list();
+        Parser::new(tokens).parse().unwrap_or_else(|_| panic!("Failed to parse"))
+    }
+
+    #[test]
+    fn printers() {
+        let statements = parse("print (1 + 2) * -f(3, x);\nif (a) { a = [1]; }");
+        assert_eq!(
+            RpnPrinter {}.print(&statements),
+            "1 2 + f 3 x call/2 neg * print\na { 1 list/1 =a; } if"
+        );
+        assert_eq!(
+            TreePrinter {}.print(&statements[..1]),
+            "Print @1:8-1:25
+  expression: Binary * @1:8-1:25
+    left: Grouping @1:8-1:13
+      expression: Binary + @1:8-1:13
+        left: Literal 1 @1:8-1:9
+        right: Literal 2 @1:12-1:13
+    right: Unary - @1:17-1:25
+      right: Call @1:18-1:25
+        callee: Variable f @1:18-1:19
+        argument: Literal 3 @1:20-1:21
+        argument: Variable x @1:23-1:24"
+        );
+        assert!(DotPrinter::new().print(&statements[..1]).contains("n3 [label=\"Binary +\"];\n    n3 -> n1 [label=\"left\"];\n    n3 -> n2 [label=\"right\"];"));
+    }
+}
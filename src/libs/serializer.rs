@@ -0,0 +1,744 @@
+use std::fmt::{self, Display};
+use std::rc::Rc;
+use crate::libs::bigint::BigInt;
+use crate::libs::expr::{visitor::StmtVisitor, visitor::Visitor, ast::Expr, ast::MatchArm, ast::Object, ast::Pattern, ast::MethodSig, ast::Stmt, ast::TypeExpr, ast::TypedName};
+use crate::libs::lex::{LiteralValue, Token, TokenType};
+use crate::libs::number;
+use crate::libs::span::{self, Span};
+use crate::libs::{json, sexp};
+use crate::libs::symbol::Symbol;
+
+// The data model shared by the JSON and S-expression forms of a syntax tree. A program is a list
+// of statement nodes; a node has a kind (its variant name) and named fields. Expressions,
+// statements and patterns start with a "span" field, and tokens are nodes of kind "Token".
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tree {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    List(Vec<Tree>),
+    Node(String, Vec<(String, Tree)>),
+}
+
+pub struct DecodeError {
+    pub message: String,
+}
+
+impl DecodeError {
+    pub fn new(message: &str) -> Self {
+        Self { message: message.to_string() }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to read the syntax tree: {}", self.message)
+    }
+}
+
+type DecodeResult<T> = Result<T, DecodeError>;
+
+// The text forms a tree can be written in, named like the `--emit` values.
+#[derive(Clone, Copy)]
+pub enum Format {
+    Json,
+    Sexp,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "ast-json" => Some(Format::Json),
+            "ast-sexp" => Some(Format::Sexp),
+            _ => None,
+        }
+    }
+
+    pub fn write(self, statements: &[Stmt]) -> String {
+        let tree = serialize(statements);
+        match self {
+            Format::Json => json::write(&tree),
+            Format::Sexp => sexp::write(&tree),
+        }
+    }
+
+    pub fn read(self, text: &str) -> DecodeResult<Vec<Stmt>> {
+        let tree = match self {
+            Format::Json => json::parse(text)?,
+            Format::Sexp => sexp::parse(text)?,
+        };
+        deserialize(&tree)
+    }
+}
+
+pub fn serialize(statements: &[Stmt]) -> Tree {
+    Serializer {}.stmts(statements)
+}
+
+pub fn deserialize(tree: &Tree) -> DecodeResult<Vec<Stmt>> {
+    list(tree)?.iter().map(stmt).collect()
+}
+
+struct Serializer {}
+
+impl Serializer {
+    fn expr(&mut self, expr: &Option<Box<Expr>>) -> Tree {
+        match expr {
+            Some(expr) => with_span(self.visit(expr), span::expr_span(expr)),
+            None => Tree::Nil,
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) -> Tree {
+        Tree::List(exprs.iter().map(|expr| with_span(self.visit(expr), span::expr_span(expr))).collect())
+    }
+
+    fn stmt(&mut self, stmt: &Option<Box<Stmt>>) -> Tree {
+        match stmt {
+            Some(stmt) => with_span(self.visit_stmt(stmt), span::stmt_span(stmt)),
+            None => Tree::Nil,
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Tree {
+        Tree::List(stmts.iter().map(|stmt| with_span(self.visit_stmt(stmt), span::stmt_span(stmt))).collect())
+    }
+
+    fn pattern(pattern: &Pattern) -> Tree {
+        let tree = match pattern {
+            Pattern::Literal { token, value } => node("Literal", vec![("token", self::token(token)), ("value", self::value(value))]),
+            Pattern::Binding { name } => node("Binding", vec![("name", token(name))]),
+            Pattern::Wildcard { token } => node("Wildcard", vec![("token", self::token(token))]),
+            Pattern::List { bracket, elements, rest } => node("List", vec![
+                ("bracket", token(bracket)),
+                ("elements", Tree::List(elements.iter().map(Self::pattern).collect())),
+                ("rest", rest.as_deref().map_or(Tree::Nil, Self::pattern)),
+            ]),
+            Pattern::Box { name, fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(field, pattern)| Tree::List(vec![token(field), Self::pattern(pattern)]))
+                    .collect();
+                node("Box", vec![("name", token(name)), ("fields", Tree::List(fields))])
+            }
+        };
+        with_span(tree, span::pattern_span(pattern))
+    }
+}
+
+fn node(kind: &str, fields: Vec<(&str, Tree)>) -> Tree {
+    Tree::Node(kind.to_string(), fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect())
+}
+
+fn with_span(tree: Tree, span: Option<Span>) -> Tree {
+    let Tree::Node(kind, mut fields) = tree else { return tree };
+    let span = span.map_or(Tree::Nil, |span| {
+        let bounds = [span.line, span.column, span.end_line, span.end_column];
+        Tree::List(bounds.into_iter().map(|bound| Tree::Number(bound as f64)).collect())
+    });
+    fields.insert(0, ("span".to_string(), span));
+    Tree::Node(kind, fields)
+}
+
+fn token(token: &Token) -> Tree {
+    node("Token", vec![
+        ("type", Tree::Str(format!("{:?}", token.token_type))),
+        ("lexeme", Tree::Str(token.lexeme.to_string())),
+        ("line", Tree::Number(token.line as f64)),
+        ("column", Tree::Number(token.column as f64)),
+    ])
+}
+
+fn tokens(tokens: &[Token]) -> Tree {
+    Tree::List(tokens.iter().map(token).collect())
+}
+
+fn type_node(type_: &TypeExpr) -> Tree {
+    let arguments = Tree::List(type_.arguments.iter().map(type_node).collect());
+    with_span(node("TypeExpr", vec![("name", token(&type_.name)), ("arguments", arguments)]), span::type_span(type_))
+}
+
+fn type_expr(type_: &Option<TypeExpr>) -> Tree {
+    type_.as_ref().map_or(Tree::Nil, type_node)
+}
+
+fn typed_names(names: &[TypedName]) -> Tree {
+    let names = names
+        .iter()
+        .map(|typed| node("TypedName", vec![("name", token(&typed.name)), ("annotation", type_expr(&typed.annotation))]));
+    Tree::List(names.collect())
+}
+
+// Only literals are held in a tree, so functions, boxes and other runtime values never show up.
+// Integers are written out in decimal, since a tree's numbers are floats.
+fn value(value: &Object) -> Tree {
+    match value {
+        Object::Float(n) => Tree::Number(*n),
+        Object::Int(_) | Object::BigInt(_) => node("Int", vec![("value", Tree::Str(value.to_string()))]),
+        Object::Str(s) => Tree::Str(s.to_string()),
+        Object::Bool(b) => Tree::Bool(*b),
+        Object::Nil => Tree::Nil,
+        value => unreachable!("'{value}' is not a literal."),
+    }
+}
+
+impl Visitor<Tree> for Serializer {
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) -> Tree {
+        node("Assign", vec![("name", token(name)), ("value", self.expr(value))])
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> Tree {
+        node("Binary", vec![("left", self.expr(left)), ("operator", token(operator)), ("right", self.expr(right))])
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, paren: &Token, arguments: &[Expr]) -> Tree {
+        node("Call", vec![("callee", self.expr(callee)), ("paren", token(paren)), ("arguments", self.exprs(arguments))])
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, name: &Token) -> Tree {
+        node("Get", vec![("object", self.expr(object)), ("name", token(name))])
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) -> Tree {
+        node("Grouping", vec![("expression", self.expr(expression))])
+    }
+
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, bracket: &Token, index: &Option<Box<Expr>>) -> Tree {
+        node("Index", vec![("object", self.expr(object)), ("bracket", token(bracket)), ("index", self.expr(index))])
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) -> Tree {
+        node("IndexSet", vec![
+            ("object", self.expr(object)),
+            ("bracket", token(bracket)),
+            ("index", self.expr(index)),
+            ("value", self.expr(value)),
+        ])
+    }
+
+    fn visit_lambda(
+        &mut self,
+        keyword: &Token,
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> Tree {
+        node("Lambda", vec![
+            ("keyword", token(keyword)),
+            ("params", typed_names(params)),
+            ("result", type_expr(result)),
+            ("body", self.stmts(body)),
+        ])
+    }
+
+    fn visit_list(&mut self, bracket: &Token, elements: &[Expr]) -> Tree {
+        node("List", vec![("bracket", token(bracket)), ("elements", self.exprs(elements))])
+    }
+
+    fn visit_literal(&mut self, token: &Token, value: &Object) -> Tree {
+        node("Literal", vec![("token", self::token(token)), ("value", self::value(value))])
+    }
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> Tree {
+        node("Logical", vec![("left", self.expr(left)), ("operator", token(operator)), ("right", self.expr(right))])
+    }
+
+    fn visit_match(&mut self, keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) -> Tree {
+        let arms = arms
+            .iter()
+            .map(|arm| {
+                node("MatchArm", vec![
+                    ("pattern", Self::pattern(&arm.pattern)),
+                    ("guard", self.expr(&arm.guard)),
+                    ("body", self.expr(&arm.body)),
+                ])
+            })
+            .collect();
+        node("Match", vec![("keyword", token(keyword)), ("subject", self.expr(subject)), ("arms", Tree::List(arms))])
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, name: &Token, value: &Option<Box<Expr>>) -> Tree {
+        node("Set", vec![("object", self.expr(object)), ("name", token(name)), ("value", self.expr(value))])
+    }
+
+    fn visit_self_ref(&mut self, keyword: &Token) -> Tree {
+        node("SelfRef", vec![("keyword", token(keyword))])
+    }
+
+    fn visit_super(&mut self, keyword: &Token, method: &Token) -> Tree {
+        node("Super", vec![("keyword", token(keyword)), ("method", token(method))])
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Option<Box<Expr>>) -> Tree {
+        node("Unary", vec![("operator", token(operator)), ("right", self.expr(right))])
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> Tree {
+        node("Variable", vec![("name", token(name))])
+    }
+
+    fn visit_yield(&mut self, keyword: &Token, value: &Option<Box<Expr>>) -> Tree {
+        node("Yield", vec![("keyword", token(keyword)), ("value", self.expr(value))])
+    }
+}
+
+impl StmtVisitor<Tree> for Serializer {
+    fn visit_block(&mut self, statements: &[Stmt]) -> Tree {
+        node("Block", vec![("statements", self.stmts(statements))])
+    }
+
+    fn visit_box(&mut self, name: &Token, superclass: &Option<Box<Expr>>, fields: &[TypedName], methods: &[Stmt]) -> Tree {
+        node("Box", vec![
+            ("name", token(name)),
+            ("superclass", self.expr(superclass)),
+            ("fields", typed_names(fields)),
+            ("methods", self.stmts(methods)),
+        ])
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) -> Tree {
+        node("Expression", vec![("expression", self.expr(expression))])
+    }
+
+    fn visit_for(&mut self, keyword: &Token, name: &Token, iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> Tree {
+        node("For", vec![
+            ("keyword", token(keyword)),
+            ("name", token(name)),
+            ("iterable", self.expr(iterable)),
+            ("body", self.stmt(body)),
+        ])
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        type_params: &[Token],
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> Tree {
+        node("Function", vec![
+            ("name", token(name)),
+            ("type_params", tokens(type_params)),
+            ("params", typed_names(params)),
+            ("result", type_expr(result)),
+            ("body", self.stmts(body)),
+        ])
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Option<Box<Expr>>,
+        then_branch: &Option<Box<Stmt>>,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> Tree {
+        node("If", vec![
+            ("condition", self.expr(condition)),
+            ("then_branch", self.stmt(then_branch)),
+            ("else_branch", self.stmt(else_branch)),
+        ])
+    }
+
+    fn visit_import(&mut self, keyword: &Token, path: &Token, names: &[Token]) -> Tree {
+        node("Import", vec![("keyword", token(keyword)), ("path", token(path)), ("names", tokens(names))])
+    }
+
+    fn visit_interface(&mut self, name: &Token, methods: &[MethodSig]) -> Tree {
+        let methods = methods.iter().map(|method| {
+            node("MethodSig", vec![
+                ("name", token(&method.name)),
+                ("params", typed_names(&method.params)),
+                ("result", type_expr(&method.result)),
+            ])
+        });
+        node("Interface", vec![("name", token(name)), ("methods", Tree::List(methods.collect()))])
+    }
+
+    fn visit_let(&mut self, name: &Token, annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) -> Tree {
+        node("Let", vec![
+            ("name", token(name)),
+            ("annotation", type_expr(annotation)),
+            ("initializer", self.expr(initializer)),
+        ])
+    }
+
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) -> Tree {
+        node("Print", vec![("expression", self.expr(expression))])
+    }
+
+    fn visit_return(&mut self, keyword: &Token, value: &Option<Box<Expr>>) -> Tree {
+        node("Return", vec![("keyword", token(keyword)), ("value", self.expr(value))])
+    }
+
+    fn visit_throw(&mut self, keyword: &Token, value: &Option<Box<Expr>>) -> Tree {
+        node("Throw", vec![("keyword", token(keyword)), ("value", self.expr(value))])
+    }
+
+    fn visit_try(
+        &mut self,
+        keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) -> Tree {
+        node("Try", vec![
+            ("keyword", token(keyword)),
+            ("body", self.stmt(body)),
+            ("name", name.as_ref().map_or(Tree::Nil, token)),
+            ("handler", self.stmt(handler)),
+            ("finally", self.stmt(finally)),
+        ])
+    }
+
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> Tree {
+        node("While", vec![("condition", self.expr(condition)), ("body", self.stmt(body))])
+    }
+}
+
+// A decoded node: its kind and a way to look up its fields. Spans are not read back, they follow
+// from the tokens.
+struct Fields<'a> {
+    kind: &'a str,
+    fields: &'a [(String, Tree)],
+}
+
+impl<'a> Fields<'a> {
+    fn of(tree: &'a Tree) -> DecodeResult<Self> {
+        match tree {
+            Tree::Node(kind, fields) => Ok(Self { kind, fields }),
+            _ => Err(DecodeError::new("Expect a node.")),
+        }
+    }
+
+    fn get(&self, name: &str) -> DecodeResult<&'a Tree> {
+        match self.fields.iter().find(|(field, _)| field == name) {
+            Some((_, value)) => Ok(value),
+            None => Err(DecodeError::new(&format!("Missing field '{name}' in '{}'.", self.kind))),
+        }
+    }
+
+    fn expr(&self, name: &str) -> DecodeResult<Option<Box<Expr>>> {
+        match self.get(name)? {
+            Tree::Nil => Ok(None),
+            tree => Ok(Some(Box::new(expr(tree)?))),
+        }
+    }
+
+    fn exprs(&self, name: &str) -> DecodeResult<Vec<Expr>> {
+        list(self.get(name)?)?.iter().map(expr).collect()
+    }
+
+    fn stmt(&self, name: &str) -> DecodeResult<Option<Box<Stmt>>> {
+        match self.get(name)? {
+            Tree::Nil => Ok(None),
+            tree => Ok(Some(Box::new(stmt(tree)?))),
+        }
+    }
+
+    fn stmts(&self, name: &str) -> DecodeResult<Vec<Stmt>> {
+        list(self.get(name)?)?.iter().map(stmt).collect()
+    }
+
+    fn token(&self, name: &str) -> DecodeResult<Token> {
+        token_of(self.get(name)?)
+    }
+
+    fn optional_token(&self, name: &str) -> DecodeResult<Option<Token>> {
+        match self.get(name)? {
+            Tree::Nil => Ok(None),
+            tree => token_of(tree).map(Some),
+        }
+    }
+
+    fn tokens(&self, name: &str) -> DecodeResult<Vec<Token>> {
+        list(self.get(name)?)?.iter().map(token_of).collect()
+    }
+
+    fn type_expr(&self, name: &str) -> DecodeResult<Option<TypeExpr>> {
+        match self.get(name)? {
+            Tree::Nil => Ok(None),
+            tree => type_expr_of(tree).map(Some),
+        }
+    }
+
+    fn typed_names(&self, name: &str) -> DecodeResult<Vec<TypedName>> {
+        list(self.get(name)?)?.iter().map(typed_name_of).collect()
+    }
+
+    fn value(&self, name: &str) -> DecodeResult<Object> {
+        match self.get(name)? {
+            Tree::Nil => Ok(Object::Nil),
+            Tree::Bool(b) => Ok(Object::Bool(*b)),
+            Tree::Number(n) => Ok(Object::Float(*n)),
+            Tree::Str(s) => Ok(Object::Str(Rc::new(s.clone()))),
+            tree @ Tree::Node(kind, _) if kind == "Int" => match Fields::of(tree)?.get("value")? {
+                Tree::Str(s) => BigInt::parse(s).map(number::int).ok_or_else(|| DecodeError::new(&format!("Invalid int '{s}'."))),
+                _ => Err(DecodeError::new("Expect a string in 'Int'.")),
+            },
+            _ => Err(DecodeError::new(&format!("Expect a literal value in '{}'.", self.kind))),
+        }
+    }
+
+    fn unknown(&self) -> DecodeError {
+        DecodeError::new(&format!("Unknown node kind '{}'.", self.kind))
+    }
+}
+
+fn list(tree: &Tree) -> DecodeResult<&[Tree]> {
+    match tree {
+        Tree::List(elements) => Ok(elements),
+        _ => Err(DecodeError::new("Expect a list.")),
+    }
+}
+
+fn number(tree: &Tree, kind: &str) -> DecodeResult<usize> {
+    match tree {
+        Tree::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        _ => Err(DecodeError::new(&format!("Expect a position in '{kind}'."))),
+    }
+}
+
+fn token_of(tree: &Tree) -> DecodeResult<Token> {
+    let fields = Fields::of(tree)?;
+    if fields.kind != "Token" {
+        return Err(DecodeError::new(&format!("Expect a token but got '{}'.", fields.kind)));
+    }
+    let (Tree::Str(name), Tree::Str(lexeme)) = (fields.get("type")?, fields.get("lexeme")?) else {
+        return Err(DecodeError::new("Expect the type and the lexeme of a token to be strings."));
+    };
+    let Some(token_type) = TokenType::from_name(name) else {
+        return Err(DecodeError::new(&format!("Unknown token type '{name}'.")));
+    };
+
+    // The literal is not stored, it is read from the lexeme like the scanner does.
+    let literal = match token_type {
+        TokenType::NUMBER => LiteralValue::number(lexeme).unwrap_or(LiteralValue::Float(f64::NAN)),
+        TokenType::STRING => LiteralValue::String(Symbol::intern(lexeme.trim_matches('"'))),
+        _ => LiteralValue::Nil,
+    };
+
+    Ok(Token {
+        token_type,
+        literal,
+        line: number(fields.get("line")?, "Token")?,
+        column: number(fields.get("column")?, "Token")?,
+        // A serialized tree is a single file, which runs as the main one.
+        file: 0,
+        end: None,
+        lexeme: Symbol::intern(lexeme),
+    })
+}
+
+fn type_expr_of(tree: &Tree) -> DecodeResult<TypeExpr> {
+    let node = Fields::of(tree)?;
+    if node.kind != "TypeExpr" {
+        return Err(node.unknown());
+    }
+    let arguments = list(node.get("arguments")?)?.iter().map(type_expr_of).collect::<DecodeResult<_>>()?;
+    Ok(TypeExpr { name: node.token("name")?, arguments })
+}
+
+fn typed_name_of(tree: &Tree) -> DecodeResult<TypedName> {
+    let node = Fields::of(tree)?;
+    if node.kind != "TypedName" {
+        return Err(node.unknown());
+    }
+    Ok(TypedName { name: node.token("name")?, annotation: node.type_expr("annotation")? })
+}
+
+fn expr(tree: &Tree) -> DecodeResult<Expr> {
+    let node = Fields::of(tree)?;
+    Ok(match node.kind {
+        "Assign" => Expr::Assign { name: node.token("name")?, value: node.expr("value")? },
+        "Binary" => Expr::Binary { left: node.expr("left")?, operator: node.token("operator")?, right: node.expr("right")? },
+        "Call" => Expr::Call { callee: node.expr("callee")?, paren: node.token("paren")?, arguments: node.exprs("arguments")? },
+        "Get" => Expr::Get { object: node.expr("object")?, name: node.token("name")? },
+        "Grouping" => Expr::Grouping { expression: node.expr("expression")? },
+        "Index" => Expr::Index { object: node.expr("object")?, bracket: node.token("bracket")?, index: node.expr("index")? },
+        "IndexSet" => Expr::IndexSet {
+            object: node.expr("object")?,
+            bracket: node.token("bracket")?,
+            index: node.expr("index")?,
+            value: node.expr("value")?,
+        },
+        "Lambda" => Expr::Lambda {
+            keyword: node.token("keyword")?,
+            params: node.typed_names("params")?,
+            result: node.type_expr("result")?,
+            body: Rc::new(node.stmts("body")?),
+        },
+        "List" => Expr::List { bracket: node.token("bracket")?, elements: node.exprs("elements")? },
+        "Literal" => Expr::Literal { token: node.token("token")?, value: node.value("value")? },
+        "Logical" => Expr::Logical { left: node.expr("left")?, operator: node.token("operator")?, right: node.expr("right")? },
+        "Match" => Expr::Match {
+            keyword: node.token("keyword")?,
+            subject: node.expr("subject")?,
+            arms: list(node.get("arms")?)?.iter().map(match_arm).collect::<DecodeResult<_>>()?,
+        },
+        "Set" => Expr::Set { object: node.expr("object")?, name: node.token("name")?, value: node.expr("value")? },
+        "SelfRef" => Expr::SelfRef { keyword: node.token("keyword")? },
+        "Super" => Expr::Super { keyword: node.token("keyword")?, method: node.token("method")? },
+        "Unary" => Expr::Unary { operator: node.token("operator")?, right: node.expr("right")? },
+        "Variable" => Expr::Variable { name: node.token("name")? },
+        "Yield" => Expr::Yield { keyword: node.token("keyword")?, value: node.expr("value")? },
+        _ => return Err(node.unknown()),
+    })
+}
+
+fn stmt(tree: &Tree) -> DecodeResult<Stmt> {
+    let node = Fields::of(tree)?;
+    Ok(match node.kind {
+        "Block" => Stmt::Block { statements: node.stmts("statements")? },
+        "Box" => Stmt::Box {
+            name: node.token("name")?,
+            superclass: node.expr("superclass")?,
+            fields: node.typed_names("fields")?,
+            methods: node.stmts("methods")?,
+        },
+        "Expression" => Stmt::Expression { expression: node.expr("expression")? },
+        "Function" => Stmt::Function {
+            name: node.token("name")?,
+            type_params: node.tokens("type_params")?,
+            params: node.typed_names("params")?,
+            result: node.type_expr("result")?,
+            body: Rc::new(node.stmts("body")?),
+        },
+        "If" => Stmt::If {
+            condition: node.expr("condition")?,
+            then_branch: node.stmt("then_branch")?,
+            else_branch: node.stmt("else_branch")?,
+        },
+        "For" => Stmt::For {
+            keyword: node.token("keyword")?,
+            name: node.token("name")?,
+            iterable: node.expr("iterable")?,
+            body: node.stmt("body")?,
+        },
+        "Import" => Stmt::Import {
+            keyword: node.token("keyword")?,
+            path: node.token("path")?,
+            names: node.tokens("names")?,
+        },
+        "Interface" => Stmt::Interface {
+            name: node.token("name")?,
+            methods: list(node.get("methods")?)?.iter().map(method_sig).collect::<DecodeResult<_>>()?,
+        },
+        "Let" => Stmt::Let {
+            name: node.token("name")?,
+            annotation: node.type_expr("annotation")?,
+            initializer: node.expr("initializer")?,
+        },
+        "Print" => Stmt::Print { expression: node.expr("expression")? },
+        "Return" => Stmt::Return { keyword: node.token("keyword")?, value: node.expr("value")? },
+        "Throw" => Stmt::Throw { keyword: node.token("keyword")?, value: node.expr("value")? },
+        "Try" => Stmt::Try {
+            keyword: node.token("keyword")?,
+            body: node.stmt("body")?,
+            name: node.optional_token("name")?,
+            handler: node.stmt("handler")?,
+            finally: node.stmt("finally")?,
+        },
+        "While" => Stmt::While { condition: node.expr("condition")?, body: node.stmt("body")? },
+        _ => return Err(node.unknown()),
+    })
+}
+
+fn method_sig(tree: &Tree) -> DecodeResult<MethodSig> {
+    let node = Fields::of(tree)?;
+    if node.kind != "MethodSig" {
+        return Err(node.unknown());
+    }
+    Ok(MethodSig { name: node.token("name")?, params: node.typed_names("params")?, result: node.type_expr("result")? })
+}
+
+fn match_arm(tree: &Tree) -> DecodeResult<MatchArm> {
+    let node = Fields::of(tree)?;
+    if node.kind != "MatchArm" {
+        return Err(node.unknown());
+    }
+    Ok(MatchArm { pattern: pattern(node.get("pattern")?)?, guard: node.expr("guard")?, body: node.expr("body")? })
+}
+
+fn pattern(tree: &Tree) -> DecodeResult<Pattern> {
+    let node = Fields::of(tree)?;
+    Ok(match node.kind {
+        "Literal" => Pattern::Literal { token: node.token("token")?, value: node.value("value")? },
+        "Binding" => Pattern::Binding { name: node.token("name")? },
+        "Wildcard" => Pattern::Wildcard { token: node.token("token")? },
+        "List" => Pattern::List {
+            bracket: node.token("bracket")?,
+            elements: list(node.get("elements")?)?.iter().map(pattern).collect::<DecodeResult<_>>()?,
+            rest: match node.get("rest")? {
+                Tree::Nil => None,
+                rest => Some(Box::new(pattern(rest)?)),
+            },
+        },
+        "Box" => {
+            let fields = list(node.get("fields")?)?.iter().map(|field| match list(field)? {
+                [name, field_pattern] => Ok((token_of(name)?, pattern(field_pattern)?)),
+                _ => Err(DecodeError::new("Expect a box pattern field to be a [name, pattern] pair.")),
+            });
+            Pattern::Box { name: node.token("name")?, fields: fields.collect::<DecodeResult<_>>()? }
+        }
+        _ => return Err(node.unknown()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::json;
+    use crate::libs::lex::Lox;
+    use crate::libs::parser::Parser;
+    use crate::libs::serializer::{deserialize, serialize};
+    use crate::libs::sexp;
+
+    const SOURCE: &str = "interface Shape { area(self) -> Number; }
+box Point { x: Number; y; init(self, x) { self.x = x; } }
+fun f(p: Point) -> String {
+    return match p { Point { x: 0 } => \"origin\", [a, ..rest] if a == 1 => -a, _ => [p[0], \"s\"] };
+}
+let g = (a) -> a or !nil;
+let h: Function = fun (a, b: Bool) -> Bool { return b; };
+fun first<T>(items: List<T>, map: Map<String, T>) -> T { return items[0]; }
+while (false) print super.x;";
+
+    #[test]
+    fn round_trip() {
+        let tokens = Lox::new(SOURCE.to_string()).get_token_list();
+        let statements = Parser::new(tokens).parse().unwrap_or_else(|_| panic!("Failed to parse"));
+        let tree = serialize(&statements);
+
+        let from_json = json::parse(&json::write(&tree)).unwrap_or_else(|error| panic!("{error}"));
+        assert_eq!(from_json, tree);
+        let from_sexp = sexp::parse(&sexp::write(&tree)).unwrap_or_else(|error| panic!("{error}"));
+        assert_eq!(from_sexp, tree);
+
+        let statements = deserialize(&from_json).unwrap_or_else(|error| panic!("{error}"));
+        assert_eq!(serialize(&statements), tree);
+    }
+
+    #[test]
+    fn golden() {
+        let tokens = Lox::new("print -x;".to_string()).get_token_list();
+        let statements = Parser::new(tokens).parse().unwrap_or_else(|_| panic!("Failed to parse"));
+        assert_eq!(
+            sexp::write(&serialize(&statements)),
+            "[(Print
+   :span [1 7 1 9]
+   :expression (Unary
+     :span [1 7 1 9]
+     :operator (Token :type \"MINUS\" :lexeme \"-\" :line 1 :column 7)
+     :right (Variable
+       :span [1 8 1 9]
+       :name (Token :type \"IDENTIFIER\" :lexeme \"x\" :line 1 :column 8))))]"
+        );
+    }
+}
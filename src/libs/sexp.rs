@@ -0,0 +1,105 @@
+use crate::libs::json::{self, Reader};
+use crate::libs::serializer::{DecodeError, Tree};
+
+// S-expression form of a syntax tree: a node is `(Kind :field value ...)`, a list is `[a b]`, and
+// the other values are written as `nil`, `true`, numbers and quoted strings.
+
+const WIDTH: usize = 100;
+
+pub fn write(tree: &Tree) -> String {
+    let mut result = String::new();
+    write_tree(&mut result, tree, 0, 0);
+    result
+}
+
+// `column` is where the value starts and `indent` where its continuation lines start.
+fn write_tree(out: &mut String, tree: &Tree, column: usize, indent: usize) {
+    let flat = flat(tree);
+    if column + flat.len() <= WIDTH {
+        out.push_str(&flat);
+        return;
+    }
+
+    match tree {
+        Tree::List(elements) => {
+            out.push('[');
+            for (position, element) in elements.iter().enumerate() {
+                if position != 0 {
+                    out.push_str(&format!("\n{}", " ".repeat(indent + 1)));
+                }
+                write_tree(out, element, indent + 1, indent + 1);
+            }
+            out.push(']');
+        }
+        Tree::Node(kind, fields) => {
+            let pad = " ".repeat(indent + 2);
+            out.push_str(&format!("({kind}"));
+            for (name, value) in fields {
+                out.push_str(&format!("\n{pad}:{name} "));
+                write_tree(out, value, indent + name.len() + 4, indent + 2);
+            }
+            out.push(')');
+        }
+        _ => out.push_str(&flat),
+    }
+}
+
+fn flat(tree: &Tree) -> String {
+    match tree {
+        Tree::Nil => "nil".to_string(),
+        Tree::Bool(b) => b.to_string(),
+        Tree::Number(n) => n.to_string(),
+        Tree::Str(s) => json::string(s),
+        Tree::List(elements) => format!("[{}]", elements.iter().map(flat).collect::<Vec<_>>().join(" ")),
+        Tree::Node(kind, fields) => {
+            let fields: String = fields.iter().map(|(name, value)| format!(" :{name} {}", flat(value))).collect();
+            format!("({kind}{fields})")
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Tree, DecodeError> {
+    let mut reader = Reader::new(text);
+    let tree = value(&mut reader)?;
+    reader.end()?;
+    Ok(tree)
+}
+
+fn value(reader: &mut Reader) -> Result<Tree, DecodeError> {
+    reader.skip_whitespace();
+    match reader.peek() {
+        Some('(') => {
+            reader.advance();
+            let kind = reader.word();
+            if kind.is_empty() {
+                return Err(reader.error("Expect node kind after '('."));
+            }
+            let mut fields = Vec::new();
+            while !reader.matching(')') {
+                reader.consume(':', "Expect ':' before field name or ')' after node.")?;
+                let name = reader.word();
+                if name.is_empty() {
+                    return Err(reader.error("Expect field name after ':'."));
+                }
+                fields.push((name, value(reader)?));
+            }
+            Ok(Tree::Node(kind, fields))
+        }
+        Some('[') => {
+            reader.advance();
+            let mut elements = Vec::new();
+            while !reader.matching(']') {
+                elements.push(value(reader)?);
+            }
+            Ok(Tree::List(elements))
+        }
+        Some('"') => Ok(Tree::Str(reader.string()?)),
+        Some('-' | '0'..='9') => reader.number(),
+        _ => match reader.word().as_str() {
+            "nil" => Ok(Tree::Nil),
+            "true" => Ok(Tree::Bool(true)),
+            "false" => Ok(Tree::Bool(false)),
+            _ => Err(reader.error("Expect a value.")),
+        },
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::rc::Rc;
+use crate::libs::bigint::BigInt;
+use crate::libs::chunk::{BoxPrototype, Capture, Chunk, Constant, OpCode, Prototype};
+use crate::libs::lex::{Token, TokenType};
+use crate::libs::symbol::Symbol;
+
+// The `.slsc` file a compiled script is saved in, so it can run again without being scanned,
+// parsed and checked. Numbers are little-endian:
+//
+//   file      "SLSC", u16 version, u32 checksum of the rest, the script's function
+//   function  name (u8 0, or 1 and a token), u8 1 for a generator or 0, u16 count and params as
+//             tokens, u16 cells, u16 count and upvalues as (u8 0 for a cell or 1 for an upvalue
+//             of the enclosing function, u16 index), u32 length and code,
+//             u32 count and (u32 offset, u32 line) line table, u16 count and constants
+//   constant  u8 tag and: 0 f64 float, 1 string, 2 function,
+//             3 box: name, u16 count and field names, u16 count and method functions,
+//             4 i64 int, 5 big int as a decimal string
+//   token     string, u32 line
+//   string    u32 length, UTF-8 bytes
+//
+// Functions are stored in the constant pools of the ones that declare them.
+pub const MAGIC: &[u8; 4] = b"SLSC";
+pub const FORMAT_VERSION: u16 = 3;
+
+const FLOAT: u8 = 0;
+const STRING: u8 = 1;
+const FUNCTION: u8 = 2;
+const BOX: u8 = 3;
+const INT: u8 = 4;
+const BIG_INT: u8 = 5;
+
+pub struct LoadError {
+    pub message: String,
+}
+
+impl LoadError {
+    fn new(message: &str) -> Self {
+        Self { message: message.to_string() }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to load the compiled file: {}", self.message)
+    }
+}
+
+type LoadResult<T> = Result<T, LoadError>;
+
+pub fn write(script: &Prototype) -> Vec<u8> {
+    let mut body = Vec::new();
+    function(&mut body, script);
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(FORMAT_VERSION.to_le_bytes());
+    bytes.extend(checksum(&body).to_le_bytes());
+    bytes.extend(body);
+    bytes
+}
+
+// Checks the header first, so a file from another version is reported as such rather than as
+// corrupt, then the checksum, then that the code only refers to what exists.
+pub fn read(bytes: &[u8]) -> LoadResult<Rc<Prototype>> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(LoadError::new("Not a compiled file, the header is missing."));
+    }
+    let version = reader.u16()?;
+    if version != FORMAT_VERSION {
+        let message = format!("The file has format version {version}, but version {FORMAT_VERSION} is supported.");
+        return Err(LoadError::new(&message));
+    }
+    let expected = reader.u32()?;
+    if checksum(&bytes[reader.position..]) != expected {
+        return Err(LoadError::new("The file is corrupt, its checksum doesn't match."));
+    }
+
+    let script = reader.function()?;
+    if !script.upvalues.is_empty() {
+        return Err(LoadError::new("The script closes over variables, but nothing encloses it."));
+    }
+    if reader.position != bytes.len() {
+        return Err(reader.error("Unexpected data after the script"));
+    }
+    Ok(script)
+}
+
+// FNV-1a.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, &byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+}
+
+fn function(bytes: &mut Vec<u8>, prototype: &Prototype) {
+    match prototype.name {
+        Some(ref name) => {
+            bytes.push(1);
+            token(bytes, name);
+        }
+        None => bytes.push(0),
+    }
+    bytes.push(u8::from(prototype.generator));
+    bytes.extend((prototype.params.len() as u16).to_le_bytes());
+    for param in &prototype.params {
+        token(bytes, param);
+    }
+    bytes.extend((prototype.cells as u16).to_le_bytes());
+    bytes.extend((prototype.upvalues.len() as u16).to_le_bytes());
+    for capture in &prototype.upvalues {
+        let (kind, index) = match *capture {
+            Capture::Cell(index) => (0, index),
+            Capture::Upvalue(index) => (1, index),
+        };
+        bytes.push(kind);
+        bytes.extend((index as u16).to_le_bytes());
+    }
+
+    let chunk = &prototype.chunk;
+    bytes.extend((chunk.code.len() as u32).to_le_bytes());
+    bytes.extend(&chunk.code);
+    bytes.extend((chunk.lines.len() as u32).to_le_bytes());
+    for &(offset, line) in &chunk.lines {
+        bytes.extend((offset as u32).to_le_bytes());
+        bytes.extend((line as u32).to_le_bytes());
+    }
+    bytes.extend((chunk.constants.len() as u16).to_le_bytes());
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Float(n) => {
+                bytes.push(FLOAT);
+                bytes.extend(n.to_le_bytes());
+            }
+            Constant::Int(n) => {
+                bytes.push(INT);
+                bytes.extend(n.to_le_bytes());
+            }
+            Constant::BigInt(n) => {
+                bytes.push(BIG_INT);
+                string(bytes, &n.to_string());
+            }
+            Constant::Str(s) => {
+                bytes.push(STRING);
+                string(bytes, s);
+            }
+            Constant::Function(prototype) => {
+                bytes.push(FUNCTION);
+                function(bytes, prototype);
+            }
+            Constant::Box(prototype) => {
+                bytes.push(BOX);
+                string(bytes, &prototype.name);
+                bytes.extend((prototype.fields.len() as u16).to_le_bytes());
+                for field in &prototype.fields {
+                    string(bytes, field);
+                }
+                bytes.extend((prototype.methods.len() as u16).to_le_bytes());
+                for method in &prototype.methods {
+                    function(bytes, method);
+                }
+            }
+        }
+    }
+}
+
+fn token(bytes: &mut Vec<u8>, token: &Token) {
+    string(bytes, &token.lexeme);
+    bytes.extend((token.line as u32).to_le_bytes());
+}
+
+fn string(bytes: &mut Vec<u8>, s: &str) {
+    bytes.extend((s.len() as u32).to_le_bytes());
+    bytes.extend(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: &str) -> LoadError {
+        LoadError::new(&format!("{message} at byte {}.", self.position))
+    }
+
+    fn take(&mut self, count: usize) -> LoadResult<&'a [u8]> {
+        if self.bytes.len() - self.position < count {
+            return Err(self.error("Unexpected end of file"));
+        }
+        let taken = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> LoadResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> LoadResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> LoadResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn count(&mut self) -> LoadResult<usize> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn string(&mut self) -> LoadResult<String> {
+        let length = self.count()?;
+        let start = self.position;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| LoadError::new(&format!("Invalid UTF-8 in the string at byte {start}.")))
+    }
+
+    fn token(&mut self) -> LoadResult<Token> {
+        let lexeme = self.string()?;
+        let line = self.count()?;
+        Ok(Token::synthetic(TokenType::IDENTIFIER, Symbol::intern(&lexeme), line))
+    }
+
+    fn function(&mut self) -> LoadResult<Rc<Prototype>> {
+        let name = match self.u8()? {
+            0 => None,
+            1 => Some(self.token()?),
+            _ => return Err(self.error("Invalid function name")),
+        };
+        let generator = match self.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(self.error("Invalid generator flag")),
+        };
+        let params = (0..self.u16()?).map(|_| self.token()).collect::<LoadResult<_>>()?;
+        let cells = self.u16()? as usize;
+        let upvalues = (0..self.u16()?)
+            .map(|_| match self.u8()? {
+                0 => Ok(Capture::Cell(self.u16()? as usize)),
+                1 => Ok(Capture::Upvalue(self.u16()? as usize)),
+                _ => Err(self.error("Invalid upvalue")),
+            })
+            .collect::<LoadResult<_>>()?;
+
+        let length = self.count()?;
+        let code = self.take(length)?.to_vec();
+        let lines = (0..self.count()?).map(|_| Ok((self.count()?, self.count()?))).collect::<LoadResult<_>>()?;
+        let mut constants = Vec::new();
+        for _ in 0..self.u16()? {
+            constants.push(self.constant()?);
+        }
+
+        let chunk = Chunk { code, constants, lines };
+        let prototype = Prototype { name, params, chunk, cells, upvalues, generator, file: 0 };
+        verify(&prototype)?;
+        Ok(Rc::new(prototype))
+    }
+
+    fn constant(&mut self) -> LoadResult<Constant> {
+        match self.u8()? {
+            FLOAT => Ok(Constant::Float(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))),
+            INT => Ok(Constant::Int(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))),
+            BIG_INT => {
+                let digits = self.string()?;
+                let n = BigInt::parse(&digits).ok_or_else(|| self.error(&format!("Invalid big int '{digits}'")))?;
+                Ok(Constant::BigInt(Rc::new(n)))
+            }
+            STRING => Ok(Constant::Str(Symbol::intern(&self.string()?))),
+            FUNCTION => Ok(Constant::Function(self.function()?)),
+            BOX => {
+                let name = Symbol::intern(&self.string()?);
+                let fields = (0..self.u16()?).map(|_| Ok(Symbol::intern(&self.string()?))).collect::<LoadResult<_>>()?;
+                let methods = (0..self.u16()?).map(|_| self.function()).collect::<LoadResult<_>>()?;
+                Ok(Constant::Box(Rc::new(BoxPrototype { name, fields, methods })))
+            }
+            tag => Err(self.error(&format!("Unknown constant tag {tag}"))),
+        }
+    }
+}
+
+// The VM trusts its code, so what is loaded must be made of whole instructions that end with a
+// return, jump to the start of one, use constants of the kind they expect and cells and upvalues
+// that exist, only yield in generators and keep to the values and try statements they have.
+fn verify(prototype: &Prototype) -> LoadResult<()> {
+    let chunk = &prototype.chunk;
+    let name = prototype.name.as_ref().map_or("<script>", |name| name.lexeme.as_str());
+    let error = |offset: usize, message: &str| {
+        LoadError::new(&format!("Invalid code in '{name}' at offset {offset}: {message}."))
+    };
+
+    let mut starts = HashSet::new();
+    let mut jumps = Vec::new();
+    let mut offset = 0;
+    let mut last = None;
+    while offset < chunk.code.len() {
+        let Some(op) = OpCode::from_byte(chunk.code[offset]) else {
+            return Err(error(offset, &format!("unknown opcode {}", chunk.code[offset])));
+        };
+        let next = offset + 1 + 2 * op.operands();
+        if next > chunk.code.len() {
+            return Err(error(offset, "the operands are cut off"));
+        }
+        let operand = |index: usize| chunk.read_u16(offset + 1 + 2 * index) as usize;
+        let constant = |index: usize| chunk.constants.get(operand(index));
+
+        let fits = match op {
+            OpCode::Constant => {
+                matches!(constant(0), Some(Constant::Float(_) | Constant::Int(_) | Constant::BigInt(_) | Constant::Str(_)))
+            }
+            OpCode::Define
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::CheckFields
+            | OpCode::SetProperty
+            | OpCode::MatchInstance
+            | OpCode::MatchField
+            | OpCode::Import => matches!(constant(0), Some(Constant::Str(_))),
+            OpCode::GetCell | OpCode::SetCell | OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                matches!(constant(1), Some(Constant::Str(_)))
+            }
+            OpCode::GetOuter | OpCode::SetOuter | OpCode::GetSuper => matches!(constant(2), Some(Constant::Str(_))),
+            OpCode::Closure => matches!(constant(0), Some(Constant::Function(_))),
+            OpCode::Box => matches!(constant(0), Some(Constant::Box(_))),
+            _ => true,
+        };
+        if !fits {
+            return Err(error(offset, &format!("{op:?} refers to a missing or wrong constant")));
+        }
+        let captures = |function: &Prototype| {
+            function.upvalues.iter().all(|&capture| match capture {
+                Capture::Cell(index) => index < prototype.cells,
+                Capture::Upvalue(index) => index < prototype.upvalues.len(),
+            })
+        };
+        let exists = match op {
+            OpCode::NewCell | OpCode::DefineCell | OpCode::GetCell | OpCode::SetCell => operand(0) < prototype.cells,
+            OpCode::GetUpvalue | OpCode::SetUpvalue => operand(0) < prototype.upvalues.len(),
+            OpCode::Closure => matches!(constant(0), Some(Constant::Function(function)) if captures(function)),
+            OpCode::Box => matches!(constant(0), Some(Constant::Box(class)) if class.methods.iter().all(|method| captures(method))),
+            _ => true,
+        };
+        if !exists {
+            return Err(error(offset, &format!("{op:?} refers to a missing cell or upvalue")));
+        }
+        // Only a generator's frame can be put aside.
+        if op == OpCode::Yield && !prototype.generator {
+            return Err(error(offset, "only a generator can yield"));
+        }
+        match op {
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Try | OpCode::ForNext => jumps.push((offset, next + operand(0))),
+            OpCode::Loop => jumps.push((offset, next.wrapping_sub(operand(0)))),
+            _ => (),
+        }
+
+        starts.insert(offset);
+        last = Some(op);
+        offset = next;
+    }
+
+    if last != Some(OpCode::Return) {
+        return Err(error(chunk.code.len(), "the code doesn't end with a return"));
+    }
+    if let Some(&(offset, _)) = jumps.iter().find(|(_, target)| !starts.contains(target)) {
+        return Err(error(offset, "the jump doesn't land on an instruction"));
+    }
+    flow(prototype, error)
+}
+
+// Where the code is at an instruction: how many values it has on the stack and how many try
+// statements are running.
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Flow {
+    height: usize,
+    tries: usize,
+}
+
+// Every way to an instruction must arrive with the same stack height and try statements, and no
+// instruction may take more than there are. The frame's own values below the code's, its callee
+// and arguments, can't be taken, only read and written as locals.
+fn flow(prototype: &Prototype, error: impl Fn(usize, &str) -> LoadError) -> LoadResult<()> {
+    let chunk = &prototype.chunk;
+    let frame = 1 + prototype.params.len();
+    let mut seen: HashMap<usize, Flow> = HashMap::new();
+    let mut pending = vec![(0, Flow::default())];
+    while let Some((offset, flow)) = pending.pop() {
+        match seen.get(&offset) {
+            Some(other) if *other == flow => continue,
+            Some(_) => return Err(error(offset, "the ways to the instruction leave different stacks or try statements")),
+            None => seen.insert(offset, flow),
+        };
+        // `verify` has made sure of the opcodes and their operands.
+        let op = OpCode::from_byte(chunk.code[offset]).expect("a verified opcode");
+        let next = offset + 1 + 2 * op.operands();
+        let operand = if op.operands() > 0 { chunk.read_u16(offset + 1) as usize } else { 0 };
+
+        let (takes, leaves) = op.stack_effect(operand);
+        if flow.height < takes {
+            return Err(error(offset, &format!("{op:?} needs more values than the {} on the stack", flow.height)));
+        }
+        if matches!(op, OpCode::GetLocal | OpCode::SetLocal) && operand >= frame + flow.height {
+            let message = format!("{op:?} reaches past the {} values of the frame", frame + flow.height);
+            return Err(error(offset, &message));
+        }
+        let mut after = Flow { height: flow.height - takes + leaves, ..flow };
+        match op {
+            // An error goes back to the stack of the try statement, with the exception pushed.
+            OpCode::Try => {
+                pending.push((next + operand, Flow { height: flow.height + 1, ..flow }));
+                after.tries += 1;
+            }
+            OpCode::EndTry => match after.tries.checked_sub(1) {
+                Some(tries) => after.tries = tries,
+                None => return Err(error(offset, "there is no try statement to end")),
+            },
+            OpCode::JumpIfFalse => pending.push((next + operand, after)),
+            OpCode::ForNext => pending.push((next + operand, flow)),
+            OpCode::Jump => {
+                pending.push((next + operand, after));
+                continue;
+            }
+            OpCode::Loop => {
+                pending.push((next - operand, after));
+                continue;
+            }
+            OpCode::Return | OpCode::Throw | OpCode::NoMatch => continue,
+            _ => (),
+        }
+        pending.push((next, after));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::chunk::{Chunk, OpCode, Prototype};
+    use crate::libs::compiler;
+    use crate::libs::disassembler;
+    use crate::libs::lex::Lox;
+    use crate::libs::slsc::{self, FORMAT_VERSION};
+
+    fn message(bytes: &[u8]) -> String {
+        slsc::read(bytes).err().map(|error| error.to_string()).unwrap_or_default()
+    }
+
+    // The message loading a script of these instructions gives.
+    fn loaded(code: &[(OpCode, &[u16])]) -> String {
+        let mut chunk = Chunk::new();
+        for (op, operands) in code {
+            chunk.write(*op as u8, 1);
+            for &operand in *operands {
+                chunk.write_u16(operand, 1);
+            }
+        }
+        let script = Prototype { name: None, params: Vec::new(), chunk, cells: 0, upvalues: Vec::new(), generator: false, file: 0 };
+        message(&slsc::write(&script))
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = "box A { x; init(x) { self.x = x; } } fun f(a) { return match a { A { x } => x, _ => -1.5 }; } print f(A(\"s\"));";
+        let statements = Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+        let script = compiler::build(statements).unwrap_or_else(|| panic!("Failed to compile"));
+
+        let bytes = slsc::write(&script);
+        let loaded = slsc::read(&bytes).unwrap_or_else(|error| panic!("{error}"));
+        assert_eq!(disassembler::disassemble(&loaded), disassembler::disassemble(&script));
+
+        assert_eq!(message(b"#!/bin/sh"), "Failed to load the compiled file: Not a compiled file, the header is missing.");
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            message(&newer),
+            "Failed to load the compiled file: The file has format version 4, but version 3 is supported."
+        );
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(message(&corrupt), "Failed to load the compiled file: The file is corrupt, its checksum doesn't match.");
+        assert_eq!(message(&bytes[..bytes.len() - 1]), "Failed to load the compiled file: The file is corrupt, its checksum doesn't match.");
+    }
+
+    #[test]
+    fn stack_effects() {
+        let invalid = |message: &str| format!("Failed to load the compiled file: Invalid code in '<script>' at {message}.");
+        assert_eq!(loaded(&[(OpCode::Nil, &[]), (OpCode::Return, &[])]), "");
+        assert_eq!(
+            loaded(&[(OpCode::Pop, &[]), (OpCode::Nil, &[]), (OpCode::Return, &[])]),
+            invalid("offset 0: Pop needs more values than the 0 on the stack")
+        );
+        assert_eq!(
+            loaded(&[(OpCode::Nil, &[]), (OpCode::Nil, &[]), (OpCode::Call, &[2]), (OpCode::Return, &[])]),
+            invalid("offset 2: Call needs more values than the 2 on the stack")
+        );
+        assert_eq!(
+            loaded(&[(OpCode::List, &[1]), (OpCode::Return, &[])]),
+            invalid("offset 0: List needs more values than the 0 on the stack")
+        );
+        assert_eq!(
+            loaded(&[(OpCode::PopScope, &[1]), (OpCode::Nil, &[]), (OpCode::Return, &[])]),
+            invalid("offset 0: PopScope needs more values than the 0 on the stack")
+        );
+        // The script's frame has one value, where a function has its callee.
+        assert_eq!(loaded(&[(OpCode::GetLocal, &[0]), (OpCode::Return, &[])]), "");
+        assert_eq!(
+            loaded(&[(OpCode::GetLocal, &[1]), (OpCode::Return, &[])]),
+            invalid("offset 0: GetLocal reaches past the 1 values of the frame")
+        );
+        assert_eq!(
+            loaded(&[(OpCode::Nil, &[]), (OpCode::DefineCell, &[0]), (OpCode::Nil, &[]), (OpCode::Return, &[])]),
+            invalid("offset 1: DefineCell refers to a missing cell or upvalue")
+        );
+        assert_eq!(
+            loaded(&[(OpCode::EndTry, &[]), (OpCode::Nil, &[]), (OpCode::Return, &[])]),
+            invalid("offset 0: there is no try statement to end")
+        );
+        // A jump over a push leaves one way to the return with a value and one without.
+        assert_eq!(
+            loaded(&[(OpCode::True, &[]), (OpCode::JumpIfFalse, &[1]), (OpCode::Nil, &[]), (OpCode::Return, &[])]),
+            invalid("offset 5: the ways to the instruction leave different stacks or try statements")
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use std::fmt::{self, Display};
+use std::rc::Rc;
+use crate::libs::expr::{visitor::StmtVisitor, visitor::Visitor, ast::Expr, ast::MatchArm, ast::Object, ast::Pattern, ast::MethodSig, ast::Stmt, ast::TypeExpr, ast::TypedName};
+use crate::libs::lex::Token;
+
+// The source range of a node: from the start of the first token the tree holds for it to the end of
+// the last one. Punctuation the parser drops, like a closing ';' or ')', is not part of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    // Exclusive, so a single-character token at 1:1 ends at 1:2.
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    pub fn of(token: &Token) -> Self {
+        match token.end {
+            Some((end_line, end_column)) => {
+                Span { line: token.line, column: token.column, end_line: end_line as usize, end_column: end_column as usize }
+            }
+            None => Self::at(token.line, token.column, &token.lexeme),
+        }
+    }
+
+    // The span of `lexeme` starting at `line` and `column`.
+    pub fn at(line: usize, column: usize, lexeme: &str) -> Self {
+        let lines: Vec<&str> = lexeme.split('\n').collect();
+        let last = lines[lines.len() - 1].chars().count();
+        Self {
+            line,
+            column,
+            end_line: line + lines.len() - 1,
+            end_column: if lines.len() == 1 { column + last } else { last + 1 },
+        }
+    }
+
+    // The smallest span covering both.
+    pub fn to(self, other: Span) -> Span {
+        let (line, column) = (self.line, self.column).min((other.line, other.column));
+        let (end_line, end_column) = (self.end_line, self.end_column).max((other.end_line, other.end_column));
+        Span { line, column, end_line, end_column }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}-{}:{}", self.line, self.column, self.end_line, self.end_column)
+    }
+}
+
+// None only for nodes without any token, like an empty block.
+pub fn expr_span(expr: &Expr) -> Option<Span> {
+    SpanFinder {}.visit(expr)
+}
+
+pub fn stmt_span(stmt: &Stmt) -> Option<Span> {
+    SpanFinder {}.visit_stmt(stmt)
+}
+
+pub fn pattern_span(pattern: &Pattern) -> Option<Span> {
+    match pattern {
+        Pattern::Literal { token, .. } | Pattern::Wildcard { token } => Some(Span::of(token)),
+        Pattern::Binding { name } => Some(Span::of(name)),
+        Pattern::List { bracket, elements, rest } => {
+            let elements = elements.iter().map(pattern_span);
+            join([Some(Span::of(bracket))].into_iter().chain(elements).chain([rest.as_deref().and_then(pattern_span)]))
+        }
+        Pattern::Box { name, fields } => {
+            let fields = fields.iter().map(|(field, pattern)| join([Some(Span::of(field)), pattern_span(pattern)]));
+            join([Some(Span::of(name))].into_iter().chain(fields))
+        }
+    }
+}
+
+pub fn arm_span(arm: &MatchArm) -> Option<Span> {
+    let mut finder = SpanFinder {};
+    join([pattern_span(&arm.pattern), finder.expr(&arm.guard), finder.expr(&arm.body)])
+}
+
+fn join(spans: impl IntoIterator<Item = Option<Span>>) -> Option<Span> {
+    spans.into_iter().flatten().reduce(Span::to)
+}
+
+pub fn type_span(type_: &TypeExpr) -> Option<Span> {
+    join([Some(Span::of(&type_.name))].into_iter().chain(type_.arguments.iter().map(type_span)))
+}
+
+fn tokens(tokens: &[Token]) -> Option<Span> {
+    join(tokens.iter().map(|token| Some(Span::of(token))))
+}
+
+fn typed_names(names: &[TypedName]) -> Option<Span> {
+    join(names.iter().map(|typed| join([Some(Span::of(&typed.name)), annotation(&typed.annotation)])))
+}
+
+fn annotation(annotation: &Option<TypeExpr>) -> Option<Span> {
+    annotation.as_ref().and_then(type_span)
+}
+
+struct SpanFinder {}
+
+impl SpanFinder {
+    fn expr(&mut self, expr: &Option<Box<Expr>>) -> Option<Span> {
+        self.visit_expr(expr).flatten()
+    }
+
+    fn stmt(&mut self, stmt: &Option<Box<Stmt>>) -> Option<Span> {
+        stmt.as_ref().and_then(|stmt| self.visit_stmt(stmt))
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) -> Option<Span> {
+        join(exprs.iter().map(|expr| self.visit(expr)).collect::<Vec<_>>())
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Option<Span> {
+        join(stmts.iter().map(|stmt| self.visit_stmt(stmt)).collect::<Vec<_>>())
+    }
+}
+
+impl Visitor<Option<Span>> for SpanFinder {
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) -> Option<Span> {
+        join([Some(Span::of(name)), self.expr(value)])
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> Option<Span> {
+        join([self.expr(left), Some(Span::of(operator)), self.expr(right)])
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, paren: &Token, arguments: &[Expr]) -> Option<Span> {
+        join([self.expr(callee), self.exprs(arguments), Some(Span::of(paren))])
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, name: &Token) -> Option<Span> {
+        join([self.expr(object), Some(Span::of(name))])
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) -> Option<Span> {
+        self.expr(expression)
+    }
+
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, bracket: &Token, index: &Option<Box<Expr>>) -> Option<Span> {
+        join([self.expr(object), Some(Span::of(bracket)), self.expr(index)])
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) -> Option<Span> {
+        join([self.expr(object), Some(Span::of(bracket)), self.expr(index), self.expr(value)])
+    }
+
+    fn visit_lambda(
+        &mut self,
+        keyword: &Token,
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> Option<Span> {
+        join([Some(Span::of(keyword)), typed_names(params), annotation(result), self.stmts(body)])
+    }
+
+    fn visit_list(&mut self, bracket: &Token, elements: &[Expr]) -> Option<Span> {
+        join([Some(Span::of(bracket)), self.exprs(elements)])
+    }
+
+    fn visit_literal(&mut self, token: &Token, _value: &Object) -> Option<Span> {
+        Some(Span::of(token))
+    }
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> Option<Span> {
+        join([self.expr(left), Some(Span::of(operator)), self.expr(right)])
+    }
+
+    fn visit_match(&mut self, keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) -> Option<Span> {
+        join([Some(Span::of(keyword)), self.expr(subject)].into_iter().chain(arms.iter().map(arm_span)))
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, name: &Token, value: &Option<Box<Expr>>) -> Option<Span> {
+        join([self.expr(object), Some(Span::of(name)), self.expr(value)])
+    }
+
+    fn visit_self_ref(&mut self, keyword: &Token) -> Option<Span> {
+        Some(Span::of(keyword))
+    }
+
+    fn visit_super(&mut self, keyword: &Token, method: &Token) -> Option<Span> {
+        join([Some(Span::of(keyword)), Some(Span::of(method))])
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Option<Box<Expr>>) -> Option<Span> {
+        join([Some(Span::of(operator)), self.expr(right)])
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> Option<Span> {
+        Some(Span::of(name))
+    }
+
+    fn visit_yield(&mut self, keyword: &Token, value: &Option<Box<Expr>>) -> Option<Span> {
+        join([Some(Span::of(keyword)), self.expr(value)])
+    }
+}
+
+impl StmtVisitor<Option<Span>> for SpanFinder {
+    fn visit_block(&mut self, statements: &[Stmt]) -> Option<Span> {
+        self.stmts(statements)
+    }
+
+    fn visit_box(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Box<Expr>>,
+        fields: &[TypedName],
+        methods: &[Stmt],
+    ) -> Option<Span> {
+        join([Some(Span::of(name)), self.expr(superclass), typed_names(fields), self.stmts(methods)])
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) -> Option<Span> {
+        self.expr(expression)
+    }
+
+    fn visit_for(&mut self, keyword: &Token, _name: &Token, _iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> Option<Span> {
+        join([Some(Span::of(keyword)), self.stmt(body)])
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        type_params: &[Token],
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> Option<Span> {
+        join([Some(Span::of(name)), tokens(type_params), typed_names(params), annotation(result), self.stmts(body)])
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Option<Box<Expr>>,
+        then_branch: &Option<Box<Stmt>>,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> Option<Span> {
+        join([self.expr(condition), self.stmt(then_branch), self.stmt(else_branch)])
+    }
+
+    fn visit_import(&mut self, keyword: &Token, path: &Token, names: &[Token]) -> Option<Span> {
+        join([keyword, path].into_iter().chain(names).map(|token| Some(Span::of(token))))
+    }
+
+    fn visit_interface(&mut self, name: &Token, methods: &[MethodSig]) -> Option<Span> {
+        let methods = methods.iter().map(|method| {
+            join([Some(Span::of(&method.name)), typed_names(&method.params), annotation(&method.result)])
+        });
+        join([Some(Span::of(name))].into_iter().chain(methods))
+    }
+
+    fn visit_let(&mut self, name: &Token, annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) -> Option<Span> {
+        join([Some(Span::of(name)), self::annotation(annotation), self.expr(initializer)])
+    }
+
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) -> Option<Span> {
+        self.expr(expression)
+    }
+
+    fn visit_return(&mut self, keyword: &Token, value: &Option<Box<Expr>>) -> Option<Span> {
+        join([Some(Span::of(keyword)), self.expr(value)])
+    }
+
+    fn visit_throw(&mut self, keyword: &Token, value: &Option<Box<Expr>>) -> Option<Span> {
+        join([Some(Span::of(keyword)), self.expr(value)])
+    }
+
+    fn visit_try(
+        &mut self,
+        keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) -> Option<Span> {
+        join([Some(Span::of(keyword)), self.stmt(body), name.as_ref().map(Span::of), self.stmt(handler), self.stmt(finally)])
+    }
+
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> Option<Span> {
+        join([self.expr(condition), self.stmt(body)])
+    }
+}
@@ -0,0 +1,580 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::f64::consts;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::libs::bigint::BigInt;
+use crate::libs::environment::Environment;
+use crate::libs::expr::ast::Object;
+use crate::libs::generator::{Generator, Resumed};
+use crate::libs::interpreter::{Interpreter, RuntimeError};
+use crate::libs::iterator;
+use crate::libs::lex::{Token, TokenType};
+use crate::libs::map::{Key, Map};
+use crate::libs::module::Module;
+use crate::libs::native::{self, error, type_name, NativeFn, NativeFunction};
+use crate::libs::number as numbers;
+use crate::libs::symbol::Symbol;
+
+// The standard library: the `math` module, the `Map` constructor, and the methods of strings,
+// lists, maps, ranges, iterators and generators. Methods get the value they are called on as their
+// first argument.
+
+type NativeResult = Result<Object, RuntimeError>;
+
+const MATH: [(&str, usize, NativeFn); 17] = [
+    ("sqrt", 1, |_, arguments| unary(arguments, "sqrt", f64::sqrt)),
+    ("pow", 2, pow),
+    ("floor", 1, |_, arguments| rounded(arguments, "floor", f64::floor)),
+    ("ceil", 1, |_, arguments| rounded(arguments, "ceil", f64::ceil)),
+    ("round", 1, |_, arguments| rounded(arguments, "round", f64::round)),
+    ("abs", 1, |_, arguments| {
+        number(&arguments[0], "abs", "its argument")?;
+        match numbers::compare(&arguments[0], &Object::Int(0)) {
+            Some(Some(Ordering::Less)) => Ok(numbers::negate(&arguments[0]).unwrap_or(Object::Nil)),
+            _ => Ok(arguments[0].clone()),
+        }
+    }),
+    ("min", 2, |_, arguments| extreme(arguments, "min", Ordering::Less)),
+    ("max", 2, |_, arguments| extreme(arguments, "max", Ordering::Greater)),
+    ("sin", 1, |_, arguments| unary(arguments, "sin", f64::sin)),
+    ("cos", 1, |_, arguments| unary(arguments, "cos", f64::cos)),
+    ("tan", 1, |_, arguments| unary(arguments, "tan", f64::tan)),
+    ("asin", 1, |_, arguments| unary(arguments, "asin", f64::asin)),
+    ("acos", 1, |_, arguments| unary(arguments, "acos", f64::acos)),
+    ("atan", 1, |_, arguments| unary(arguments, "atan", f64::atan)),
+    ("atan2", 2, |_, arguments| Ok(Object::Float(number(&arguments[0], "atan2", "the y coordinate")?.atan2(number(&arguments[1], "atan2", "the x coordinate")?)))),
+    ("random", 0, random),
+    ("seed", 1, seed),
+];
+
+const STRING_METHODS: [(&str, usize, NativeFn); 11] = [
+    ("iter", 0, iter),
+    ("split", 1, split),
+    ("trim", 0, |_, arguments| Ok(text(string(&arguments[0], "trim", "")?.trim()))),
+    ("find", 1, find),
+    ("replace", 2, replace),
+    ("upper", 0, |_, arguments| Ok(text(&string(&arguments[0], "upper", "")?.to_uppercase()))),
+    ("lower", 0, |_, arguments| Ok(text(&string(&arguments[0], "lower", "")?.to_lowercase()))),
+    ("char_at", 1, char_at),
+    ("substring", 2, substring),
+    ("contains", 1, |_, arguments| {
+        let needle = string(&arguments[1], "contains", "the text to look for")?;
+        Ok(Object::Bool(string(&arguments[0], "contains", "")?.contains(needle.as_str())))
+    }),
+    ("starts_with", 1, |_, arguments| {
+        let prefix = string(&arguments[1], "starts_with", "the prefix")?;
+        Ok(Object::Bool(string(&arguments[0], "starts_with", "")?.starts_with(prefix.as_str())))
+    }),
+];
+
+const LIST_METHODS: [(&str, usize, NativeFn); 10] = [
+    ("iter", 0, iter),
+    ("push", 1, push),
+    ("pop", 0, pop),
+    ("sort", 1, sort),
+    ("map", 1, map),
+    ("filter", 1, filter),
+    ("reduce", 2, reduce),
+    ("slice", 2, slice),
+    ("contains", 1, |_, arguments| {
+        let list = elements(&arguments[0]);
+        Ok(Object::Bool(list.iter().any(|element| Interpreter::is_equal(element, &arguments[1]))))
+    }),
+    ("index_of", 1, |_, arguments| {
+        let list = elements(&arguments[0]);
+        let found = list.iter().position(|element| Interpreter::is_equal(element, &arguments[1]));
+        Ok(Object::Int(found.map_or(-1, |i| i as i64)))
+    }),
+];
+
+const MAP_METHODS: [(&str, usize, NativeFn); 7] = [
+    ("iter", 0, iter),
+    ("get", 1, |_, arguments| Ok(entries(&arguments[0]).borrow().get(&key(&arguments[1], "get")?).unwrap_or(Object::Nil))),
+    ("set", 2, |_, arguments| {
+        let key = key(&arguments[1], "set")?;
+        entries(&arguments[0]).borrow_mut().insert(key, arguments[1].clone(), arguments[2].clone());
+        Ok(arguments[2].clone())
+    }),
+    ("contains", 1, |_, arguments| Ok(Object::Bool(entries(&arguments[0]).borrow().contains(&key(&arguments[1], "contains")?)))),
+    ("remove", 1, |_, arguments| {
+        let key = key(&arguments[1], "remove")?;
+        Ok(entries(&arguments[0]).borrow_mut().remove(&key).unwrap_or(Object::Nil))
+    }),
+    ("keys", 0, |interpreter, arguments| {
+        let keys = entries(&arguments[0]).borrow().entries().iter().map(|(key, _)| key.clone()).collect();
+        Ok(interpreter.heap.list(keys))
+    }),
+    ("values", 0, |interpreter, arguments| {
+        let values = entries(&arguments[0]).borrow().entries().iter().map(|(_, value)| value.clone()).collect();
+        Ok(interpreter.heap.list(values))
+    }),
+];
+
+const RANGE_METHODS: [(&str, usize, NativeFn); 1] = [("iter", 0, iter)];
+
+// An iterator is its own iterator, so that one can be looped over.
+const ITERATOR_METHODS: [(&str, usize, NativeFn); 2] = [
+    ("iter", 0, |_, arguments| Ok(arguments[0].clone())),
+    ("next", 0, |interpreter, arguments| {
+        let Object::Iterator(iter) = &arguments[0] else { unreachable!("next() is a method of iterators") };
+        let call = Token::synthetic(TokenType::IDENTIFIER, Symbol::intern(""), 0);
+        let item = iterator::next(&call, iter, &mut |object, method| interpreter.call_method(&call, object, method))?;
+        Ok(item.unwrap_or(Object::Nil))
+    }),
+];
+
+// `next` gives what the generator yields and nil once it has returned, like an iterator; `resume`
+// also passes a value in, and gives what the generator returns too.
+const GENERATOR_METHODS: [(&str, usize, NativeFn); 4] = [
+    ("iter", 0, |_, arguments| Ok(arguments[0].clone())),
+    ("next", 0, |interpreter, arguments| {
+        let generator = generator(&arguments[0]);
+        if generator.borrow().is_done() {
+            return Ok(Object::Nil);
+        }
+        match interpreter.resume(generator, Object::Nil)? {
+            Resumed::Yielded(value) => Ok(value),
+            Resumed::Returned(_) => Ok(Object::Nil),
+        }
+    }),
+    ("resume", 1, |interpreter, arguments| match interpreter.resume(generator(&arguments[0]), arguments[1].clone())? {
+        Resumed::Yielded(value) | Resumed::Returned(value) => Ok(value),
+    }),
+    ("done", 0, |_, arguments| Ok(Object::Bool(generator(&arguments[0]).borrow().is_done()))),
+];
+
+// Defines the library's globals: the `math` module and the `Map` constructor.
+pub fn define(globals: &mut Environment) {
+    let mut math = Environment::new();
+    for (name, arity, function) in MATH {
+        native::define(&mut math, name, arity, function);
+    }
+    math.define(Symbol::intern("pi"), Object::Float(consts::PI));
+    math.define(Symbol::intern("e"), Object::Float(consts::E));
+    let name = Symbol::intern("math");
+    globals.define(name, Object::Module(Rc::new(Module::new(name, Rc::new(RefCell::new(math))))));
+
+    native::define(globals, "Map", 0, |interpreter, _| Ok(interpreter.heap.map(Map::new())));
+}
+
+// The methods of the values whose type the type checker calls `kind`.
+fn methods(kind: &str) -> &'static [(&'static str, usize, NativeFn)] {
+    match kind {
+        "String" => &STRING_METHODS,
+        "List" => &LIST_METHODS,
+        "Map" => &MAP_METHODS,
+        "Range" => &RANGE_METHODS,
+        "Iterator" => &ITERATOR_METHODS,
+        "Generator" => &GENERATOR_METHODS,
+        _ => &[],
+    }
+}
+
+pub fn has_method(kind: &str, name: &str) -> bool {
+    methods(kind).iter().any(|&(method, _, _)| method == name)
+}
+
+// A property of a value that isn't an instance: a name in a module, or a method bound to a string,
+// list, map, range, iterator or generator.
+pub fn property(object: &Object, name: &Token) -> Result<Object, RuntimeError> {
+    if let Object::Module(module) = object {
+        return module.get(name);
+    }
+    if !matches!(object, Object::Str(_) | Object::List(_) | Object::Map(_) | Object::Range(_) | Object::Iterator(_) | Object::Generator(_)) {
+        return Err(RuntimeError::new(name, "Only instances have properties."));
+    }
+
+    let kind = type_name(object);
+    let method = methods(&kind).iter().find(|&&(method, _, _)| name.lexeme == method);
+    match method {
+        Some(&(_, arity, function)) => {
+            Ok(Object::NativeFunction(Rc::new(NativeFunction::method(object.clone(), name.lexeme, arity, function))))
+        }
+        None => Err(RuntimeError::new(name, &format!("Undefined property '{}' on {kind}.", name.lexeme))),
+    }
+}
+
+// Argument checks. `param` names the argument in the error; methods' receivers are always right.
+
+fn number(value: &Object, function: &str, param: &str) -> Result<f64, RuntimeError> {
+    numbers::to_f64(value)
+        .ok_or_else(|| error(&format!("{function}() expects {param} to be a Number, got {}.", type_name(value))))
+}
+
+fn string(value: &Object, function: &str, param: &str) -> Result<Rc<String>, RuntimeError> {
+    match value {
+        Object::Str(s) => Ok(Rc::clone(s)),
+        other => Err(error(&format!("{function}() expects {param} to be a String, got {}.", type_name(other)))),
+    }
+}
+
+// A position from 0 to `len`, inclusive.
+fn position(value: &Object, function: &str, param: &str, len: usize) -> Result<usize, RuntimeError> {
+    let n = match value {
+        Object::Int(n) if *n >= 0 => *n as u64,
+        Object::BigInt(n) if !n.is_negative() => u64::MAX,
+        Object::Int(_) | Object::BigInt(_) | Object::Float(_) => {
+            return Err(error(&format!("{function}() expects {param} to be a non-negative integer, got {value}.")));
+        }
+        other => return Err(error(&format!("{function}() expects {param} to be an Int, got {}.", type_name(other)))),
+    };
+    if n > len as u64 {
+        return Err(error(&format!("{function}() {param} {value} is out of bounds for length {len}.")));
+    }
+    Ok(n as usize)
+}
+
+fn callable(value: &Object, function: &str, param: &str) -> Result<Object, RuntimeError> {
+    match value {
+        Object::Function(_) | Object::NativeFunction(_) | Object::Box(_) => Ok(value.clone()),
+        other => Err(error(&format!("{function}() expects {param}, got {}.", type_name(other)))),
+    }
+}
+
+fn key(value: &Object, function: &str) -> Result<Key, RuntimeError> {
+    Key::of(value).ok_or_else(|| {
+        error(&format!("{function}() expects a key that is a Number, String, Bool or nil, got {}.", type_name(value)))
+    })
+}
+
+// A fresh iterator over a string, list, map or range, which goes through it lazily.
+fn iter(interpreter: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let iter = iterator::of(&arguments[0], &mut interpreter.heap).expect("iter() is a method of values loops go through");
+    Ok(Object::Iterator(iter))
+}
+
+fn generator(value: &Object) -> &Rc<RefCell<Generator>> {
+    let Object::Generator(generator) = value else { unreachable!("a method of generators") };
+    generator
+}
+
+fn text(s: &str) -> Object {
+    Object::Str(Rc::new(s.to_string()))
+}
+
+// A copy of a list's elements, so that callbacks can change the list while it is worked on.
+fn elements(list: &Object) -> Vec<Object> {
+    match list {
+        Object::List(list) => list.borrow().clone(),
+        _ => unreachable!("list methods are bound to lists"),
+    }
+}
+
+fn entries(map: &Object) -> &Rc<RefCell<Map>> {
+    match map {
+        Object::Map(map) => map,
+        _ => unreachable!("map methods are bound to maps"),
+    }
+}
+
+fn unary(arguments: &[Object], function: &str, operation: fn(f64) -> f64) -> NativeResult {
+    Ok(Object::Float(operation(number(&arguments[0], function, "its argument")?)))
+}
+
+// math
+
+// Integers are whole already; a float is rounded by `operation` to an integer, unless it is infinite
+// or NaN.
+fn rounded(arguments: &[Object], function: &str, operation: fn(f64) -> f64) -> NativeResult {
+    match &arguments[0] {
+        Object::Int(_) | Object::BigInt(_) => Ok(arguments[0].clone()),
+        value => {
+            let n = operation(number(value, function, "its argument")?);
+            Ok(BigInt::from_f64(n).map_or(Object::Float(n), numbers::int))
+        }
+    }
+}
+
+// The first argument unless the second is further toward `side`, keeping the kind of the number.
+fn extreme(arguments: &[Object], function: &str, side: Ordering) -> NativeResult {
+    number(&arguments[0], function, "the first argument")?;
+    number(&arguments[1], function, "the second argument")?;
+    match numbers::compare(&arguments[1], &arguments[0]) {
+        Some(Some(ordering)) if ordering == side => Ok(arguments[1].clone()),
+        _ => Ok(arguments[0].clone()),
+    }
+}
+
+// Exact for an integer to a non-negative integer power; a float otherwise.
+fn pow(_: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let base = number(&arguments[0], "pow", "the base")?;
+    let exponent = number(&arguments[1], "pow", "the exponent")?;
+    let integer = match &arguments[0] {
+        Object::Int(n) => Some(BigInt::from(*n)),
+        Object::BigInt(n) => Some(n.as_ref().clone()),
+        _ => None,
+    };
+    match (integer, &arguments[1]) {
+        (Some(integer), Object::Int(power)) if *power >= 0 => {
+            let power = u32::try_from(*power).map_err(|_| error("pow() exponent is too large."))?;
+            Ok(numbers::int(integer.pow(power)))
+        }
+        _ => Ok(Object::Float(base.powf(exponent))),
+    }
+}
+
+thread_local! {
+    // The state of a xorshift generator; never 0.
+    static RANDOM: Cell<u64> = Cell::new(scramble(
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_nanos() as u64),
+    ));
+}
+
+// Spreads the bits of a seed, so that close seeds start far apart.
+fn scramble(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)).max(1)
+}
+
+// A number from 0 up to, not including, 1.
+fn random(_: &mut Interpreter, _: &[Object]) -> NativeResult {
+    let next = RANDOM.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    });
+    Ok(Object::Float((next >> 11) as f64 / (1u64 << 53) as f64))
+}
+
+// Makes `random` give the same numbers for the same seed.
+fn seed(_: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let seed = number(&arguments[0], "seed", "the seed")?;
+    RANDOM.with(|state| state.set(scramble(seed.to_bits())));
+    Ok(Object::Nil)
+}
+
+// Strings. Positions count characters, not bytes.
+
+fn split(interpreter: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let s = string(&arguments[0], "split", "")?;
+    let separator = string(&arguments[1], "split", "the separator")?;
+    let parts = match separator.is_empty() {
+        true => s.chars().map(|c| text(c.encode_utf8(&mut [0; 4]))).collect(),
+        false => s.split(separator.as_str()).map(text).collect(),
+    };
+    Ok(interpreter.heap.list(parts))
+}
+
+// The position of the first occurrence, or -1.
+fn find(_: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let s = string(&arguments[0], "find", "")?;
+    let needle = string(&arguments[1], "find", "the text to look for")?;
+    let found = s.find(needle.as_str()).map(|byte| s[..byte].chars().count());
+    Ok(Object::Int(found.map_or(-1, |i| i as i64)))
+}
+
+fn replace(_: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let s = string(&arguments[0], "replace", "")?;
+    let from = string(&arguments[1], "replace", "the text to replace")?;
+    let to = string(&arguments[2], "replace", "the replacement")?;
+    if from.is_empty() {
+        return Err(error("replace() can't replace an empty string."));
+    }
+    Ok(text(&s.replace(from.as_str(), &to)))
+}
+
+fn char_at(_: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let s = string(&arguments[0], "char_at", "")?;
+    let len = s.chars().count();
+    let index = position(&arguments[1], "char_at", "index", len)?;
+    match s.chars().nth(index) {
+        Some(c) => Ok(text(c.encode_utf8(&mut [0; 4]))),
+        None => Err(error(&format!("char_at() index {index} is out of bounds for length {len}."))),
+    }
+}
+
+// The characters from `start` up to, not including, `end`.
+fn substring(_: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let s = string(&arguments[0], "substring", "")?;
+    let len = s.chars().count();
+    let start = position(&arguments[1], "substring", "start", len)?;
+    let end = position(&arguments[2], "substring", "end", len)?;
+    if start > end {
+        return Err(error(&format!("substring() start {start} is after end {end}.")));
+    }
+    Ok(text(&s.chars().skip(start).take(end - start).collect::<String>()))
+}
+
+// Lists.
+
+fn push(_: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let Object::List(list) = &arguments[0] else { unreachable!("list methods are bound to lists") };
+    list.borrow_mut().push(arguments[1].clone());
+    Ok(arguments[0].clone())
+}
+
+fn pop(_: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let Object::List(list) = &arguments[0] else { unreachable!("list methods are bound to lists") };
+    let last = list.borrow_mut().pop();
+    last.ok_or_else(|| error("pop() on an empty list."))
+}
+
+// Sorts the list in place and gives it back. The comparator returns a negative number when its
+// first argument goes first; with nil, numbers and strings are sorted in their natural order.
+fn sort(interpreter: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let comparator = match &arguments[1] {
+        Object::Nil => None,
+        other => Some(callable(other, "sort", "a comparator function or nil")?),
+    };
+    let mut compare = |a: &Object, b: &Object| -> Result<Ordering, RuntimeError> {
+        match (&comparator, a, b) {
+            (Some(comparator), _, _) => {
+                let result = native::call(interpreter, comparator, vec![a.clone(), b.clone()])?;
+                let order = number(&result, "sort", "the comparator's result")?;
+                Ok(order.partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+            }
+            (None, Object::Str(a), Object::Str(b)) => Ok(a.cmp(b)),
+            (None, a, b) => match numbers::compare(a, b) {
+                Some(ordering) => Ok(ordering.unwrap_or(Ordering::Equal)),
+                None => Err(error(&format!(
+                    "sort() without a comparator needs numbers or strings, got {} and {}.",
+                    type_name(a),
+                    type_name(b)
+                ))),
+            },
+        }
+    };
+
+    let sorted = merge_sort(elements(&arguments[0]), &mut compare)?;
+    if let Object::List(list) = &arguments[0] {
+        *list.borrow_mut() = sorted;
+    }
+    Ok(arguments[0].clone())
+}
+
+// A stable sort that stops at the comparator's first error. Unlike the standard library's, it
+// doesn't mind a comparator that isn't a consistent order.
+fn merge_sort(
+    mut items: Vec<Object>,
+    compare: &mut dyn FnMut(&Object, &Object) -> Result<Ordering, RuntimeError>,
+) -> Result<Vec<Object>, RuntimeError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let (left, right) = (merge_sort(items, compare)?, merge_sort(right, compare)?);
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        let next = match compare(a, b)? {
+            Ordering::Greater => right.next(),
+            _ => left.next(),
+        };
+        merged.extend(next);
+    }
+    merged.extend(left.chain(right));
+    Ok(merged)
+}
+
+fn map(interpreter: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let function = callable(&arguments[1], "map", "a function")?;
+    let mut mapped = Vec::new();
+    for element in elements(&arguments[0]) {
+        mapped.push(native::call(interpreter, &function, vec![element])?);
+    }
+    Ok(interpreter.heap.list(mapped))
+}
+
+fn filter(interpreter: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let function = callable(&arguments[1], "filter", "a function")?;
+    let mut kept = Vec::new();
+    for element in elements(&arguments[0]) {
+        if Interpreter::is_truthy(&native::call(interpreter, &function, vec![element.clone()])?) {
+            kept.push(element);
+        }
+    }
+    Ok(interpreter.heap.list(kept))
+}
+
+// Folds the elements into `initial` from the first to the last: function(accumulated, element).
+fn reduce(interpreter: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let function = callable(&arguments[1], "reduce", "a function")?;
+    let mut accumulated = arguments[2].clone();
+    for element in elements(&arguments[0]) {
+        accumulated = native::call(interpreter, &function, vec![accumulated, element])?;
+    }
+    Ok(accumulated)
+}
+
+// A new list of the elements from `start` up to, not including, `end`.
+fn slice(interpreter: &mut Interpreter, arguments: &[Object]) -> NativeResult {
+    let list = elements(&arguments[0]);
+    let start = position(&arguments[1], "slice", "start", list.len())?;
+    let end = position(&arguments[2], "slice", "end", list.len())?;
+    if start > end {
+        return Err(error(&format!("slice() start {start} is after end {end}.")));
+    }
+    Ok(interpreter.heap.list(list[start..end].to_vec()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use crate::libs::expr::ast::Object;
+    use crate::libs::interpreter::Interpreter;
+    use crate::libs::lex::{Token, TokenType};
+    use crate::libs::stdlib;
+    use crate::libs::testing::run;
+
+    #[test]
+    fn math() {
+        let source = "
+            print math.sqrt(16) + math.pow(2, 3) + math.floor(1.5);
+            math.seed(7);
+            let first = math.random();
+            math.seed(7);
+            print first == math.random();
+        ";
+        assert_eq!(run(source), "13.0\ntrue\n");
+    }
+
+    #[test]
+    fn strings() {
+        let source = "
+            print \" a,b \".trim().split(\",\");
+            print \"hello\".find(\"l\") + \"hello\".replace(\"l\", \"L\").upper().char_at(2).lower().find(\"l\");
+            print \"hello\".substring(1, 3);
+        ";
+        assert_eq!(run(source), "[a, b]\n2\nel\n");
+    }
+
+    #[test]
+    fn lists() {
+        let source = "
+            let xs = [3, 1, 2];
+            print xs.sort((a, b) -> b - a).map((x) -> x * 10).filter((x) -> x > 10);
+            print xs.reduce((sum, x) -> sum + x, 0) + len(xs.slice(1, 3));
+            print xs.contains(2);
+        ";
+        assert_eq!(run(source), "[30, 20]\n8\ntrue\n");
+    }
+
+    #[test]
+    fn maps() {
+        let source = "
+            let m = Map();
+            m[\"a\"] = 1;
+            m.set(2, \"b\");
+            print m;
+            print m.keys();
+        ";
+        assert_eq!(run(source), "{a: 1, 2: b}\n[a, 2]\n");
+    }
+
+    #[test]
+    fn argument_errors_are_at_the_call() {
+        let mut interpreter = Interpreter::with_output(Box::new(io::sink()));
+        let list = interpreter.heap.list(vec![Object::Float(1.0)]);
+        let name = Token::synthetic(TokenType::IDENTIFIER, "map".into(), 3);
+        let Ok(Object::NativeFunction(map)) = stdlib::property(&list, &name) else { panic!("Expected a method") };
+        let error = map.call(&mut interpreter, &name, &[Object::Float(1.0)]).err().map(|error| (error.token.line, error.message));
+        assert_eq!(error, Some((3, "map() expects a function, got Float.".to_string())));
+    }
+}
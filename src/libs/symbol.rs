@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::ptr;
+use std::sync::{LazyLock, Mutex};
+
+// Every distinct text interned so far. Each is stored once and kept for the whole run.
+static SYMBOLS: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+// Names the interpreters look up themselves.
+pub static INIT: LazyLock<Symbol> = LazyLock::new(|| Symbol::intern("init"));
+pub static SELF: LazyLock<Symbol> = LazyLock::new(|| Symbol::intern("self"));
+pub static SUPER: LazyLock<Symbol> = LazyLock::new(|| Symbol::intern("super"));
+pub static MESSAGE: LazyLock<Symbol> = LazyLock::new(|| Symbol::intern("message"));
+pub static STACK: LazyLock<Symbol> = LazyLock::new(|| Symbol::intern("stack"));
+pub static ITER: LazyLock<Symbol> = LazyLock::new(|| Symbol::intern("iter"));
+pub static NEXT: LazyLock<Symbol> = LazyLock::new(|| Symbol::intern("next"));
+
+// An interned string: identifiers, field names and string constants. Since each text is stored
+// once, symbols are compared and hashed by address rather than by their characters.
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+impl Symbol {
+    pub fn intern(text: &str) -> Symbol {
+        let mut symbols = SYMBOLS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(&interned) = symbols.get(text) {
+            return Symbol(interned);
+        }
+        let interned: &'static str = Box::leak(text.into());
+        symbols.insert(interned);
+        Symbol(interned)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        ptr::hash(self.0, state);
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(text: &str) -> Self {
+        Symbol::intern(text)
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::symbol::Symbol;
+
+    #[test]
+    fn interning() {
+        let name = String::from("count");
+        assert!(Symbol::intern("count") == Symbol::intern(&name));
+        assert!(Symbol::intern("count") != Symbol::intern("counter"));
+        assert_eq!(Symbol::intern("count").as_str().as_ptr(), Symbol::intern(&name).as_str().as_ptr());
+        assert!(Symbol::intern("count") == "count");
+    }
+}
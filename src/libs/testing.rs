@@ -0,0 +1,75 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::thread;
+use crate::libs::interpreter::{Interpreter, STACK_SIZE};
+use crate::libs::parser::Parser;
+use crate::libs::lex::Lox;
+use crate::libs::vm::Vm;
+
+// An output the tests hand to a backend and read back once it has run.
+#[derive(Clone, Default)]
+pub struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl SharedOutput {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs the source on the tree-walker, which must succeed, and gives what it printed.
+pub fn run_tree(source: &str) -> String {
+    let tokens = Lox::new(source.to_string()).get_token_list();
+    let statements = Parser::new(tokens).parse().unwrap_or_else(|_| panic!("Failed to parse"));
+    let output = SharedOutput::default();
+    let mut interpreter = Interpreter::with_output(Box::new(output.clone()));
+    assert!(interpreter.run(statements));
+    output.text()
+}
+
+// Runs the source on both backends, which must agree on whether it ran and print the same, and
+// gives whether it ran and what it printed.
+pub fn run_both(source: &str) -> (bool, String) {
+    run_both_with(source, |_| {}, |_| {})
+}
+
+// Like run_both, with `tree` and `vm` setting each backend up before it runs. The backends run on
+// a stack as deep as the one programs get.
+pub fn run_both_with(source: &str, tree: impl FnOnce(&mut Interpreter) + Send, vm: impl FnOnce(&mut Vm) + Send) -> (bool, String) {
+    let parse = || Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+    let run = || {
+        let (tree_output, vm_output) = (SharedOutput::default(), SharedOutput::default());
+        let mut interpreter = Interpreter::with_output(Box::new(tree_output.clone()));
+        tree(&mut interpreter);
+        let tree_ran = interpreter.run(parse());
+        let mut machine = Vm::with_output(Box::new(vm_output.clone()));
+        vm(&mut machine);
+        let vm_ran = machine.run(parse());
+        (tree_ran, vm_ran, tree_output.text(), vm_output.text())
+    };
+    let (tree_ran, vm_ran, tree_printed, vm_printed) = thread::scope(|scope| {
+        let program = thread::Builder::new().stack_size(STACK_SIZE).spawn_scoped(scope, run).unwrap();
+        program.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+
+    assert_eq!(tree_ran, vm_ran, "the backends disagree on whether {source} runs");
+    assert_eq!(tree_printed, vm_printed);
+    (vm_ran, vm_printed)
+}
+
+// Runs the source on both backends, which must succeed and print the same, and gives the output.
+pub fn run(source: &str) -> String {
+    let (ran, printed) = run_both(source);
+    assert!(ran, "{source} failed");
+    printed
+}
@@ -0,0 +1,270 @@
+use std::rc::Rc;
+use crate::libs::ast_printer::AstPrinter;
+use crate::libs::expr::{visitor::StmtVisitor, visitor::Visitor, ast::Expr, ast::MatchArm, ast::Object, ast::MethodSig, ast::Stmt, ast::TypeExpr, ast::TypedName};
+use crate::libs::lex::Token;
+use crate::libs::span::{self, Span};
+
+// Prints one node per line, indented under its parent: the kind, the tokens that tell it apart
+// and its span. Children are labeled with the field they come from.
+pub struct TreePrinter {}
+
+type Child = (String, String);
+
+impl TreePrinter {
+    pub fn print(&mut self, statements: &[Stmt]) -> String {
+        statements.iter().map(|statement| self.stmt(statement)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> String {
+        with_span(self.visit_stmt(stmt), span::stmt_span(stmt))
+    }
+
+    fn expr(&mut self, label: &str, expr: &Option<Box<Expr>>) -> Option<Child> {
+        let expr = expr.as_ref()?;
+        Some((label.to_string(), with_span(self.visit(expr), span::expr_span(expr))))
+    }
+
+    fn exprs(&mut self, label: &str, exprs: &[Expr]) -> Vec<Child> {
+        exprs
+            .iter()
+            .map(|expr| (label.to_string(), with_span(self.visit(expr), span::expr_span(expr))))
+            .collect()
+    }
+
+    fn stmts(&mut self, label: &str, stmts: &[Stmt]) -> Vec<Child> {
+        stmts.iter().map(|stmt| (label.to_string(), self.stmt(stmt))).collect()
+    }
+
+    fn branch(&mut self, label: &str, stmt: &Option<Box<Stmt>>) -> Option<Child> {
+        Some((label.to_string(), self.stmt(stmt.as_ref()?)))
+    }
+}
+
+fn node(head: String, children: Vec<Child>) -> String {
+    let mut result = head;
+    for (label, child) in children {
+        for (position, line) in child.lines().enumerate() {
+            if position == 0 {
+                result.push_str(&format!("\n  {label}: {line}"));
+            } else {
+                result.push_str(&format!("\n  {line}"));
+            }
+        }
+    }
+    result
+}
+
+// Puts the span at the end of the first line.
+fn with_span(text: String, span: Option<Span>) -> String {
+    let Some(span) = span else { return text };
+    match text.split_once('\n') {
+        Some((head, rest)) => format!("{head} @{span}\n{rest}"),
+        None => format!("{text} @{span}"),
+    }
+}
+
+fn names(names: &[TypedName]) -> String {
+    names.iter().map(AstPrinter::typed_name).collect::<Vec<_>>().join(", ")
+}
+
+
+impl Visitor<String> for TreePrinter {
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) -> String {
+        node(format!("Assign {}", name.lexeme), self.expr("value", value).into_iter().collect())
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> String {
+        let children = [self.expr("left", left), self.expr("right", right)];
+        node(format!("Binary {}", operator.lexeme), children.into_iter().flatten().collect())
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, _paren: &Token, arguments: &[Expr]) -> String {
+        let mut children: Vec<Child> = self.expr("callee", callee).into_iter().collect();
+        children.extend(self.exprs("argument", arguments));
+        node("Call".to_string(), children)
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, name: &Token) -> String {
+        node(format!("Get {}", name.lexeme), self.expr("object", object).into_iter().collect())
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) -> String {
+        node("Grouping".to_string(), self.expr("expression", expression).into_iter().collect())
+    }
+
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, _bracket: &Token, index: &Option<Box<Expr>>) -> String {
+        let children = [self.expr("object", object), self.expr("index", index)];
+        node("Index".to_string(), children.into_iter().flatten().collect())
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        _bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) -> String {
+        let children = [self.expr("object", object), self.expr("index", index), self.expr("value", value)];
+        node("IndexSet".to_string(), children.into_iter().flatten().collect())
+    }
+
+    fn visit_lambda(
+        &mut self,
+        _keyword: &Token,
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> String {
+        node(format!("Lambda ({}){}", names(params), AstPrinter::returns(result)), self.stmts("body", body))
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &[Expr]) -> String {
+        node("List".to_string(), self.exprs("element", elements))
+    }
+
+    fn visit_literal(&mut self, token: &Token, _value: &Object) -> String {
+        format!("Literal {}", token.lexeme)
+    }
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> String {
+        let children = [self.expr("left", left), self.expr("right", right)];
+        node(format!("Logical {}", operator.lexeme), children.into_iter().flatten().collect())
+    }
+
+    fn visit_match(&mut self, _keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) -> String {
+        let mut children: Vec<Child> = self.expr("subject", subject).into_iter().collect();
+        for arm in arms {
+            let pattern = AstPrinter::pattern(&arm.pattern);
+            let arm_children = [self.expr("guard", &arm.guard), self.expr("body", &arm.body)];
+            let printed = node(format!("Arm {pattern}"), arm_children.into_iter().flatten().collect());
+            children.push(("arm".to_string(), with_span(printed, span::arm_span(arm))));
+        }
+        node("Match".to_string(), children)
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, name: &Token, value: &Option<Box<Expr>>) -> String {
+        let children = [self.expr("object", object), self.expr("value", value)];
+        node(format!("Set {}", name.lexeme), children.into_iter().flatten().collect())
+    }
+
+    fn visit_self_ref(&mut self, _keyword: &Token) -> String {
+        "SelfRef".to_string()
+    }
+
+    fn visit_super(&mut self, _keyword: &Token, method: &Token) -> String {
+        format!("Super {}", method.lexeme)
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Option<Box<Expr>>) -> String {
+        node(format!("Unary {}", operator.lexeme), self.expr("right", right).into_iter().collect())
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> String {
+        format!("Variable {}", name.lexeme)
+    }
+
+    fn visit_yield(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> String {
+        node("Yield".to_string(), self.expr("value", value).into_iter().collect())
+    }
+}
+
+impl StmtVisitor<String> for TreePrinter {
+    fn visit_block(&mut self, statements: &[Stmt]) -> String {
+        node("Block".to_string(), self.stmts("statement", statements))
+    }
+
+    fn visit_box(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Box<Expr>>,
+        fields: &[TypedName],
+        methods: &[Stmt],
+    ) -> String {
+        let mut children: Vec<Child> = self.expr("superclass", superclass).into_iter().collect();
+        children.extend(self.stmts("method", methods));
+        node(format!("Box {} ({})", name.lexeme, names(fields)), children)
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) -> String {
+        node("Expression".to_string(), self.expr("expression", expression).into_iter().collect())
+    }
+
+    fn visit_for(&mut self, _keyword: &Token, name: &Token, iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> String {
+        let children = [self.expr("iterable", iterable), self.branch("body", body)];
+        node(format!("For {}", name.lexeme), children.into_iter().flatten().collect())
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        type_params: &[Token],
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> String {
+        let type_params = AstPrinter::type_params(type_params);
+        let header = format!("Function {}{type_params} ({}){}", name.lexeme, names(params), AstPrinter::returns(result));
+        node(header, self.stmts("body", body))
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Option<Box<Expr>>,
+        then_branch: &Option<Box<Stmt>>,
+        else_branch: &Option<Box<Stmt>>,
+    ) -> String {
+        let children = [
+            self.expr("condition", condition),
+            self.branch("then", then_branch),
+            self.branch("else", else_branch),
+        ];
+        node("If".to_string(), children.into_iter().flatten().collect())
+    }
+
+    fn visit_import(&mut self, keyword: &Token, path: &Token, names: &[Token]) -> String {
+        node(AstPrinter::import(keyword, path, names), Vec::new())
+    }
+
+    fn visit_interface(&mut self, name: &Token, methods: &[MethodSig]) -> String {
+        let children = methods.iter().map(|method| ("method".to_string(), AstPrinter::method_sig(method))).collect();
+        node(format!("Interface {}", name.lexeme), children)
+    }
+
+    fn visit_let(&mut self, name: &Token, annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) -> String {
+        let name = AstPrinter::annotated(name, annotation);
+        node(format!("Let {name}"), self.expr("initializer", initializer).into_iter().collect())
+    }
+
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) -> String {
+        node("Print".to_string(), self.expr("expression", expression).into_iter().collect())
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> String {
+        node("Return".to_string(), self.expr("value", value).into_iter().collect())
+    }
+
+    fn visit_throw(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> String {
+        node("Throw".to_string(), self.expr("value", value).into_iter().collect())
+    }
+
+    fn visit_try(
+        &mut self,
+        _keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) -> String {
+        let children = [self.branch("body", body), self.branch("catch", handler), self.branch("finally", finally)];
+        let head = match name {
+            Some(name) => format!("Try catch ({})", name.lexeme),
+            None => "Try".to_string(),
+        };
+        node(head, children.into_iter().flatten().collect())
+    }
+
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) -> String {
+        let children = [self.expr("condition", condition), self.branch("body", body)];
+        node("While".to_string(), children.into_iter().flatten().collect())
+    }
+}
@@ -0,0 +1,1173 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+use crate::libs::expr::{visitor::StmtVisitor, visitor::Visitor, ast::Expr, ast::MatchArm, ast::Object, ast::Pattern, ast::Stmt};
+use crate::libs::expr::ast::{MethodSig, TypeExpr, TypedName};
+use crate::libs::generator;
+use crate::libs::lex::{Token, TokenType};
+use crate::libs::span::{self, Span};
+use crate::libs::stdlib;
+
+// A static type. `Any` is what unannotated code gets: it is compatible with every other type both
+// ways, so only the places where types are known can disagree.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Any,
+    Nil,
+    Bool,
+    // Any number; `Int` and `Float` are the two kinds of it, and either may be used where a
+    // `Number` is expected.
+    Number,
+    Int,
+    Float,
+    String,
+    List(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+    // `None` for the `Function` annotation, which takes any function.
+    Function(Option<Rc<FunctionType>>),
+    // A box itself, which makes instances when called.
+    Box(String),
+    Instance(String),
+    // Any box with the methods the interface requires.
+    Interface(String),
+    // A type parameter of a generic function, which stands for whatever the caller passes.
+    Param(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FunctionType {
+    pub type_params: Vec<String>,
+    pub params: Vec<Type>,
+    pub result: Type,
+}
+
+impl FunctionType {
+    fn new(params: Vec<Type>, result: Type) -> Self {
+        Self { type_params: Vec::new(), params, result }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "Any"),
+            Type::Nil => write!(f, "Nil"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Number => write!(f, "Number"),
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::String => write!(f, "String"),
+            Type::List(element) => write!(f, "List<{element}>"),
+            Type::Map(key, value) => write!(f, "Map<{key}, {value}>"),
+            Type::Function(None) => write!(f, "Function"),
+            Type::Function(Some(function)) => {
+                let params: Vec<String> = function.params.iter().map(Type::to_string).collect();
+                match function.type_params.is_empty() {
+                    true => write!(f, "fun({}) -> {}", params.join(", "), function.result),
+                    false => write!(f, "fun<{}>({}) -> {}", function.type_params.join(", "), params.join(", "), function.result),
+                }
+            }
+            Type::Box(name) => write!(f, "box {name}"),
+            Type::Instance(name) | Type::Interface(name) | Type::Param(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+pub struct TypeError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Type error: {}", self.span, self.message)
+    }
+}
+
+// Something the checker finds suspicious, which doesn't stop the program from running.
+pub struct Warning {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Warning: {}", self.span, self.message)
+    }
+}
+
+#[derive(Default)]
+struct BoxType {
+    superclass: Option<String>,
+    fields: HashMap<String, Type>,
+    methods: HashMap<String, Rc<FunctionType>>,
+}
+
+// A variable without an annotation takes the type of its initializer, until some assignment gives
+// it a value of another type: then it is `Any`, from its declaration on.
+struct Variable {
+    type_: Type,
+    annotated: bool,
+    declaration: (usize, usize),
+}
+
+// Checks the program before it runs: values must fit the annotations they meet, and operators,
+// calls and property accesses must make sense for the types that are known.
+pub fn check(statements: &[Stmt]) -> Result<Vec<Warning>, Vec<TypeError>> {
+    // A variable found to change type makes the earlier passes moot, so go again until none does.
+    let mut widened = HashMap::new();
+    loop {
+        let previous = widened.clone();
+        let mut checker = TypeChecker::new(widened);
+        checker.begin_scope();
+        checker.declarations(statements);
+        checker.statements(statements);
+
+        if checker.widened == previous {
+            return if checker.errors.is_empty() { Ok(checker.warnings) } else { Err(checker.errors) };
+        }
+        widened = checker.widened;
+    }
+}
+
+struct TypeChecker {
+    scopes: Vec<HashMap<String, Variable>>,
+    boxes: HashMap<String, BoxType>,
+    interfaces: HashMap<String, HashMap<String, Rc<FunctionType>>>,
+    // The type parameters in scope, from the generic functions being checked.
+    type_params: Vec<Vec<String>>,
+    // The result types of the functions being checked, innermost last.
+    returns: Vec<Type>,
+    current_box: Option<String>,
+    // The type each variable that changes type is given instead, by where it is declared.
+    widened: HashMap<(usize, usize), Type>,
+    // The (from, to) pairs `conforms` is in the middle of checking. An interface can mention itself in
+    // its methods, so meeting a pair again means it holds as far as this check can tell.
+    assumed: RefCell<Vec<(Type, Type)>>,
+    // The types of the elements of each list literal, by where it starts.
+    lists: HashMap<(usize, usize, usize), Vec<Type>>,
+    errors: Vec<TypeError>,
+    warnings: Vec<Warning>,
+}
+
+impl TypeChecker {
+    fn new(widened: HashMap<(usize, usize), Type>) -> Self {
+        // The built-in box of exceptions, which boxes of a program may derive from.
+        let error = BoxType {
+            superclass: None,
+            fields: HashMap::from([("message".to_string(), Type::Any), ("stack".to_string(), Type::Any)]),
+            methods: HashMap::from([("init".to_string(), Rc::new(FunctionType::new(vec![Type::Any], Type::Any)))]),
+        };
+        Self {
+            scopes: Vec::new(),
+            boxes: HashMap::from([("Error".to_string(), error)]),
+            interfaces: HashMap::new(),
+            type_params: Vec::new(),
+            returns: Vec::new(),
+            current_box: None,
+            widened,
+            assumed: RefCell::new(Vec::new()),
+            lists: HashMap::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(TypeError { span, message });
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.visit_stmt(statement);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token, type_: Type, annotated: bool) {
+        let declaration = (name.line, name.column);
+        let type_ = self.widened.get(&declaration).cloned().unwrap_or(type_);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.to_string(), Variable { type_, annotated, declaration });
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Variable> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // What a block declares is known before its statements run: boxes, interfaces and functions with
+    // their types, and variables with their annotations. Box and interface names come first so any
+    // annotation can use them.
+    fn declarations(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            match statement {
+                Stmt::Box { name, .. } => {
+                    self.boxes.insert(name.lexeme.to_string(), BoxType::default());
+                }
+                Stmt::Interface { name, .. } => {
+                    self.interfaces.insert(name.lexeme.to_string(), HashMap::new());
+                }
+                _ => (),
+            }
+        }
+        for statement in statements {
+            match statement {
+                Stmt::Box { name, superclass, fields, methods } => {
+                    let superclass = match superclass.as_deref() {
+                        Some(Expr::Variable { name }) if self.boxes.contains_key(name.lexeme.as_str()) => Some(name.lexeme.to_string()),
+                        _ => None,
+                    };
+                    let fields = fields
+                        .iter()
+                        .map(|field| (field.name.lexeme.to_string(), self.annotation(&field.annotation)))
+                        .collect();
+                    let mut signatures = HashMap::new();
+                    for method in methods {
+                        if let Stmt::Function { name, type_params, params, result, body } = method {
+                            signatures.insert(name.lexeme.to_string(), Rc::new(self.signature(type_params, params, result, body)));
+                        }
+                    }
+                    self.boxes.insert(name.lexeme.to_string(), BoxType { superclass, fields, methods: signatures });
+                    self.declare(name, Type::Box(name.lexeme.to_string()), false);
+                }
+                Stmt::Interface { name, methods } => {
+                    let methods = methods
+                        .iter()
+                        .map(|method| (method.name.lexeme.to_string(), Rc::new(self.signature(&[], &method.params, &method.result, &[]))))
+                        .collect();
+                    self.interfaces.insert(name.lexeme.to_string(), methods);
+                }
+                Stmt::Function { name, type_params, params, result, body } => {
+                    let signature = self.signature(type_params, params, result, body);
+                    self.declare(name, Type::Function(Some(Rc::new(signature))), false);
+                }
+                Stmt::Let { name, annotation, .. } => {
+                    let type_ = self.annotation(annotation);
+                    self.declare(name, type_, annotation.is_some());
+                }
+                // What a module holds is only known once it runs.
+                Stmt::Import { names, .. } => names.iter().for_each(|name| self.declare(name, Type::Any, false)),
+                _ => (),
+            }
+        }
+    }
+
+    fn annotation(&mut self, annotation: &Option<TypeExpr>) -> Type {
+        match annotation {
+            Some(annotation) => self.type_expr(annotation),
+            None => Type::Any,
+        }
+    }
+
+    // Type arguments left out are `Any`, so a bare `List` is `List<Any>`.
+    fn type_expr(&mut self, type_: &TypeExpr) -> Type {
+        let name = type_.name.lexeme.as_str();
+        let mut arguments: Vec<Type> = type_.arguments.iter().map(|argument| self.type_expr(argument)).collect();
+        let expected = match name {
+            "List" => 1,
+            "Map" => 2,
+            _ => 0,
+        };
+        if !arguments.is_empty() && arguments.len() != expected {
+            let message = match expected {
+                0 => format!("Type '{name}' takes no type arguments."),
+                _ => format!("Type '{name}' takes {expected} type arguments but got {}.", arguments.len()),
+            };
+            self.error(span::type_span(type_).unwrap_or(Span::of(&type_.name)), message);
+        }
+        arguments.resize(expected, Type::Any);
+        let mut argument = arguments.into_iter().map(Box::new);
+
+        match name {
+            name if self.type_params.iter().any(|names| names.iter().any(|param| param == name)) => Type::Param(name.to_string()),
+            "Any" => Type::Any,
+            "Nil" => Type::Nil,
+            "Bool" => Type::Bool,
+            "Number" => Type::Number,
+            "Int" => Type::Int,
+            "Float" => Type::Float,
+            "String" => Type::String,
+            "List" => Type::List(argument.next().unwrap()),
+            "Map" => Type::Map(argument.next().unwrap(), argument.next().unwrap()),
+            "Function" => Type::Function(None),
+            name if self.boxes.contains_key(name) => Type::Instance(name.to_string()),
+            name if self.interfaces.contains_key(name) => Type::Interface(name.to_string()),
+            name => {
+                self.error(Span::of(&type_.name), format!("Unknown type '{name}'."));
+                Type::Any
+            }
+        }
+    }
+
+    // Calling a generator function gives its generator, so its result isn't what its body returns.
+    fn signature(&mut self, type_params: &[Token], params: &[TypedName], result: &Option<TypeExpr>, body: &[Stmt]) -> FunctionType {
+        let type_params: Vec<String> = type_params.iter().map(|param| param.lexeme.to_string()).collect();
+        self.type_params.push(type_params.clone());
+        let params = params.iter().map(|param| self.annotation(&param.annotation)).collect();
+        let annotated = self.annotation(result);
+        let result = if generator::yields(body) { Type::Any } else { annotated };
+        self.type_params.pop();
+        FunctionType { type_params, params, result }
+    }
+
+    fn function(&mut self, params: &[TypedName], signature: &FunctionType, body: &[Stmt]) {
+        self.begin_scope();
+        for (param, type_) in params.iter().zip(&signature.params) {
+            self.declare(&param.name, type_.clone(), true);
+        }
+        self.type_params.push(signature.type_params.clone());
+        self.returns.push(signature.result.clone());
+        self.declarations(body);
+        self.statements(body);
+        self.returns.pop();
+        self.type_params.pop();
+        self.end_scope();
+    }
+
+    fn type_of(&mut self, expr: &Option<Box<Expr>>) -> Type {
+        self.visit_expr(expr).unwrap_or(Type::Nil)
+    }
+
+    // Whether a value of type `from` may be used where `to` is expected.
+    fn assignable(&self, from: &Type, to: &Type) -> bool {
+        match (from, to) {
+            (Type::Any, _) | (_, Type::Any) | (Type::Nil, _) => true,
+            (Type::Int | Type::Float, Type::Number) => true,
+            (Type::Instance(from), Type::Instance(to)) => self.is_subbox(from, to),
+            (Type::Instance(_) | Type::Interface(_), Type::Interface(_)) => self.conforms(from, to).is_ok(),
+            // Lists and maps can be changed through either reference, so their type arguments must agree.
+            (Type::List(from), Type::List(to)) => self.consistent(from, to),
+            (Type::Map(from_key, from_value), Type::Map(to_key, to_value)) => {
+                self.consistent(from_key, to_key) && self.consistent(from_value, to_value)
+            }
+            (Type::Function(_) | Type::Box(_), Type::Function(None)) | (Type::Function(None), Type::Function(_)) => true,
+            // Generic functions are not compared: checking them needs their calls.
+            (Type::Function(Some(from)), Type::Function(Some(to)))
+                if !from.type_params.is_empty() || !to.type_params.is_empty() => true,
+            (Type::Function(Some(from)), Type::Function(Some(to))) => {
+                from.params.len() == to.params.len()
+                    && from.params.iter().zip(&to.params).all(|(from, to)| self.assignable(to, from))
+                    && self.assignable(&from.result, &to.result)
+            }
+            (from, to) => from == to,
+        }
+    }
+
+    // The same type, up to the `Any`s in either.
+    fn consistent(&self, left: &Type, right: &Type) -> bool {
+        match (left, right) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::List(left), Type::List(right)) => self.consistent(left, right),
+            (Type::Map(left_key, left_value), Type::Map(right_key, right_value)) => {
+                self.consistent(left_key, right_key) && self.consistent(left_value, right_value)
+            }
+            (left, right) => left == right,
+        }
+    }
+
+    // Whether `value`, of type `type_`, may be used where `expected` is; the error says why not when
+    // there is more to say than the types. A list literal makes a list nothing else refers to yet,
+    // so only its elements need to fit.
+    fn fits(&self, value: Option<&Expr>, type_: &Type, expected: &Type) -> Result<(), String> {
+        if let (Some(Expr::List { bracket, elements }), Type::List(element)) = (value, expected) {
+            let types = &self.lists[&(bracket.file, bracket.line, bracket.column)];
+            for (index, (value, type_)) in elements.iter().zip(types).enumerate() {
+                if self.fits(Some(value), type_, element).is_err() {
+                    return Err(format!(": element {} is {type_}", index + 1));
+                }
+            }
+            return Ok(());
+        }
+        match self.assignable(type_, expected) {
+            true => Ok(()),
+            false => Err(self.reason(type_, expected)),
+        }
+    }
+
+    // Whether a box or an interface has every method an interface requires, with a signature that
+    // fits; the error says what is missing.
+    fn conforms(&self, from: &Type, to: &Type) -> Result<(), String> {
+        let Type::Interface(interface) = to else { return Ok(()) };
+        let Some(required) = self.interfaces.get(interface) else { return Ok(()) };
+        let pair = (from.clone(), to.clone());
+        if self.assumed.borrow().contains(&pair) {
+            return Ok(());
+        }
+        self.assumed.borrow_mut().push(pair);
+        let result = self.methods_conform(from, required);
+        self.assumed.borrow_mut().pop();
+        result
+    }
+
+    fn methods_conform(&self, from: &Type, required: &HashMap<String, Rc<FunctionType>>) -> Result<(), String> {
+        let mut names: Vec<&String> = required.keys().collect();
+        names.sort();
+
+        for name in names {
+            let method = match from {
+                Type::Instance(box_name) => self.method(box_name, name),
+                Type::Interface(other) => self.interfaces.get(other).and_then(|methods| methods.get(name).cloned()),
+                _ => return Ok(()),
+            };
+            let expected = Type::Function(Some(Rc::clone(&required[name])));
+            match method {
+                Some(method) => {
+                    let method = Type::Function(Some(method));
+                    if !self.assignable(&method, &expected) {
+                        return Err(format!("its method '{name}' is {method}, not {expected}"));
+                    }
+                }
+                None => return Err(format!("it has no method '{name}'")),
+            }
+        }
+        Ok(())
+    }
+
+    // Why `from` doesn't fit `to`, to add to an error, when there is more to say than the types.
+    fn reason(&self, from: &Type, to: &Type) -> String {
+        match self.conforms(from, to) {
+            Err(reason) => format!(": {reason}"),
+            Ok(()) => String::new(),
+        }
+    }
+
+    fn is_subbox(&self, from: &str, to: &str) -> bool {
+        let mut current = Some(from);
+        while let Some(name) = current {
+            if name == to {
+                return true;
+            }
+            current = self.boxes.get(name).and_then(|box_type| box_type.superclass.as_deref());
+        }
+        false
+    }
+
+    // A field or a method of a box or of its superclasses; fields shadow methods like at run time.
+    fn member(&self, box_name: &str, name: &str) -> Option<Type> {
+        let box_type = self.boxes.get(box_name)?;
+        if let Some(field) = box_type.fields.get(name) {
+            return Some(field.clone());
+        }
+        if let Some(method) = box_type.methods.get(name) {
+            return Some(Type::Function(Some(Rc::clone(method))));
+        }
+        self.member(box_type.superclass.as_deref()?, name)
+    }
+
+    fn method(&self, box_name: &str, name: &str) -> Option<Rc<FunctionType>> {
+        let box_type = self.boxes.get(box_name)?;
+        match box_type.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self.method(box_type.superclass.as_deref()?, name),
+        }
+    }
+
+    fn field(&self, box_name: &str, name: &str) -> Option<Type> {
+        let box_type = self.boxes.get(box_name)?;
+        match box_type.fields.get(name) {
+            Some(field) => Some(field.clone()),
+            None => self.field(box_type.superclass.as_deref()?, name),
+        }
+    }
+
+    // Checks the arguments of a call and gives its result. The type parameters of a generic function
+    // are inferred from the arguments; the ones nothing tells about are `Any`.
+    fn call(&mut self, paren: &Token, function: &FunctionType, arguments: &[(Type, &Expr)]) -> Type {
+        if function.params.len() != arguments.len() {
+            let message = format!("Expected {} arguments but got {}.", function.params.len(), arguments.len());
+            self.error(Span::of(paren), message);
+            return Type::Any;
+        }
+
+        let mut bindings = HashMap::new();
+        for (param, (argument, _)) in function.params.iter().zip(arguments) {
+            infer(&function.type_params, param, argument, &mut bindings);
+        }
+        for (index, (param, (argument, value))) in function.params.iter().zip(arguments).enumerate() {
+            let param = substitute(&function.type_params, param, &bindings);
+            if let Err(reason) = self.fits(Some(value), argument, &param) {
+                let span = span::expr_span(value).unwrap_or(Span::of(paren));
+                self.error(span, format!("Can't pass {argument} as argument {} of type {param}{reason}.", index + 1));
+            }
+        }
+        substitute(&function.type_params, &function.result, &bindings)
+    }
+
+    fn numbers(&mut self, span: Span, left: &Type, right: &Type) {
+        if !is_number(left) || !is_number(right) {
+            self.error(span, format!("Operands must be numbers, got {left} and {right}."));
+        }
+    }
+
+    // The names a match arm binds, typed by the fields of the box they come from when it is known.
+    fn pattern(&mut self, pattern: &Pattern, type_: Type) {
+        match pattern {
+            Pattern::Binding { name } => self.declare(name, type_, true),
+            Pattern::List { elements, rest, .. } => {
+                for element in elements {
+                    self.pattern(element, Type::Any);
+                }
+                if let Some(rest) = rest {
+                    self.pattern(rest, Type::List(Box::new(Type::Any)));
+                }
+            }
+            Pattern::Box { name, fields } => {
+                for (field, pattern) in fields {
+                    let type_ = self.field(&name.lexeme, &field.lexeme).unwrap_or(Type::Any);
+                    self.pattern(pattern, type_);
+                }
+            }
+            Pattern::Literal { .. } | Pattern::Wildcard { .. } => (),
+        }
+    }
+}
+
+fn span_of(expr: &Option<Box<Expr>>) -> Span {
+    expr.as_deref().and_then(span::expr_span).unwrap_or(Span::at(0, 0, ""))
+}
+
+fn is_number(type_: &Type) -> bool {
+    matches!(type_, Type::Number | Type::Int | Type::Float | Type::Any)
+}
+
+// The narrowest type both fit, short of `Any` except for numbers of different kinds.
+fn join(left: Type, right: Type) -> Type {
+    match (left, right) {
+        (left, right) if left == right => left,
+        (Type::Number | Type::Int | Type::Float, Type::Number | Type::Int | Type::Float) => Type::Number,
+        _ => Type::Any,
+    }
+}
+
+// The signature of a method of a list or a map, in terms of its element, key and value types.
+fn container_method(container: &Type, name: &str) -> Option<FunctionType> {
+    let list = |element: &Type| Type::List(Box::new(element.clone()));
+    let function = |params: Vec<Type>, result| Type::Function(Some(Rc::new(FunctionType::new(params, result))));
+    let (params, result) = match (container, name) {
+        (Type::List(element), "push") => (vec![*element.clone()], list(element)),
+        (Type::List(element), "pop") => (Vec::new(), *element.clone()),
+        (Type::List(element), "sort") => (vec![Type::Any], list(element)),
+        (Type::List(element), "map") => (vec![function(vec![*element.clone()], Type::Any)], list(&Type::Any)),
+        (Type::List(element), "filter") => (vec![function(vec![*element.clone()], Type::Any)], list(element)),
+        (Type::List(element), "reduce") => (vec![function(vec![Type::Any, *element.clone()], Type::Any), Type::Any], Type::Any),
+        (Type::List(element), "slice") => (vec![Type::Int, Type::Int], list(element)),
+        (Type::List(element), "contains") => (vec![*element.clone()], Type::Bool),
+        (Type::List(element), "index_of") => (vec![*element.clone()], Type::Int),
+        (Type::Map(key, value), "get" | "remove") => (vec![*key.clone()], *value.clone()),
+        (Type::Map(key, value), "set") => (vec![*key.clone(), *value.clone()], *value.clone()),
+        (Type::Map(key, _), "contains") => (vec![*key.clone()], Type::Bool),
+        (Type::Map(key, _), "keys") => (Vec::new(), list(key)),
+        (Type::Map(_, value), "values") => (Vec::new(), list(value)),
+        _ => return None,
+    };
+    Some(FunctionType::new(params, result))
+}
+
+// What arithmetic on two numbers gives, like `number::arithmetic` does at run time: `/` always makes a
+// float, and otherwise two ints stay an int and a float makes the result one.
+fn arithmetic(operator: &TokenType, left: &Type, right: &Type) -> Type {
+    match (left, right) {
+        _ if matches!(operator, TokenType::SLASH) => Type::Float,
+        (Type::Int, Type::Int) => Type::Int,
+        (Type::Float, _) | (_, Type::Float) => Type::Float,
+        _ => Type::Number,
+    }
+}
+
+// Binds the type parameters that appear in `param` to the parts of `argument` in the same place. The
+// first binding wins; an argument that disagrees with it is reported when the arguments are checked.
+fn infer(type_params: &[String], param: &Type, argument: &Type, bindings: &mut HashMap<String, Type>) {
+    match (param, argument) {
+        (_, Type::Any | Type::Nil) => (),
+        (Type::Param(name), argument) if type_params.contains(name) => {
+            bindings.entry(name.clone()).or_insert_with(|| argument.clone());
+        }
+        (Type::List(param), Type::List(argument)) => infer(type_params, param, argument, bindings),
+        (Type::Map(param_key, param_value), Type::Map(key, value)) => {
+            infer(type_params, param_key, key, bindings);
+            infer(type_params, param_value, value, bindings);
+        }
+        (Type::Function(Some(param)), Type::Function(Some(argument))) => {
+            for (param, argument) in param.params.iter().zip(&argument.params) {
+                infer(type_params, param, argument, bindings);
+            }
+            infer(type_params, &param.result, &argument.result, bindings);
+        }
+        _ => (),
+    }
+}
+
+fn substitute(type_params: &[String], type_: &Type, bindings: &HashMap<String, Type>) -> Type {
+    match type_ {
+        Type::Param(name) if type_params.contains(name) => bindings.get(name).cloned().unwrap_or(Type::Any),
+        Type::List(element) => Type::List(Box::new(substitute(type_params, element, bindings))),
+        Type::Map(key, value) => Type::Map(
+            Box::new(substitute(type_params, key, bindings)),
+            Box::new(substitute(type_params, value, bindings)),
+        ),
+        Type::Function(Some(function)) if !type_params.is_empty() => {
+            let params = function.params.iter().map(|param| substitute(type_params, param, bindings)).collect();
+            let result = substitute(type_params, &function.result, bindings);
+            Type::Function(Some(Rc::new(FunctionType { type_params: function.type_params.clone(), params, result })))
+        }
+        type_ => type_.clone(),
+    }
+}
+
+impl Visitor<Type> for TypeChecker {
+    fn visit_assign(&mut self, name: &Token, value: &Option<Box<Expr>>) -> Type {
+        let type_ = self.type_of(value);
+        let Some(variable) = self.lookup(&name.lexeme) else { return type_ };
+        let (declared, annotated, declaration) = (variable.type_.clone(), variable.annotated, variable.declaration);
+
+        if annotated {
+            if let Err(reason) = self.fits(value.as_deref(), &type_, &declared) {
+                self.error(span_of(value), format!("Can't assign {type_} to '{}' of type {declared}{reason}.", name.lexeme));
+            }
+        } else if type_ != declared {
+            let widened = match self.widened.get(&declaration) {
+                Some(widened) => join(widened.clone(), join(declared, type_.clone())),
+                None => join(declared, type_.clone()),
+            };
+            self.widened.insert(declaration, widened);
+        }
+        type_
+    }
+
+    fn visit_binary(&mut self, left: &Option<Box<Expr>>, operator: &Token, right: &Option<Box<Expr>>) -> Type {
+        let (left_type, right_type) = (self.type_of(left), self.type_of(right));
+        let span = span_of(left).to(span_of(right));
+
+        match operator.token_type {
+            TokenType::PLUS => match (&left_type, &right_type) {
+                (Type::Number | Type::Int | Type::Float, Type::Number | Type::Int | Type::Float | Type::Any)
+                | (Type::Any, Type::Number | Type::Int | Type::Float) => arithmetic(&operator.token_type, &left_type, &right_type),
+                (Type::String, Type::String | Type::Any) | (Type::Any, Type::String) => Type::String,
+                (Type::Any, Type::Any) => Type::Any,
+                _ => {
+                    let message = format!("Operands must be two numbers or two strings, got {left_type} and {right_type}.");
+                    self.error(span, message);
+                    Type::Any
+                }
+            },
+            TokenType::MINUS | TokenType::STAR | TokenType::SLASH | TokenType::PERCENT | TokenType::TILDE_SLASH => {
+                self.numbers(span, &left_type, &right_type);
+                arithmetic(&operator.token_type, &left_type, &right_type)
+            }
+            TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL => {
+                self.numbers(span, &left_type, &right_type);
+                Type::Bool
+            }
+            TokenType::EQUAL_EQUAL | TokenType::BANG_EQUAL => Type::Bool,
+            // There is no Range type; a range is only ever looped over.
+            TokenType::DOT_DOT | TokenType::DOT_DOT_EQUAL => {
+                self.numbers(span, &left_type, &right_type);
+                Type::Any
+            }
+            _ => Type::Any,
+        }
+    }
+
+    fn visit_call(&mut self, callee: &Option<Box<Expr>>, paren: &Token, arguments: &[Expr]) -> Type {
+        let callee_type = self.type_of(callee);
+        let arguments: Vec<(Type, &Expr)> = arguments.iter().map(|argument| (self.visit(argument), argument)).collect();
+
+        match callee_type {
+            Type::Function(Some(function)) => self.call(paren, &function, &arguments),
+            Type::Box(name) => {
+                let init = match self.member(&name, "init") {
+                    Some(Type::Function(Some(init))) => init,
+                    _ => Rc::new(FunctionType::new(Vec::new(), Type::Any)),
+                };
+                self.call(paren, &init, &arguments);
+                Type::Instance(name)
+            }
+            Type::Any | Type::Function(None) => Type::Any,
+            other => {
+                self.error(span_of(callee), format!("Can only call functions and boxes, got {other}."));
+                Type::Any
+            }
+        }
+    }
+
+    fn visit_get(&mut self, object: &Option<Box<Expr>>, name: &Token) -> Type {
+        match self.type_of(object) {
+            Type::Any => Type::Any,
+            Type::Instance(box_name) => self.member(&box_name, &name.lexeme).unwrap_or_else(|| {
+                self.error(Span::of(name), format!("Undefined property '{}' on box '{box_name}'.", name.lexeme));
+                Type::Any
+            }),
+            Type::Interface(interface) => {
+                let method = self.interfaces.get(&interface).and_then(|methods| methods.get(name.lexeme.as_str()).cloned());
+                method.map_or_else(
+                    || {
+                        self.error(Span::of(name), format!("Undefined property '{}' on interface '{interface}'.", name.lexeme));
+                        Type::Any
+                    },
+                    |method| Type::Function(Some(method)),
+                )
+            }
+            // Only the methods of lists and maps are typed, by their type arguments.
+            container @ (Type::String | Type::List(_) | Type::Map(..)) => {
+                let kind = match container {
+                    Type::String => "String",
+                    Type::List(_) => "List",
+                    _ => "Map",
+                };
+                if !stdlib::has_method(kind, &name.lexeme) {
+                    self.error(Span::of(name), format!("Undefined property '{}' on {kind}.", name.lexeme));
+                }
+                Type::Function(container_method(&container, &name.lexeme).map(Rc::new))
+            }
+            other => {
+                self.error(span_of(object), format!("Only instances have properties, got {other}."));
+                Type::Any
+            }
+        }
+    }
+
+    fn visit_grouping(&mut self, expression: &Option<Box<Expr>>) -> Type {
+        self.type_of(expression)
+    }
+
+    // Gives the type of the element.
+    fn visit_index(&mut self, object: &Option<Box<Expr>>, _bracket: &Token, index: &Option<Box<Expr>>) -> Type {
+        let object_type = self.type_of(object);
+        let index_type = self.type_of(index);
+        match object_type {
+            Type::List(element) => {
+                if !is_number(&index_type) {
+                    self.error(span_of(index), format!("List index must be a number, got {index_type}."));
+                }
+                *element
+            }
+            Type::Map(key, value) => {
+                if !self.assignable(&index_type, &key) {
+                    self.error(span_of(index), format!("Map key must be {key}, got {index_type}."));
+                }
+                *value
+            }
+            Type::Any => Type::Any,
+            other => {
+                self.error(span_of(object), format!("Only lists and maps can be indexed, got {other}."));
+                Type::Any
+            }
+        }
+    }
+
+    fn visit_index_set(
+        &mut self,
+        object: &Option<Box<Expr>>,
+        bracket: &Token,
+        index: &Option<Box<Expr>>,
+        value: &Option<Box<Expr>>,
+    ) -> Type {
+        let element = self.visit_index(object, bracket, index);
+        let value_type = self.type_of(value);
+        if let Err(reason) = self.fits(value.as_deref(), &value_type, &element) {
+            let container = self.type_of(object);
+            self.error(span_of(value), format!("Can't store {value_type} in {container}{reason}."));
+        }
+        value_type
+    }
+
+    fn visit_lambda(
+        &mut self,
+        _keyword: &Token,
+        params: &[TypedName],
+        result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) -> Type {
+        let signature = self.signature(&[], params, result, body);
+        self.function(params, &signature, body);
+        Type::Function(Some(Rc::new(signature)))
+    }
+
+    // The elements' type is the narrowest all of them fit; nil fits any.
+    fn visit_list(&mut self, bracket: &Token, elements: &[Expr]) -> Type {
+        let types: Vec<Type> = elements.iter().map(|element| self.visit(element)).collect();
+        let known = types.iter().filter(|type_| **type_ != Type::Nil).cloned();
+        let element = known.reduce(join).unwrap_or(Type::Any);
+        self.lists.insert((bracket.file, bracket.line, bracket.column), types);
+        Type::List(Box::new(element))
+    }
+
+    fn visit_literal(&mut self, _token: &Token, value: &Object) -> Type {
+        match value {
+            Object::Float(_) => Type::Float,
+            Object::Int(_) | Object::BigInt(_) => Type::Int,
+            Object::Str(_) => Type::String,
+            Object::Bool(_) => Type::Bool,
+            _ => Type::Nil,
+        }
+    }
+
+    fn visit_logical(&mut self, left: &Option<Box<Expr>>, _operator: &Token, right: &Option<Box<Expr>>) -> Type {
+        let left = self.type_of(left);
+        let right = self.type_of(right);
+        join(left, right)
+    }
+
+    fn visit_match(&mut self, keyword: &Token, subject: &Option<Box<Expr>>, arms: &[MatchArm]) -> Type {
+        let subject = self.type_of(subject);
+        // Only an unguarded arm that takes any value is sure to match; without one, a value no arm
+        // matches is a runtime error.
+        if !arms.iter().any(|arm| arm.guard.is_none() && arm.pattern.is_irrefutable()) {
+            let message = "This match may not be exhaustive: add a '_' arm for the values no arm matches.".to_string();
+            self.warnings.push(Warning { span: Span::of(keyword), message });
+        }
+        let mut result = None;
+        for arm in arms {
+            self.begin_scope();
+            self.pattern(&arm.pattern, subject.clone());
+            self.type_of(&arm.guard);
+            let body = self.type_of(&arm.body);
+            self.end_scope();
+            result = Some(match result {
+                Some(result) => join(result, body),
+                None => body,
+            });
+        }
+        result.unwrap_or(Type::Any)
+    }
+
+    fn visit_set(&mut self, object: &Option<Box<Expr>>, name: &Token, value: &Option<Box<Expr>>) -> Type {
+        let value_type = self.type_of(value);
+        match self.type_of(object) {
+            Type::Any => (),
+            Type::Instance(box_name) => match self.field(&box_name, &name.lexeme) {
+                Some(field) => {
+                    if let Err(reason) = self.fits(value.as_deref(), &value_type, &field) {
+                        let message = format!("Can't assign {value_type} to field '{}' of type {field}{reason}.", name.lexeme);
+                        self.error(span_of(value), message);
+                    }
+                }
+                None => self.error(Span::of(name), format!("Undefined field '{}' in box '{box_name}'.", name.lexeme)),
+            },
+            other => self.error(span_of(object), format!("Only instances have fields, got {other}.")),
+        }
+        value_type
+    }
+
+    fn visit_self_ref(&mut self, _keyword: &Token) -> Type {
+        self.current_box.clone().map_or(Type::Any, Type::Instance)
+    }
+
+    fn visit_super(&mut self, _keyword: &Token, method: &Token) -> Type {
+        let superclass = self.current_box.as_ref().and_then(|name| self.boxes.get(name)?.superclass.clone());
+        let Some(superclass) = superclass else { return Type::Any };
+        self.member(&superclass, &method.lexeme).unwrap_or_else(|| {
+            self.error(Span::of(method), format!("Undefined property '{}' on box '{superclass}'.", method.lexeme));
+            Type::Any
+        })
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Option<Box<Expr>>) -> Type {
+        let right_type = self.type_of(right);
+        match operator.token_type {
+            TokenType::MINUS => {
+                if !is_number(&right_type) {
+                    self.error(span_of(right), format!("Operand must be a number, got {right_type}."));
+                }
+                match right_type {
+                    Type::Int | Type::Float => right_type,
+                    _ => Type::Number,
+                }
+            }
+            _ => Type::Bool,
+        }
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> Type {
+        self.lookup(&name.lexeme).map_or(Type::Any, |variable| variable.type_.clone())
+    }
+
+    // What a `yield` evaluates to is whatever the generator is resumed with.
+    fn visit_yield(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) -> Type {
+        self.type_of(value);
+        Type::Any
+    }
+}
+
+impl StmtVisitor<()> for TypeChecker {
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.begin_scope();
+        self.declarations(statements);
+        self.statements(statements);
+        self.end_scope();
+    }
+
+    fn visit_box(&mut self, name: &Token, superclass: &Option<Box<Expr>>, _fields: &[TypedName], methods: &[Stmt]) {
+        self.type_of(superclass);
+        let enclosing = self.current_box.replace(name.lexeme.to_string());
+        for method in methods {
+            let Stmt::Function { name: method, params, body, .. } = method else { continue };
+            let signature = self.boxes.get(name.lexeme.as_str()).and_then(|box_type| box_type.methods.get(method.lexeme.as_str()).cloned());
+            if let Some(signature) = signature {
+                self.function(params, &signature, body);
+            }
+        }
+        self.current_box = enclosing;
+    }
+
+    fn visit_expression(&mut self, expression: &Option<Box<Expr>>) {
+        self.type_of(expression);
+    }
+
+    // The loop variable has the type of the elements when it is known.
+    fn visit_for(&mut self, _keyword: &Token, name: &Token, iterable: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) {
+        let element = match self.type_of(iterable) {
+            Type::List(element) => *element,
+            Type::Map(key, _) => *key,
+            Type::String => Type::String,
+            Type::Instance(box_name) if self.member(&box_name, "iter").is_none() => {
+                self.error(span_of(iterable), format!("Can't loop over {box_name}, which has no iter() method."));
+                Type::Any
+            }
+            type_ @ (Type::Nil | Type::Bool | Type::Number | Type::Int | Type::Float | Type::Function(_) | Type::Box(_)) => {
+                self.error(span_of(iterable), format!("Can't loop over {type_}."));
+                Type::Any
+            }
+            _ => Type::Any,
+        };
+        self.begin_scope();
+        self.declare(name, element, false);
+        if let Some(body) = body {
+            self.visit_stmt(body);
+        }
+        self.end_scope();
+    }
+
+    fn visit_function(
+        &mut self,
+        name: &Token,
+        _type_params: &[Token],
+        params: &[TypedName],
+        _result: &Option<TypeExpr>,
+        body: &Rc<Vec<Stmt>>,
+    ) {
+        let signature = match self.lookup(&name.lexeme).map(|variable| &variable.type_) {
+            Some(Type::Function(Some(signature))) => Rc::clone(signature),
+            _ => Rc::new(FunctionType::new(vec![Type::Any; params.len()], Type::Any)),
+        };
+        self.function(params, &signature, body);
+    }
+
+    fn visit_if(&mut self, condition: &Option<Box<Expr>>, then_branch: &Option<Box<Stmt>>, else_branch: &Option<Box<Stmt>>) {
+        self.type_of(condition);
+        for branch in [then_branch, else_branch].into_iter().flatten() {
+            self.visit_stmt(branch);
+        }
+    }
+
+    fn visit_import(&mut self, _keyword: &Token, _path: &Token, _names: &[Token]) {}
+
+    fn visit_interface(&mut self, _name: &Token, _methods: &[MethodSig]) {}
+
+    fn visit_let(&mut self, name: &Token, annotation: &Option<TypeExpr>, initializer: &Option<Box<Expr>>) {
+        let value = self.type_of(initializer);
+        let Some(variable) = self.scopes.last().and_then(|scope| scope.get(name.lexeme.as_str())) else { return };
+        let declared = variable.type_.clone();
+
+        if annotation.is_some() {
+            let fits = self.fits(initializer.as_deref(), &value, &declared);
+            if let (Some(_), Err(reason)) = (initializer, fits) {
+                let message = format!("Can't assign {value} to '{}' of type {declared}{reason}.", name.lexeme);
+                self.error(span_of(initializer), message);
+            }
+        } else {
+            self.declare(name, value, false);
+        }
+    }
+
+    fn visit_print(&mut self, expression: &Option<Box<Expr>>) {
+        self.type_of(expression);
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) {
+        let type_ = self.type_of(value);
+        let Some(expected) = self.returns.last().cloned() else { return };
+        if let Err(reason) = self.fits(value.as_deref(), &type_, &expected) {
+            self.error(span_of(value), format!("Can't return {type_} from a function returning {expected}{reason}."));
+        }
+    }
+
+    fn visit_throw(&mut self, _keyword: &Token, value: &Option<Box<Expr>>) {
+        self.type_of(value);
+    }
+
+    // Any value can be thrown, so nothing is known of the one caught.
+    fn visit_try(
+        &mut self,
+        _keyword: &Token,
+        body: &Option<Box<Stmt>>,
+        name: &Option<Token>,
+        handler: &Option<Box<Stmt>>,
+        finally: &Option<Box<Stmt>>,
+    ) {
+        if let Some(body) = body {
+            self.visit_stmt(body);
+        }
+        if let (Some(name), Some(handler)) = (name, handler) {
+            self.begin_scope();
+            self.declare(name, Type::Any, false);
+            self.visit_stmt(handler);
+            self.end_scope();
+        }
+        if let Some(finally) = finally {
+            self.visit_stmt(finally);
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Option<Box<Expr>>, body: &Option<Box<Stmt>>) {
+        self.type_of(condition);
+        if let Some(body) = body {
+            self.visit_stmt(body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::lex::Lox;
+    use crate::libs::type_checker::check;
+
+    // What the checker reports: the errors, or the warnings when there are none.
+    fn errors(source: &str) -> Vec<String> {
+        let statements = Lox::new(source.to_string()).parse().unwrap_or_else(|| panic!("Failed to parse"));
+        match check(&statements) {
+            Ok(warnings) => warnings.iter().map(|warning| warning.to_string()).collect(),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn gradual() {
+        // Unannotated code keeps working, even when a variable changes type.
+        assert!(errors("let x = 1; x = \"a\"; print x + \"b\"; fun f(a) { return a; } print f(1) + f(\"s\");").is_empty());
+        assert!(errors("let n: Number = 1; fun twice(a: Number) -> Number { return a * 2; } print twice(n) + 1;").is_empty());
+        assert!(errors("box P { x: Number; init(x: Number) { self.x = x; } } let p: P = P(1); print p.x - 1;").is_empty());
+    }
+
+    #[test]
+    fn mismatches() {
+        assert_eq!(errors("let x: Number = \"one\";"), ["[line 1:17-1:22] Type error: Can't assign String to 'x' of type Number."]);
+        assert_eq!(
+            errors("fun f(a: String) -> Number {\n    return a;\n}\nprint f(2);"),
+            [
+                "[line 2:12-2:13] Type error: Can't return String from a function returning Number.",
+                "[line 4:9-4:10] Type error: Can't pass Int as argument 1 of type String.",
+            ]
+        );
+        // Inferred from the initializer, since `s` is never assigned anything else.
+        assert_eq!(errors("let s = \"a\"; print s - 1;"), ["[line 1:20-1:25] Type error: Operands must be numbers, got String and Int."]);
+        assert_eq!(
+            errors("box B { x: Bool; } let b = B(); b.x = 1; b.y = 2; print b.z;"),
+            [
+                "[line 1:39-1:40] Type error: Can't assign Int to field 'x' of type Bool.",
+                "[line 1:44-1:45] Type error: Undefined field 'y' in box 'B'.",
+                "[line 1:59-1:60] Type error: Undefined property 'z' on box 'B'.",
+            ]
+        );
+        assert_eq!(errors("let q: Queue;"), ["[line 1:8-1:13] Type error: Unknown type 'Queue'."]);
+    }
+
+    #[test]
+    fn generics_and_interfaces() {
+        let shapes = "interface Shape { area() -> Number; }
+box Square { side: Number; init(side: Number) { self.side = side; } area() -> Number { return self.side * self.side; } }
+box Label { area() -> String { return \"big\"; } }
+box Point { }
+fun total(shape: Shape) -> Number { return shape.area(); }
+fun first<T>(items: List<T>) -> T { return items[0]; }
+let sides: List<Number> = [1, 2];
+";
+        assert!(errors(&format!("{shapes}print total(Square(2)) + first(sides);")).is_empty());
+        assert_eq!(
+            errors(&format!("{shapes}total(Label()); total(Point()); let s: String = first(sides); sides[0] = \"x\"; let m: Map<String> = nil;")),
+            [
+                "[line 8:86-8:96] Type error: Type 'Map' takes 2 type arguments but got 1.",
+                "[line 8:7-8:14] Type error: Can't pass Label as argument 1 of type Shape: its method 'area' is fun() -> String, not fun() -> Number.",
+                "[line 8:23-8:30] Type error: Can't pass Point as argument 1 of type Shape: it has no method 'area'.",
+                "[line 8:49-8:61] Type error: Can't assign Number to 's' of type String.",
+                "[line 8:74-8:77] Type error: Can't store String in List<Number>.",
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_and_maps() {
+        let source = "let m: Map<String, Number> = Map();
+let ns: List<Number> = [1, 2.5, nil];
+fun first<T>(items: List<T>) -> T { return items[0]; }
+";
+        assert!(errors(&format!("{source}let nested: List<List<Int>> = [[1], []]; let keys: List<String> = m.keys(); let n: Number = first([1, 2]);")).is_empty());
+        assert!(errors(&format!("{source}let total: Number = ns.reduce((a, b) -> a + b, 0); ns.push(3); m.set(\"a\", ns.pop());")).is_empty());
+        assert_eq!(
+            errors(&format!("{source}let xs: List<Number> = [1, 2, \"x\"]; let s: String = first([1, 2]); ns.push(\"s\"); m.set(1, \"x\");")),
+            [
+                "[line 4:24-4:34] Type error: Can't assign List<Any> to 'xs' of type List<Number>: element 3 is String.",
+                "[line 4:53-4:66] Type error: Can't assign Int to 's' of type String.",
+                "[line 4:76-4:79] Type error: Can't pass String as argument 1 of type Number.",
+                "[line 4:88-4:89] Type error: Can't pass Int as argument 1 of type String.",
+                "[line 4:91-4:94] Type error: Can't pass String as argument 2 of type Number.",
+            ]
+        );
+    }
+
+    #[test]
+    fn annotated_arrow_lambdas() {
+        assert!(errors("let f = (x: Number) -> x + 1; print f(1);").is_empty());
+        assert!(errors("let g = (xs: List<Number>, m: Map<String, List<Number>>) -> xs; print g([1], nil);").is_empty());
+        assert_eq!(errors("let f = (x: Number) -> x + 1;\nf(\"a\");"), ["[line 2:3-2:6] Type error: Can't pass String as argument 1 of type Number."]);
+    }
+
+    #[test]
+    fn self_referential_interfaces() {
+        assert!(errors("interface Node { next() -> Node; } fun f(a: Node) -> Node { return a; }").is_empty());
+        let shapes = "interface Shape { scale() -> Shape; }
+box Sq { side: Number; init(side: Number) { self.side = side; } scale() -> Sq { return Sq(self.side * 2); } }
+box Bad { scale() -> Number { return 1; } }
+";
+        assert!(errors(&format!("{shapes}let s: Shape = Sq(3);")).is_empty());
+        assert_eq!(
+            errors(&format!("{shapes}let b: Shape = Bad();")),
+            ["[line 4:16-4:21] Type error: Can't assign Bad to 'b' of type Shape: its method 'scale' is fun() -> Number, not fun() -> Shape."]
+        );
+    }
+
+    #[test]
+    fn ints_and_floats() {
+        assert!(errors("let i: Int = 1; let f: Float = 1.5; let n: Number = i; n = f; let g: Float = -f * i;").is_empty());
+        assert!(errors("let i = 1; let j: Int = i ~/ 2 + i % 3; let h: Float = i / 2;").is_empty());
+        assert!(errors("let x = 1; x = x + 0.5; let n: Number = x;").is_empty());
+        assert_eq!(
+            errors("let i: Int = 1.5;\nlet n: Number = 1;\nlet j: Int = n;\nlet k: Int = 4 / 2;"),
+            [
+                "[line 1:14-1:17] Type error: Can't assign Float to 'i' of type Int.",
+                "[line 3:14-3:15] Type error: Can't assign Number to 'j' of type Int.",
+                "[line 4:14-4:19] Type error: Can't assign Float to 'k' of type Int.",
+            ]
+        );
+        assert_eq!(errors("let x = 1;\nx = 0.5;\nlet y: Int = x;"), ["[line 3:14-3:15] Type error: Can't assign Number to 'y' of type Int."]);
+    }
+
+    #[test]
+    fn non_exhaustive_matches() {
+        assert!(errors("let x = 1; print match x { 1 => \"one\", n if n > 1 => \"many\", _ => \"none\" };").is_empty());
+        assert!(errors("let x = [1]; print match x { [a] => a, rest => rest };").is_empty());
+        assert_eq!(
+            errors("let x = 1;\nprint match x { 1 => \"one\", n if n > 1 => \"many\" };"),
+            ["[line 2:7-2:12] Warning: This match may not be exhaustive: add a '_' arm for the values no arm matches."]
+        );
+    }
+}
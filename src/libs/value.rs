@@ -0,0 +1,228 @@
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::rc::Rc;
+use crate::libs::expr::ast::Object;
+
+// A value in 8 bytes, for the VM's stack. A float is stored as itself. Anything else is a quiet
+// NaN other than the one arithmetic produces, with a tag in bits 48 to 50 and 63 and, for objects
+// on the heap, the pointer of their `Rc` in the low 48 bits. An int that fits in 48 bits is stored
+// in them; a wider one gets an `Rc` of its own. Converting to and from `Object` moves the reference
+// rather than counting it again.
+pub struct Value(u64);
+
+const QUIET_NAN: u64 = 0x7ff8_0000_0000_0000;
+const PAYLOAD: u64 = 0x0000_ffff_ffff_ffff;
+
+// Tag 0 is the NaN numbers use. Nil, false and true are in the payload of SINGLETON.
+const SINGLETON: u64 = 1;
+const STR: u64 = 2;
+const FUNCTION: u64 = 3;
+const BOX: u64 = 4;
+const INSTANCE: u64 = 5;
+const LIST: u64 = 6;
+const NATIVE: u64 = 7;
+const MAP: u64 = 8;
+const MODULE: u64 = 9;
+const INT: u64 = 10;
+const WIDE_INT: u64 = 11;
+const BIG_INT: u64 = 12;
+const RANGE: u64 = 13;
+const ITERATOR: u64 = 14;
+const GENERATOR: u64 = 15;
+
+const NIL: u64 = 0;
+const FALSE: u64 = 1;
+const TRUE: u64 = 2;
+
+const fn boxed(tag: u64, payload: u64) -> u64 {
+    QUIET_NAN | (tag & 7) << 48 | (tag & 8) << 60 | payload
+}
+
+impl Value {
+    pub const NIL: Value = Value(boxed(SINGLETON, NIL));
+
+    pub fn float(n: f64) -> Value {
+        // Every NaN is stored as the one arithmetic makes, which isn't a tagged value.
+        Value(if n.is_nan() { f64::NAN.to_bits() } else { n.to_bits() })
+    }
+
+    pub fn int(n: i64) -> Value {
+        match (-(1 << 47)..1 << 47).contains(&n) {
+            true => Value(boxed(INT, n as u64 & PAYLOAD)),
+            false => Value::from_pointer(WIDE_INT, Rc::into_raw(Rc::new(n))),
+        }
+    }
+
+    pub fn bool(b: bool) -> Value {
+        Value(boxed(SINGLETON, if b { TRUE } else { FALSE }))
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        self.tag().is_none().then(|| f64::from_bits(self.0))
+    }
+
+    // Only ints stored in the value itself, for the VM's fast paths.
+    pub fn as_int(&self) -> Option<i64> {
+        (self.0 & !PAYLOAD == boxed(INT, 0)).then_some(((self.0 & PAYLOAD) << 16) as i64 >> 16)
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        self.0 != boxed(SINGLETON, NIL) && self.0 != boxed(SINGLETON, FALSE)
+    }
+
+    // The value as an `Object` it lends, without a reference of its own.
+    pub fn as_object(&self) -> ObjectRef<'_> {
+        ObjectRef(ManuallyDrop::new(unsafe { self.object() }), PhantomData)
+    }
+
+    // None for numbers.
+    fn tag(&self) -> Option<u64> {
+        let tag = (self.0 >> 48 & 7) | (self.0 >> 60 & 8);
+        (self.0 & QUIET_NAN == QUIET_NAN && tag != 0).then_some(tag)
+    }
+
+    fn pointer<T>(&self) -> *const T {
+        (self.0 & PAYLOAD) as *const T
+    }
+
+    fn from_pointer<T>(tag: u64, pointer: *const T) -> Value {
+        let address = pointer as u64;
+        assert!(address <= PAYLOAD, "a heap address doesn't fit in a value");
+        Value(boxed(tag, address))
+    }
+
+    // Safety: the result owns the reference the value holds, so one of the two must not be
+    // dropped.
+    unsafe fn object(&self) -> Object {
+        match self.tag() {
+            None => Object::Float(f64::from_bits(self.0)),
+            Some(INT) => Object::Int(((self.0 & PAYLOAD) << 16) as i64 >> 16),
+            // A copy: the `Rc` stays with the value.
+            Some(WIDE_INT) => Object::Int(unsafe { *self.pointer::<i64>() }),
+            Some(BIG_INT) => Object::BigInt(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(SINGLETON) => match self.0 & PAYLOAD {
+                NIL => Object::Nil,
+                payload => Object::Bool(payload == TRUE),
+            },
+            Some(STR) => Object::Str(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(FUNCTION) => Object::Function(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(BOX) => Object::Box(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(INSTANCE) => Object::Instance(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(LIST) => Object::List(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(NATIVE) => Object::NativeFunction(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(MAP) => Object::Map(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(MODULE) => Object::Module(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(RANGE) => Object::Range(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(ITERATOR) => Object::Iterator(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(GENERATOR) => Object::Generator(unsafe { Rc::from_raw(self.pointer()) }),
+            Some(tag) => unreachable!("unknown value tag {tag}"),
+        }
+    }
+}
+
+impl From<Object> for Value {
+    fn from(object: Object) -> Self {
+        match object {
+            Object::Float(n) => Value::float(n),
+            Object::Int(n) => Value::int(n),
+            Object::BigInt(n) => Value::from_pointer(BIG_INT, Rc::into_raw(n)),
+            Object::Bool(b) => Value::bool(b),
+            Object::Nil => Value::NIL,
+            Object::Str(s) => Value::from_pointer(STR, Rc::into_raw(s)),
+            Object::Function(function) => Value::from_pointer(FUNCTION, Rc::into_raw(function)),
+            Object::Box(class) => Value::from_pointer(BOX, Rc::into_raw(class)),
+            Object::Instance(instance) => Value::from_pointer(INSTANCE, Rc::into_raw(instance)),
+            Object::List(list) => Value::from_pointer(LIST, Rc::into_raw(list)),
+            Object::NativeFunction(function) => Value::from_pointer(NATIVE, Rc::into_raw(function)),
+            Object::Map(map) => Value::from_pointer(MAP, Rc::into_raw(map)),
+            Object::Module(module) => Value::from_pointer(MODULE, Rc::into_raw(module)),
+            Object::Range(range) => Value::from_pointer(RANGE, Rc::into_raw(range)),
+            Object::Iterator(iter) => Value::from_pointer(ITERATOR, Rc::into_raw(iter)),
+            Object::Generator(generator) => Value::from_pointer(GENERATOR, Rc::into_raw(generator)),
+        }
+    }
+}
+
+impl From<Value> for Object {
+    fn from(value: Value) -> Self {
+        if value.tag() == Some(WIDE_INT) {
+            // Copied out, and the value lets go of its `Rc` when dropped.
+            return Object::clone(&value.as_object());
+        }
+        let value = ManuallyDrop::new(value);
+        unsafe { value.object() }
+    }
+}
+
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        Value::from(Object::clone(&self.as_object()))
+    }
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        // Only objects on the heap and wide ints hold a reference.
+        match self.tag() {
+            None | Some(SINGLETON | INT) => {}
+            Some(WIDE_INT) => drop(unsafe { Rc::from_raw(self.pointer::<i64>()) }),
+            Some(_) => drop(unsafe { self.object() }),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", *self.as_object())
+    }
+}
+
+pub struct ObjectRef<'a>(ManuallyDrop<Object>, PhantomData<&'a Value>);
+
+impl Deref for ObjectRef<'_> {
+    type Target = Object;
+
+    fn deref(&self) -> &Object {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::mem;
+    use std::rc::Rc;
+    use crate::libs::expr::ast::Object;
+    use crate::libs::value::Value;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(mem::size_of::<Value>(), 8);
+
+        for n in [0.0, -1.5, f64::INFINITY, f64::MAX] {
+            assert_eq!(Value::float(n).as_float(), Some(n));
+        }
+        assert!(Value::float(f64::NAN).as_float().is_some_and(f64::is_nan));
+        assert!(Value::float(f64::from_bits(0xfffc_0000_0000_0001)).as_float().is_some_and(f64::is_nan));
+        for n in [0, -1, (1 << 47) - 1, -(1 << 47), 1 << 47, i64::MIN, i64::MAX] {
+            let value = Value::int(n);
+            assert_eq!(value.as_int(), (-(1 << 47)..(1 << 47)).contains(&n).then_some(n));
+            assert!(matches!(Object::from(value.clone()), Object::Int(m) if m == n));
+            assert_eq!(value.as_float(), None);
+        }
+        assert!(!Value::NIL.is_truthy() && !Value::bool(false).is_truthy() && Value::bool(true).is_truthy());
+        assert!(Value::float(0.0).is_truthy() && Value::int(0).is_truthy());
+        assert!(matches!(Object::from(Value::bool(true)), Object::Bool(true)));
+
+        let text = Rc::new("shared".to_string());
+        let value = Value::from(Object::Str(Rc::clone(&text)));
+        assert_eq!(Rc::strong_count(&text), 2);
+        let copy = value.clone();
+        assert_eq!(Rc::strong_count(&text), 3);
+        assert_eq!(copy.to_string(), "shared");
+        drop(value);
+        assert!(matches!(Object::from(copy), Object::Str(s) if Rc::ptr_eq(&s, &text)));
+        assert_eq!(Rc::strong_count(&text), 1);
+    }
+}
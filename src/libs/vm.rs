@@ -0,0 +1,1171 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+use crate::libs::chunk::{Capture, Constant, OpCode, Prototype};
+use crate::libs::compiler;
+use crate::libs::environment::Environment;
+use crate::libs::exception::{self, TraceFrame, SCRIPT};
+use crate::libs::expr::ast::{Object, Stmt};
+use crate::libs::function::{Body, Function, Upvalue};
+use crate::libs::generator::{Generator, Resumed, State};
+use crate::libs::heap::{Heap, Traced};
+use crate::libs::instance::{BoxClass, Instance};
+use crate::libs::interpreter::{Interpreter, RuntimeError, MAX_FRAMES};
+use crate::libs::iterator;
+use crate::libs::lex::{Token, TokenType};
+use crate::libs::loader::{Load, Loader};
+use crate::libs::module::Module;
+use crate::libs::native::{self, NativeFn};
+use crate::libs::stdlib;
+use crate::libs::symbol::{Symbol, INIT, SUPER};
+use crate::libs::value::{ObjectRef, Value};
+
+// A call in progress.
+struct Frame {
+    // None for the top level of a file.
+    function: Option<Rc<Function>>,
+    prototype: Rc<Prototype>,
+    ip: usize,
+    // Where the callee is on the stack, with the arguments and locals after it; the call's result
+    // replaces everything from there.
+    base: usize,
+    // The variables of the call that closures share.
+    cells: Vec<Upvalue>,
+    // The environment to go back to when the call returns.
+    caller: Rc<RefCell<Environment>>,
+    // The caller's globals, which differ when the function comes from another module.
+    globals: Rc<RefCell<Environment>>,
+    // What the call returns whatever the body returns, like "self" for an initializer.
+    initialized: Option<Object>,
+}
+
+// Where an error goes while the body or handler of a try statement runs.
+struct Handler {
+    // How many frames there were; the last is the one the statement is in.
+    frames: usize,
+    stack: usize,
+    environment: Rc<RefCell<Environment>>,
+    // The offset of the code handling the error.
+    target: usize,
+}
+
+// A generator's call while it isn't running: its frame, whose `base` is 0 and whose `caller` and
+// `globals` hold its own environment and globals, its part of the stack and its try statements,
+// counted from its frame.
+pub(crate) struct Suspended {
+    frame: Frame,
+    stack: Vec<Value>,
+    handlers: Vec<Handler>,
+    // Whether it stopped at a `yield`, which takes the value it is resumed with.
+    started: bool,
+}
+
+impl Suspended {
+    // What the call holds that may be on the heap, for the heap to trace through its generator.
+    pub(crate) fn references(&self) -> Vec<Traced> {
+        let function = self.frame.function.clone().map(Traced::Function);
+        let handlers = self.handlers.iter().map(|handler| &handler.environment);
+        let environments = [&self.frame.caller, &self.frame.globals].into_iter().chain(handlers);
+        let environments = environments.map(|environment| Traced::Environment(Rc::clone(environment)));
+        let cells = self.frame.cells.iter().map(|cell| Traced::Upvalue(Rc::clone(cell)));
+        let values = self.stack.iter().filter_map(|value| Traced::of(&value.as_object()));
+        let initialized = self.frame.initialized.iter().filter_map(Traced::of);
+        function.into_iter().chain(environments).chain(cells).chain(values).chain(initialized).collect()
+    }
+}
+
+type VmResult<T = ()> = Result<T, RuntimeError>;
+
+// Runs the bytecode of `compiler` on a stack of values, with an explicit stack of frames instead of
+// Rust recursion. Functions, boxes and environments are the tree-walker's; on the stack values are
+// packed into 8 bytes, so numbers are worked on without going through an `Object`.
+pub struct Vm {
+    pub globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    stack: Vec<Value>,
+    // String constants, made once and shared by every push.
+    strings: HashMap<Symbol, Rc<String>>,
+    frames: Vec<Frame>,
+    handlers: Vec<Handler>,
+    // The call the last `yield` put aside, for the `resume` running it.
+    yielded: Option<Suspended>,
+    // The offset of the instruction running, for the line of its errors.
+    start: usize,
+    pub heap: Heap,
+    pub loader: Loader,
+    // What native functions are given to run with; it prints the program's output, and the heap is
+    // lent to it for each call.
+    host: Interpreter,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        // The globals live as long as the interpreter, so they stay out of the heap, as a root.
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        native::define_builtins(&mut globals.borrow_mut());
+        Self::sharing(globals, output)
+    }
+
+    // A VM on the globals of an interpreter that runs compiled code on it, which it is lent for each
+    // run.
+    pub(crate) fn nested(globals: Rc<RefCell<Environment>>) -> Self {
+        Self::sharing(globals, Box::new(io::sink()))
+    }
+
+    fn sharing(globals: Rc<RefCell<Environment>>, output: Box<dyn Write>) -> Self {
+        Self {
+            environment: Rc::clone(&globals),
+            globals: Rc::clone(&globals),
+            stack: Vec::new(),
+            strings: HashMap::new(),
+            frames: Vec::new(),
+            handlers: Vec::new(),
+            yielded: None,
+            start: 0,
+            heap: Heap::new(),
+            loader: Loader::default(),
+            host: Interpreter::sharing(globals, output),
+        }
+    }
+
+    // Makes the interpreter the host, so that the VM runs on its heap and loader, prints to its
+    // output and calls the functions it made through it. `give_back` undoes it.
+    pub(crate) fn lend(&mut self, interpreter: &mut Interpreter) {
+        mem::swap(&mut self.host, interpreter);
+        mem::swap(&mut self.heap, &mut self.host.heap);
+        mem::swap(&mut self.loader, &mut self.host.loader);
+    }
+
+    pub(crate) fn give_back(&mut self, interpreter: &mut Interpreter) {
+        mem::swap(&mut self.loader, &mut self.host.loader);
+        mem::swap(&mut self.heap, &mut self.host.heap);
+        mem::swap(&mut self.host, interpreter);
+    }
+
+    // Runs a compiled function until it returns, for a native function calling it back.
+    pub(crate) fn call_function(&mut self, function: &Rc<Function>, arguments: Vec<Object>) -> VmResult<Object> {
+        let (base, depth) = (self.stack.len(), self.frames.len());
+        // In place of the callee.
+        self.push(Value::NIL);
+        for argument in arguments {
+            self.push(argument);
+        }
+        self.enter(function, base);
+        // A generator function has already given its generator.
+        if self.frames.len() > depth { self.finish(depth) } else { Ok(self.pop()) }
+    }
+
+    // Runs the frame pushed last until it returns, with what it returned. An error goes to the
+    // innermost try statement of the frames run; without one, the VM goes back to the state from
+    // before the frame.
+    fn finish(&mut self, depth: usize) -> VmResult<Object> {
+        loop {
+            let mut error = match self.dispatch(depth) {
+                Ok(()) => return Ok(self.pop()),
+                Err(error) => error,
+            };
+            if self.handlers.last().is_some_and(|handler| handler.frames > depth) {
+                self.catch(error);
+                continue;
+            }
+
+            for index in (depth..self.frames.len()).rev() {
+                error.trace.frames.push(self.trace_frame(index));
+            }
+            let frame = self.frames.drain(depth..).next().expect("the frame that failed");
+            self.stack.truncate(frame.base);
+            self.environment = frame.caller;
+            self.globals = frame.globals;
+            return Err(error);
+        }
+    }
+
+    // Goes back to where the innermost try statement was, with the exception pushed for its
+    // handler.
+    fn catch(&mut self, mut error: RuntimeError) {
+        let handler = self.handlers.pop().expect("a try statement to catch the error");
+        for index in (handler.frames..self.frames.len()).rev() {
+            error.trace.frames.push(self.trace_frame(index));
+        }
+        let frames = [error.trace.frames.as_slice(), &[self.trace_frame(handler.frames - 1)]].concat();
+
+        if let Some(frame) = self.frames.drain(handler.frames..).next() {
+            self.globals = frame.globals;
+        }
+        self.stack.truncate(handler.stack);
+        self.environment = handler.environment;
+        let exception = exception::caught(error, &frames, &self.loader, &mut self.heap);
+        self.push(exception);
+        self.frame().ip = handler.target;
+    }
+
+    // The frame at `index` in a stack trace: the top one is at the instruction running, the others
+    // at their calls.
+    fn trace_frame(&self, index: usize) -> TraceFrame {
+        let frame = &self.frames[index];
+        let offset = if index + 1 == self.frames.len() { self.start } else { frame.ip - 1 };
+        let function = frame.function.as_ref().map_or(SCRIPT, |function| function.trace_name());
+        TraceFrame { function, file: frame.prototype.file, line: frame.prototype.chunk.line(offset) }
+    }
+
+    // Makes a Rust function callable from scripts as a global named `name`.
+    pub fn register(&mut self, name: &str, arity: usize, function: NativeFn) {
+        native::define(&mut self.globals.borrow_mut(), name, arity, function);
+        self.loader.register(name, arity, function);
+    }
+
+    pub fn run(&mut self, statements: Vec<Stmt>) -> bool {
+        compiler::build(statements).is_some_and(|script| self.execute(script))
+    }
+
+    pub fn execute(&mut self, script: Rc<Prototype>) -> bool {
+        let (base, cells) = (self.stack.len(), self.cells(&script));
+        // In place of a callee.
+        self.push(Value::NIL);
+        self.frames.push(Frame {
+            function: None,
+            cells,
+            prototype: script,
+            ip: 0,
+            base,
+            caller: Rc::clone(&self.environment),
+            globals: Rc::clone(&self.globals),
+            initialized: None,
+        });
+        match self.finish(0) {
+            Ok(_) => true,
+            Err(error) => {
+                exception::report(&error, &self.loader);
+                false
+            }
+        }
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no function is running")
+    }
+
+    // New cells for a call.
+    fn cells(&mut self, prototype: &Prototype) -> Vec<Upvalue> {
+        (0..prototype.cells).map(|_| self.heap.upvalue()).collect()
+    }
+
+    // The running function's upvalue at `index`; only a function has some.
+    fn upvalue(&mut self, index: usize) -> Upvalue {
+        let function = self.frame().function.as_ref().expect("a function with upvalues");
+        Rc::clone(&function.upvalues()[index])
+    }
+
+    // The cells a closure made of `prototype` shares with the running frame.
+    fn captures(&mut self, prototype: &Prototype) -> Vec<Upvalue> {
+        let captures = prototype.upvalues.iter().map(|&capture| match capture {
+            Capture::Cell(index) => Rc::clone(&self.frame().cells[index]),
+            Capture::Upvalue(index) => self.upvalue(index),
+        });
+        captures.collect()
+    }
+
+    fn read_u16(&mut self) -> usize {
+        let frame = self.frame();
+        let value = frame.prototype.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value as usize
+    }
+
+    fn prototype(&mut self) -> Rc<Prototype> {
+        Rc::clone(&self.frame().prototype)
+    }
+
+    // The name a string constant holds.
+    fn name(&mut self) -> Symbol {
+        let index = self.read_u16();
+        match self.frame().prototype.chunk.constants[index] {
+            Constant::Str(name) => name,
+            _ => Symbol::intern(""),
+        }
+    }
+
+    fn error(&mut self, message: &str) -> RuntimeError {
+        RuntimeError::new(&self.token(Symbol::intern("")), message)
+    }
+
+    // A token at the line of the running instruction, for the functions that report errors at one.
+    fn token(&mut self, lexeme: Symbol) -> Token {
+        let start = self.start;
+        let line = self.frame().prototype.chunk.line(start);
+        Token::synthetic(TokenType::IDENTIFIER, lexeme, line)
+    }
+
+    fn push(&mut self, value: impl Into<Value>) {
+        self.stack.push(value.into());
+    }
+
+    fn pop(&mut self) -> Object {
+        self.pop_value().into()
+    }
+
+    fn pop_value(&mut self) -> Value {
+        self.stack.pop().expect("the stack is empty")
+    }
+
+    fn peek(&self, distance: usize) -> ObjectRef<'_> {
+        self.peek_value(distance).as_object()
+    }
+
+    fn peek_value(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    // Runs until a return leaves `depth` frames, with what it returned on the stack.
+    fn dispatch(&mut self, depth: usize) -> VmResult {
+        loop {
+            let frame = self.frame();
+            let start = frame.ip;
+            let byte = frame.prototype.chunk.code[start];
+            frame.ip += 1;
+            self.start = start;
+            let Some(op) = OpCode::from_byte(byte) else {
+                return Err(self.error(&format!("Unknown opcode {byte}.")));
+            };
+
+            match op {
+                OpCode::Constant => {
+                    let index = self.read_u16();
+                    let value = match self.frame().prototype.chunk.constants[index] {
+                        Constant::Float(n) => Value::float(n),
+                        Constant::Int(n) => Value::int(n),
+                        Constant::BigInt(ref n) => Object::BigInt(Rc::clone(n)).into(),
+                        Constant::Str(s) => {
+                            let text = self.strings.entry(s).or_insert_with(|| Rc::new(s.to_string()));
+                            Object::Str(Rc::clone(text)).into()
+                        }
+                        Constant::Function(_) | Constant::Box(_) => Value::NIL,
+                    };
+                    self.push(value);
+                }
+                OpCode::Nil => self.push(Value::NIL),
+                OpCode::True => self.push(Value::bool(true)),
+                OpCode::False => self.push(Value::bool(false)),
+                OpCode::Pop => {
+                    self.pop_value();
+                }
+                OpCode::Dup => {
+                    let value = self.peek_value(0).clone();
+                    self.push(value);
+                }
+                OpCode::Swap => {
+                    let top = self.stack.len() - 1;
+                    self.stack.swap(top, top - 1);
+                }
+                OpCode::Define => {
+                    let name = self.name();
+                    let value = self.pop();
+                    self.environment.borrow_mut().define(name, value);
+                }
+                OpCode::GetLocal => {
+                    let slot = self.read_u16() + self.frame().base;
+                    let value = self.stack[slot].clone();
+                    self.push(value);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.read_u16() + self.frame().base;
+                    self.stack[slot] = self.peek_value(0).clone();
+                }
+                OpCode::GetCell | OpCode::GetUpvalue => {
+                    let (index, name) = (self.read_u16(), self.name());
+                    let cell = match op {
+                        OpCode::GetCell => Rc::clone(&self.frame().cells[index]),
+                        _ => self.upvalue(index),
+                    };
+                    let value = cell.borrow().clone();
+                    match value {
+                        Some(value) => self.push(value),
+                        None => return Err(self.error(&format!("Undefined variable '{name}'."))),
+                    }
+                }
+                OpCode::SetCell | OpCode::SetUpvalue => {
+                    let (index, name) = (self.read_u16(), self.name());
+                    let cell = match op {
+                        OpCode::SetCell => Rc::clone(&self.frame().cells[index]),
+                        _ => self.upvalue(index),
+                    };
+                    let mut variable = cell.borrow_mut();
+                    if variable.is_none() {
+                        return Err(self.error(&format!("Undefined variable '{name}'.")));
+                    }
+                    *variable = Some(self.peek_value(0).clone());
+                }
+                OpCode::GetOuter => {
+                    let (depth, slot, name) = (self.read_u16(), self.read_u16(), self.name());
+                    match Environment::get_slot(&self.environment, depth, slot) {
+                        Some(value) => self.push(value),
+                        None => return Err(self.error(&format!("Undefined variable '{name}'."))),
+                    }
+                }
+                OpCode::SetOuter => {
+                    let (depth, slot, name) = (self.read_u16(), self.read_u16(), self.name());
+                    let value = self.peek(0).clone();
+                    if !Environment::assign_slot(&self.environment, depth, slot, value) {
+                        return Err(self.error(&format!("Undefined variable '{name}'.")));
+                    }
+                }
+                OpCode::NewCell => {
+                    let index = self.read_u16();
+                    let cell = self.heap.upvalue();
+                    self.frame().cells[index] = cell;
+                }
+                OpCode::DefineCell => {
+                    let index = self.read_u16();
+                    let value = self.pop_value();
+                    *self.frame().cells[index].borrow_mut() = Some(value);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.name();
+                    let value = self.globals.borrow().value(name);
+                    match value {
+                        Some(value) => self.push(value),
+                        None => return Err(self.error(&format!("Undefined variable '{name}'."))),
+                    }
+                }
+                OpCode::SetGlobal => {
+                    let name = self.name();
+                    let value = self.peek(0).clone();
+                    if !self.globals.borrow_mut().set_value(name, value) {
+                        return Err(self.error(&format!("Undefined variable '{name}'.")));
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.name();
+                    let value = match self.pop() {
+                        Object::Instance(instance) => Instance::get(&instance, &self.token(name), &mut self.heap)?,
+                        other => stdlib::property(&other, &self.token(name))?,
+                    };
+                    self.push(value);
+                }
+                OpCode::CheckFields => {
+                    self.read_u16();
+                    if !matches!(*self.peek(0), Object::Instance(_)) {
+                        return Err(self.error("Only instances have fields."));
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = self.name();
+                    let value = self.pop();
+                    let Object::Instance(instance) = self.pop() else {
+                        return Err(self.error("Only instances have fields."));
+                    };
+                    instance.borrow_mut().set(&self.token(name), value.clone())?;
+                    self.push(value);
+                }
+                OpCode::GetSuper => self.get_super()?,
+                OpCode::Index => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    let value = Interpreter::index(&object, &index).map_err(|message| self.error(&message))?;
+                    self.push(value);
+                }
+                OpCode::IndexSet => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let object = self.pop();
+                    Interpreter::index_set(&object, index, value.clone()).map_err(|message| self.error(&message))?;
+                    self.push(value);
+                }
+                OpCode::List => {
+                    let count = self.read_u16();
+                    let values = self.stack.drain(self.stack.len() - count..).map(Object::from).collect();
+                    let list = self.heap.list(values);
+                    self.push(list);
+                }
+                OpCode::Equal | OpCode::NotEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let equal = Interpreter::is_equal(&left, &right);
+                    self.push(Value::bool(equal == matches!(op, OpCode::Equal)));
+                }
+                OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide
+                | OpCode::IntDivide
+                | OpCode::Remainder
+                | OpCode::Range
+                | OpCode::RangeInclusive => self.binary(op)?,
+                OpCode::Not => {
+                    let value = self.pop_value();
+                    self.push(Value::bool(!value.is_truthy()));
+                }
+                OpCode::Negate => {
+                    let value = match self.pop() {
+                        Object::Float(n) => Object::Float(-n),
+                        Object::Int(n) if n != i64::MIN => Object::Int(-n),
+                        value => Interpreter::unary(&TokenType::MINUS, value).map_err(|message| self.error(message))?,
+                    };
+                    self.push(value);
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    self.host.print(&value);
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16();
+                    self.frame().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16();
+                    if !self.peek_value(0).is_truthy() {
+                        self.frame().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16();
+                    self.frame().ip -= offset;
+                }
+                OpCode::Call => {
+                    let count = self.read_u16();
+                    self.call(count)?;
+                }
+                OpCode::Closure => {
+                    let index = self.read_u16();
+                    let Constant::Function(prototype) = &self.prototype().chunk.constants[index] else {
+                        return Err(self.error("Expect a function constant."));
+                    };
+                    let upvalues = self.captures(prototype);
+                    let function = Function::compiled(Rc::clone(prototype), Rc::clone(&self.environment), upvalues, false);
+                    let function = self.heap.function(function);
+                    self.push(Object::Function(function));
+                }
+                OpCode::Box => self.declare_box()?,
+                OpCode::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().expect("no function is running");
+                    self.stack.truncate(frame.base);
+                    self.environment = frame.caller;
+                    self.globals = frame.globals;
+                    self.push(frame.initialized.unwrap_or(value));
+                    while self.handlers.last().is_some_and(|handler| handler.frames > self.frames.len()) {
+                        self.handlers.pop();
+                    }
+                    if self.frames.len() == depth {
+                        return Ok(());
+                    }
+                }
+                OpCode::Reserve => {
+                    let count = self.read_u16();
+                    self.stack.resize(self.stack.len() + count, Value::NIL);
+                }
+                OpCode::PopScope => {
+                    let count = self.read_u16();
+                    self.stack.truncate(self.stack.len() - count);
+                }
+                OpCode::MatchList | OpCode::MatchListRest => {
+                    let count = self.read_u16();
+                    let fits = match &*self.peek(0) {
+                        Object::List(list) if matches!(op, OpCode::MatchList) => list.borrow().len() == count,
+                        Object::List(list) => list.borrow().len() >= count,
+                        _ => false,
+                    };
+                    self.push(Value::bool(fits));
+                }
+                OpCode::ListElement | OpCode::ListRest => {
+                    let index = self.read_u16();
+                    let Object::List(list) = self.peek(0).clone() else {
+                        return Err(self.error("Only lists can be indexed."));
+                    };
+                    // A pattern tests the length first; only loaded code can get this wrong.
+                    let len = list.borrow().len();
+                    if index > len || (op == OpCode::ListElement && index == len) {
+                        return Err(self.error(&format!("List index {index} is out of bounds.")));
+                    }
+                    let value = match op {
+                        OpCode::ListElement => list.borrow()[index].clone(),
+                        _ => {
+                            let rest = list.borrow()[index..].to_vec();
+                            self.heap.list(rest)
+                        }
+                    };
+                    self.push(value);
+                }
+                OpCode::MatchInstance => {
+                    let name = self.name();
+                    let Object::Box(class) = self.peek(0).clone() else {
+                        return Err(self.error(&format!("'{name}' is not a box.")));
+                    };
+                    let is_a = match &*self.peek(1) {
+                        Object::Instance(instance) => instance.borrow().is_a(&class),
+                        _ => false,
+                    };
+                    self.push(Value::bool(is_a));
+                }
+                OpCode::MatchField => {
+                    let name = self.name();
+                    let (Object::Box(class), Object::Instance(instance)) = (self.peek(0).clone(), self.peek(1).clone()) else {
+                        return Err(self.error("Only instances have fields."));
+                    };
+                    let field = instance.borrow().field(name);
+                    match field {
+                        Some(value) => self.push(value),
+                        None => {
+                            let message = format!("Undefined field '{name}' in box '{}'.", class.name);
+                            return Err(self.error(&message));
+                        }
+                    }
+                }
+                OpCode::NoMatch => {
+                    let subject = self.pop();
+                    return Err(self.error(&format!("No match arm matches value '{subject}'.")));
+                }
+                OpCode::Import => {
+                    let path = self.name();
+                    let path = self.token(path);
+                    let module = self.import(&path)?;
+                    self.push(Object::Module(module));
+                }
+                OpCode::Try => {
+                    let offset = self.read_u16();
+                    let handler = Handler {
+                        frames: self.frames.len(),
+                        stack: self.stack.len(),
+                        environment: Rc::clone(&self.environment),
+                        target: self.frame().ip + offset,
+                    };
+                    self.handlers.push(handler);
+                }
+                OpCode::EndTry => {
+                    self.handlers.pop();
+                }
+                OpCode::Throw => {
+                    let value = self.pop();
+                    return Err(RuntimeError::thrown(&self.token(Symbol::intern("")), value));
+                }
+                OpCode::Iterate => {
+                    let value = self.pop();
+                    let token = self.token(Symbol::intern(""));
+                    let iterable = iterator::iterable(&token, value, &mut |object, method| self.call_method(object, method))?;
+                    let iter = iterator::of(&iterable, &mut self.heap).expect("a value loops go through");
+                    self.push(Object::Iterator(iter));
+                }
+                OpCode::Yield => {
+                    let value = self.pop();
+                    let call = self.suspend(true);
+                    self.yielded = Some(call);
+                    self.push(value);
+                    // The generator's frame is the one its resume started from.
+                    return Ok(());
+                }
+                OpCode::ForNext => {
+                    let offset = self.read_u16();
+                    let Object::Iterator(iter) = Object::clone(&self.peek(0)) else {
+                        return Err(self.error("Expect an iterator under a for loop's items."));
+                    };
+                    // Built-in iterators need no token unless they fail.
+                    let item = match iterator::advance(&iter) {
+                        Some(item) => item.map_err(|message| self.error(message))?,
+                        None => {
+                            let token = self.token(Symbol::intern(""));
+                            iterator::next(&token, &iter, &mut |object, method| self.call_method(object, method))?
+                        }
+                    };
+                    match item {
+                        Some(item) => self.push(item),
+                        None => self.frame().ip += offset,
+                    }
+                }
+            }
+        }
+    }
+
+    // Ints stored in values and floats are handled by `fast_binary`; anything else goes to the
+    // tree-walker's operators, for the same results and errors.
+    fn binary(&mut self, op: OpCode) -> VmResult {
+        let right = self.pop_value();
+        let left = self.pop_value();
+        let value = match Self::fast_binary(op, &left, &right) {
+            Some(value) => value,
+            None => {
+                let operator = match op {
+                    OpCode::Greater => TokenType::GREATER,
+                    OpCode::GreaterEqual => TokenType::GREATER_EQUAL,
+                    OpCode::Less => TokenType::LESS,
+                    OpCode::LessEqual => TokenType::LESS_EQUAL,
+                    OpCode::Add => TokenType::PLUS,
+                    OpCode::Subtract => TokenType::MINUS,
+                    OpCode::Multiply => TokenType::STAR,
+                    OpCode::IntDivide => TokenType::TILDE_SLASH,
+                    OpCode::Remainder => TokenType::PERCENT,
+                    OpCode::Range => TokenType::DOT_DOT,
+                    OpCode::RangeInclusive => TokenType::DOT_DOT_EQUAL,
+                    _ => TokenType::SLASH,
+                };
+                Interpreter::binary(&operator, left.into(), right.into()).map_err(|message| self.error(message))?.into()
+            }
+        };
+        self.push(value);
+        Ok(())
+    }
+
+    // The result when both operands are ints stored in values or floats, unless it might overflow
+    // or fail.
+    fn fast_binary(op: OpCode, left: &Value, right: &Value) -> Option<Value> {
+        if let (Some(a), Some(b)) = (left.as_int(), right.as_int()) {
+            // Sums and differences of 48-bit ints fit in an `i64`.
+            return match op {
+                OpCode::Greater => Some(Value::bool(a > b)),
+                OpCode::GreaterEqual => Some(Value::bool(a >= b)),
+                OpCode::Less => Some(Value::bool(a < b)),
+                OpCode::LessEqual => Some(Value::bool(a <= b)),
+                OpCode::Add => Some(Value::int(a + b)),
+                OpCode::Subtract => Some(Value::int(a - b)),
+                OpCode::Multiply => a.checked_mul(b).map(Value::int),
+                OpCode::Divide if b != 0 => Some(Value::float(a as f64 / b as f64)),
+                _ => None,
+            };
+        }
+        // With a float on either side, an int is used as a float, which it converts to exactly.
+        let number = |value: &Value| value.as_float().or_else(|| value.as_int().map(|n| n as f64));
+        let (a, b) = (number(left)?, number(right)?);
+        match op {
+            OpCode::Greater => Some(Value::bool(a > b)),
+            OpCode::GreaterEqual => Some(Value::bool(a >= b)),
+            OpCode::Less => Some(Value::bool(a < b)),
+            OpCode::LessEqual => Some(Value::bool(a <= b)),
+            OpCode::Add => Some(Value::float(a + b)),
+            OpCode::Subtract => Some(Value::float(a - b)),
+            OpCode::Multiply => Some(Value::float(a * b)),
+            OpCode::Divide => Some(Value::float(a / b)),
+            _ => None,
+        }
+    }
+
+    // Calls the value under the top `count` values, which are its arguments.
+    fn call(&mut self, count: usize) -> VmResult {
+        let base = self.stack.len() - count - 1;
+        let callee = Object::clone(&self.stack[base].as_object());
+        let arity = match callee {
+            Object::Function(ref function) => function.arity(),
+            Object::NativeFunction(ref function) => function.arity(),
+            Object::Box(ref class) => class.arity(),
+            _ => return Err(self.error("Can only call functions and boxes.")),
+        };
+        if count != arity {
+            return Err(self.error(&format!("Expected {arity} arguments but got {count}.")));
+        }
+        let frame = match callee {
+            Object::Function(_) => true,
+            Object::Box(ref class) => class.find_method(*INIT).is_some(),
+            _ => false,
+        };
+        if frame && self.host.depth + self.calls() >= MAX_FRAMES {
+            return Err(self.error("Stack overflow."));
+        }
+
+        match callee {
+            Object::Function(ref function) if Self::is_compiled(function) => self.enter(function, base),
+            Object::NativeFunction(function) => {
+                let arguments = self.stack.drain(base + 1..).map(Object::from).collect::<Vec<_>>();
+                let call = self.token(function.name);
+                mem::swap(&mut self.heap, &mut self.host.heap);
+                mem::swap(&mut self.loader, &mut self.host.loader);
+                self.host.depth += self.calls();
+                let result = function.call(&mut self.host, &call, &arguments);
+                self.host.depth -= self.calls();
+                mem::swap(&mut self.loader, &mut self.host.loader);
+                mem::swap(&mut self.heap, &mut self.host.heap);
+                self.stack.truncate(base);
+                self.push(result?);
+            }
+            Object::Box(ref class) if class.find_method(*INIT).is_none_or(|initializer| Self::is_compiled(&initializer)) => {
+                let instance = BoxClass::instantiate(class, &mut self.heap);
+                match class.find_method(*INIT) {
+                    Some(initializer) => {
+                        let initializer = initializer.bind(instance, &mut self.heap);
+                        self.enter(&initializer, base)
+                    }
+                    None => {
+                        self.stack.truncate(base);
+                        self.push(instance);
+                    }
+                }
+            }
+            // What the tree-walker made, called by one of its generators, runs on the tree-walker.
+            callee => {
+                let arguments = self.stack.drain(base + 1..).map(Object::from).collect::<Vec<_>>();
+                let call = self.token(Symbol::intern(""));
+                mem::swap(&mut self.heap, &mut self.host.heap);
+                mem::swap(&mut self.loader, &mut self.host.loader);
+                self.host.depth += self.calls();
+                let result = self.host.call_value(callee, &call, arguments);
+                self.host.depth -= self.calls();
+                mem::swap(&mut self.loader, &mut self.host.loader);
+                mem::swap(&mut self.heap, &mut self.host.heap);
+                self.stack.truncate(base);
+                self.push(result?);
+            }
+        }
+        Ok(())
+    }
+
+    // The calls running, which the frame of a script at the bottom is not.
+    fn calls(&self) -> usize {
+        let script = self.frames.first().is_some_and(|frame| frame.function.is_none());
+        self.frames.len() - usize::from(script)
+    }
+
+    fn is_compiled(function: &Function) -> bool {
+        matches!(function.body(), Body::Compiled(_))
+    }
+
+    // Starts running `function` with the arguments above `base`, which become its first locals.
+    fn enter(&mut self, function: &Rc<Function>, base: usize) {
+        let Body::Compiled(prototype) = function.body() else {
+            unreachable!("the VM only runs compiled functions");
+        };
+        let cells = self.cells(prototype);
+        let caller = mem::replace(&mut self.environment, Rc::clone(function.closure()));
+        let globals = mem::replace(&mut self.globals, Rc::clone(function.globals()));
+        self.frames.push(Frame {
+            function: Some(Rc::clone(function)),
+            prototype: Rc::clone(prototype),
+            ip: 0,
+            base,
+            cells,
+            caller,
+            globals,
+            initialized: function.initialized(),
+        });
+
+        // A generator's call waits to be resumed, and what it gives back is the generator.
+        if prototype.generator {
+            let call = self.suspend(false);
+            let generator = Generator::new(function.trace_name(), call);
+            let generator = self.heap.generator(generator);
+            self.push(Object::Generator(generator));
+        }
+    }
+
+    // Takes the running frame off the VM with its part of the stack and its try statements, and
+    // goes back to its caller.
+    fn suspend(&mut self, started: bool) -> Suspended {
+        let mut frame = self.frames.pop().expect("a generator to suspend");
+        let depth = self.frames.len();
+        let base = mem::take(&mut frame.base);
+        let first = self.handlers.iter().position(|handler| handler.frames > depth).unwrap_or(self.handlers.len());
+        let handlers = self.handlers.split_off(first).into_iter().map(|handler| Handler {
+            frames: handler.frames - depth,
+            stack: handler.stack - base,
+            ..handler
+        });
+        let handlers = handlers.collect();
+        let stack = self.stack.split_off(base);
+        mem::swap(&mut frame.caller, &mut self.environment);
+        mem::swap(&mut frame.globals, &mut self.globals);
+        Suspended { frame, stack, handlers, started }
+    }
+
+    // Runs a generator until it yields or returns. `value` is what the `yield` it stopped at
+    // evaluates to; the first run has no `yield` to give it to.
+    pub(crate) fn resume(&mut self, generator: &Rc<RefCell<Generator>>, value: Object) -> VmResult<Resumed> {
+        let state = mem::replace(&mut generator.borrow_mut().state, State::Running);
+        let Suspended { mut frame, stack, handlers, started } = match state {
+            State::Suspended(call) => call,
+            // Not `self.error`: the VM may have no frame to take a line from.
+            State::Running => return Err(native::error("Can't resume a generator that is running.")),
+            State::Done => {
+                generator.borrow_mut().state = State::Done;
+                return Err(native::error("Can't resume a finished generator."));
+            }
+        };
+
+        let (depth, base, start) = (self.frames.len(), self.stack.len(), self.start);
+        self.stack.extend(stack);
+        self.handlers.extend(handlers.into_iter().map(|handler| Handler {
+            frames: handler.frames + depth,
+            stack: handler.stack + base,
+            ..handler
+        }));
+        mem::swap(&mut frame.caller, &mut self.environment);
+        mem::swap(&mut frame.globals, &mut self.globals);
+        frame.base = base;
+        self.frames.push(frame);
+        if started {
+            self.push(value);
+        }
+
+        let result = self.finish(depth);
+        self.start = start;
+        let call = self.yielded.take();
+        let resumed = match (result, call) {
+            (Ok(value), Some(call)) => {
+                generator.borrow_mut().state = State::Suspended(call);
+                return Ok(Resumed::Yielded(value));
+            }
+            (Ok(value), None) => Ok(Resumed::Returned(value)),
+            (Err(error), _) => Err(error),
+        };
+        generator.borrow_mut().state = State::Done;
+        resumed
+    }
+
+    // Calls the method `name` of a value with no arguments, as loops call `iter` and `next`.
+    fn call_method(&mut self, object: &Object, name: Symbol) -> VmResult<Object> {
+        let method = match object {
+            Object::Instance(instance) => Instance::get(instance, &self.token(name), &mut self.heap)?,
+            other => stdlib::property(other, &self.token(name))?,
+        };
+        let (depth, start) = (self.frames.len(), self.start);
+        self.push(method);
+        self.call(0)?;
+        // A native method has already left its result; a compiled one runs here.
+        let result = if self.frames.len() > depth { self.finish(depth) } else { Ok(self.pop()) };
+        self.start = start;
+        result
+    }
+
+    // Runs a module the first time it is imported, in globals of its own.
+    fn import(&mut self, path: &Token) -> VmResult<Rc<Module>> {
+        let statements = match self.loader.start(path)? {
+            Load::Loaded(module) => return Ok(module),
+            Load::Parsed(statements) => statements,
+        };
+        let Some(script) = compiler::build(statements) else {
+            self.loader.abandon();
+            return Err(RuntimeError::new(path, &format!("Failed to load module '{}'.", path.lexeme)));
+        };
+
+        let globals = self.loader.globals();
+        let (depth, start, base) = (self.frames.len(), self.start, self.stack.len());
+        let cells = self.cells(&script);
+        self.push(Value::NIL);
+        self.frames.push(Frame {
+            function: None,
+            cells,
+            prototype: script,
+            ip: 0,
+            base,
+            caller: mem::replace(&mut self.environment, Rc::clone(&globals)),
+            globals: mem::replace(&mut self.globals, Rc::clone(&globals)),
+            initialized: None,
+        });
+        let result = self.finish(depth);
+        self.start = start;
+        match result {
+            Ok(_) => Ok(self.loader.finish(globals)),
+            Err(error) => {
+                self.loader.abandon();
+                Err(error)
+            }
+        }
+    }
+
+    // "self" is always in the scope just inside the one holding "super".
+    fn get_super(&mut self) -> VmResult {
+        let (depth, slot, method) = (self.read_u16(), self.read_u16(), self.name());
+        let superclass = Environment::get_slot(&self.environment, depth, slot);
+        let instance = depth.checked_sub(1).and_then(|depth| Environment::get_slot(&self.environment, depth, 0));
+        let (Some(Object::Box(superclass)), Some(instance)) = (superclass, instance) else {
+            return Err(self.error("Can't use 'super' here."));
+        };
+
+        match superclass.find_method(method) {
+            Some(found) => {
+                let method = found.bind(instance, &mut self.heap);
+                self.push(Object::Function(method));
+                Ok(())
+            }
+            None => Err(self.error(&format!("Undefined property '{method}'."))),
+        }
+    }
+
+    // Methods of a derived box see "super" in an environment of its own, like in the tree-walker.
+    // The box replaces its superclass on the stack, for the compiler to define.
+    fn declare_box(&mut self) -> VmResult {
+        let index = self.read_u16();
+        let Constant::Box(prototype) = &self.prototype().chunk.constants[index] else {
+            return Err(self.error("Expect a box constant."));
+        };
+        let superclass = match self.pop() {
+            Object::Box(superclass) => Some(superclass),
+            Object::Nil => None,
+            _ => return Err(self.error("Superclass must be a box.")),
+        };
+
+        let closure = match superclass {
+            Some(ref superclass) => {
+                let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                environment.define(*SUPER, Object::Box(Rc::clone(superclass)));
+                self.heap.environment(environment)
+            }
+            None => Rc::clone(&self.environment),
+        };
+
+        let mut methods = HashMap::new();
+        for method in &prototype.methods {
+            let name = method.name.as_ref().map_or(Symbol::intern(""), |name| name.lexeme);
+            let upvalues = self.captures(method);
+            let function = Function::compiled(Rc::clone(method), Rc::clone(&closure), upvalues, name == *INIT);
+            methods.insert(name, self.heap.function(function.in_box(prototype.name)));
+        }
+
+        let class = BoxClass::new(prototype.name, superclass, prototype.fields.clone(), methods);
+        let class = self.heap.class(class);
+        self.push(Object::Box(class));
+        Ok(())
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::libs::testing::{run, run_both};
+
+    #[test]
+    fn recursion_and_loops() {
+        let source = "
+            fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            print fib(10);
+
+            let i = 0;
+            let text = \"\";
+            while (i < 3) { let j = i; text = text + \"ab\"; i = i + 1; }
+            print text;
+            print nil or \"or\";
+            print false and 1;
+        ";
+        assert_eq!(run(source), "55\nababab\nor\nfalse\n");
+    }
+
+    #[test]
+    fn closures() {
+        let source = "
+            fun counter() { let count = 0; return () -> count = count + 1; }
+            let next = counter();
+            next();
+            print next();
+        ";
+        assert_eq!(run(source), "2\n");
+    }
+
+    #[test]
+    fn boxes() {
+        let source = "
+            box Shape { sides; init(sides) { self.sides = sides; } name() { return \"shape\"; } }
+            box Square <- Shape { init() { super.init(4); } name() { return \"square \" + super.name(); } }
+            let square = Square();
+            print square.sides;
+            print square.name();
+            print square;
+        ";
+        assert_eq!(run(source), "4\nsquare shape\nSquare instance\n");
+    }
+
+    #[test]
+    fn lists_and_patterns() {
+        let source = "
+            box Shape { sides; init(sides) { self.sides = sides; } }
+            box Square <- Shape { init() { super.init(4); } }
+            let list = [1, [2, 3]];
+            list[0] = \"one\";
+            print list;
+            print match list { [first, [_, ..rest]] => first + \"!\", _ => \"no\" };
+            print match Square() { Shape { sides: 3 } => \"triangle\", Square { sides } if sides == 4 => sides, _ => 0 };
+        ";
+        assert_eq!(run(source), "[one, [2, 3]]\none!\n4\n");
+    }
+
+    #[test]
+    fn runtime_errors_stop_both() {
+        let source = "
+            fun add(a, b) { return a + b; }
+            print add(1, \"a\");
+            print \"not reached\";
+        ";
+        assert_eq!(run_both(source), (false, String::new()));
+    }
+
+    #[test]
+    fn stack_overflow() {
+        // Calls through native functions count too.
+        let source = "
+            fun down(n) { if (n == 0) return 0; return 1 + down(n - 1); }
+            print down(2000);
+            fun forever(n) { return forever(n + 1); }
+            try { forever(0); } catch (e) { print e.message; }
+            fun mapped(n) { return [n].map((x) -> mapped(x + 1)); }
+            try { mapped(0); } catch (e) { print e.message; }
+            forever(0);
+        ";
+        assert_eq!(run_both(source), (false, "2000\nStack overflow.\nStack overflow.\n".to_string()));
+    }
+
+    #[test]
+    fn ints_with_floats() {
+        // Parameters, so that nothing is folded before the VM sees it.
+        let source = "
+            fun show(a, b) { print [a + b, a - b, a * b, a / b, a < b, a >= b]; }
+            show(3, 0.5);
+            show(0.5, 3);
+            show(7, 2);
+            show(1, 0.0);
+        ";
+        let expected = "[3.5, 2.5, 1.5, 6.0, false, true]\n[3.5, -2.5, 1.5, 0.16666666666666666, true, false]\n[9, 5, 14, 3.5, false, true]\n[1.0, 1.0, 0.0, inf, false, true]\n";
+        assert_eq!(run(source), expected);
+    }
+
+    #[test]
+    fn closures_share_a_cell() {
+        let source = "
+            fun pair() {
+                let n = 0;
+                return [() -> n = n + 1, () -> n];
+            }
+            let counter = pair();
+            counter[0]();
+            counter[0]();
+            print counter[1]();
+        ";
+        assert_eq!(run(source), "2\n");
+    }
+
+    #[test]
+    fn each_iteration_has_its_own_cell() {
+        let source = "
+            let getters = [];
+            for i in 0..3 { let twice = i * 2; getters.push(() -> [i, twice]); }
+            print [getters[0](), getters[2]()];
+        ";
+        assert_eq!(run(source), "[[0, 0], [2, 4]]\n");
+    }
+
+    #[test]
+    fn captures_pass_through_functions() {
+        let source = "
+            fun outer(a) { fun middle() { return () -> a; } a = a + 1; return middle()(); }
+            print outer(1);
+        ";
+        assert_eq!(run(source), "2\n");
+    }
+
+    #[test]
+    fn captured_use_before_definition_fails() {
+        let source = "
+            fun early() { let get = () -> late; print get(); let late = 1; }
+            early();
+        ";
+        assert_eq!(run_both(source), (false, String::new()));
+    }
+}
@@ -1,13 +1,279 @@
-mod libs;
-use libs::lex::Lox;
+use std::path::Path;
+use std::rc::Rc;
+use std::{env, fs, process, thread};
+use sinterpreter::libs::ast_printer::AstPrinter;
+use sinterpreter::libs::chunk::Prototype;
+use sinterpreter::libs::compiler;
+use sinterpreter::libs::disassembler;
+use sinterpreter::libs::dot_printer::DotPrinter;
+use sinterpreter::libs::expr::ast::Stmt;
+use sinterpreter::libs::expr::visitor::StmtVisitor;
+use sinterpreter::libs::formatter;
+use sinterpreter::libs::heap::Heap;
+use sinterpreter::libs::interpreter::{Interpreter, STACK_SIZE};
+use sinterpreter::libs::lex::Lox;
+use sinterpreter::libs::loader::Loader;
+use sinterpreter::libs::rpn_printer::RpnPrinter;
+use sinterpreter::libs::serializer::Format;
+use sinterpreter::libs::slsc;
+use sinterpreter::libs::tree_printer::TreePrinter;
+use sinterpreter::libs::vm::Vm;
+
+const USAGE: &str = "Usage: sinterpreter [--emit=ast|ast-tree|ast-dot|rpn|ast-json|ast-sexp] [--from=ast-json|ast-sexp] [--backend=tree|vm] [--gc-threshold=n] [--gc-stress] [--path=dirs] [file]
+       sinterpreter fmt [--check|--write] file...
+       sinterpreter compile file [-o output]
+       sinterpreter disasm file";
+const PRINTERS: [&str; 4] = ["ast", "ast-tree", "ast-dot", "rpn"];
+const BACKENDS: [&str; 2] = ["tree", "vm"];
+// Exit codes from sysexits.h: a program with errors found before it runs, and one that failed while
+// running, uncaught exceptions included.
+const DATA_ERROR: i32 = 65;
+const SOFTWARE_ERROR: i32 = 70;
 
 fn main() {
     env_logger::init();
-    let lex = Lox::init();
+
+    // The program runs on a thread of its own, with a stack deep enough for the tree-walker.
+    let program = thread::Builder::new().stack_size(STACK_SIZE).spawn(start).expect("Failed to start the program");
+    if program.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn start() {
+    match env::args().nth(1).as_deref() {
+        Some("fmt") => return fmt(env::args().skip(2).collect()),
+        Some("compile") => return compile(env::args().skip(2).collect()),
+        Some("disasm") => return disasm(env::args().skip(2).collect()),
+        _ => (),
+    }
+
+    let mut file_name = None;
+    let mut emit = None;
+    let mut from = None;
+    let mut backend = None;
+    let mut heap = Heap::new();
+    let mut stress = false;
+    let mut search_path = Vec::new();
+    for argument in env::args().skip(1) {
+        match argument.split_once('=') {
+            Some(("--emit", name)) if PRINTERS.contains(&name) => emit = Some(name.to_string()),
+            Some(("--emit", name)) => {
+                format(name);
+                emit = Some(name.to_string());
+            }
+            Some(("--from", name)) => from = Some(format(name)),
+            Some(("--backend", name)) if BACKENDS.contains(&name) => backend = Some(name.to_string()),
+            Some(("--backend", name)) => usage(&format!("Unknown backend '{name}'.")),
+            Some(("--gc-threshold", count)) => match count.parse() {
+                Ok(threshold) if threshold > 0 => heap = Heap::with_threshold(threshold),
+                _ => usage(&format!("Invalid heap threshold '{count}'.")),
+            },
+            // Directories to look for imported modules in, after the importing file's own.
+            Some(("--path", directories)) => search_path.extend(env::split_paths(directories)),
+            None if argument == "--gc-stress" => stress = true,
+            _ if argument.starts_with("--") => usage(&format!("Unknown option '{argument}'.")),
+            _ => file_name = Some(argument),
+        }
+    }
+
+    if stress {
+        heap = heap.stressed();
+    }
+    let loader = Loader::new(file_name.as_deref().map(Path::new), search_path);
+
+    // A compiled file only runs on the VM, without being checked again.
+    if let Some(file_name) = file_name.as_ref().filter(|name| name.ends_with(".slsc")) {
+        if emit.is_some() || from.is_some() || backend.as_deref().is_some_and(|backend| backend != "vm") {
+            usage("A compiled file can only run on the VM.");
+        }
+        let Some(script) = load(file_name) else { std::process::exit(DATA_ERROR) };
+        let mut vm = Vm::new();
+        vm.heap = heap;
+        vm.loader = loader;
+        if !vm.execute(script) {
+            std::process::exit(SOFTWARE_ERROR);
+        }
+        return;
+    }
+
+    let backend = backend.unwrap_or_else(|| "tree".to_string());
+
+    // A serialized tree is run as it is, skipping the scanner and the parser.
+    if let Some(from) = from {
+        let Some(file_name) = file_name else { usage("'--from' needs a file.") };
+        let statements = fs::read_to_string(&file_name)
+            .map_err(|error| error.to_string())
+            .and_then(|text| from.read(&text).map_err(|error| error.to_string()));
+        match statements {
+            Ok(statements) => run(&backend, heap, loader, statements),
+            Err(error) => {
+                eprintln!("Gotten error: {}", error);
+                std::process::exit(DATA_ERROR);
+            }
+        }
+        return;
+    }
+
+    let lex = Lox::init(file_name);
     if let Err(error) = lex {
         eprintln!("Gotten error: {}", error);
-        return;
+        std::process::exit(1);
     }
     let mut lex = lex.unwrap();
-    lex.run();
+    let Some(statements) = lex.parse() else { std::process::exit(DATA_ERROR) };
+    match emit {
+        Some(emit) => println!("{}", print(&emit, &statements)),
+        None => run(&backend, heap, loader, statements),
+    }
+}
+
+// Runs the program on `backend`, leaving with the exit code for the errors it has, if any. The checks
+// are done here rather than by the backends' `run`, to tell their errors from the run's.
+fn run(backend: &str, heap: Heap, loader: Loader, statements: Vec<Stmt>) {
+    let ran = match backend {
+        "vm" => {
+            let Some(script) = compiler::build(statements) else { std::process::exit(DATA_ERROR) };
+            let mut vm = Vm::new();
+            vm.heap = heap;
+            vm.loader = loader;
+            vm.execute(script)
+        }
+        _ => {
+            let Some((statements, locals)) = Interpreter::prepare(statements) else { std::process::exit(DATA_ERROR) };
+            let mut interpreter = Interpreter::new();
+            interpreter.heap = heap;
+            interpreter.loader = loader;
+            interpreter.resolve(locals);
+            interpreter.interpret(&statements)
+        }
+    };
+    if !ran {
+        std::process::exit(SOFTWARE_ERROR);
+    }
+}
+
+// Compiles a script to a `.slsc` file, next to it unless "-o" names another.
+fn compile(arguments: Vec<String>) {
+    let mut arguments = arguments.into_iter();
+    let mut file = None;
+    let mut output = None;
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "-o" => output = Some(arguments.next().unwrap_or_else(|| usage("'-o' needs a file."))),
+            _ if argument.starts_with('-') => usage(&format!("Unknown option '{argument}'.")),
+            _ if file.is_none() => file = Some(argument),
+            _ => usage("'compile' takes one file."),
+        }
+    }
+    let Some(file) = file else { usage("'compile' needs a file.") };
+    let output = output.unwrap_or_else(|| Path::new(&file).with_extension("slsc").to_string_lossy().into_owned());
+
+    let Some(script) = build(&file) else { std::process::exit(DATA_ERROR) };
+    if let Err(error) = fs::write(&output, slsc::write(&script)) {
+        eprintln!("Gotten error: {output}: {error}");
+        std::process::exit(1);
+    }
+}
+
+// Prints the bytecode of a script, or of a compiled file.
+fn disasm(arguments: Vec<String>) {
+    let [file] = arguments.as_slice() else { usage("'disasm' needs one file.") };
+    let script = if file.ends_with(".slsc") { load(file) } else { build(file) };
+    match script {
+        Some(script) => print!("{}", disassembler::disassemble(&script)),
+        None => std::process::exit(DATA_ERROR),
+    }
+}
+
+fn build(file: &str) -> Option<Rc<Prototype>> {
+    let mut lex = Lox::init(Some(file.to_string()))
+        .map_err(|error| eprintln!("Gotten error: {file}: {error}"))
+        .ok()?;
+    compiler::build(lex.parse()?)
+}
+
+fn load(file: &str) -> Option<Rc<Prototype>> {
+    let bytes = fs::read(file).map_err(|error| eprintln!("Gotten error: {file}: {error}")).ok()?;
+    slsc::read(&bytes).map_err(|error| eprintln!("Gotten error: {file}: {error}")).ok()
+}
+
+// Prints the formatted files, or with "--write" rewrites the ones that change. "--check" only
+// lists the files that aren't formatted and fails if there are any.
+fn fmt(arguments: Vec<String>) {
+    let mut check = false;
+    let mut write = false;
+    let mut files = Vec::new();
+    for argument in arguments {
+        match argument.as_str() {
+            "--check" => check = true,
+            "--write" => write = true,
+            _ if argument.starts_with("--") => usage(&format!("Unknown option '{argument}'.")),
+            _ => files.push(argument),
+        }
+    }
+    if files.is_empty() {
+        usage("'fmt' needs at least one file.");
+    }
+    if check && write {
+        usage("'--check' and '--write' can't be used together.");
+    }
+
+    let mut failed = false;
+    for file in files {
+        let source = match fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("Gotten error: {file}: {error}");
+                failed = true;
+                continue;
+            }
+        };
+        let formatted = match formatter::format(&source) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                eprintln!("Gotten error: {file}: {error}");
+                failed = true;
+                continue;
+            }
+        };
+
+        if check {
+            if formatted != source {
+                println!("{file} is not formatted.");
+                failed = true;
+            }
+        } else if write {
+            if formatted != source {
+                if let Err(error) = fs::write(&file, formatted) {
+                    eprintln!("Gotten error: {file}: {error}");
+                    failed = true;
+                }
+            }
+        } else {
+            print!("{formatted}");
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn print(emit: &str, statements: &[Stmt]) -> String {
+    match emit {
+        "ast" => statements.iter().map(|statement| AstPrinter {}.visit_stmt(statement)).collect::<Vec<_>>().join("\n"),
+        "ast-tree" => TreePrinter {}.print(statements),
+        "ast-dot" => DotPrinter::new().print(statements),
+        "rpn" => RpnPrinter {}.print(statements),
+        _ => format(emit).write(statements),
+    }
+}
+
+fn format(name: &str) -> Format {
+    Format::from_name(name).unwrap_or_else(|| usage(&format!("Unknown format '{name}'.")))
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("{message}\n{USAGE}");
+    process::exit(64);
 }